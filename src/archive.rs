@@ -0,0 +1,377 @@
+//! A `.tar`, `.tar.gz`, or `.zip` archive whose entries are the contents of
+//! a torrent, so that torrents can be created without extracting them.
+//!
+//! The format is detected from the archive's first bytes, not its filename.
+//! Entries are read in torrent order, which needn't be archive order, so
+//! archives read from standard input are first copied to an anonymous
+//! temporary file. Compressed tar archives are likewise decompressed to an
+//! anonymous temporary file once, so that the entries of every tar archive
+//! are read by seeking to them.
+
+use crate::common::*;
+
+use flate2::read::MultiGzDecoder;
+use zip::ZipArchive;
+
+#[derive(Debug)]
+pub(crate) struct Archive {
+  entries: BTreeMap<FilePath, Entry>,
+  file: File,
+  format: Format,
+  origin: Option<PathBuf>,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+enum Format {
+  Tar,
+  Zip,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Entry {
+  length: u64,
+  location: u64,
+}
+
+pub(crate) struct ArchiveReader {
+  file: File,
+  format: Format,
+  zip: Option<ZipArchive<File>>,
+}
+
+impl Archive {
+  const EXTENSIONS: &'static [&'static str] = &[".tar.gz", ".tgz", ".tar", ".zip"];
+
+  pub(crate) fn open(path: &Path) -> Result<Self> {
+    let file = File::open(path).context(error::Filesystem { path })?;
+    Self::new(file, Some(path.to_owned()))
+  }
+
+  /// Read an archive from `input`, which can only be read once, by copying
+  /// it to a temporary file.
+  pub(crate) fn spool(input: &mut dyn BufRead) -> Result<Self> {
+    let mut file = tempfile::tempfile().context(error::Stdin)?;
+    io::copy(input, &mut file).context(error::Stdin)?;
+    Self::new(file, None)
+  }
+
+  /// `filename` without a trailing archive extension, for use as a torrent's
+  /// default name.
+  pub(crate) fn stem(filename: &str) -> &str {
+    Self::EXTENSIONS
+      .iter()
+      .find_map(|extension| {
+        filename
+          .strip_suffix(extension)
+          .filter(|stem| !stem.is_empty())
+      })
+      .unwrap_or(filename)
+  }
+
+  fn new(file: File, origin: Option<PathBuf>) -> Result<Self> {
+    let mut entries = Vec::new();
+
+    let (file, format) =
+      Self::list(file, &mut entries).map_err(|source| Self::io_error(origin.as_deref(), source))?;
+
+    let mut archive = Self {
+      entries: BTreeMap::new(),
+      file,
+      format,
+      origin,
+    };
+
+    // Later entries replace earlier ones with the same path, as they would
+    // when extracting the archive.
+    for (path, entry) in entries {
+      let relative = path
+        .components()
+        .filter(|component| *component != path::Component::CurDir)
+        .collect::<PathBuf>();
+
+      if relative.components().count() == 0 {
+        continue;
+      }
+
+      archive
+        .entries
+        .insert(FilePath::from_relative_path(&relative)?, entry);
+    }
+
+    Ok(archive)
+  }
+
+  // Detect the format of the archive in `file` and list its regular files.
+  // Returns the file to read entries from, which is a temporary file with
+  // the decompressed archive if `file` is compressed.
+  fn list(mut file: File, entries: &mut Vec<(PathBuf, Entry)>) -> io::Result<(File, Format)> {
+    let mut magic = Vec::new();
+    file.seek(SeekFrom::Start(0))?;
+    (&mut file).take(4).read_to_end(&mut magic)?;
+    file.seek(SeekFrom::Start(0))?;
+
+    if magic.starts_with(&[0x1f, 0x8b]) {
+      let mut decompressed = tempfile::tempfile()?;
+      io::copy(
+        &mut MultiGzDecoder::new(BufReader::new(&mut file)),
+        &mut decompressed,
+      )?;
+      decompressed.seek(SeekFrom::Start(0))?;
+      file = decompressed;
+    }
+
+    let format = if magic.starts_with(b"PK\x03\x04") || magic.starts_with(b"PK\x05\x06") {
+      Format::Zip
+    } else {
+      Format::Tar
+    };
+
+    match format {
+      Format::Tar => {
+        Self::list_tar(tar::Archive::new(&mut file).entries_with_seek()?, entries)?;
+      }
+      Format::Zip => {
+        let mut archive = ZipArchive::new(&mut file)?;
+
+        for index in 0..archive.len() {
+          let entry = archive.by_index_raw(index)?;
+
+          if !entry.is_file() {
+            continue;
+          }
+
+          entries.push((
+            PathBuf::from(entry.name()),
+            Entry {
+              length: entry.size(),
+              location: index.into_u64(),
+            },
+          ));
+        }
+      }
+    }
+
+    Ok((file, format))
+  }
+
+  fn list_tar<R: Read>(
+    tar_entries: tar::Entries<R>,
+    entries: &mut Vec<(PathBuf, Entry)>,
+  ) -> io::Result<()> {
+    for entry in tar_entries {
+      let entry = entry?;
+
+      if !entry.header().entry_type().is_file() {
+        continue;
+      }
+
+      entries.push((
+        entry.path()?.into_owned(),
+        Entry {
+          length: entry.size(),
+          location: entry.raw_file_position(),
+        },
+      ));
+    }
+
+    Ok(())
+  }
+
+  /// The paths and lengths of the regular files in the archive.
+  pub(crate) fn entries(&self) -> impl Iterator<Item = (&FilePath, Bytes)> {
+    self
+      .entries
+      .iter()
+      .map(|(path, entry)| (path, Bytes(entry.length)))
+  }
+
+  pub(crate) fn reader(&self) -> Result<ArchiveReader> {
+    let file = self.file.try_clone().map_err(|source| self.error(source))?;
+
+    let zip = if self.format == Format::Zip {
+      Some(
+        ZipArchive::new(file.try_clone().map_err(|source| self.error(source))?)
+          .map_err(|source| self.error(source.into()))?,
+      )
+    } else {
+      None
+    };
+
+    Ok(ArchiveReader {
+      file,
+      format: self.format,
+      zip,
+    })
+  }
+
+  /// Call `f` with a reader of the data of the entry at `path`, and check
+  /// that all of it was read.
+  pub(crate) fn read<T>(
+    &self,
+    reader: &mut ArchiveReader,
+    path: &FilePath,
+    f: impl FnOnce(&mut dyn Read) -> io::Result<(T, Bytes)>,
+  ) -> Result<(T, Bytes)> {
+    let entry = self
+      .entries
+      .get(path)
+      .ok_or_else(|| Error::internal(format!("Archive entry missing: `{path}`")))?;
+
+    reader.read(*entry, f).map_err(|source| self.error(source))
+  }
+
+  pub(crate) fn error(&self, source: io::Error) -> Error {
+    Self::io_error(self.origin.as_deref(), source)
+  }
+
+  fn io_error(origin: Option<&Path>, source: io::Error) -> Error {
+    match origin {
+      Some(path) => Error::Filesystem {
+        path: path.to_owned(),
+        source,
+      },
+      None => Error::Stdin { source },
+    }
+  }
+}
+
+impl ArchiveReader {
+  fn read<T>(
+    &mut self,
+    entry: Entry,
+    f: impl FnOnce(&mut dyn Read) -> io::Result<(T, Bytes)>,
+  ) -> io::Result<(T, Bytes)> {
+    let (value, length) = match self.format {
+      Format::Tar => {
+        self.file.seek(SeekFrom::Start(entry.location))?;
+        f(&mut (&mut self.file).take(entry.length))?
+      }
+      Format::Zip => {
+        let zip = self
+          .zip
+          .as_mut()
+          .ok_or_else(|| io::Error::other("zip archive not opened"))?;
+
+        let index = usize::try_from(entry.location).map_err(io::Error::other)?;
+
+        f(&mut zip.by_index(index)?)?
+      }
+    };
+
+    if length.count() != entry.length {
+      return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok((value, length))
+  }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+  use super::*;
+
+  /// A tar archive of `files`, gzip-compressed if `gzip` is true.
+  pub(crate) fn tar(files: &[(&str, &str)], gzip: bool) -> Vec<u8> {
+    let mut builder = tar::Builder::new(Vec::new());
+
+    for (path, contents) in files {
+      let mut header = tar::Header::new_gnu();
+      header.set_size(contents.len().into_u64());
+      header.set_mode(0o644);
+      header.set_cksum();
+      builder
+        .append_data(&mut header, path, contents.as_bytes())
+        .unwrap();
+    }
+
+    let bytes = builder.into_inner().unwrap();
+
+    if gzip {
+      let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+      encoder.write_all(&bytes).unwrap();
+      encoder.finish().unwrap()
+    } else {
+      bytes
+    }
+  }
+
+  pub(crate) fn zip(files: &[(&str, &str)]) -> Vec<u8> {
+    let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+
+    for (path, contents) in files {
+      writer
+        .start_file(*path, zip::write::SimpleFileOptions::default())
+        .unwrap();
+      writer.write_all(contents.as_bytes()).unwrap();
+    }
+
+    writer.finish().unwrap().into_inner()
+  }
+
+  fn contents(bytes: &[u8]) -> Vec<(String, String)> {
+    let mut file = tempfile::tempfile().unwrap();
+    file.write_all(bytes).unwrap();
+
+    let archive = Archive::new(file, None).unwrap();
+    let mut reader = archive.reader().unwrap();
+
+    // Read in reverse archive order, to exercise rewinding.
+    archive
+      .entries()
+      .collect::<Vec<(&FilePath, Bytes)>>()
+      .into_iter()
+      .rev()
+      .map(|(path, _)| {
+        let (text, _) = archive
+          .read(&mut reader, path, |read| {
+            let mut text = String::new();
+            let length = read.read_to_string(&mut text)?;
+            Ok((text, Bytes::from(length.into_u64())))
+          })
+          .unwrap();
+        (path.to_string(), text)
+      })
+      .collect()
+  }
+
+  #[test]
+  fn formats() {
+    let files = [("./b/c", "cc"), ("a", "a"), ("b/d", "ddd")];
+
+    let want = [
+      ("b/d".to_owned(), "ddd".to_owned()),
+      ("b/c".to_owned(), "cc".to_owned()),
+      ("a".to_owned(), "a".to_owned()),
+    ];
+
+    assert_eq!(contents(&tar(&files, false)), want);
+    assert_eq!(contents(&tar(&files, true)), want);
+    assert_eq!(contents(&zip(&files)), want);
+  }
+
+  #[test]
+  fn duplicate() {
+    assert_eq!(
+      contents(&tar(&[("a", "old"), ("a", "new")], false)),
+      [("a".to_owned(), "new".to_owned())]
+    );
+  }
+
+  #[test]
+  fn parent_component() {
+    let mut file = tempfile::tempfile().unwrap();
+    file.write_all(&zip(&[("../a", "a")])).unwrap();
+
+    assert_matches!(Archive::new(file, None), Err(Error::PathComponent { .. }));
+  }
+
+  #[test]
+  fn stem() {
+    assert_eq!(Archive::stem("foo.tar.gz"), "foo");
+    assert_eq!(Archive::stem("foo.tgz"), "foo");
+    assert_eq!(Archive::stem("foo.tar"), "foo");
+    assert_eq!(Archive::stem("foo.zip"), "foo");
+    assert_eq!(Archive::stem("foo"), "foo");
+    assert_eq!(Archive::stem(".zip"), ".zip");
+  }
+}
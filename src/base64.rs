@@ -0,0 +1,45 @@
+use crate::common::*;
+
+/// Encode `bytes` as standard, padded base64, as used by HTTP basic
+/// authentication and the WebSocket handshake.
+pub(crate) fn base64(bytes: &[u8]) -> String {
+  const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+  let mut encoded = String::new();
+
+  for chunk in bytes.chunks(3) {
+    let n = chunk
+      .iter()
+      .enumerate()
+      .fold(0u32, |n, (i, &byte)| n | u32::from(byte) << (16 - 8 * i));
+
+    for i in 0..4 {
+      if i <= chunk.len() {
+        encoded.push(char::from(
+          ALPHABET[((n >> (18 - 6 * i)) & 0x3F).into_usize()],
+        ));
+      } else {
+        encoded.push('=');
+      }
+    }
+  }
+
+  encoded
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn padding() {
+    assert_eq!(base64(b""), "");
+    assert_eq!(base64(b"f"), "Zg==");
+    assert_eq!(base64(b"fo"), "Zm8=");
+    assert_eq!(base64(b"foo"), "Zm9v");
+    assert_eq!(
+      base64(b"Aladdin:open sesame"),
+      "QWxhZGRpbjpvcGVuIHNlc2FtZQ=="
+    );
+  }
+}
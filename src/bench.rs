@@ -56,7 +56,7 @@ impl Bench for HasherBench {
       Bytes::from(TEMPFILE_BYTES),
     );
 
-    let hasher = Hasher::new(false, 16 << 10, None);
+    let hasher = Hasher::new(ChecksumKinds::default(), 16 << 10, None);
 
     let _result = hasher.hash_files(&files).unwrap();
   }
@@ -0,0 +1,263 @@
+use crate::common::*;
+
+/// A list of IP address ranges that peers must not be contacted at. Loaded
+/// from eMule `ipfilter.dat` files, `PeerGuardian` `.p2p` files, or files of
+/// CIDR blocks and single addresses, which may be mixed freely.
+#[derive(Debug, Default)]
+pub(crate) struct Blocklist {
+  // Sorted, non-overlapping, inclusive ranges. IPv4 addresses are mapped into
+  // the IPv6 address space, so that both can be stored together.
+  ranges: Vec<(u128, u128)>,
+}
+
+enum Line {
+  Range(u128, u128),
+  Ignored,
+}
+
+impl Blocklist {
+  // eMule ranges with an access level above this are allowed, not blocked.
+  const EMULE_MAX_BLOCKED_LEVEL: u32 = 127;
+
+  pub(crate) fn load(path: &Path) -> Result<Self> {
+    let bytes = fs::read(path).context(error::Filesystem { path })?;
+    Self::parse(path, &String::from_utf8_lossy(&bytes))
+  }
+
+  fn parse(path: &Path, text: &str) -> Result<Self> {
+    let mut ranges = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+      let line = line.trim();
+
+      if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+        continue;
+      }
+
+      match Self::parse_line(line) {
+        Some(Line::Range(start, end)) => ranges.push((start.min(end), start.max(end))),
+        Some(Line::Ignored) => {}
+        None => {
+          return Err(Error::BlocklistParse {
+            path: path.to_owned(),
+            line: i + 1,
+            text: line.to_owned(),
+          })
+        }
+      }
+    }
+
+    ranges.sort_unstable();
+
+    let mut merged: Vec<(u128, u128)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+      match merged.last_mut() {
+        Some(last) if start <= last.1.saturating_add(1) => last.1 = last.1.max(end),
+        _ => merged.push((start, end)),
+      }
+    }
+
+    Ok(Self { ranges: merged })
+  }
+
+  fn parse_line(line: &str) -> Option<Line> {
+    // PeerGuardian: `description:1.2.3.0-1.2.3.255`. Descriptions may contain
+    // commas and slashes, so this form is tried first. The range follows the
+    // last colon, and since it can't contain one, it must be IPv4.
+    if let Some(range) = line
+      .rsplit_once(':')
+      .and_then(|(_, range)| Self::parse_range(range))
+    {
+      return Some(range);
+    }
+
+    // eMule: `001.002.003.000 - 001.002.003.255 , 000 , description`
+    if let Some((range, rest)) = line.split_once(',') {
+      let level = rest.split(',').next()?.trim().parse::<u32>().ok()?;
+
+      if level > Self::EMULE_MAX_BLOCKED_LEVEL {
+        return Some(Line::Ignored);
+      }
+
+      return Self::parse_range(range);
+    }
+
+    // CIDR: `1.2.3.0/24`
+    if let Some((ip, bits)) = line.split_once('/') {
+      let ip = Self::parse_ip(ip)?;
+      let bits = bits.trim().parse::<u32>().ok()?;
+      let bits = if ip.is_ipv4() { bits + 96 } else { bits };
+
+      if bits > 128 {
+        return None;
+      }
+
+      let mask = u128::MAX.checked_shl(128 - bits).unwrap_or(0);
+      let start = Self::key(ip) & mask;
+
+      return Some(Line::Range(start, start | !mask));
+    }
+
+    if line.contains('-') {
+      return Self::parse_range(line);
+    }
+
+    let ip = Self::key(Self::parse_ip(line)?);
+
+    Some(Line::Range(ip, ip))
+  }
+
+  fn parse_range(text: &str) -> Option<Line> {
+    let (start, end) = text.split_once('-')?;
+    let start = Self::parse_ip(start)?;
+    let end = Self::parse_ip(end)?;
+
+    if start.is_ipv4() != end.is_ipv4() {
+      return None;
+    }
+
+    Some(Line::Range(Self::key(start), Self::key(end)))
+  }
+
+  // eMule files pad IPv4 octets with zeros, which `Ipv4Addr` rejects.
+  fn parse_ip(text: &str) -> Option<IpAddr> {
+    let text = text.trim();
+
+    if let Ok(ip) = text.parse() {
+      return Some(ip);
+    }
+
+    let octets = text
+      .split('.')
+      .map(|octet| octet.parse::<u8>().ok())
+      .collect::<Option<Vec<u8>>>()?;
+
+    Some(Ipv4Addr::from(<[u8; 4]>::try_from(octets).ok()?).into())
+  }
+
+  fn key(ip: IpAddr) -> u128 {
+    match ip {
+      IpAddr::V4(ip) => ip.to_ipv6_mapped().into(),
+      IpAddr::V6(ip) => ip.into(),
+    }
+  }
+
+  pub(crate) fn contains(&self, ip: IpAddr) -> bool {
+    let key = Self::key(ip);
+    let i = self.ranges.partition_point(|(start, _)| *start <= key);
+    i > 0 && self.ranges[i - 1].1 >= key
+  }
+
+  /// Remove blocked peers from `peers`, returning the peers removed.
+  pub(crate) fn filter(&self, peers: &mut Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let (blocked, allowed) = peers.drain(..).partition(|peer| self.contains(peer.ip()));
+    *peers = allowed;
+    blocked
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(text: &str) -> Blocklist {
+    Blocklist::parse(Path::new("list"), text).unwrap()
+  }
+
+  fn blocked(blocklist: &Blocklist, ip: &str) -> bool {
+    blocklist.contains(ip.parse().unwrap())
+  }
+
+  #[test]
+  fn emule() {
+    let blocklist = parse(
+      "001.002.003.000 - 001.002.003.255 , 000 , Foo\n\
+       010.000.000.000 - 010.255.255.255 , 200 , Allowed\n",
+    );
+    assert!(blocked(&blocklist, "1.2.3.0"));
+    assert!(blocked(&blocklist, "1.2.3.42"));
+    assert!(blocked(&blocklist, "1.2.3.255"));
+    assert!(!blocked(&blocklist, "1.2.4.0"));
+    assert!(!blocked(&blocklist, "10.0.0.1"));
+  }
+
+  #[test]
+  fn peerguardian() {
+    let blocklist = parse("# comment\nSome: Org:1.2.3.0-1.2.3.255\n\nOther:5.6.7.8-5.6.7.8\n");
+    assert!(blocked(&blocklist, "1.2.3.128"));
+    assert!(blocked(&blocklist, "5.6.7.8"));
+    assert!(!blocked(&blocklist, "5.6.7.9"));
+  }
+
+  #[test]
+  fn peerguardian_descriptions() {
+    let blocklist = parse(
+      "Acme, Inc:1.2.3.0-1.2.3.255\n\
+       Foo/Bar:5.6.7.0-5.6.7.255\n\
+       Baz, 1/2: Qux:9.9.9.9-9.9.9.9\n",
+    );
+    assert!(blocked(&blocklist, "1.2.3.128"));
+    assert!(!blocked(&blocklist, "1.2.4.0"));
+    assert!(blocked(&blocklist, "5.6.7.8"));
+    assert!(!blocked(&blocklist, "5.6.8.0"));
+    assert!(blocked(&blocklist, "9.9.9.9"));
+  }
+
+  #[test]
+  fn ipv6_range() {
+    let blocklist = parse("2001:db8::1-2001:db8::ff\n");
+    assert!(blocked(&blocklist, "2001:db8::42"));
+    assert!(!blocked(&blocklist, "2001:db8::100"));
+  }
+
+  #[test]
+  fn cidr() {
+    let blocklist = parse("192.168.0.0/16\n2001:db8::/32\n9.9.9.9\n::1\n");
+    assert!(blocked(&blocklist, "192.168.255.1"));
+    assert!(!blocked(&blocklist, "192.169.0.0"));
+    assert!(blocked(&blocklist, "2001:db8::42"));
+    assert!(!blocked(&blocklist, "2001:db9::"));
+    assert!(blocked(&blocklist, "9.9.9.9"));
+    assert!(!blocked(&blocklist, "9.9.9.10"));
+    assert!(blocked(&blocklist, "::1"));
+    assert!(blocked(&blocklist, "::ffff:192.168.1.1"));
+  }
+
+  #[test]
+  fn merged() {
+    let blocklist = parse("1.0.0.0-1.0.0.10\n1.0.0.5-1.0.0.20\n1.0.0.21\n0.0.0.0/0\n");
+    assert_eq!(blocklist.ranges.len(), 1);
+    assert!(blocked(&blocklist, "255.255.255.255"));
+    assert!(!blocked(&blocklist, "::2"));
+  }
+
+  #[test]
+  fn filter() {
+    let blocklist = parse("1.2.3.4\n");
+    let mut peers = vec![
+      "1.2.3.4:1".parse().unwrap(),
+      "1.2.3.5:1".parse().unwrap(),
+      "1.2.3.4:2".parse().unwrap(),
+    ];
+    assert_eq!(
+      blocklist.filter(&mut peers),
+      ["1.2.3.4:1".parse().unwrap(), "1.2.3.4:2".parse().unwrap()]
+    );
+    assert_eq!(peers, ["1.2.3.5:1".parse().unwrap()]);
+  }
+
+  #[test]
+  fn invalid() {
+    for text in [
+      "foo",
+      "1.2.3.4/33",
+      "1.2.3.4 - ::1",
+      "1.2.3.4 - 1.2.3.5 , x , y",
+    ] {
+      assert_matches!(
+        Blocklist::parse(Path::new("list"), &format!("1.1.1.1\n{text}\n")),
+        Err(Error::BlocklistParse { line: 2, .. })
+      );
+    }
+  }
+}
@@ -0,0 +1,87 @@
+use crate::common::*;
+
+/// Computes whole-file checksums of the data written to it.
+pub(crate) struct ChecksumContext {
+  md5: Option<md5::Context>,
+  sha1: Option<Sha1>,
+  sha256: Option<ring::digest::Context>,
+}
+
+impl ChecksumContext {
+  pub(crate) fn new(kinds: ChecksumKinds) -> Self {
+    Self {
+      md5: kinds.md5sum.then(md5::Context::new),
+      sha1: kinds.sha1.then(Sha1::new),
+      sha256: kinds
+        .sha256
+        .then(|| ring::digest::Context::new(&ring::digest::SHA256)),
+    }
+  }
+
+  pub(crate) fn update(&mut self, data: &[u8]) {
+    if let Some(md5) = &mut self.md5 {
+      md5.consume(data);
+    }
+
+    if let Some(sha1) = &mut self.sha1 {
+      sha1.update(data);
+    }
+
+    if let Some(sha256) = &mut self.sha256 {
+      sha256.update(data);
+    }
+  }
+
+  pub(crate) fn finish(self) -> Checksums {
+    Checksums {
+      md5sum: self.md5.map(|context| context.finalize().into()),
+      sha1: self.sha1.map(|sha1| sha1.digest().into()),
+      sha256: self.sha256.map(|context| context.finish().into()),
+    }
+  }
+}
+
+impl Write for ChecksumContext {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self.update(buf);
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn checksums() {
+    let mut context = ChecksumContext::new(ChecksumKinds {
+      md5sum: true,
+      sha1: true,
+      sha256: true,
+    });
+
+    context.update(b"ab");
+    context.update(b"c");
+
+    assert_eq!(
+      context.finish(),
+      Checksums {
+        md5sum: Some(Md5Digest::from_data("abc")),
+        sha1: Some(Sha1Digest::from_data("abc")),
+        sha256: Some(Sha256Digest::from_data("abc")),
+      }
+    );
+  }
+
+  #[test]
+  fn none() {
+    assert_eq!(
+      ChecksumContext::new(ChecksumKinds::default()).finish(),
+      Checksums::default()
+    );
+  }
+}
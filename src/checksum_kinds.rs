@@ -0,0 +1,88 @@
+use crate::common::*;
+
+/// Which whole-file checksums to compute.
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub(crate) struct ChecksumKinds {
+  pub(crate) md5sum: bool,
+  pub(crate) sha1: bool,
+  pub(crate) sha256: bool,
+}
+
+impl ChecksumKinds {
+  /// The kinds of checksums present in `checksums`.
+  pub(crate) fn of(checksums: &Checksums) -> Self {
+    Self {
+      md5sum: checksums.md5sum.is_some(),
+      sha1: checksums.sha1.is_some(),
+      sha256: checksums.sha256.is_some(),
+    }
+  }
+
+  pub(crate) fn is_empty(self) -> bool {
+    self == Self::default()
+  }
+
+  /// Whether `checksums` includes a checksum of every kind in `self`.
+  pub(crate) fn covered_by(self, checksums: &Checksums) -> bool {
+    (!self.md5sum || checksums.md5sum.is_some())
+      && (!self.sha1 || checksums.sha1.is_some())
+      && (!self.sha256 || checksums.sha256.is_some())
+  }
+
+  /// The checksums in `checksums` of the kinds in `self`.
+  pub(crate) fn select(self, checksums: Checksums) -> Checksums {
+    Checksums {
+      md5sum: checksums.md5sum.filter(|_| self.md5sum),
+      sha1: checksums.sha1.filter(|_| self.sha1),
+      sha256: checksums.sha256.filter(|_| self.sha256),
+    }
+  }
+
+  pub(crate) fn context(self) -> ChecksumContext {
+    ChecksumContext::new(self)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn covered_by() {
+    let checksums = Checksums {
+      md5sum: Some(Md5Digest::from_data("foo")),
+      sha1: None,
+      sha256: Some(Sha256Digest::from_data("foo")),
+    };
+
+    assert!(ChecksumKinds::default().covered_by(&checksums));
+    assert!(ChecksumKinds::of(&checksums).covered_by(&checksums));
+    assert!(!ChecksumKinds {
+      sha1: true,
+      ..ChecksumKinds::default()
+    }
+    .covered_by(&checksums));
+  }
+
+  #[test]
+  fn select() {
+    let checksums = Checksums {
+      md5sum: Some(Md5Digest::from_data("foo")),
+      sha1: Some(Sha1Digest::from_data("foo")),
+      sha256: Some(Sha256Digest::from_data("foo")),
+    };
+
+    assert_eq!(
+      ChecksumKinds {
+        sha1: true,
+        ..ChecksumKinds::default()
+      }
+      .select(checksums),
+      Checksums {
+        md5sum: None,
+        sha1: checksums.sha1,
+        sha256: None,
+      }
+    );
+  }
+}
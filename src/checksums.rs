@@ -0,0 +1,10 @@
+use crate::common::*;
+
+/// Whole-file checksums, which may be stored alongside a file's length in the
+/// info dictionary.
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub(crate) struct Checksums {
+  pub(crate) md5sum: Option<Md5Digest>,
+  pub(crate) sha1: Option<Sha1Digest>,
+  pub(crate) sha256: Option<Sha256Digest>,
+}
@@ -1,20 +1,26 @@
 pub(crate) use {
   crate::{
-    arguments::Arguments, bytes::Bytes, consts, env::Env, error, error::Error,
-    file_error::FileError, file_info::FileInfo, file_path::FilePath, file_status::FileStatus,
-    files::Files, hasher::Hasher, host_port::HostPort, host_port_parse_error,
-    host_port_parse_error::HostPortParseError, info::Info, infohash::Infohash, input::Input,
-    input_stream::InputStream, input_target::InputTarget, into_u64::IntoU64, into_usize::IntoUsize,
-    invariant::Invariant, lint::Lint, linter::Linter, magnet_link::MagnetLink,
-    magnet_link_parse_error, magnet_link_parse_error::MagnetLinkParseError, md5_digest::Md5Digest,
-    metainfo::Metainfo, metainfo_error::MetainfoError, mode::Mode, options::Options,
-    output_stream::OutputStream, output_target::OutputTarget, peer,
-    piece_length_picker::PieceLengthPicker, piece_list::PieceList, platform::Platform,
-    platform_interface::PlatformInterface, print::Print, reckoner::Reckoner,
-    sha1_digest::Sha1Digest, shell::Shell, sort_key::SortKey, sort_order::SortOrder,
-    sort_spec::SortSpec, status::Status, step::Step, style::Style, subcommand::Subcommand,
-    table::Table, torrent_summary::TorrentSummary, tracker, use_color::UseColor,
-    verifier::Verifier, walker::Walker, xor_args::xor_args,
+    archive::Archive, arguments::Arguments, base64::base64, blocklist::Blocklist, bytes::Bytes,
+    checksum_context::ChecksumContext, checksum_kinds::ChecksumKinds, checksums::Checksums, config,
+    config::Config, consts, control::Control, env::Env, error, error::Error,
+    file_checksum::FileChecksum, file_error::FileError, file_info::FileInfo, file_path::FilePath,
+    file_status::FileStatus, files::Files, hash_cache, hash_cache::HashCache, hasher::Hasher,
+    host_port::HostPort, host_port_parse_error, host_port_parse_error::HostPortParseError,
+    info::Info, infohash::Infohash, input::Input, input_stream::InputStream,
+    input_target::InputTarget, into_u64::IntoU64, into_usize::IntoUsize, invariant::Invariant,
+    is_public::is_public, layout::Layout, lint::Lint, linter::Linter, lsd, magnet_link::MagnetLink,
+    magnet_link_parse_error, magnet_link_parse_error::MagnetLinkParseError, mapping::Mapping,
+    md5_digest::Md5Digest, metainfo::Metainfo, metainfo_error::MetainfoError, mode::Mode, nfc::nfc,
+    options::Options, output_stream::OutputStream, output_target::OutputTarget, peer,
+    piece_length_picker::PieceLengthPicker, piece_length_strategy::PieceLengthStrategy,
+    piece_list::PieceList, piece_store::PieceStore, platform::Platform,
+    platform_interface::PlatformInterface, print::Print, profile::Profile, profile_parse_error,
+    profile_parse_error::ProfileParseError, proxy, proxy::Proxy, reactor::Reactor,
+    reckoner::Reckoner, sha1_digest::Sha1Digest, sha256_digest::Sha256Digest, shell::Shell,
+    sort_key::SortKey, sort_order::SortOrder, sort_spec::SortSpec, status::Status, step::Step,
+    style::Style, subcommand::Subcommand, table::Table, task::Task,
+    torrent_summary::TorrentSummary, tracker, use_color::UseColor, utp, verifier::Verifier,
+    walker::Walker, webseed, websocket, xor_args::xor_args,
   },
   bendy::{decoding::FromBencode, encoding::ToBencode, value::Value},
   chrono::{TimeZone, Utc},
@@ -32,26 +38,29 @@ pub(crate) use {
   snafu::{ResultExt, Snafu},
   static_assertions::const_assert,
   std::{
+    any::Any,
     borrow::Cow,
     char,
     cmp::{Ordering, Reverse},
-    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
     convert::{TryFrom, TryInto},
     env,
     ffi::{OsStr, OsString},
     fmt::{self, Display, Formatter},
     fs::{self, File},
     hash::Hash,
-    io::{self, BufRead, BufReader, Cursor, Read, Write},
+    io::{self, BufRead, BufReader, Cursor, Read, Seek, SeekFrom, Write},
     iter::{self, Sum},
-    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket},
+    mem,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
     num::{ParseFloatError, ParseIntError, TryFromIntError},
-    ops::{AddAssign, Div, DivAssign, Mul, MulAssign, Sub, SubAssign},
+    ops::{AddAssign, Div, DivAssign, Mul, MulAssign, Range, Sub, SubAssign},
     path::{self, Path, PathBuf},
     str::{self, FromStr},
     string::FromUtf8Error,
-    sync::{mpsc::channel, Once},
-    time::{Duration, SystemTime, SystemTimeError},
+    sync::{Arc, Once},
+    thread,
+    time::{Duration, Instant, SystemTime, SystemTimeError},
   },
   structopt::{
     clap::{self, AppSettings},
@@ -72,11 +81,9 @@ pub(crate) use {
   crate::{capture::Capture, test_env::TestEnv, test_env_builder::TestEnvBuilder},
   std::{
     cell::RefCell,
-    net::TcpListener,
     ops::{Deref, DerefMut},
     process::Command,
     rc::Rc,
-    thread,
   },
   tempfile::TempDir,
   temptree::temptree,
@@ -0,0 +1,350 @@
+//! The user configuration file, which supplies defaults for `torrent create`
+//! in a `[create]` table, and named presets in `[preset.NAME]` tables whose
+//! settings take precedence over those in `[create]`:
+//!
+//! ```toml
+//! [create]
+//! allow = ["small-piece-length"]
+//! sort-by = ["path"]
+//!
+//! [preset.example]
+//! announce = "https://tracker.example.com/${EXAMPLE_PASSKEY}/announce"
+//! announce-tier = [["udp://a.example.com:80", "udp://b.example.com:80"]]
+//! private = true
+//! source = "EXAMPLE"
+//! ```
+//!
+//! Announce URLs may contain `${NAME}`, which is replaced with the value of
+//! the environment variable `NAME`, so that passkeys needn't be written in
+//! the file itself.
+
+use crate::common::*;
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Config {
+  #[serde(skip)]
+  path: Option<PathBuf>,
+  #[serde(default)]
+  create: Section,
+  #[serde(default, rename = "preset")]
+  presets: BTreeMap<String, Section>,
+}
+
+/// The settings in a `[create]` or `[preset.NAME]` table, as written.
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct Section {
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  allow: Vec<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  announce: Option<String>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  announce_tier: Vec<Vec<String>>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  private: Option<bool>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  sort_by: Vec<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  source: Option<String>,
+}
+
+/// Defaults for `torrent create`, after applying a preset and expanding
+/// environment variables.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Defaults {
+  pub(crate) allow: Vec<Lint>,
+  pub(crate) announce: Option<Url>,
+  pub(crate) announce_tiers: Vec<String>,
+  pub(crate) private: bool,
+  pub(crate) sort_by: Vec<SortSpec>,
+  pub(crate) source: Option<String>,
+}
+
+impl Config {
+  /// The path of the configuration file used when none is given, in the
+  /// `imdl` directory of `$XDG_CONFIG_HOME`, or of `~/.config` if
+  /// `XDG_CONFIG_HOME` is unset.
+  pub(crate) fn default_path(env: &Env) -> Option<PathBuf> {
+    let config_home = env
+      .var_os("XDG_CONFIG_HOME")
+      .map(PathBuf::from)
+      .filter(|path| path.is_absolute())
+      .or_else(|| {
+        env
+          .var_os("HOME")
+          .map(|home| PathBuf::from(home).join(".config"))
+      })?;
+
+    Some(config_home.join("imdl").join("config.toml"))
+  }
+
+  pub(crate) fn load(path: &Path) -> Result<Self> {
+    let text = fs::read_to_string(path).context(error::Filesystem { path })?;
+
+    let mut config = toml::from_str::<Self>(&text).context(error::ConfigParse { path })?;
+
+    config.path = Some(path.to_owned());
+
+    Ok(config)
+  }
+
+  pub(crate) fn path(&self) -> Option<&Path> {
+    self.path.as_deref()
+  }
+
+  /// The defaults for `torrent create` from the `[create]` table and, if
+  /// given, the preset named `preset`, looking up environment variables
+  /// used in announce URLs with `lookup`.
+  pub(crate) fn defaults(
+    &self,
+    preset: Option<&str>,
+    lookup: impl Fn(&str) -> Option<String>,
+  ) -> Result<Defaults> {
+    let section = self.section(preset)?;
+
+    let path = self.path.clone().unwrap_or_default();
+
+    let expand = |text: &str| -> Result<String> {
+      Self::expand(text, &lookup).map_err(|variable| Error::ConfigVariable {
+        path: path.clone(),
+        variable,
+      })
+    };
+
+    let invalid = |key: &str, value: &str| Error::ConfigValue {
+      path: path.clone(),
+      key: key.to_owned(),
+      value: value.to_owned(),
+    };
+
+    let announce = section
+      .announce
+      .map(|text| {
+        let expanded = expand(&text)?;
+        expanded
+          .parse::<Url>()
+          .map_err(|_| invalid("announce", &text))
+      })
+      .transpose()?;
+
+    let mut announce_tiers = Vec::new();
+    for tier in section.announce_tier {
+      let mut urls = Vec::new();
+
+      for text in tier {
+        let expanded = expand(&text)?;
+
+        if expanded.contains(',') || expanded.parse::<Url>().is_err() {
+          return Err(invalid("announce-tier", &text));
+        }
+
+        urls.push(expanded);
+      }
+
+      announce_tiers.push(urls.join(","));
+    }
+
+    Ok(Defaults {
+      allow: section
+        .allow
+        .iter()
+        .map(|text| text.parse().map_err(|_| invalid("allow", text)))
+        .collect::<Result<Vec<Lint>>>()?,
+      announce,
+      announce_tiers,
+      private: section.private.unwrap_or_default(),
+      sort_by: section
+        .sort_by
+        .iter()
+        .map(|text| text.parse().map_err(|_| invalid("sort-by", text)))
+        .collect::<Result<Vec<SortSpec>>>()?,
+      source: section.source,
+    })
+  }
+
+  /// The settings that `torrent create` uses with the preset named `preset`
+  /// as a `[create]` table, as written in the configuration file, so that
+  /// environment variables in announce URLs, which may hold passkeys, aren't
+  /// expanded.
+  pub(crate) fn table(&self, preset: Option<&str>) -> Result<toml::Table> {
+    Ok(toml::Table::try_from(self.section(preset)?).invariant_unwrap("sections serialize"))
+  }
+
+  // The `[create]` table, with the settings of the preset named `preset`, if
+  // given, taking precedence.
+  fn section(&self, preset: Option<&str>) -> Result<Section> {
+    match preset {
+      Some(name) => Ok(
+        self
+          .presets
+          .get(name)
+          .ok_or_else(|| Error::ConfigPresetUnknown {
+            preset: name.to_owned(),
+          })?
+          .clone()
+          .or(self.create.clone()),
+      ),
+      None => Ok(self.create.clone()),
+    }
+  }
+
+  // Replace each `${NAME}` in `text` with the value of environment variable
+  // `NAME`, returning the name of the first variable that isn't set.
+  fn expand(text: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String, String> {
+    let mut expanded = String::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find("${") {
+      expanded.push_str(&rest[..start]);
+      rest = &rest[start..];
+
+      let Some(end) = rest.find('}') else {
+        break;
+      };
+
+      let name = &rest[2..end];
+
+      expanded.push_str(&lookup(name).ok_or_else(|| name.to_owned())?);
+
+      rest = &rest[end + 1..];
+    }
+
+    expanded.push_str(rest);
+
+    Ok(expanded)
+  }
+}
+
+impl Section {
+  // Settings in `self` take precedence over those in `base`.
+  fn or(self, base: Self) -> Self {
+    fn or_vec<T>(vec: Vec<T>, base: Vec<T>) -> Vec<T> {
+      if vec.is_empty() {
+        base
+      } else {
+        vec
+      }
+    }
+
+    Self {
+      allow: or_vec(self.allow, base.allow),
+      announce: self.announce.or(base.announce),
+      announce_tier: or_vec(self.announce_tier, base.announce_tier),
+      private: self.private.or(base.private),
+      sort_by: or_vec(self.sort_by, base.sort_by),
+      source: self.source.or(base.source),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn config(text: &str) -> Config {
+    toml::from_str(text).unwrap()
+  }
+
+  fn lookup(name: &str) -> Option<String> {
+    (name == "PASSKEY").then(|| "hunter2".into())
+  }
+
+  #[test]
+  fn empty() {
+    assert_eq!(
+      config("").defaults(None, lookup).unwrap(),
+      Defaults::default()
+    );
+  }
+
+  #[test]
+  fn unknown_key() {
+    assert!(toml::from_str::<Config>("[create]\nfoo = 1").is_err());
+  }
+
+  #[test]
+  fn preset() {
+    let config = config(
+      r#"
+        [create]
+        allow = ["small-piece-length"]
+        private = true
+        source = "CREATE"
+
+        [preset.foo]
+        announce = "https://example.com/${PASSKEY}/announce"
+        announce-tier = [["udp://a.example.com:1", "udp://b.example.com:1"], ["udp://c.example.com:1"]]
+        private = false
+        sort-by = ["size:descending"]
+      "#,
+    );
+
+    assert_eq!(
+      config.defaults(Some("foo"), lookup).unwrap(),
+      Defaults {
+        allow: vec![Lint::SmallPieceLength],
+        announce: Some("https://example.com/hunter2/announce".parse().unwrap()),
+        announce_tiers: vec![
+          "udp://a.example.com:1,udp://b.example.com:1".into(),
+          "udp://c.example.com:1".into(),
+        ],
+        private: false,
+        sort_by: vec!["size:descending".parse().unwrap()],
+        source: Some("CREATE".into()),
+      }
+    );
+
+    assert!(config.defaults(None, lookup).unwrap().private);
+
+    assert_matches!(
+      config.defaults(Some("bar"), lookup),
+      Err(Error::ConfigPresetUnknown { preset }) if preset == "bar"
+    );
+  }
+
+  #[test]
+  fn expand() {
+    assert_eq!(Config::expand("a${PASSKEY}b", lookup).unwrap(), "ahunter2b");
+    assert_eq!(Config::expand("a${PASSKEY", lookup).unwrap(), "a${PASSKEY");
+    assert_eq!(Config::expand("${FOO}", lookup).unwrap_err(), "FOO");
+  }
+
+  #[test]
+  fn variable_unset() {
+    assert_matches!(
+      config("[create]\nannounce = \"https://example.com/${FOO}\"").defaults(None, lookup),
+      Err(Error::ConfigVariable { variable, .. }) if variable == "FOO"
+    );
+  }
+
+  #[test]
+  fn value_invalid() {
+    for (text, key) in [
+      ("allow = [\"foo\"]", "allow"),
+      ("announce = \"foo\"", "announce"),
+      ("announce-tier = [[\"foo\"]]", "announce-tier"),
+      ("sort-by = [\"foo\"]", "sort-by"),
+    ] {
+      assert_matches!(
+        config(&format!("[create]\n{text}")).defaults(None, lookup),
+        Err(Error::ConfigValue { key: actual, .. }) if actual == key
+      );
+    }
+  }
+
+  #[test]
+  fn table() {
+    let text = r#"allow = ["small-piece-length"]
+announce = "https://example.com/${PASSKEY}/announce"
+announce-tier = [["udp://a.example.com:1", "udp://b.example.com:1"]]
+private = true
+sort-by = ["size:descending"]
+source = "FOO"
+"#;
+
+    let table = config(&format!("[create]\n{text}")).table(None).unwrap();
+
+    assert_eq!(toml::to_string(&table).unwrap(), text);
+  }
+}
@@ -15,6 +15,11 @@ pub(crate) const CREATED_BY_DEFAULT: &str = concat!(
   env!("GIT_HEAD_PARTIAL_HASH"),
 );
 
+/// Value for `created by` torrent metainfo field with `--reproducible`.
+///
+/// Example: imdl/0.0.0
+pub(crate) const CREATED_BY_REPRODUCIBLE: &str = concat!("imdl/", env!("CARGO_PKG_VERSION"));
+
 /// Value for `encoding` torrent metainfo field.
 pub(crate) const ENCODING_UTF8: &str = "UTF-8";
 
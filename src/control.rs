@@ -0,0 +1,7 @@
+/// Returned by `Reactor::run` handlers to indicate whether the reactor should
+/// keep running, or stop and cancel all outstanding tasks.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Control {
+  Continue,
+  Stop,
+}
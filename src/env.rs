@@ -6,14 +6,17 @@ pub(crate) struct Env {
   input: Box<dyn InputStream>,
   err: OutputStream,
   out: OutputStream,
+  vars: BTreeMap<OsString, OsString>,
 }
 
 impl Env {
   pub(crate) fn main(args: impl Iterator<Item = impl Into<OsString>>) -> Result<Self> {
     let dir = env::current_dir().context(error::CurrentDirectoryGet)?;
 
-    let style = env::var_os("NO_COLOR").is_none()
-      && env::var_os("TERM").as_deref() != Some(OsStr::new("dumb"));
+    let vars = env::vars_os().collect::<BTreeMap<OsString, OsString>>();
+
+    let style = !vars.contains_key(OsStr::new("NO_COLOR"))
+      && vars.get(OsStr::new("TERM")).map(OsString::as_os_str) != Some(OsStr::new("dumb"));
 
     let out_stream = OutputStream::stdout(style);
     let err_stream = OutputStream::stderr(style);
@@ -21,6 +24,7 @@ impl Env {
     Ok(Self::new(
       dir,
       args,
+      vars,
       Box::new(io::stdin()),
       out_stream,
       err_stream,
@@ -36,8 +40,8 @@ impl Env {
     let app = {
       let mut app = Arguments::clap();
 
-      let width = env::var("IMDL_TERM_WIDTH")
-        .ok()
+      let width = self
+        .var("IMDL_TERM_WIDTH")
         .and_then(|width| width.parse::<usize>().ok());
 
       if let Some(width) = width {
@@ -101,6 +105,7 @@ impl Env {
   pub(crate) fn new<S, I>(
     dir: PathBuf,
     args: I,
+    vars: BTreeMap<OsString, OsString>,
     input: Box<dyn InputStream>,
     out: OutputStream,
     err: OutputStream,
@@ -115,6 +120,7 @@ impl Env {
       dir,
       out,
       err,
+      vars,
     }
   }
 
@@ -185,6 +191,20 @@ impl Env {
     &mut self.out
   }
 
+  /// The value of the environment variable `name`, if it is set. The
+  /// environment is read once in `Env::main`, and is empty in tests unless
+  /// they set variables, so that tests don't depend on the user's
+  /// environment.
+  pub(crate) fn var_os(&self, name: &str) -> Option<&OsStr> {
+    self.vars.get(OsStr::new(name)).map(OsString::as_os_str)
+  }
+
+  /// The value of the environment variable `name`, if it is set to valid
+  /// Unicode.
+  pub(crate) fn var(&self, name: &str) -> Option<&str> {
+    self.var_os(name).and_then(OsStr::to_str)
+  }
+
   pub(crate) fn resolve(&self, path: impl AsRef<Path>) -> Result<PathBuf> {
     let path = path.as_ref();
 
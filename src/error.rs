@@ -3,8 +3,32 @@ use crate::common::*;
 #[derive(Debug, Snafu)]
 #[snafu(context(suffix(false)), visibility(pub(crate)))]
 pub(crate) enum Error {
+  #[snafu(display(
+    "`{}` is added from both `{}` and `{}`",
+    path,
+    first.display(),
+    second.display()
+  ))]
+  AddConflict {
+    path: FilePath,
+    first: PathBuf,
+    second: PathBuf,
+  },
   #[snafu(display("Failed to parse announce URL: {}", source))]
   AnnounceUrlParse { source: url::ParseError },
+  #[snafu(display("Failed to create {} of {} batch torrents", failed, total))]
+  BatchFailed { failed: usize, total: usize },
+  #[snafu(display(
+    "Failed to parse line {} of blocklist `{}`: `{}`",
+    line,
+    path.display(),
+    text
+  ))]
+  BlocklistParse {
+    path: PathBuf,
+    line: usize,
+    text: String,
+  },
   #[snafu(display("Failed to parse byte count `{}`: {}", text, source))]
   ByteParse {
     text: String,
@@ -14,6 +38,30 @@ pub(crate) enum Error {
   ByteSuffix { text: String, suffix: String },
   #[snafu(display("{}", source))]
   Clap { source: clap::Error },
+  #[snafu(display("Failed to parse configuration file `{}`: {}", path.display(), source))]
+  ConfigParse {
+    path: PathBuf,
+    source: toml::de::Error,
+  },
+  #[snafu(display("Unknown preset `{}`", preset))]
+  ConfigPresetUnknown { preset: String },
+  #[snafu(display(
+    "Invalid value for `{}` in configuration file `{}`: `{}`",
+    key,
+    path.display(),
+    value
+  ))]
+  ConfigValue {
+    path: PathBuf,
+    key: String,
+    value: String,
+  },
+  #[snafu(display(
+    "Environment variable `{}` used in configuration file `{}` is not set",
+    variable,
+    path.display()
+  ))]
+  ConfigVariable { path: PathBuf, variable: String },
   #[snafu(display("Failed to get current directory: {}", source))]
   CurrentDirectoryGet { source: io::Error },
   #[snafu(display("Filename was not valid unicode: `{}`", filename.display()))]
@@ -24,10 +72,22 @@ pub(crate) enum Error {
   Filesystem { source: io::Error, path: PathBuf },
   #[snafu(display("Error searching for files: {}", source))]
   FileSearch { source: ignore::Error },
+  #[snafu(display("File list was not valid UTF-8: {}", source))]
+  FilesFromDecode { source: str::Utf8Error },
+  #[snafu(display("File list contains `{}` more than once", path))]
+  FilesFromDuplicate { path: FilePath },
+  #[snafu(display("`--files-from` cannot be used with `--input -`"))]
+  FilesFromInputStdin,
+  #[snafu(display("Listed file `{}` is not a file under `{}`", path, root.display()))]
+  FilesFromMissing { root: PathBuf, path: FilePath },
   #[snafu(display("Failed to fetch infodict from accessible peers"))]
   FromLinkNoInfo,
   #[snafu(display("Invalid glob: {}", source))]
   GlobParse { source: globset::Error },
+  #[snafu(display("No hash cache directory given with `--hash-cache` or `IMDL_HASH_CACHE`"))]
+  HashCacheMissing,
+  #[snafu(display("Failed to serialize hash cache entry: {}", source))]
+  HashCacheSerialize { source: bendy::serde::Error },
   #[snafu(display("Failed to serialize torrent info dictionary: {}", source))]
   InfoSerialize { source: bendy::serde::Error },
   #[snafu(display("Input target empty"))]
@@ -38,11 +98,28 @@ pub(crate) enum Error {
     message,
   ))]
   Internal { message: String },
+  #[snafu(display(
+    "Failed to listen for local peer announcements on `{}`: {}",
+    addr,
+    source
+  ))]
+  LsdBind { addr: SocketAddr, source: io::Error },
+  #[snafu(display("Local peer discovery cannot be used with private torrents"))]
+  LsdPrivate,
+  #[snafu(display("Failed to receive local peer announcement: {}", source))]
+  LsdReceive { source: io::Error },
+  #[snafu(display("Failed to send local peer announcement: {}", source))]
+  LsdSend { source: io::Error },
   #[snafu(display("Failed to parse magnet link `{}`: {}", text, source))]
   MagnetLinkParse {
     text: String,
     source: MagnetLinkParseError,
   },
+  #[snafu(display(
+    "Invalid `--add` mapping `{}`: expected `SRC[:DEST]` where DEST is a relative path",
+    text
+  ))]
+  MappingParse { text: String },
   #[snafu(display("Failed to deserialize torrent metainfo from {}: {}", input, source))]
   MetainfoDeserialize {
     source: bendy::serde::Error,
@@ -108,6 +185,29 @@ pub(crate) enum Error {
     bytes: Bytes,
     source: TryFromIntError,
   },
+  #[snafu(display(
+    "Peer sent bitfield of {} bytes, but torrent needs a bitfield of {} bytes",
+    length,
+    expected
+  ))]
+  PeerBitfieldLength { length: usize, expected: usize },
+  #[snafu(display("Peer connection deadline exceeded"))]
+  PeerDeadline,
+  #[snafu(display("Received encryption handshake for the wrong infohash"))]
+  PeerEncryptionInfohash,
+  #[snafu(display("Peer doesn't support an acceptable encryption method"))]
+  PeerEncryptionMethod,
+  #[snafu(display(
+    "Peer sent encryption handshake padding of {} bytes, more than the maximum of 512",
+    length
+  ))]
+  PeerEncryptionPadding { length: usize },
+  #[snafu(display("Peer attempted an unencrypted connection, but encryption is required"))]
+  PeerEncryptionRequired,
+  #[snafu(display("Failed to synchronize with peer's encryption handshake"))]
+  PeerEncryptionSync,
+  #[snafu(display("Peer doesn't support the `{}` extension", name))]
+  PeerExtensionNotSupported { name: &'static str },
   #[snafu(display("Received peer handshake with the wrong infohash"))]
   PeerHandshakeInfohash,
   #[snafu(display("Received peer handshake with the wrong protocol header"))]
@@ -118,10 +218,27 @@ pub(crate) enum Error {
   PeerMessageExtendedPayload,
   #[snafu(display("Failed to decode bencoded message: `{}`", source))]
   PeerMessageFromBencode { source: bendy::serde::Error },
+  #[snafu(display(
+    "Peer `{:?}` message has payload of invalid length {}",
+    flavour,
+    length
+  ))]
+  PeerMessageLength {
+    flavour: peer::message::Flavour,
+    length: usize,
+  },
   #[snafu(display("Peer message payload is too large"))]
   PeerMessagePayload { source: TryFromIntError },
+  #[snafu(display(
+    "Peer sent message of {} bytes, which exceeds the limit of {} bytes",
+    length,
+    max
+  ))]
+  PeerMessageTooLarge { length: u32, max: u32 },
   #[snafu(display("Extended handshake has not been received from peer"))]
   PeerNoExtendedHandshake,
+  #[snafu(display("Peer sent more than the limit of {} bytes", max))]
+  PeerReadLimit { max: u64 },
   #[snafu(display("Received UtMetadata info dict that's failed to deserialize"))]
   PeerUtMetadataInfoDeserialize { source: bendy::serde::Error },
   #[snafu(display("Received UtMetadata info dict that's too long"))]
@@ -130,22 +247,96 @@ pub(crate) enum Error {
   PeerUtMetadataPieceLength,
   #[snafu(display("Peer doesn't know metadata size"))]
   PeerUtMetadataMetadataSizeNotKnown,
+  #[snafu(display(
+    "Peer advertised metadata size of {} bytes, which exceeds the limit of {} bytes",
+    size,
+    max
+  ))]
+  PeerUtMetadataMetadataSizeTooLarge { size: usize, max: usize },
   #[snafu(display("Peer doesn't support UtMetadata extension"))]
   PeerUtMetadataNotSupported,
   #[snafu(display("Hash of received info dict does not match"))]
   PeerUtMetadataWrongInfohash,
   #[snafu(display("Received the wrong UtMetadata piece"))]
   PeerUtMetadataWrongPiece,
+  #[snafu(display(
+    "Requested {} bytes at offset {} of piece {}, which is out of range",
+    length,
+    begin,
+    index
+  ))]
+  PieceStoreRange { index: u32, begin: u32, length: u32 },
+  #[snafu(display("Torrent has too many pieces to serve: {}", source))]
+  PieceStoreTooManyPieces { source: TryFromIntError },
   #[snafu(display("Piece length `{}` is not an even power of two", bytes))]
   PieceLengthUneven { bytes: Bytes },
+  #[snafu(display(
+    "Piece length strategy `{}` must be `auto`, `client-compat`, `max-metainfo-size:BYTES`, or \
+     `target-piece-count:N`",
+    text
+  ))]
+  PieceLengthStrategy { text: String },
   #[snafu(display("Piece length must be at least 16 KiB"))]
   PieceLengthSmall,
   #[snafu(display("Piece length cannot be zero"))]
   PieceLengthZero,
   #[snafu(display("Private torrents must have tracker"))]
   PrivateTrackerless,
+  #[snafu(display("Profile `{}` given more than once", name))]
+  ProfileDuplicate { name: String },
+  #[snafu(display("Invalid profile on line {} of `{}`: {}", line, path.display(), source))]
+  ProfileFile {
+    path: PathBuf,
+    line: usize,
+    source: ProfileParseError,
+  },
+  #[snafu(display("More than one profile writes to {}", output))]
+  ProfileOutputConflict { output: OutputTarget },
+  #[snafu(display(
+    "Profile `{}` needs an `output` setting, since other output is going to standard output",
+    profile
+  ))]
+  ProfileOutputRequired { profile: String },
+  #[snafu(display("Domain `{}` is too long to send to a SOCKS5 proxy", domain))]
+  ProxyDomain { domain: String },
+  #[snafu(display("Failed to communicate with proxy `{}`: {}", addr, source))]
+  ProxyNetwork { addr: SocketAddr, source: io::Error },
+  #[snafu(display("Proxy refused request: {}", reason))]
+  ProxyRefused { reason: String },
+  #[snafu(display("Failed to resolve proxy `{}`: {}", url, source))]
+  ProxyResolve { url: Url, source: io::Error },
+  #[snafu(display("Malformed response from proxy"))]
+  ProxyResponse,
+  #[snafu(display(
+    "HTTP proxies cannot relay UDP tracker traffic, which requires a SOCKS5 proxy"
+  ))]
+  ProxyUdp,
+  #[snafu(display(
+    "Proxy URL `{}` must have the form `socks5://HOST[:PORT]` or `http://HOST[:PORT]`",
+    url
+  ))]
+  ProxyUrl { url: Url },
+  #[snafu(display("Failed to parse `IMDL_PROXY` value `{}` as a URL: {}", text, source))]
+  ProxyUrlParse {
+    text: String,
+    source: url::ParseError,
+  },
+  #[snafu(display("Network event loop failed: {}", source))]
+  Reactor { source: io::Error },
+  #[snafu(display(
+    "Multiple files have the path `{}` after Unicode normalization with `--reproducible`",
+    path
+  ))]
+  ReproducibleCollision { path: FilePath },
+  #[snafu(display("Failed to listen on `{}`: {}", addr, source))]
+  SeedListen { addr: SocketAddr, source: io::Error },
   #[snafu(display("Completion script for shell `{}` not UTF-8: {}", shell.name(), source))]
   ShellDecode { shell: Shell, source: FromUtf8Error },
+  #[snafu(display(
+    "Failed to parse `SOURCE_DATE_EPOCH` as seconds since the epoch: `{}`",
+    text
+  ))]
+  SourceDateEpoch { text: String },
   #[snafu(display("Failed to write to standard error: {}", source))]
   Stderr { source: io::Error },
   #[snafu(display("Failed to read from standard input: {}", source))]
@@ -164,6 +355,8 @@ pub(crate) enum Error {
   TrackerCompactPeerList,
   #[snafu(display("Tracker exchange to `udp://{}` timed out.", tracker_addr))]
   TrackerExchange { tracker_addr: SocketAddr },
+  #[snafu(display("Tracker returned failure: {}", reason))]
+  TrackerFailure { reason: String },
   #[snafu(display(
     "Cannot connect to tracker `{}`: URL does not specify a valid host port",
     tracker_url
@@ -184,8 +377,12 @@ pub(crate) enum Error {
   TrackerSend { source: io::Error },
   #[snafu(display("Failed to resolve socket addrs: {}", source))]
   TrackerSocketAddrs { source: io::Error },
+  #[snafu(display("Tracker `{}` timed out", tracker_url))]
+  TrackerTimeout { tracker_url: Url },
+  #[snafu(display("Tracker TLS connection failed: {}", source))]
+  TrackerTls { source: rustls::Error },
   #[snafu(display(
-    "Cannot connect to tracker `{}`: only UDP trackers are supported",
+    "Cannot connect to tracker `{}`: only UDP and WebSocket trackers are supported",
     tracker_url
   ))]
   TrackerUdpOnly { tracker_url: Url },
@@ -195,8 +392,6 @@ pub(crate) enum Error {
   UdpSocketConnect { addr: SocketAddr, source: io::Error },
   #[snafu(display("Failed to get local UDP socket address: {}", source))]
   UdpSocketLocalAddress { source: io::Error },
-  #[snafu(display("Failed to set read timeout: {}", source))]
-  UdpSocketReadTimeout { source: io::Error },
   #[snafu(display(
     "Feature `{}` cannot be used without passing the `--unstable` flag",
     feature
@@ -204,6 +399,16 @@ pub(crate) enum Error {
   Unstable { feature: &'static str },
   #[snafu(display("Torrent verification failed."))]
   Verify,
+  #[snafu(display("Failed to listen for web seed requests on `{}`: {}", addr, source))]
+  WebseedListen { addr: SocketAddr, source: io::Error },
+  #[snafu(display("WebSocket closed by server"))]
+  WebSocketClosed,
+  #[snafu(display("Malformed WebSocket frame"))]
+  WebSocketFrame,
+  #[snafu(display("WebSocket handshake failed: {}", reason))]
+  WebSocketHandshake { reason: String },
+  #[snafu(display("WebSocket message larger than {} bytes", max))]
+  WebSocketMessageTooLarge { max: usize },
   #[snafu(display("Failed to serialize JSON: {}", source))]
   JsonSerialize { source: serde_json::Error },
 }
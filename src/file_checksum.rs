@@ -0,0 +1,31 @@
+use crate::common::*;
+
+/// A strong whole-file checksum that `torrent create --file-checksum` can
+/// add to each file's entry in the info dictionary.
+#[derive(Debug, PartialEq, Copy, Clone, VariantNames, IntoStaticStr, EnumString)]
+#[strum(serialize_all = "kebab-case")]
+pub(crate) enum FileChecksum {
+  Sha1,
+  Sha256,
+}
+
+impl FileChecksum {
+  /// The checksum kinds selected by `md5sum` and `file_checksums`.
+  pub(crate) fn kinds(md5sum: bool, file_checksums: &[FileChecksum]) -> ChecksumKinds {
+    ChecksumKinds {
+      md5sum,
+      sha1: file_checksums.contains(&Self::Sha1),
+      sha256: file_checksums.contains(&Self::Sha256),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn variants() {
+    assert_eq!(FileChecksum::VARIANTS, &["sha1", "sha256"]);
+  }
+}
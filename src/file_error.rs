@@ -11,13 +11,25 @@ pub(crate) enum FileError {
     expected: Md5Digest,
     actual: Md5Digest,
   },
+  Sha1 {
+    expected: Sha1Digest,
+    actual: Sha1Digest,
+  },
+  Sha256 {
+    expected: Sha256Digest,
+    actual: Sha256Digest,
+  },
 }
 
 impl FileError {
+  /// Check the file at `path` against its expected length and whole-file
+  /// checksums. If the file's checksums are already known, they can be given
+  /// as `actual`, and the file won't be read to compute them.
   pub(crate) fn verify(
     path: &Path,
     expected_length: Bytes,
-    expected_md5: Option<Md5Digest>,
+    expected: Checksums,
+    actual: Option<Checksums>,
   ) -> Result<(), FileError> {
     let metadata = match path.metadata() {
       Ok(metadata) => metadata,
@@ -34,29 +46,51 @@ impl FileError {
       return Err(FileError::Directory);
     }
 
-    let actual = Bytes(metadata.len());
+    let length = Bytes(metadata.len());
 
-    let difference = actual.absolute_difference(expected_length);
+    let difference = length.absolute_difference(expected_length);
 
-    if actual > expected_length {
+    if length > expected_length {
       return Err(FileError::Surfeit(difference));
     }
 
-    if actual < expected_length {
+    if length < expected_length {
       return Err(FileError::Dearth(difference));
     }
 
-    if let Some(expected) = expected_md5 {
+    let kinds = ChecksumKinds::of(&expected);
+
+    if kinds.is_empty() {
+      return Ok(());
+    }
+
+    let actual = if let Some(actual) = actual.filter(|actual| kinds.covered_by(actual)) {
+      actual
+    } else {
       let mut reader = File::open(path)?;
-      let mut context = md5::Context::new();
+      let mut context = kinds.context();
       io::copy(&mut reader, &mut context)?;
-      let actual = context.finalize().into();
+      context.finish()
+    };
 
+    if let (Some(expected), Some(actual)) = (expected.md5sum, actual.md5sum) {
       if actual != expected {
         return Err(FileError::Md5 { actual, expected });
       }
     }
 
+    if let (Some(expected), Some(actual)) = (expected.sha1, actual.sha1) {
+      if actual != expected {
+        return Err(FileError::Sha1 { actual, expected });
+      }
+    }
+
+    if let (Some(expected), Some(actual)) = (expected.sha256, actual.sha256) {
+      if actual != expected {
+        return Err(FileError::Sha256 { actual, expected });
+      }
+    }
+
     Ok(())
   }
 }
@@ -71,12 +105,21 @@ impl Print for FileError {
   fn print(&self, stream: &mut OutputStream) -> io::Result<()> {
     let style = stream.style();
 
-    if let Self::Md5 { actual, expected } = self {
+    let mismatch = match self {
+      Self::Md5 { actual, expected } => Some(("MD5", actual.to_string(), expected.to_string())),
+      Self::Sha1 { actual, expected } => Some(("SHA-1", actual.to_string(), expected.to_string())),
+      Self::Sha256 { actual, expected } => {
+        Some(("SHA-256", actual.to_string(), expected.to_string()))
+      }
+      _ => None,
+    };
+
+    if let Some((algorithm, actual, expected)) = mismatch {
       write!(
         stream,
-        "MD5 checksum mismatch: {} (expected {})",
-        style.error().paint(actual.to_string()),
-        style.good().paint(expected.to_string()),
+        "{algorithm} checksum mismatch: {} (expected {})",
+        style.error().paint(actual),
+        style.good().paint(expected),
       )?;
 
       return Ok(());
@@ -88,7 +131,7 @@ impl Print for FileError {
       Self::Directory => write!(stream, "Expected file but found directory")?,
       Self::Surfeit(difference) => write!(stream, "{difference} too long")?,
       Self::Dearth(difference) => write!(stream, "{difference} too short")?,
-      Self::Md5 { .. } => {
+      Self::Md5 { .. } | Self::Sha1 { .. } | Self::Sha256 { .. } => {
         return Err(io::Error::other(
           Error::internal("Reached unreachable branch").to_string(),
         ))
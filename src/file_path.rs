@@ -46,6 +46,17 @@ impl FilePath {
     &self.components
   }
 
+  /// This path with each component in Unicode Normalization Form C.
+  pub(crate) fn normalized(&self) -> FilePath {
+    FilePath {
+      components: self
+        .components
+        .iter()
+        .map(|component| nfc(component))
+        .collect(),
+    }
+  }
+
   pub(crate) fn absolute(&self, root: &Path) -> PathBuf {
     let mut absolute = root.to_owned();
     for component in &self.components {
@@ -54,6 +65,10 @@ impl FilePath {
     absolute
   }
 
+  pub(crate) fn starts_with(&self, prefix: &FilePath) -> bool {
+    self.components.starts_with(&prefix.components)
+  }
+
   #[cfg(test)]
   pub(crate) fn from_components(components: &[&str]) -> FilePath {
     let components: Vec<String> = components.iter().copied().map(ToOwned::to_owned).collect();
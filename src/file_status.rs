@@ -11,9 +11,10 @@ impl FileStatus {
     absolute: &Path,
     path: FilePath,
     length: Bytes,
-    md5: Option<Md5Digest>,
+    checksums: Checksums,
+    actual: Option<Checksums>,
   ) -> Self {
-    let error = FileError::verify(absolute, length, md5).err();
+    let error = FileError::verify(absolute, length, checksums, actual).err();
 
     FileStatus { path, error }
   }
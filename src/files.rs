@@ -2,6 +2,8 @@ use crate::common::*;
 
 #[derive(Debug)]
 pub(crate) struct Files {
+  archive: Option<Archive>,
+  layout: Option<Layout>,
   root: PathBuf,
   total_size: Bytes,
   contents: Option<Vec<FilePath>>,
@@ -10,7 +12,9 @@ pub(crate) struct Files {
 impl Files {
   pub(crate) fn file(root: PathBuf, total_size: Bytes) -> Files {
     Files {
+      archive: None,
       contents: None,
+      layout: None,
       root,
       total_size,
     }
@@ -18,12 +22,66 @@ impl Files {
 
   pub(crate) fn dir(root: PathBuf, total_size: Bytes, contents: Vec<FilePath>) -> Files {
     Files {
+      archive: None,
       contents: Some(contents),
+      layout: None,
       root,
       total_size,
     }
   }
 
+  /// Files whose contents are entries of `archive`, rather than files under
+  /// `root`.
+  pub(crate) fn archive(
+    root: PathBuf,
+    total_size: Bytes,
+    contents: Vec<FilePath>,
+    archive: Archive,
+  ) -> Files {
+    Files {
+      archive: Some(archive),
+      contents: Some(contents),
+      layout: None,
+      root,
+      total_size,
+    }
+  }
+
+  /// Files whose contents are found where `layout` maps them, rather than
+  /// under `root`.
+  pub(crate) fn layout(
+    root: PathBuf,
+    total_size: Bytes,
+    contents: Vec<FilePath>,
+    layout: Layout,
+  ) -> Files {
+    Files {
+      archive: None,
+      contents: Some(contents),
+      layout: Some(layout),
+      root,
+      total_size,
+    }
+  }
+
+  pub(crate) fn archived(&self) -> Option<&Archive> {
+    self.archive.as_ref()
+  }
+
+  #[cfg(test)]
+  pub(crate) fn laid_out(&self) -> Option<&Layout> {
+    self.layout.as_ref()
+  }
+
+  /// The location on disk of the file at `file_path`.
+  pub(crate) fn path(&self, file_path: &FilePath) -> PathBuf {
+    self
+      .layout
+      .as_ref()
+      .and_then(|layout| layout.source(file_path))
+      .unwrap_or_else(|| file_path.absolute(&self.root))
+  }
+
   pub(crate) fn root(&self) -> &Path {
     &self.root
   }
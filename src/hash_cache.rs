@@ -0,0 +1,680 @@
+//! An on-disk cache of piece hashes and whole-file checksums, so that unchanged
+//! files don't need to be read again when creating or verifying torrents.
+//!
+//! Only the pieces that lie entirely within a file are cached, since pieces
+//! that span files also depend on their neighbors. Which pieces those are
+//! depends on the piece length and on where the file starts relative to a
+//! piece boundary, so both are part of an entry's key, along with the
+//! file's identity, size, and modification time. Each entry is stored in
+//! its own file, named after the hash of its key, and is written as soon as
+//! its file has been hashed, so an interrupted run loses at most the file
+//! it was hashing.
+
+use crate::common::*;
+
+use std::sync::Mutex;
+
+#[derive(Debug, Clone)]
+pub(crate) struct HashCache {
+  dir: PathBuf,
+  failure: Arc<Mutex<Option<Error>>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub(crate) struct Key {
+  alignment: u64,
+  device: u64,
+  inode: u64,
+  modified_nanoseconds: u32,
+  modified_seconds: u64,
+  path: String,
+  piece_length: u64,
+  size: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Entry {
+  key: Key,
+  #[serde(
+    skip_serializing_if = "Option::is_none",
+    default,
+    with = "unwrap_or_skip"
+  )]
+  md5sum: Option<Md5Digest>,
+  pieces: PieceList,
+  #[serde(
+    skip_serializing_if = "Option::is_none",
+    default,
+    with = "unwrap_or_skip"
+  )]
+  sha1: Option<Sha1Digest>,
+  #[serde(
+    skip_serializing_if = "Option::is_none",
+    default,
+    with = "unwrap_or_skip"
+  )]
+  sha256: Option<Sha256Digest>,
+}
+
+/// Where the pieces that lie entirely within a file are: after `head` bytes
+/// that complete a piece begun by earlier files, `pieces` whole pieces, and
+/// then `tail` bytes that begin a piece finished by later files.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub(crate) struct Region {
+  pub(crate) head: u64,
+  pub(crate) pieces: usize,
+  pub(crate) tail: u64,
+}
+
+/// Hashes files piece by piece, taking the hashes of pieces from a
+/// `HashCache` when it can, for `HashCache::hash`.
+pub(crate) trait PieceHasher {
+  fn piece_length(&self) -> usize;
+
+  /// How far into a piece the next file begins.
+  fn piece_bytes_hashed(&self) -> usize;
+
+  fn pieces(&self) -> &PieceList;
+
+  /// Hash everything in `read`, updating `checksums`, if any, as well.
+  /// Returns the number of bytes hashed.
+  fn hash_read(
+    &mut self,
+    read: &mut dyn BufRead,
+    checksums: Option<&mut ChecksumContext>,
+  ) -> io::Result<u64>;
+
+  /// Take `pieces`, which cover the next `length` bytes, from the cache
+  /// instead of hashing those bytes.
+  fn skip(&mut self, pieces: &PieceList, length: u64);
+}
+
+/// The outcome of `HashCache::prune`.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub(crate) struct Pruned {
+  pub(crate) removed: usize,
+  pub(crate) kept: usize,
+}
+
+impl HashCache {
+  pub(crate) fn open(dir: &Path) -> Result<Self> {
+    fs::create_dir_all(dir).context(error::Filesystem { path: dir })?;
+
+    Ok(Self {
+      dir: dir.to_owned(),
+      failure: Arc::new(Mutex::new(None)),
+    })
+  }
+
+  /// Hash the file at `path` with `hasher`, returning its checksums of the
+  /// given `kinds` and its length. Pieces that lie entirely within the file
+  /// are taken from `cache` if the file is unchanged, and otherwise stored in
+  /// it. The cache only saves time, so failing to store them isn't an error,
+  /// but the first such failure is kept for `take_failure`.
+  pub(crate) fn hash(
+    cache: Option<&Self>,
+    hasher: &mut impl PieceHasher,
+    path: &Path,
+    kinds: ChecksumKinds,
+  ) -> io::Result<(Checksums, u64)> {
+    let key = cache.and_then(|_| {
+      Self::key(
+        path,
+        hasher.piece_length().into_u64(),
+        hasher.piece_bytes_hashed().into_u64(),
+      )
+    });
+
+    if let (Some(cache), Some(key)) = (cache, &key) {
+      if let (Some((pieces, checksums)), Some(region)) = (cache.get(key), key.region()) {
+        if kinds.covered_by(&checksums) {
+          Self::hash_cached(hasher, path, region, &pieces)?;
+          return Ok((kinds.select(checksums), key.size()));
+        }
+      }
+    }
+
+    // If this file begins partway through a piece, the first piece completed
+    // while hashing it also contains bytes of earlier files.
+    let first = hasher.pieces().count() + usize::from(hasher.piece_bytes_hashed() > 0);
+
+    let mut context = kinds.context();
+
+    let length = hasher.hash_read(&mut BufReader::new(File::open(path)?), Some(&mut context))?;
+
+    let checksums = context.finish();
+
+    if let (Some(cache), Some(key)) = (cache, key) {
+      // Don't cache the file if it changed while we were reading it.
+      if let Some(region) = key.region().filter(|_| length == key.size()) {
+        let unchanged = Self::key(path, key.piece_length(), key.alignment()).as_ref() == Some(&key);
+
+        if unchanged {
+          let pieces = hasher.pieces().slice(first..first + region.pieces);
+          if let Err(err) = cache.insert(key, pieces, checksums) {
+            if let Ok(mut failure) = cache.failure.lock() {
+              failure.get_or_insert(err);
+            }
+          }
+        }
+      }
+    }
+
+    Ok((checksums, length))
+  }
+
+  /// The first failure to store hashes since the last call, if any.
+  pub(crate) fn take_failure(&self) -> Option<Error> {
+    self.failure.lock().ok()?.take()
+  }
+
+  // Hash the parts of a file outside of `region`, taking the hashes of the
+  // pieces inside it from the cache.
+  fn hash_cached(
+    hasher: &mut impl PieceHasher,
+    path: &Path,
+    region: Region,
+    pieces: &PieceList,
+  ) -> io::Result<()> {
+    let mut file = File::open(path)?;
+
+    Self::hash_exact(hasher, &mut file, region.head)?;
+
+    let skipped = region.length(hasher.piece_length().into_u64());
+
+    hasher.skip(pieces, skipped);
+
+    file.seek(SeekFrom::Start(region.head + skipped))?;
+
+    Self::hash_exact(hasher, &mut file, region.tail)
+  }
+
+  fn hash_exact(hasher: &mut impl PieceHasher, file: &mut File, length: u64) -> io::Result<()> {
+    if hasher.hash_read(&mut BufReader::new(file.take(length)), None)? < length {
+      return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok(())
+  }
+
+  /// The key for the file at `path`, starting `alignment` bytes into a
+  /// piece of `piece_length` bytes, or `None` if it can't be read.
+  pub(crate) fn key(path: &Path, piece_length: u64, alignment: u64) -> Option<Key> {
+    let path = path.canonicalize().ok()?;
+    let metadata = path.metadata().ok()?;
+
+    if !metadata.is_file() {
+      return None;
+    }
+
+    let modified = metadata
+      .modified()
+      .ok()?
+      .duration_since(SystemTime::UNIX_EPOCH)
+      .ok()?;
+
+    #[cfg(unix)]
+    let (device, inode) = {
+      use std::os::unix::fs::MetadataExt;
+      (metadata.dev(), metadata.ino())
+    };
+
+    #[cfg(not(unix))]
+    let (device, inode) = (0, 0);
+
+    Some(Key {
+      alignment,
+      device,
+      inode,
+      modified_nanoseconds: modified.subsec_nanos(),
+      modified_seconds: modified.as_secs(),
+      path: path.to_string_lossy().into_owned(),
+      piece_length,
+      size: metadata.len(),
+    })
+  }
+
+  /// The cached pieces and whole-file checksums for `key`, if any.
+  pub(crate) fn get(&self, key: &Key) -> Option<(PieceList, Checksums)> {
+    let bytes = fs::read(self.entry_path(key)).ok()?;
+
+    let entry = bendy::serde::from_bytes::<Entry>(&bytes).ok()?;
+
+    let region = key.region()?;
+
+    if entry.key != *key || entry.pieces.count() != region.pieces {
+      return None;
+    }
+
+    Some((
+      entry.pieces,
+      Checksums {
+        md5sum: entry.md5sum,
+        sha1: entry.sha1,
+        sha256: entry.sha256,
+      },
+    ))
+  }
+
+  pub(crate) fn insert(&self, key: Key, pieces: PieceList, checksums: Checksums) -> Result<()> {
+    let path = self.entry_path(&key);
+
+    let entry = Entry {
+      key,
+      md5sum: checksums.md5sum,
+      pieces,
+      sha1: checksums.sha1,
+      sha256: checksums.sha256,
+    };
+
+    let bytes = bendy::serde::to_bytes(&entry).context(error::HashCacheSerialize)?;
+
+    // Write to a temporary file and rename it into place, so that readers,
+    // including later runs after an interruption, never see a partial entry.
+    let tmp = self.dir.join(format!(
+      "{}.{:016x}.tmp",
+      Self::file_name(&entry.key),
+      rand::random::<u64>()
+    ));
+
+    fs::write(&tmp, bytes).context(error::Filesystem { path: &tmp })?;
+    fs::rename(&tmp, &path).context(error::Filesystem { path })?;
+
+    Ok(())
+  }
+
+  /// Remove entries for files that have since changed or disappeared, as
+  /// well as unreadable entries and leftover temporary files. If `all` is
+  /// true, remove every entry. Anything else in the cache directory, such as
+  /// files that weren't written by the cache or subdirectories, is left
+  /// alone.
+  pub(crate) fn prune(&self, all: bool) -> Result<Pruned> {
+    let mut pruned = Pruned {
+      removed: 0,
+      kept: 0,
+    };
+
+    for dir_entry in fs::read_dir(&self.dir).context(error::Filesystem { path: &self.dir })? {
+      let dir_entry = dir_entry.context(error::Filesystem { path: &self.dir })?;
+
+      let path = dir_entry.path();
+
+      let is_file = dir_entry
+        .file_type()
+        .context(error::Filesystem { path: &path })?
+        .is_file();
+
+      if !is_file
+        || !dir_entry
+          .file_name()
+          .to_str()
+          .is_some_and(Self::is_cache_file)
+      {
+        continue;
+      }
+
+      if !all && Self::is_current(&path) {
+        pruned.kept += 1;
+        continue;
+      }
+
+      fs::remove_file(&path).context(error::Filesystem { path })?;
+      pruned.removed += 1;
+    }
+
+    Ok(pruned)
+  }
+
+  fn is_current(path: &Path) -> bool {
+    if path.extension().is_some() {
+      return false;
+    }
+
+    let Some(entry) = fs::read(path)
+      .ok()
+      .and_then(|bytes| bendy::serde::from_bytes::<Entry>(&bytes).ok())
+    else {
+      return false;
+    };
+
+    Self::key(
+      Path::new(&entry.key.path),
+      entry.key.piece_length,
+      entry.key.alignment,
+    )
+    .as_ref()
+      == Some(&entry.key)
+  }
+
+  // Whether `name` is the name of an entry, `HASH`, or of a temporary file
+  // written by `insert`, `HASH.RANDOM.tmp`.
+  fn is_cache_file(name: &str) -> bool {
+    let Some((hash, rest)) = name.split_at_checked(40) else {
+      return false;
+    };
+
+    if !hash
+      .bytes()
+      .all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f'))
+    {
+      return false;
+    }
+
+    rest.is_empty()
+      || rest
+        .strip_prefix('.')
+        .and_then(|rest| rest.strip_suffix(".tmp"))
+        .is_some_and(|random| !random.is_empty())
+  }
+
+  fn entry_path(&self, key: &Key) -> PathBuf {
+    self.dir.join(Self::file_name(key))
+  }
+
+  fn file_name(key: &Key) -> String {
+    let bytes = bendy::serde::to_bytes(key).invariant_unwrap("keys serialize");
+    Sha1::from(bytes).digest().to_string()
+  }
+}
+
+impl Key {
+  pub(crate) fn alignment(&self) -> u64 {
+    self.alignment
+  }
+
+  pub(crate) fn piece_length(&self) -> u64 {
+    self.piece_length
+  }
+
+  pub(crate) fn size(&self) -> u64 {
+    self.size
+  }
+
+  /// The region of the file whose pieces are cached, or `None` if it has no
+  /// whole pieces.
+  pub(crate) fn region(&self) -> Option<Region> {
+    Region::new(self.alignment, self.size, self.piece_length)
+  }
+}
+
+impl Region {
+  pub(crate) fn new(alignment: u64, size: u64, piece_length: u64) -> Option<Self> {
+    if piece_length == 0 {
+      return None;
+    }
+
+    let head = (piece_length - alignment % piece_length) % piece_length;
+
+    let pieces = size.checked_sub(head)? / piece_length;
+
+    if pieces == 0 {
+      return None;
+    }
+
+    Some(Self {
+      head,
+      pieces: pieces.try_into().ok()?,
+      tail: size - head - pieces * piece_length,
+    })
+  }
+
+  /// The number of bytes in the region's whole pieces.
+  pub(crate) fn length(self, piece_length: u64) -> u64 {
+    self.pieces.into_u64() * piece_length
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn region() {
+    assert_eq!(
+      Region::new(0, 10, 4),
+      Some(Region {
+        head: 0,
+        pieces: 2,
+        tail: 2,
+      })
+    );
+    assert_eq!(
+      Region::new(3, 10, 4),
+      Some(Region {
+        head: 1,
+        pieces: 2,
+        tail: 1,
+      })
+    );
+    assert_eq!(
+      Region::new(1, 7, 4),
+      Some(Region {
+        head: 3,
+        pieces: 1,
+        tail: 0,
+      })
+    );
+    assert_eq!(Region::new(1, 6, 4), None);
+    assert_eq!(Region::new(0, 3, 4), None);
+    assert_eq!(Region::new(2, 1, 4), None);
+  }
+
+  #[test]
+  fn hash() {
+    let tree = temptree! {
+      cache: {},
+      foo: "abcdefgh",
+    };
+
+    let cache = HashCache::open(&tree.path().join("cache")).unwrap();
+
+    let kinds = ChecksumKinds {
+      md5sum: true,
+      sha1: false,
+      sha256: false,
+    };
+
+    let mut hasher = Hasher::new(kinds, 4, None);
+    let (checksums, length) =
+      HashCache::hash(Some(&cache), &mut hasher, &tree.path().join("foo"), kinds).unwrap();
+    assert_eq!(checksums.md5sum, Some(Md5Digest::from_data("abcdefgh")));
+    assert_eq!(length, 8);
+    assert_eq!(hasher.pieces(), &PieceList::from_pieces(["abcd", "efgh"]));
+
+    let key = HashCache::key(&tree.path().join("foo"), 4, 0).unwrap();
+    assert_eq!(
+      cache.get(&key),
+      Some((PieceList::from_pieces(["abcd", "efgh"]), checksums))
+    );
+    assert_matches!(cache.take_failure(), None);
+  }
+
+  #[test]
+  fn hash_insert_failure() {
+    let tree = temptree! {
+      cache: {},
+      foo: "abcdefgh",
+    };
+
+    let cache = HashCache::open(&tree.path().join("cache")).unwrap();
+
+    fs::remove_dir(tree.path().join("cache")).unwrap();
+
+    let kinds = ChecksumKinds::default();
+
+    let mut hasher = Hasher::new(kinds, 4, None);
+    HashCache::hash(Some(&cache), &mut hasher, &tree.path().join("foo"), kinds).unwrap();
+    assert_eq!(hasher.pieces(), &PieceList::from_pieces(["abcd", "efgh"]));
+
+    assert_matches!(cache.take_failure(), Some(Error::Filesystem { .. }));
+    assert_matches!(cache.take_failure(), None);
+  }
+
+  #[test]
+  fn round_trip() {
+    let tree = temptree! {
+      cache: {},
+      foo: "abcdefgh",
+    };
+
+    let cache = HashCache::open(&tree.path().join("cache")).unwrap();
+
+    let key = HashCache::key(&tree.path().join("foo"), 4, 0).unwrap();
+
+    assert_eq!(cache.get(&key), None);
+
+    let pieces = PieceList::from_pieces(["abcd", "efgh"]);
+    let checksums = Checksums {
+      md5sum: Some(Md5Digest::from_data("abcdefgh")),
+      sha1: Some(Sha1Digest::from_data("abcdefgh")),
+      sha256: Some(Sha256Digest::from_data("abcdefgh")),
+    };
+
+    cache
+      .insert(key.clone(), pieces.clone(), checksums)
+      .unwrap();
+
+    assert_eq!(cache.get(&key), Some((pieces, checksums)));
+
+    let other = HashCache::key(&tree.path().join("foo"), 4, 1).unwrap();
+
+    assert_eq!(cache.get(&other), None);
+  }
+
+  #[test]
+  fn modified() {
+    let tree = temptree! {
+      cache: {},
+      foo: "abcdefgh",
+    };
+
+    let cache = HashCache::open(&tree.path().join("cache")).unwrap();
+
+    let path = tree.path().join("foo");
+
+    let key = HashCache::key(&path, 4, 0).unwrap();
+
+    cache
+      .insert(
+        key,
+        PieceList::from_pieces(["abcd", "efgh"]),
+        Checksums::default(),
+      )
+      .unwrap();
+
+    fs::write(&path, "abcdefghijkl").unwrap();
+
+    let key = HashCache::key(&path, 4, 0).unwrap();
+
+    assert_eq!(cache.get(&key), None);
+  }
+
+  #[test]
+  fn prune() {
+    let tree = temptree! {
+      cache: {},
+      foo: "abcdefgh",
+      bar: "abcdefgh",
+    };
+
+    let cache = HashCache::open(&tree.path().join("cache")).unwrap();
+
+    for name in ["foo", "bar"] {
+      let key = HashCache::key(&tree.path().join(name), 4, 0).unwrap();
+      cache
+        .insert(
+          key,
+          PieceList::from_pieces(["abcd", "efgh"]),
+          Checksums::default(),
+        )
+        .unwrap();
+    }
+
+    fs::write(
+      tree
+        .path()
+        .join(format!("cache/{}.0123456789abcdef.tmp", "0".repeat(40))),
+      "",
+    )
+    .unwrap();
+
+    fs::remove_file(tree.path().join("bar")).unwrap();
+
+    assert_eq!(
+      cache.prune(false).unwrap(),
+      Pruned {
+        removed: 2,
+        kept: 1,
+      }
+    );
+
+    let key = HashCache::key(&tree.path().join("foo"), 4, 0).unwrap();
+    assert!(cache.get(&key).is_some());
+
+    assert_eq!(
+      cache.prune(true).unwrap(),
+      Pruned {
+        removed: 1,
+        kept: 0,
+      }
+    );
+
+    assert_eq!(cache.get(&key), None);
+  }
+
+  #[test]
+  fn prune_skips_foreign_files() {
+    let tree = temptree! {
+      cache: {
+        "notes.txt": "important",
+        "0123456789abcdef0123456789abcdef0123456x": "",
+        "0123456789abcdef0123456789abcdef01234567.json": "",
+        "0123456789abcdef0123456789abcdef01234567": {
+          foo: "",
+        },
+      },
+    };
+
+    let cache = HashCache::open(&tree.path().join("cache")).unwrap();
+
+    assert_eq!(
+      cache.prune(true).unwrap(),
+      Pruned {
+        removed: 0,
+        kept: 0,
+      }
+    );
+
+    assert_eq!(
+      fs::read_to_string(tree.path().join("cache/notes.txt")).unwrap(),
+      "important"
+    );
+    assert!(tree
+      .path()
+      .join("cache/0123456789abcdef0123456789abcdef0123456x")
+      .is_file());
+    assert!(tree
+      .path()
+      .join("cache/0123456789abcdef0123456789abcdef01234567.json")
+      .is_file());
+    assert!(tree
+      .path()
+      .join("cache/0123456789abcdef0123456789abcdef01234567/foo")
+      .is_file());
+  }
+
+  #[test]
+  fn is_cache_file() {
+    let hash = "0123456789abcdef0123456789abcdef01234567";
+    assert!(HashCache::is_cache_file(hash));
+    assert!(HashCache::is_cache_file(&format!(
+      "{hash}.0123456789abcdef.tmp"
+    )));
+    assert!(!HashCache::is_cache_file(&format!("{hash}..tmp")));
+    assert!(!HashCache::is_cache_file(&format!("{hash}.tmp")));
+    assert!(!HashCache::is_cache_file(&hash[1..]));
+    assert!(!HashCache::is_cache_file(&hash.to_uppercase()));
+    assert!(!HashCache::is_cache_file("junk.tmp"));
+    assert!(!HashCache::is_cache_file(""));
+  }
+}
@@ -1,9 +1,12 @@
 use crate::common::*;
 
+use hash_cache::PieceHasher;
+
 pub(crate) struct Hasher {
   buffer: Vec<u8>,
+  cache: Option<HashCache>,
+  checksums: ChecksumKinds,
   length: u64,
-  md5sum: bool,
   piece_bytes_hashed: usize,
   piece_length: usize,
   pieces: PieceList,
@@ -12,28 +15,47 @@ pub(crate) struct Hasher {
 }
 
 impl Hasher {
-  pub(crate) fn new(md5sum: bool, piece_length: usize, progress_bar: Option<ProgressBar>) -> Self {
+  pub(crate) fn new(
+    checksums: ChecksumKinds,
+    piece_length: usize,
+    progress_bar: Option<ProgressBar>,
+  ) -> Self {
     Self {
       buffer: vec![0; piece_length],
+      cache: None,
+      checksums,
       length: 0,
       piece_bytes_hashed: 0,
       pieces: PieceList::new(),
       sha1: Sha1::new(),
       piece_length,
-      md5sum,
       progress_bar,
     }
   }
 
+  /// Reuse piece hashes and whole-file checksums of unchanged files from `cache`,
+  /// and store those of files that had to be read.
+  pub(crate) fn caching(self, cache: Option<HashCache>) -> Self {
+    Self { cache, ..self }
+  }
+
   pub(crate) fn hash_files(mut self, files: &Files) -> Result<(Mode, PieceList), Error> {
     let mode = if let Some(contents) = files.contents() {
-      let files = self.hash_contents(files.root(), contents)?;
+      let files = match files.archived() {
+        Some(archive) => self.hash_archive(archive, contents)?,
+        None => self.hash_contents(files, contents)?,
+      };
 
       Mode::Multiple { files }
     } else {
-      let (md5sum, length) = self.hash_file(files.root())?;
+      let (checksums, length) = self.hash_file(files.root())?;
 
-      Mode::Single { md5sum, length }
+      Mode::Single {
+        length,
+        md5sum: checksums.md5sum,
+        sha1: checksums.sha1,
+        sha256: checksums.sha256,
+      }
     };
 
     self.finish();
@@ -42,9 +64,14 @@ impl Hasher {
   }
 
   pub(crate) fn hash_stdin(mut self, stdin: &mut dyn BufRead) -> Result<(Mode, PieceList), Error> {
-    let (md5sum, length) = self.hash_read_io(stdin).context(error::Stdin)?;
+    let (checksums, length) = self.hash_read_io(stdin).context(error::Stdin)?;
 
-    let mode = Mode::Single { md5sum, length };
+    let mode = Mode::Single {
+      length,
+      md5sum: checksums.md5sum,
+      sha1: checksums.sha1,
+      sha256: checksums.sha256,
+    };
 
     self.finish();
 
@@ -61,19 +88,21 @@ impl Hasher {
 
   fn hash_contents(
     &mut self,
-    root: &Path,
+    contents: &Files,
     file_paths: &[FilePath],
   ) -> Result<Vec<FileInfo>, Error> {
     let mut files = Vec::new();
 
     for file_path in file_paths {
-      let path = file_path.absolute(root);
+      let path = contents.path(file_path);
 
-      let (md5sum, length) = self.hash_file(&path)?;
+      let (checksums, length) = self.hash_file(&path)?;
 
       files.push(FileInfo {
         path: file_path.clone(),
-        md5sum,
+        md5sum: checksums.md5sum,
+        sha1: checksums.sha1,
+        sha256: checksums.sha256,
         length,
       });
     }
@@ -81,22 +110,71 @@ impl Hasher {
     Ok(files)
   }
 
-  fn hash_file(&mut self, path: &Path) -> Result<(Option<Md5Digest>, Bytes), Error> {
-    let file = File::open(path).context(error::Filesystem { path })?;
+  // Hash the entries of `archive` at `file_paths`. Archive entries have no
+  // identity on disk, so the hash cache isn't used.
+  fn hash_archive(
+    &mut self,
+    archive: &Archive,
+    file_paths: &[FilePath],
+  ) -> Result<Vec<FileInfo>, Error> {
+    let mut reader = archive.reader()?;
+
+    let mut files = Vec::new();
+
+    for file_path in file_paths {
+      let (checksums, length) = archive.read(&mut reader, file_path, |read| {
+        self.hash_read_io(&mut BufReader::new(read))
+      })?;
 
-    self
-      .hash_read_io(&mut BufReader::new(file))
-      .context(error::Filesystem { path })
+      files.push(FileInfo {
+        path: file_path.clone(),
+        md5sum: checksums.md5sum,
+        sha1: checksums.sha1,
+        sha256: checksums.sha256,
+        length,
+      });
+    }
+
+    Ok(files)
   }
 
-  fn hash_read_io(&mut self, file: &mut dyn BufRead) -> io::Result<(Option<Md5Digest>, Bytes)> {
-    let mut bytes_hashed = 0;
+  fn hash_file(&mut self, path: &Path) -> Result<(Checksums, Bytes), Error> {
+    let cache = self.cache.clone();
 
-    let mut md5 = if self.md5sum {
-      Some(md5::Context::new())
-    } else {
-      None
-    };
+    let (checksums, length) = HashCache::hash(cache.as_ref(), self, path, self.checksums)
+      .context(error::Filesystem { path })?;
+
+    Ok((checksums, Bytes(length)))
+  }
+
+  fn hash_read_io(&mut self, file: &mut dyn BufRead) -> io::Result<(Checksums, Bytes)> {
+    let mut context = self.checksums.context();
+
+    let bytes_hashed = self.hash_read(file, Some(&mut context))?;
+
+    Ok((context.finish(), Bytes::from(bytes_hashed)))
+  }
+}
+
+impl PieceHasher for Hasher {
+  fn piece_length(&self) -> usize {
+    self.piece_length
+  }
+
+  fn piece_bytes_hashed(&self) -> usize {
+    self.piece_bytes_hashed
+  }
+
+  fn pieces(&self) -> &PieceList {
+    &self.pieces
+  }
+
+  fn hash_read(
+    &mut self,
+    file: &mut dyn BufRead,
+    mut checksums: Option<&mut ChecksumContext>,
+  ) -> io::Result<u64> {
+    let mut bytes_hashed = 0;
 
     loop {
       let remaining = &mut self.buffer[..self.piece_length - self.piece_bytes_hashed];
@@ -120,8 +198,8 @@ impl Hasher {
         self.piece_bytes_hashed = 0;
       }
 
-      if let Some(md5) = md5.as_mut() {
-        md5.consume(read);
+      if let Some(checksums) = checksums.as_mut() {
+        checksums.update(read);
       }
 
       if let Some(progress_bar) = &self.progress_bar {
@@ -131,9 +209,16 @@ impl Hasher {
 
     self.length += bytes_hashed.into_u64();
 
-    Ok((
-      md5.map(|context| context.finalize().into()),
-      Bytes::from(bytes_hashed.into_u64()),
-    ))
+    Ok(bytes_hashed.into_u64())
+  }
+
+  fn skip(&mut self, pieces: &PieceList, length: u64) {
+    self.pieces.extend(pieces);
+
+    if let Some(progress_bar) = &self.progress_bar {
+      progress_bar.inc(length);
+    }
+
+    self.length += length;
   }
 }
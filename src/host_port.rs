@@ -6,6 +6,33 @@ pub(crate) struct HostPort {
   port: u16,
 }
 
+impl HostPort {
+  pub(crate) fn new(host: Host, port: u16) -> Self {
+    Self { host, port }
+  }
+
+  pub(crate) fn host(&self) -> &Host {
+    &self.host
+  }
+
+  pub(crate) fn port(&self) -> u16 {
+    self.port
+  }
+
+  /// The address this refers to, if its host is an IP address rather than a
+  /// domain that must be looked up. URLs with schemes that `url` doesn't
+  /// know, like `udp://`, have IPv4 hosts that are parsed as domains.
+  pub(crate) fn socket_addr(&self) -> Option<SocketAddr> {
+    let ip = match &self.host {
+      Host::Domain(domain) => domain.parse().ok()?,
+      Host::Ipv4(ip) => IpAddr::V4(*ip),
+      Host::Ipv6(ip) => IpAddr::V6(*ip),
+    };
+
+    Some(SocketAddr::new(ip, self.port))
+  }
+}
+
 impl FromStr for HostPort {
   type Err = HostPortParseError;
 
@@ -57,6 +84,20 @@ impl Display for HostPort {
   }
 }
 
+impl From<SocketAddr> for HostPort {
+  fn from(addr: SocketAddr) -> Self {
+    let host = match addr.ip() {
+      IpAddr::V4(ip) => Host::Ipv4(ip),
+      IpAddr::V6(ip) => Host::Ipv6(ip),
+    };
+
+    Self {
+      host,
+      port: addr.port(),
+    }
+  }
+}
+
 impl TryFrom<&Url> for HostPort {
   type Error = HostPortParseError;
 
@@ -201,6 +242,21 @@ mod tests {
     assert_eq!(host_port.port, 12345);
   }
 
+  #[test]
+  fn socket_addr() {
+    let url = Url::parse("udp://127.0.0.1:12345").unwrap();
+    assert_eq!(
+      HostPort::try_from(&url).unwrap().socket_addr(),
+      Some("127.0.0.1:12345".parse().unwrap())
+    );
+
+    let url = Url::parse("udp://imdl.io:12345").unwrap();
+    assert_eq!(HostPort::try_from(&url).unwrap().socket_addr(), None);
+
+    let addr = "[::1]:6881".parse().unwrap();
+    assert_eq!(HostPort::from(addr).socket_addr(), Some(addr));
+  }
+
   #[test]
   fn test_from_url_no_port() {
     let url = Url::parse("udp://imdl.io").unwrap();
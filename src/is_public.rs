@@ -0,0 +1,71 @@
+use crate::common::*;
+
+/// Whether `ip` may be reachable from the public internet, rather than being
+/// a loopback, private, link-local, or otherwise special address. Used to
+/// keep peers from directing requests at hosts on our own network.
+pub(crate) fn is_public(ip: IpAddr) -> bool {
+  match ip {
+    IpAddr::V4(ip) => {
+      !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        // 100.64.0.0/10, shared address space for carrier-grade NAT
+        || (ip.octets()[0] == 100 && ip.octets()[1] & 0xc0 == 64))
+    }
+    IpAddr::V6(ip) => {
+      if let Some(ip) = ip.to_ipv4_mapped() {
+        return is_public(ip.into());
+      }
+
+      let first = ip.segments()[0];
+
+      !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // fc00::/7, unique local addresses
+        || first & 0xfe00 == 0xfc00
+        // fe80::/10, link-local addresses
+        || first & 0xffc0 == 0xfe80)
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn public() {
+    for ip in ["1.2.3.4", "100.128.0.1", "2001:db8::1", "::ffff:1.2.3.4"] {
+      assert!(is_public(ip.parse().unwrap()), "{ip}");
+    }
+  }
+
+  #[test]
+  fn not_public() {
+    for ip in [
+      "0.0.0.0",
+      "127.0.0.1",
+      "10.0.0.1",
+      "172.16.0.1",
+      "192.168.1.1",
+      "169.254.0.1",
+      "100.64.0.1",
+      "255.255.255.255",
+      "224.0.0.1",
+      "::",
+      "::1",
+      "fc00::1",
+      "fd12::1",
+      "fe80::1",
+      "ff02::1",
+      "::ffff:127.0.0.1",
+      "::ffff:192.168.0.1",
+    ] {
+      assert!(!is_public(ip.parse().unwrap()), "{ip}");
+    }
+  }
+}
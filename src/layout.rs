@@ -0,0 +1,102 @@
+use crate::common::*;
+
+/// A virtual directory tree, built from `--add SRC[:DEST]` mappings, in which
+/// the files of a torrent may come from several places on disk, under
+/// different names.
+#[derive(Debug, Clone)]
+pub(crate) struct Layout {
+  mappings: Vec<(FilePath, PathBuf)>,
+}
+
+impl Layout {
+  pub(crate) fn new(mappings: &[Mapping], env: &Env) -> Result<Self> {
+    let mut resolved = Vec::new();
+
+    for mapping in mappings {
+      let source = env.resolve(&mapping.source)?;
+
+      let destination = if let Some(destination) = &mapping.destination {
+        destination.clone()
+      } else {
+        let filename = source.file_name().ok_or_else(|| Error::FilenameExtract {
+          path: mapping.source.clone(),
+        })?;
+
+        FilePath::from_relative_path(Path::new(filename))?
+      };
+
+      resolved.push((destination, source));
+    }
+
+    Ok(Self { mappings: resolved })
+  }
+
+  /// Each destination path and the source path it's mapped from.
+  pub(crate) fn mappings(&self) -> &[(FilePath, PathBuf)] {
+    &self.mappings
+  }
+
+  /// The source of the file at `path` in the torrent, if any mapping covers
+  /// it. Mappings with longer destinations take precedence, and when several
+  /// directories are mapped to the same destination, the first that contains
+  /// the file is used.
+  pub(crate) fn source(&self, path: &FilePath) -> Option<PathBuf> {
+    let mut candidates = self
+      .mappings
+      .iter()
+      .filter(|(destination, _)| path.starts_with(destination))
+      .map(|(destination, source)| {
+        let mut candidate = source.clone();
+        for component in &path.components()[destination.components().len()..] {
+          candidate.push(component);
+        }
+        (destination.components().len(), candidate)
+      })
+      .collect::<Vec<(usize, PathBuf)>>();
+
+    candidates.sort_by_key(|(depth, _)| Reverse(*depth));
+
+    candidates
+      .iter()
+      .find(|(_, candidate)| candidate.is_file())
+      .or(candidates.first())
+      .map(|(_, candidate)| candidate.clone())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn source() {
+    let tree = temptree! {
+      a: {
+        x: "",
+      },
+      b: {
+        y: "",
+      },
+      c: "",
+    };
+
+    let layout = Layout {
+      mappings: vec![
+        (FilePath::from_components(&["d"]), tree.path().join("a")),
+        (FilePath::from_components(&["d"]), tree.path().join("b")),
+        (
+          FilePath::from_components(&["d", "z"]),
+          tree.path().join("c"),
+        ),
+      ],
+    };
+
+    let source = |components: &[&str]| layout.source(&FilePath::from_components(components));
+
+    assert_eq!(source(&["d", "x"]), Some(tree.path().join("a").join("x")));
+    assert_eq!(source(&["d", "y"]), Some(tree.path().join("b").join("y")));
+    assert_eq!(source(&["d", "z"]), Some(tree.path().join("c")));
+    assert_eq!(source(&["d", "w"]), Some(tree.path().join("a").join("w")));
+    assert_eq!(source(&["e"]), None);
+  }
+}
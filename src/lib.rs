@@ -26,18 +26,28 @@ mod test_env_builder;
 #[cfg(test)]
 mod capture;
 
+mod archive;
 mod arguments;
+mod base64;
 pub mod bench;
+mod blocklist;
 mod bytes;
+mod checksum_context;
+mod checksum_kinds;
+mod checksums;
 mod common;
+mod config;
 mod consts;
+mod control;
 mod env;
 mod error;
+mod file_checksum;
 mod file_error;
 mod file_info;
 mod file_path;
 mod file_status;
 mod files;
+mod hash_cache;
 mod hasher;
 mod host_port;
 mod host_port_parse_error;
@@ -49,26 +59,38 @@ mod input_target;
 mod into_u64;
 mod into_usize;
 mod invariant;
+mod is_public;
+mod layout;
 mod lint;
 mod linter;
+mod lsd;
 mod magnet_link;
 mod magnet_link_parse_error;
+mod mapping;
 mod md5_digest;
 mod metainfo;
 mod metainfo_error;
 mod mode;
+mod nfc;
 mod options;
 mod output_stream;
 mod output_target;
 mod peer;
 mod piece_length_picker;
+mod piece_length_strategy;
 mod piece_list;
+mod piece_store;
 mod platform;
 mod platform_interface;
 mod print;
+mod profile;
+mod profile_parse_error;
+mod proxy;
+mod reactor;
 mod reckoner;
 mod run;
 mod sha1_digest;
+mod sha256_digest;
 mod shell;
 mod sort_key;
 mod sort_order;
@@ -78,9 +100,13 @@ mod step;
 mod style;
 mod subcommand;
 mod table;
+mod task;
 mod torrent_summary;
 mod tracker;
 mod use_color;
+mod utp;
 mod verifier;
 mod walker;
+mod webseed;
+mod websocket;
 mod xor_args;
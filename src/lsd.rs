@@ -0,0 +1,40 @@
+//! Local Service Discovery, from BEP 14: peers on the same network find each
+//! other by multicasting `BT-SEARCH` announcements for the infohashes they
+//! have, instead of going through a tracker.
+
+use std::net::{SocketAddrV4, UdpSocket};
+
+pub(crate) use announcement::Announcement;
+pub(crate) use searcher::Searcher;
+
+use crate::common::*;
+
+mod announcement;
+mod searcher;
+
+/// The multicast group that announcements are sent to.
+pub(crate) const GROUP: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(239, 192, 152, 143), 6771);
+
+/// Open a socket that receives announcements sent to the multicast group.
+pub(crate) fn bind() -> Result<UdpSocket> {
+  let addr = SocketAddr::from((Ipv4Addr::UNSPECIFIED, GROUP.port()));
+
+  let socket = UdpSocket::bind(addr).context(error::LsdBind { addr })?;
+
+  socket
+    .join_multicast_v4(GROUP.ip(), &Ipv4Addr::UNSPECIFIED)
+    .context(error::LsdBind { addr })?;
+
+  Ok(socket)
+}
+
+/// Send `announcement` to the multicast group.
+pub(crate) fn send(announcement: &Announcement) -> Result<()> {
+  let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).context(error::UdpSocketBind)?;
+
+  socket
+    .send_to(announcement.serialize().as_bytes(), GROUP)
+    .context(error::LsdSend)?;
+
+  Ok(())
+}
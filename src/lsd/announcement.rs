@@ -0,0 +1,165 @@
+use crate::common::*;
+
+/// A `BT-SEARCH` message, announcing that the torrents with the given
+/// infohashes are available from the sender on `port`.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Announcement {
+  pub(crate) port: u16,
+  pub(crate) infohashes: Vec<Infohash>,
+  /// An opaque value that lets the sender recognize its own announcements.
+  pub(crate) cookie: Option<String>,
+}
+
+impl Announcement {
+  const REQUEST_LINE: &'static str = "BT-SEARCH * HTTP/1.1";
+
+  pub(crate) fn serialize(&self) -> String {
+    let mut lines = vec![
+      Self::REQUEST_LINE.to_owned(),
+      format!("Host: {}", lsd::GROUP),
+      format!("Port: {}", self.port),
+    ];
+
+    lines.extend(
+      self
+        .infohashes
+        .iter()
+        .map(|infohash| format!("Infohash: {infohash}")),
+    );
+
+    lines.extend(self.cookie.iter().map(|cookie| format!("cookie: {cookie}")));
+
+    // The headers are followed by an empty line and an empty body.
+    lines.extend([String::new(), String::new(), String::new()]);
+
+    lines.join("\r\n")
+  }
+
+  /// Parse a datagram, returning `None` if it isn't a well-formed
+  /// announcement. Unknown headers and malformed infohashes are ignored.
+  pub(crate) fn parse(buf: &[u8]) -> Option<Self> {
+    let text = str::from_utf8(buf).ok()?;
+
+    let mut lines = text.split("\r\n");
+
+    if lines.next()? != Self::REQUEST_LINE {
+      return None;
+    }
+
+    let mut port = None;
+    let mut infohashes = Vec::new();
+    let mut cookie = None;
+
+    for line in lines.take_while(|line| !line.is_empty()) {
+      let (name, value) = line.split_once(':')?;
+      let value = value.trim();
+
+      match name.trim().to_ascii_lowercase().as_str() {
+        "port" => port = Some(value.parse().ok()?),
+        "infohash" => {
+          if let Some(infohash) = Self::parse_infohash(value) {
+            infohashes.push(infohash);
+          }
+        }
+        "cookie" => cookie = Some(value.to_owned()),
+        _ => {}
+      }
+    }
+
+    Some(Self {
+      port: port?,
+      infohashes,
+      cookie,
+    })
+  }
+
+  fn parse_infohash(text: &str) -> Option<Infohash> {
+    let bytes: [u8; 20] = hex::decode(text).ok()?.try_into().ok()?;
+    Some(bytes.into())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn round_trip() {
+    let announcement = Announcement {
+      port: 6881,
+      infohashes: vec![[0xab; 20].into(), [0x01; 20].into()],
+      cookie: Some("foo".into()),
+    };
+
+    let message = announcement.serialize();
+
+    assert_eq!(
+      message,
+      "BT-SEARCH * HTTP/1.1\r\n\
+       Host: 239.192.152.143:6771\r\n\
+       Port: 6881\r\n\
+       Infohash: abababababababababababababababababababab\r\n\
+       Infohash: 0101010101010101010101010101010101010101\r\n\
+       cookie: foo\r\n\
+       \r\n\
+       \r\n"
+    );
+
+    assert_eq!(Announcement::parse(message.as_bytes()), Some(announcement));
+  }
+
+  #[test]
+  fn case_insensitive() {
+    let message = "BT-SEARCH * HTTP/1.1\r\n\
+                   HOST: 239.192.152.143:6771\r\n\
+                   port:1234\r\n\
+                   INFOHASH: ABABABABABABABABABABABABABABABABABABABAB\r\n\
+                   X-Unknown: bar\r\n\
+                   \r\n\
+                   \r\n";
+
+    assert_eq!(
+      Announcement::parse(message.as_bytes()),
+      Some(Announcement {
+        port: 1234,
+        infohashes: vec![[0xab; 20].into()],
+        cookie: None,
+      })
+    );
+  }
+
+  #[test]
+  fn bad_infohash_ignored() {
+    let message = "BT-SEARCH * HTTP/1.1\r\nPort: 1\r\nInfohash: abc\r\n\r\n\r\n";
+
+    assert_eq!(
+      Announcement::parse(message.as_bytes()),
+      Some(Announcement {
+        port: 1,
+        infohashes: Vec::new(),
+        cookie: None,
+      })
+    );
+  }
+
+  #[test]
+  fn invalid() {
+    assert_eq!(
+      Announcement::parse(b"GET / HTTP/1.1\r\nPort: 1\r\n\r\n"),
+      None
+    );
+    assert_eq!(
+      Announcement::parse(b"BT-SEARCH * HTTP/1.1\r\nHost: x\r\n\r\n"),
+      None
+    );
+    assert_eq!(
+      Announcement::parse(b"BT-SEARCH * HTTP/1.1\r\nPort: foo\r\n\r\n"),
+      None
+    );
+    assert_eq!(
+      Announcement::parse(b"BT-SEARCH * HTTP/1.1\r\nPort\r\n\r\n"),
+      None
+    );
+    assert_eq!(Announcement::parse(&[0xff, 0xfe]), None);
+  }
+}
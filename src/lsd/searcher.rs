@@ -0,0 +1,189 @@
+use crate::common::*;
+
+/// A non-blocking listen for local peers announcing an infohash, driven by a
+/// `Reactor`. Each time announcements arrive, the searcher finishes with the
+/// peers they name, and may be pushed again to keep listening. Once the
+/// timeout passes, it finishes with no peers.
+#[derive(Debug)]
+pub(crate) struct Searcher {
+  infohash: Infohash,
+  timeout: Duration,
+  socket: Option<mio::net::UdpSocket>,
+  deadline: Option<Instant>,
+}
+
+impl Searcher {
+  pub(crate) const TIMEOUT: Duration = Duration::from_secs(10);
+
+  const RX_BUF_LEN: usize = 8192;
+
+  pub(crate) fn new(infohash: Infohash, timeout: Duration) -> Self {
+    Self {
+      infohash,
+      timeout,
+      socket: None,
+      deadline: None,
+    }
+  }
+
+  #[cfg(test)]
+  fn with_socket(infohash: Infohash, timeout: Duration, socket: std::net::UdpSocket) -> Self {
+    socket.set_nonblocking(true).unwrap();
+
+    Self {
+      socket: Some(mio::net::UdpSocket::from_std(socket)),
+      ..Self::new(infohash, timeout)
+    }
+  }
+}
+
+impl Task for Searcher {
+  type Output = Vec<SocketAddr>;
+
+  fn start(&mut self, now: Instant) -> Result<()> {
+    // The socket is kept when the searcher is pushed again, so that
+    // announcements that arrived in the meantime aren't lost.
+    if self.socket.is_none() {
+      let socket = lsd::bind()?;
+
+      socket.set_nonblocking(true).context(error::LsdReceive)?;
+
+      self.socket = Some(mio::net::UdpSocket::from_std(socket));
+    }
+
+    self.deadline.get_or_insert(now + self.timeout);
+
+    Ok(())
+  }
+
+  fn source(&mut self) -> &mut dyn mio::event::Source {
+    self
+      .socket
+      .as_mut()
+      .invariant_unwrap("socket is opened by start")
+  }
+
+  fn interest(&self) -> mio::Interest {
+    mio::Interest::READABLE
+  }
+
+  fn wakeup(&self) -> Option<Instant> {
+    self.deadline
+  }
+
+  fn advance(&mut self, now: Instant) -> Result<Option<Vec<SocketAddr>>> {
+    let Some(socket) = &self.socket else {
+      return Ok(None);
+    };
+
+    let mut peers = Vec::new();
+    let mut buf = [0; Self::RX_BUF_LEN];
+
+    loop {
+      match socket.recv_from(&mut buf) {
+        Ok((n, addr)) => {
+          let Some(announcement) = lsd::Announcement::parse(&buf[..n]) else {
+            continue;
+          };
+
+          if announcement.infohashes.contains(&self.infohash) {
+            let peer = SocketAddr::new(addr.ip(), announcement.port);
+            if !peers.contains(&peer) {
+              peers.push(peer);
+            }
+          }
+        }
+        Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+        Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+        Err(source) => return Err(Error::LsdReceive { source }),
+      }
+    }
+
+    if !peers.is_empty() || self.deadline.is_some_and(|deadline| now >= deadline) {
+      return Ok(Some(peers));
+    }
+
+    Ok(None)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::net::UdpSocket;
+
+  fn searcher(infohash: Infohash, timeout: Duration) -> (Searcher, SocketAddr) {
+    let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let addr = socket.local_addr().unwrap();
+    (Searcher::with_socket(infohash, timeout, socket), addr)
+  }
+
+  #[test]
+  fn finds_peers() {
+    let infohash = Infohash::from([1; 20]);
+    let (searcher, addr) = searcher(infohash, Duration::from_secs(10));
+
+    let sender = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+
+    for (port, infohashes) in [
+      (1000, vec![Infohash::from([2; 20])]),
+      (2000, vec![Infohash::from([2; 20]), infohash]),
+      (3000, vec![infohash]),
+    ] {
+      let announcement = lsd::Announcement {
+        port,
+        infohashes,
+        cookie: None,
+      };
+      sender
+        .send_to(announcement.serialize().as_bytes(), addr)
+        .unwrap();
+    }
+    sender.send_to(b"garbage", addr).unwrap();
+
+    let mut reactor = Reactor::new(1).unwrap();
+    reactor.push(searcher);
+
+    let mut found = Vec::new();
+    reactor
+      .run(|reactor, searcher, result| {
+        let peers = result.unwrap();
+        assert!(!peers.is_empty());
+        found.extend(peers);
+        if found.len() < 2 {
+          reactor.push(searcher);
+        }
+        Ok(Control::Continue)
+      })
+      .unwrap();
+
+    assert_eq!(
+      found,
+      [
+        SocketAddr::from((Ipv4Addr::LOCALHOST, 2000)),
+        SocketAddr::from((Ipv4Addr::LOCALHOST, 3000)),
+      ]
+    );
+  }
+
+  #[test]
+  fn timeout() {
+    let (searcher, _) = searcher(Infohash::from([1; 20]), Duration::from_millis(50));
+
+    let mut reactor = Reactor::new(1).unwrap();
+    reactor.push(searcher);
+
+    let start = Instant::now();
+    let mut results = Vec::new();
+    reactor
+      .run(|_, _, result| {
+        results.push(result.unwrap());
+        Ok(Control::Continue)
+      })
+      .unwrap();
+
+    assert_eq!(results, [Vec::<SocketAddr>::new()]);
+    assert!(start.elapsed() >= Duration::from_millis(50));
+  }
+}
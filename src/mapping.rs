@@ -0,0 +1,86 @@
+use crate::common::*;
+
+/// A `SRC[:DEST]` argument to `--add`, which places the file or directory at
+/// `SRC` at `DEST` within a torrent, or, if `DEST` is omitted, at the
+/// filename of `SRC`.
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct Mapping {
+  pub(crate) source: PathBuf,
+  pub(crate) destination: Option<FilePath>,
+}
+
+impl FromStr for Mapping {
+  type Err = Error;
+
+  fn from_str(text: &str) -> Result<Self, Self::Err> {
+    let (source, destination) = match text.rsplit_once(':') {
+      // A single letter before the colon is a Windows drive letter, not a
+      // source path.
+      Some((source, _))
+        if cfg!(windows)
+          && source.len() == 1
+          && source.chars().all(|c| c.is_ascii_alphabetic()) =>
+      {
+        (text, None)
+      }
+      Some((source, destination)) => (source, Some(destination)),
+      None => (text, None),
+    };
+
+    if source.is_empty() {
+      return Err(Error::MappingParse { text: text.into() });
+    }
+
+    let destination = destination
+      .map(|destination| {
+        let relative = Path::new(destination)
+          .components()
+          .filter(|component| *component != path::Component::CurDir)
+          .collect::<PathBuf>();
+
+        if relative.components().count() == 0 {
+          return Err(Error::MappingParse { text: text.into() });
+        }
+
+        FilePath::from_relative_path(&relative)
+          .map_err(|_| Error::MappingParse { text: text.into() })
+      })
+      .transpose()?;
+
+    Ok(Self {
+      source: source.into(),
+      destination,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse() {
+    assert_eq!(
+      "foo".parse::<Mapping>().unwrap(),
+      Mapping {
+        source: "foo".into(),
+        destination: None,
+      }
+    );
+
+    assert_eq!(
+      "foo/bar:./baz/qux".parse::<Mapping>().unwrap(),
+      Mapping {
+        source: "foo/bar".into(),
+        destination: Some(FilePath::from_components(&["baz", "qux"])),
+      }
+    );
+
+    for text in ["", ":foo", "foo:", "foo:.", "foo:../bar", "foo:/bar"] {
+      assert!(
+        matches!(text.parse::<Mapping>(), Err(Error::MappingParse { .. })),
+        "{text}"
+      );
+    }
+  }
+}
@@ -91,8 +91,14 @@ impl Metainfo {
     paths
   }
 
-  pub(crate) fn verify(&self, base: &Path, progress_bar: Option<ProgressBar>) -> Result<Status> {
-    Verifier::verify(self, base, progress_bar)
+  pub(crate) fn verify(
+    &self,
+    base: &Path,
+    layout: Option<&Layout>,
+    progress_bar: Option<ProgressBar>,
+    cache: Option<&HashCache>,
+  ) -> Result<Status> {
+    Verifier::verify(self, base, layout, progress_bar, cache)
   }
 
   pub(crate) fn content_size(&self) -> Bytes {
@@ -148,6 +154,8 @@ impl Metainfo {
         mode: Mode::Single {
           length: Bytes(32 * 1024),
           md5sum: Some(Md5Digest::from_hex("000102030405060708090a0b0c0d0e0f")),
+          sha1: None,
+          sha256: None,
         },
         update_url: Some("https://update.example".parse().unwrap()),
       },
@@ -172,6 +180,8 @@ impl Metainfo {
         length: Bytes(32 * 1024),
         path: FilePath::from_components(&["DIR", "FILE"]),
         md5sum: Some(Md5Digest::from_hex("000102030405060708090a0b0c0d0e0f")),
+        sha1: None,
+        sha256: None,
       }],
     };
     instance
@@ -196,6 +206,8 @@ impl Metainfo {
         mode: Mode::Single {
           length: Bytes(5),
           md5sum: None,
+          sha1: None,
+          sha256: None,
         },
         update_url: None,
       },
@@ -232,6 +244,8 @@ impl Metainfo {
           files: vec![FileInfo {
             length: Bytes(1024),
             md5sum: None,
+            sha1: None,
+            sha256: None,
             path: FilePath::from_components(&["a", "b"]),
           }],
         },
@@ -11,6 +11,18 @@ pub(crate) enum Mode {
       with = "unwrap_or_skip"
     )]
     md5sum: Option<Md5Digest>,
+    #[serde(
+      skip_serializing_if = "Option::is_none",
+      default,
+      with = "unwrap_or_skip"
+    )]
+    sha1: Option<Sha1Digest>,
+    #[serde(
+      skip_serializing_if = "Option::is_none",
+      default,
+      with = "unwrap_or_skip"
+    )]
+    sha256: Option<Sha256Digest>,
   },
   Multiple {
     files: Vec<FileInfo>,
@@ -35,6 +47,8 @@ mod tests {
     let input = Mode::Single {
       length: Bytes(10),
       md5sum: None,
+      sha1: None,
+      sha256: None,
     };
 
     let have = bendy::serde::ser::to_bytes(&input).unwrap();
@@ -51,6 +65,8 @@ mod tests {
     let input = Mode::Single {
       length: Bytes(10),
       md5sum: Some(Md5Digest::from_hex("000102030405060708090a0b0c0d0e0f")),
+      sha1: None,
+      sha256: None,
     };
 
     let have = bendy::serde::ser::to_bytes(&input).unwrap();
@@ -70,6 +86,8 @@ mod tests {
     let value = Mode::Single {
       length: Bytes(10),
       md5sum: Some(Md5Digest::from_hex("000102030405060708090a0b0c0d0e0f")),
+      sha1: None,
+      sha256: None,
     };
 
     let bencode = bendy::serde::ser::to_bytes(&value).unwrap();
@@ -86,6 +104,8 @@ mod tests {
         length: Bytes(10),
         path: FilePath::from_components(&["foo", "bar"]),
         md5sum: Some(Md5Digest::from_hex("000102030405060708090a0b0c0d0e0f")),
+        sha1: None,
+        sha256: None,
       }],
     };
 
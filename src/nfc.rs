@@ -0,0 +1,22 @@
+use crate::common::*;
+
+/// `text` in Unicode Normalization Form C, so that names which look the same
+/// but were composed differently, as they often are by macOS and by other
+/// platforms, are encoded identically.
+pub(crate) fn nfc(text: &str) -> String {
+  icu_normalizer::ComposingNormalizerBorrowed::new_nfc()
+    .normalize(text)
+    .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn composed() {
+    assert_eq!(nfc("e\u{301}"), "\u{e9}");
+    assert_eq!(nfc("\u{e9}"), "\u{e9}");
+    assert_eq!(nfc("foo"), "foo");
+  }
+}
@@ -32,6 +32,38 @@ pub(crate) struct Options {
             standard error are connected to a terminal."
   )]
   pub(crate) terminal: bool,
+  #[structopt(
+    long = "proxy",
+    value_name = "URL",
+    help = "Send tracker and peer traffic through the proxy at `URL`, which may be \
+            `socks5://[USER:PASSWORD@]HOST[:PORT]` or `http://[USER:PASSWORD@]HOST[:PORT]`. \
+            Peer connections are tunneled through either kind of proxy, but UDP tracker \
+            announces are relayed with SOCKS5 UDP ASSOCIATE, and so require a SOCKS5 proxy. \
+            Defaults to the value of `IMDL_PROXY`, if set."
+  )]
+  proxy: Option<Url>,
+  #[structopt(
+    long = "hash-cache",
+    value_name = "DIR",
+    parse(from_os_str),
+    help = "Cache piece hashes and MD5 checksums in `DIR`, so that `torrent create` and `torrent \
+            verify` can skip reading files that haven't changed since they were last hashed. \
+            Files are considered unchanged if their path, size, modification time, device, and \
+            inode are the same. Defaults to the value of `IMDL_HASH_CACHE`, if set."
+  )]
+  hash_cache: Option<PathBuf>,
+  #[structopt(
+    long = "config",
+    value_name = "FILE",
+    parse(from_os_str),
+    help = "Read configuration from `FILE`. Defaults to the value of `IMDL_CONFIG`, if set, then \
+            to `$XDG_CONFIG_HOME/imdl/config.toml`, or \
+            `~/.config/imdl/config.toml` if `XDG_CONFIG_HOME` is not set, if it exists. The \
+            `[create]` table sets defaults for `torrent create`, and `[preset.NAME]` tables set \
+            defaults used with `--preset NAME`. Run `imdl config show` to see the effective \
+            configuration."
+  )]
+  config: Option<PathBuf>,
 }
 
 impl Options {
@@ -42,4 +74,44 @@ impl Options {
       Err(Error::Unstable { feature })
     }
   }
+
+  /// The hash cache in the directory given with `--hash-cache` or
+  /// `IMDL_HASH_CACHE`, if any.
+  pub(crate) fn hash_cache(&self, env: &Env) -> Result<Option<HashCache>> {
+    self
+      .hash_cache
+      .as_deref()
+      .or_else(|| env.var_os("IMDL_HASH_CACHE").map(Path::new))
+      .map(|dir| HashCache::open(&env.resolve(dir)?))
+      .transpose()
+  }
+
+  /// The configuration file given with `--config` or `IMDL_CONFIG`, or the
+  /// default configuration file if it exists.
+  pub(crate) fn config(&self, env: &Env) -> Result<Config> {
+    if let Some(path) = self
+      .config
+      .as_deref()
+      .or_else(|| env.var_os("IMDL_CONFIG").map(Path::new))
+    {
+      return Config::load(&env.resolve(path)?);
+    }
+
+    match Config::default_path(env) {
+      Some(path) if path.is_file() => Config::load(&path),
+      _ => Ok(Config::default()),
+    }
+  }
+
+  /// The proxy given with `--proxy` or `IMDL_PROXY`, if any.
+  pub(crate) fn proxy(&self, env: &Env) -> Result<Option<Proxy>> {
+    match (&self.proxy, env.var("IMDL_PROXY")) {
+      (Some(url), _) => Proxy::from_url(url).map(Some),
+      (None, Some(text)) => {
+        let url = text.parse().context(error::ProxyUrlParse { text })?;
+        Proxy::from_url(&url).map(Some)
+      }
+      (None, None) => Ok(None),
+    }
+  }
 }
@@ -1,6 +1,34 @@
+pub(crate) use candidates::Candidates;
+#[cfg(test)]
 pub(crate) use client::Client;
+pub(crate) use client_id::ClientId;
+pub(crate) use encryption::Encryption;
+pub(crate) use fetcher::Fetcher;
+pub(crate) use limits::Limits;
+pub(crate) use priority::priority;
+pub(crate) use probe::{Probe, Report};
+pub(crate) use rc4::Rc4;
+pub(crate) use seeder::Seeder;
+pub(crate) use session::Session;
+pub(crate) use transport::Transport;
+pub(crate) use wire::Wire;
 
+pub(crate) mod candidates;
+pub(crate) mod cipher_stream;
+#[cfg(test)]
 pub(crate) mod client;
+pub(crate) mod client_id;
 pub(crate) mod connection;
+pub(crate) mod encryption;
+pub(crate) mod fetcher;
 pub(crate) mod handshake;
+pub(crate) mod limits;
 pub(crate) mod message;
+pub(crate) mod mse;
+pub(crate) mod priority;
+pub(crate) mod probe;
+pub(crate) mod rc4;
+pub(crate) mod seeder;
+pub(crate) mod session;
+pub(crate) mod transport;
+pub(crate) mod wire;
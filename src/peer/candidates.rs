@@ -0,0 +1,107 @@
+use crate::common::*;
+
+/// Peers waiting to be connected to. Once our external address is known,
+/// peers are taken in order of BEP 40 canonical priority, highest first.
+/// Until then, they're taken in the order they were added.
+#[derive(Debug, Default)]
+pub(crate) struct Candidates {
+  external: Option<IpAddr>,
+  pending: Vec<SocketAddr>,
+}
+
+impl Candidates {
+  pub(crate) fn push(&mut self, addr: SocketAddr) {
+    self.pending.push(addr);
+  }
+
+  /// Set our external address, for example from the `yourip` field of a
+  /// peer's extension handshake.
+  pub(crate) fn set_external(&mut self, ip: IpAddr) {
+    self.external = Some(ip);
+  }
+
+  /// How many of `max` connections may be in flight. Until our external
+  /// address is known, half of them are held back, so that they go to the
+  /// highest priority peers once it is.
+  pub(crate) fn limit(&self, max: usize) -> usize {
+    if self.external.is_some() {
+      max
+    } else {
+      max.div_ceil(2)
+    }
+  }
+
+  /// Take the peer to connect to next.
+  pub(crate) fn pop(&mut self) -> Option<SocketAddr> {
+    let i = match self.external {
+      // We don't listen for connections, so our port is unknown.
+      Some(ip) => {
+        let external = SocketAddr::new(ip, 0);
+        self
+          .pending
+          .iter()
+          .enumerate()
+          .max_by_key(|(i, addr)| (peer::priority(external, **addr), Reverse(*i)))?
+          .0
+      }
+      None => 0,
+    };
+
+    (i < self.pending.len()).then(|| self.pending.remove(i))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn addr(s: &str) -> SocketAddr {
+    s.parse().unwrap()
+  }
+
+  #[test]
+  fn insertion_order() {
+    let mut candidates = Candidates::default();
+    candidates.push(addr("1.1.1.1:1"));
+    candidates.push(addr("2.2.2.2:2"));
+    assert_eq!(candidates.pop(), Some(addr("1.1.1.1:1")));
+    assert_eq!(candidates.pop(), Some(addr("2.2.2.2:2")));
+    assert_eq!(candidates.pop(), None);
+  }
+
+  #[test]
+  fn limit() {
+    let mut candidates = Candidates::default();
+    assert_eq!(candidates.limit(50), 25);
+    assert_eq!(candidates.limit(1), 1);
+    candidates.set_external("123.213.32.10".parse().unwrap());
+    assert_eq!(candidates.limit(50), 50);
+  }
+
+  #[test]
+  fn priority_order() {
+    let external = "123.213.32.10".parse().unwrap();
+
+    let addrs = [
+      addr("98.76.54.32:1"),
+      addr("123.213.32.234:2"),
+      addr("[::1]:3"),
+      addr("10.0.0.1:4"),
+      addr("200.1.2.3:5"),
+    ];
+
+    let mut candidates = Candidates::default();
+    for addr in addrs {
+      candidates.push(addr);
+    }
+    candidates.set_external(external);
+
+    let mut expected = addrs.to_vec();
+    expected.sort_by_key(|addr| Reverse(peer::priority(SocketAddr::new(external, 0), *addr)));
+
+    let popped = iter::from_fn(|| candidates.pop()).collect::<Vec<SocketAddr>>();
+
+    assert_eq!(popped, expected);
+    assert_eq!(popped.last(), Some(&addr("[::1]:3")));
+  }
+}
@@ -0,0 +1,167 @@
+use crate::common::*;
+
+use peer::mse::{self, Cipher};
+use peer::{Encryption, Transport};
+
+/// A blocking peer transport that transparently encrypts and decrypts traffic
+/// once a message stream encryption handshake has completed, or passes it
+/// through unchanged on plaintext connections.
+#[derive(Debug)]
+pub(crate) struct CipherStream {
+  inner: Transport,
+  cipher: Option<Cipher>,
+  // Decrypted bytes received during the handshake that belong to the
+  // BitTorrent stream.
+  pending: Vec<u8>,
+}
+
+impl CipherStream {
+  const READ_CHUNK: usize = 4096;
+
+  #[cfg(test)]
+  pub(crate) fn plaintext(inner: Transport) -> Self {
+    Self {
+      inner,
+      cipher: None,
+      pending: Vec::new(),
+    }
+  }
+
+  /// Perform the initiator's side of the encryption handshake.
+  #[cfg(test)]
+  pub(crate) fn initiate(
+    inner: Transport,
+    infohash: Infohash,
+    encryption: Encryption,
+  ) -> Result<Self> {
+    let (handshake, output) = mse::Handshake::initiate(infohash, encryption);
+    Self::negotiate(inner, handshake, Vec::new(), output)
+  }
+
+  /// Accept an incoming connection, which may start with either a plaintext
+  /// `BitTorrent` handshake or an encryption handshake.
+  pub(crate) fn accept(
+    mut inner: Transport,
+    infohash: Infohash,
+    encryption: Encryption,
+  ) -> Result<Self> {
+    let mut start = vec![0; peer::handshake::HEADER.len()];
+    inner.read_exact(&mut start).context(error::Network)?;
+
+    if mse::Handshake::is_plaintext(&start) {
+      if encryption == Encryption::Require {
+        return Err(Error::PeerEncryptionRequired);
+      }
+
+      return Ok(Self {
+        inner,
+        cipher: None,
+        pending: start,
+      });
+    }
+
+    if encryption == Encryption::Disable {
+      return Err(Error::PeerHandshakeHeader);
+    }
+
+    let handshake = mse::Handshake::respond(infohash, encryption);
+    Self::negotiate(inner, handshake, start, Vec::new())
+  }
+
+  fn negotiate(
+    mut inner: Transport,
+    mut handshake: mse::Handshake,
+    mut input: Vec<u8>,
+    mut output: Vec<u8>,
+  ) -> Result<Self> {
+    let mut buf = [0; Self::READ_CHUNK];
+
+    loop {
+      let established = handshake.advance(&mut input, &mut output)?;
+
+      inner.write_all(&output).context(error::Network)?;
+      output.clear();
+
+      if let Some(mut established) = established {
+        // Anything left over was sent after the handshake, and so is part of
+        // the BitTorrent stream.
+        if let Some(cipher) = &mut established.cipher {
+          cipher.decrypt.apply(&mut input);
+        }
+
+        let mut pending = established.payload;
+        pending.extend(input);
+
+        return Ok(Self {
+          inner,
+          cipher: established.cipher,
+          pending,
+        });
+      }
+
+      match inner.read(&mut buf) {
+        Ok(0) => {
+          return Err(Error::Network {
+            source: io::ErrorKind::UnexpectedEof.into(),
+          })
+        }
+        Ok(n) => input.extend_from_slice(&buf[..n]),
+        Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+        Err(source) => return Err(Error::Network { source }),
+      }
+    }
+  }
+
+  #[cfg(test)]
+  pub(crate) fn is_encrypted(&self) -> bool {
+    self.cipher.is_some()
+  }
+
+  pub(crate) fn read_timeout(&self) -> io::Result<Option<Duration>> {
+    self.inner.read_timeout()
+  }
+
+  pub(crate) fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+    self.inner.set_read_timeout(timeout)
+  }
+}
+
+impl Read for CipherStream {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    if !self.pending.is_empty() {
+      let n = buf.len().min(self.pending.len());
+      buf[..n].copy_from_slice(&self.pending[..n]);
+      self.pending.drain(..n);
+      return Ok(n);
+    }
+
+    let n = self.inner.read(buf)?;
+
+    if let Some(cipher) = &mut self.cipher {
+      cipher.decrypt.apply(&mut buf[..n]);
+    }
+
+    Ok(n)
+  }
+}
+
+impl Write for CipherStream {
+  // Writes are all-or-nothing, since bytes that have been encrypted must be
+  // sent to keep the keystream in sync.
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    match &mut self.cipher {
+      Some(cipher) => {
+        let mut encrypted = buf.to_vec();
+        cipher.encrypt.apply(&mut encrypted);
+        self.inner.write_all(&encrypted)?;
+      }
+      None => self.inner.write_all(buf)?,
+    }
+
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.inner.flush()
+  }
+}
@@ -4,20 +4,14 @@ use message::extended;
 use message::Message;
 use peer::connection::Connection;
 use peer::message;
+use peer::Session;
 
+/// A blocking peer client, used to play the other side of the protocol in
+/// tests.
 #[derive(Debug)]
 pub(crate) struct Client {
-  infohash: Infohash,
   conn: Connection,
-  state: State,
-  info: Option<Info>,
-  extension_handshake: Option<extended::Handshake>,
-}
-
-#[derive(Debug, PartialEq)]
-pub(crate) enum State {
-  Idle,
-  WantInfo(Vec<u8>),
+  session: Session,
 }
 
 impl Client {
@@ -28,271 +22,139 @@ impl Client {
       return Err(Error::PeerUtMetadataNotSupported);
     }
 
-    Ok(Client {
-      infohash,
-      conn,
-      state: State::Idle,
-      info: None,
-      extension_handshake: None,
-    })
-  }
-
-  fn send_extension_handshake(&mut self) -> Result<()> {
-    let mut handshake = extended::Handshake::default();
-    if let Some(info) = &self.info {
-      let info_dict = bendy::serde::ser::to_bytes(&info).context(error::InfoSerialize)?;
-      handshake.with_metadata_size(info_dict.len());
-    }
-    self.conn.send(&Message::new_extended(
-      extended::Id::Handshake.into(),
-      handshake,
-    )?)
+    Ok(Self::new(conn, infohash))
   }
 
-  pub(crate) fn fetch_info_dict(mut self) -> Result<Info> {
-    self.state = State::WantInfo(Vec::new());
-
-    self.send_extension_handshake()?;
-
-    loop {
-      if let Some(info) = self.info {
-        return Ok(info);
-      }
-
-      let msg = self.conn.recv()?;
-      if msg.flavour != message::Flavour::Extended {
-        continue;
-      }
-
-      self.handle_msg(&msg)?;
-    }
-  }
+  pub(crate) fn listen(listener: &TcpListener, infohash: Infohash) -> Result<Self> {
+    let (conn, _) = listener.accept().context(error::Network)?;
+    conn
+      .set_read_timeout(Some(Duration::new(3, 0)))
+      .context(error::Network)?;
 
-  fn handle_msg(&mut self, msg: &Message) -> Result<()> {
-    match msg.flavour {
-      message::Flavour::Extended => self.handle_extended(msg),
-      _ => Ok(()),
-    }
+    Ok(Self::new(Connection::from(conn, infohash)?, infohash))
   }
 
-  fn handle_extended(&mut self, msg: &Message) -> Result<()> {
-    let (id, payload) = msg.parse_extended_payload()?;
-    match id {
-      extended::Id::Handshake => self.handle_extension_handshake(payload),
-      extended::Id::UtMetadata => self.handle_ut_metadata(payload),
-      extended::Id::NotImplemented(_) => Ok(()),
+  fn new(conn: Connection, infohash: Infohash) -> Self {
+    Client {
+      session: Session::new(infohash, conn.limits()),
+      conn,
     }
   }
 
-  fn handle_extension_handshake(&mut self, payload: &[u8]) -> Result<()> {
-    let handshake: extended::Handshake = Message::from_bencode(payload)?;
-
-    // Drop the peer if we want info and the peer can't give it to us.
-    if let State::WantInfo(_) = self.state {
-      if handshake.metadata_size.is_none() {
-        return Err(Error::PeerUtMetadataMetadataSizeNotKnown);
-      } else if !handshake
-        .message_ids
-        .contains_key(extended::UtMetadata::NAME)
-      {
-        return Err(Error::PeerUtMetadataNotSupported);
-      }
+  fn flush(&mut self) -> Result<()> {
+    for msg in self.session.outbox() {
+      self.conn.send(&msg)?;
     }
-
-    self.extension_handshake.replace(handshake);
-
-    if let State::WantInfo(_) = self.state {
-      self.send_ut_metadata_request(0)?;
-    }
-
     Ok(())
   }
 
-  fn handle_ut_metadata(&mut self, payload: &[u8]) -> Result<()> {
-    let metadata_size = self.ut_metadata_size()?;
-
-    let msg: extended::UtMetadata = Message::from_bencode(payload)?;
-    match msg.msg_type.into() {
-      extended::ut_metadata::MsgType::Data => (),
-      extended::ut_metadata::MsgType::Request | extended::ut_metadata::MsgType::Reject => {
-        return Ok(())
-      }
-    }
-
-    if let State::WantInfo(info_buf) = &mut self.state {
-      let piece = info_buf.len() / extended::UtMetadata::PIECE_LENGTH;
-      if msg.piece != piece {
-        return Err(Error::PeerUtMetadataWrongPiece);
-      }
-
-      // The ut_metadata::MsgType::Data payload splits into two parts,
-      // 1. a bencoded UtMetadata message,
-      // 2. the binary info_dict peice data.
-      // Their boundary is not delimited. Bencode the message to find the piece offset.
-      let piece_offset = bendy::serde::ser::to_bytes(&msg)
-        .context(error::PeerMessageBencode)?
-        .len();
-      if payload[piece_offset..].len() > extended::UtMetadata::PIECE_LENGTH {
-        return Err(Error::PeerUtMetadataPieceLength);
-      }
-      info_buf.extend_from_slice(&payload[piece_offset..]);
-
-      return match info_buf.len().cmp(&metadata_size) {
-        Ordering::Equal => {
-          let info = Self::verify_info_dict(info_buf, self.infohash)?;
-          self.info = Some(info);
-          self.state = State::Idle;
-          Ok(())
-        }
-        Ordering::Less => self.send_ut_metadata_request(piece + 1),
-        Ordering::Greater => Err(Error::PeerUtMetadataInfoLength),
-      };
-    }
-
-    Ok(())
+  fn send_extension_handshake(&mut self) -> Result<()> {
+    self.session.send_extension_handshake()?;
+    self.flush()
   }
 
-  pub(crate) fn send_ut_metadata_request(&mut self, piece: usize) -> Result<()> {
-    let id = self.ut_metadata_msg_id()?;
-    let msg = Message::new_extended(id, extended::UtMetadata::request(piece))?;
-
-    self.conn.send(&msg)
+  fn handle_msg(&mut self, msg: &Message) -> Result<()> {
+    let result = self.session.handle_msg(msg);
+    self.flush()?;
+    result
   }
 
-  fn verify_info_dict(buf: &[u8], target: Infohash) -> Result<Info> {
-    let info =
-      bendy::serde::de::from_bytes::<Info>(buf).context(error::PeerUtMetadataInfoDeserialize)?;
+  pub(crate) fn fetch_info_dict(mut self) -> Result<Info> {
+    self.session.metadata_exchange().fetch();
 
-    let infohash = Infohash::from_bencoded_info_dict(
-      &bendy::serde::ser::to_bytes(&info).context(error::InfoSerialize)?,
-    );
+    self.send_extension_handshake()?;
 
-    if infohash == target {
-      Ok(info)
-    } else {
-      Err(Error::PeerUtMetadataWrongInfohash)
-    }
-  }
+    loop {
+      if let Some(info) = self.session.metadata_exchange().take_info() {
+        return Ok(info);
+      }
 
-  fn ut_metadata_size(&self) -> Result<usize> {
-    match &self.extension_handshake {
-      Some(handshake) => match handshake.metadata_size {
-        Some(size) => Ok(size),
-        None => Err(Error::PeerUtMetadataMetadataSizeNotKnown),
-      },
-      None => Err(Error::PeerNoExtendedHandshake),
-    }
-  }
+      let msg = self.conn.recv()?;
 
-  fn ut_metadata_msg_id(&self) -> Result<u8> {
-    match &self.extension_handshake {
-      Some(handshake) => match handshake.message_ids.get(extended::UtMetadata::NAME) {
-        Some(id) => Ok(*id),
-        None => Err(Error::PeerUtMetadataNotSupported),
-      },
-      None => Err(Error::PeerNoExtendedHandshake),
+      self.handle_msg(&msg)?;
     }
   }
 
-  #[cfg(test)]
-  pub(crate) fn listen(listener: &TcpListener, infohash: Infohash) -> Result<Self> {
-    let (conn, _) = listener.accept().context(error::Network)?;
-    conn
-      .set_read_timeout(Some(Duration::new(3, 0)))
-      .context(error::Network)?;
-
-    Ok(Client {
-      infohash,
-      conn: Connection::from(conn, infohash)?,
-      state: State::Idle,
-      extension_handshake: None,
-      info: None,
-    })
-  }
-
-  #[cfg(test)]
   pub(crate) fn send_ut_metadata_data(
     &mut self,
     piece: usize,
     total_size: usize,
     data: &[u8],
   ) -> Result<()> {
-    let message_id = self.ut_metadata_msg_id()?;
-
-    let msg = Message::new_extended_with_trailer(
-      message_id,
-      extended::UtMetadata::data(piece, total_size),
-      data,
-    )?;
-
-    self.conn.send(&msg)
+    self
+      .session
+      .sender(extended::UtMetadata::NAME)
+      .send_with_trailer(extended::UtMetadata::data(piece, total_size), data)?;
+    self.flush()
   }
 
-  #[cfg(test)]
   pub(crate) fn spawn_info_dict_seeder(info: &Info) -> (thread::JoinHandle<()>, SocketAddr) {
+    Self::spawn_info_dict_seeder_with_trackers(info, &[])
+  }
+
+  /// Spawn an info dict seeder that also tells fetchers about `trackers` with
+  /// tracker exchange, as soon as their extension handshake arrives.
+  pub(crate) fn spawn_info_dict_seeder_with_trackers(
+    info: &Info,
+    trackers: &[&str],
+  ) -> (thread::JoinHandle<()>, SocketAddr) {
+    let added = trackers
+      .iter()
+      .map(|tracker| (*tracker).to_owned())
+      .collect::<Vec<String>>();
     let info_dict = bendy::serde::ser::to_bytes(info).unwrap();
     let infohash = Infohash::from_bencoded_info_dict(&info_dict);
     let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, 0)).unwrap();
     let addr = (Ipv4Addr::LOCALHOST, listener.local_addr().unwrap().port()).into();
     let seeder = thread::spawn(move || {
-      let mut seeder = Client::listen(&listener, infohash).unwrap();
-      let handshake = extended::Handshake {
-        metadata_size: Some(info_dict.len()),
-        ..extended::Handshake::default()
-      };
-      let msg = Message::new_extended(extended::Id::Handshake.into(), handshake).unwrap();
-      seeder.conn.send(&msg).unwrap();
-
-      // The first message from the fetcher is an extension handshake.
-      let msg = seeder.conn.recv().unwrap();
-      assert_eq!(msg.flavour, message::Flavour::Extended);
-      let (id, _) = msg.parse_extended_payload().unwrap();
-      assert_eq!(id, extended::Id::Handshake);
-      seeder.handle_msg(&msg).unwrap();
-
-      let mut pieces = info_dict.len() / extended::UtMetadata::PIECE_LENGTH;
-      if !info_dict
-        .len()
-        .is_multiple_of(extended::UtMetadata::PIECE_LENGTH)
-      {
-        pieces += 1;
-      }
+      Client::listen(&listener, infohash)
+        .unwrap()
+        .seed_info_dict(info_dict, added);
+    });
 
-      // Respond to any serviceable ut_metadata request. Ignore errors.
-      loop {
-        let Ok(msg) = seeder.conn.recv() else {
-          continue;
-        };
-
-        let Ok((_, payload)) = msg.parse_extended_payload() else {
-          continue;
-        };
-
-        let req: extended::UtMetadata = match Message::from_bencode(payload) {
-          Ok(req) => req,
-          Err(_) => continue,
-        };
-        if req.piece > pieces {
-          continue;
-        }
+    (seeder, addr)
+  }
 
-        let range = std::ops::Range {
-          start: extended::UtMetadata::PIECE_LENGTH * req.piece,
-          end: if pieces == 1 {
-            info_dict.len()
-          } else {
-            extended::UtMetadata::PIECE_LENGTH * (req.piece + 1)
-          },
-        };
-
-        seeder
-          .send_ut_metadata_data(req.piece, info_dict.len(), &info_dict[range])
-          .unwrap();
-      }
+  /// Spawn an info dict seeder that only accepts connections over uTP.
+  pub(crate) fn spawn_utp_info_dict_seeder(info: &Info) -> (thread::JoinHandle<()>, SocketAddr) {
+    let info_dict = bendy::serde::ser::to_bytes(info).unwrap();
+    let infohash = Infohash::from_bencoded_info_dict(&info_dict);
+    let listener = utp::Listener::bind((Ipv4Addr::LOCALHOST, 0).into()).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let seeder = thread::spawn(move || {
+      let (mut stream, _) = listener.accept().unwrap();
+      stream.set_read_timeout(Some(Duration::new(3, 0)));
+      Self::new(Connection::from(stream, infohash).unwrap(), infohash)
+        .seed_info_dict(info_dict, Vec::new());
     });
 
     (seeder, addr)
   }
+
+  // Serve `info_dict`, and send `added` with tracker exchange, if not empty,
+  // as soon as the fetcher's extension handshake arrives.
+  fn seed_info_dict(mut self, info_dict: Vec<u8>, added: Vec<String>) {
+    self.session.metadata_exchange().serve(info_dict);
+    self.send_extension_handshake().unwrap();
+
+    // Respond to ut_metadata requests until the fetcher hangs up. Ignore
+    // errors.
+    let mut added = Some(added).filter(|added| !added.is_empty());
+    while let Ok(msg) = self.conn.recv() {
+      self.handle_msg(&msg).ok();
+
+      if self.session.extension_handshake.is_some() {
+        if let Some(added) = added.take() {
+          self
+            .session
+            .sender(extended::TrackerExchange::NAME)
+            .send(extended::tracker_exchange::LtTex { added })
+            .ok();
+          self.flush().ok();
+        }
+      }
+    }
+  }
 }
 
 #[cfg(test)]
@@ -308,6 +170,8 @@ mod tests {
       pieces: PieceList::new(),
       mode: Mode::Single {
         md5sum: None,
+        sha1: None,
+        sha256: None,
         length: Bytes(1),
       },
       update_url: None,
@@ -323,6 +187,8 @@ mod tests {
       pieces: PieceList::from_pieces(["hello", "cargo", "test"]),
       mode: Mode::Single {
         md5sum: None,
+        sha1: None,
+        sha256: None,
         length: Bytes(1),
       },
       update_url: None,
@@ -356,7 +222,9 @@ mod tests {
     info: Info,
   ) -> peer::Client {
     let mut c = Client::connect(&addr, infohash).unwrap();
-    c.info = Some(info);
+    c.session
+      .metadata_exchange()
+      .serve(bendy::serde::ser::to_bytes(&info).unwrap());
     c.send_extension_handshake().unwrap();
     expect_extended_handshake(&mut c);
     expect_ut_metadata_request(&mut c, 0);
@@ -365,19 +233,19 @@ mod tests {
 
   fn expect_extended_handshake(c: &mut Client) {
     let msg = c.conn.recv().unwrap();
-    assert_eq!(msg.flavour, message::Flavour::Extended);
     let (id, _) = msg.parse_extended_payload().unwrap();
-    assert_eq!(id, extended::Id::Handshake);
+    assert_eq!(id, extended::Handshake::ID);
     c.handle_msg(&msg).unwrap();
-    assert_matches!(&c.extension_handshake, Some(..));
-    assert_matches!(c.ut_metadata_msg_id(), Ok(..));
+    assert!(c.session.sender(extended::UtMetadata::NAME).supported());
   }
 
   fn expect_ut_metadata_request(c: &mut Client, piece: usize) {
     let msg = c.conn.recv().unwrap();
-    assert_eq!(msg.flavour, message::Flavour::Extended);
     let (id, payload) = msg.parse_extended_payload().unwrap();
-    assert_eq!(id, extended::Id::UtMetadata);
+    assert_eq!(
+      Some(id),
+      c.session.extensions.local_id(extended::UtMetadata::NAME)
+    );
     let ut_metadata_request: extended::UtMetadata = Message::from_bencode(payload).unwrap();
     assert_eq!(
       ut_metadata_request.msg_type,
@@ -396,6 +264,8 @@ mod tests {
       pieces: PieceList::new(),
       mode: Mode::Single {
         md5sum: None,
+        sha1: None,
+        sha256: None,
         length: Bytes(1),
       },
       update_url: None,
@@ -411,7 +281,19 @@ mod tests {
   fn handshake_bad_bt_header() {
     let info = new_one_piece_info();
     let infohash = info.infohash_lossy().unwrap();
-    let (handle, addr) = spawn_idle_peer(infohash);
+    // With encryption enabled, a connection that doesn't start with the
+    // protocol header is taken to be an encryption handshake.
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = thread::spawn(move || {
+      let (stream, _) = listener.accept().unwrap();
+      Connection::accept(
+        stream.into(),
+        infohash,
+        peer::handshake::IMDL_RESERVED_BYTES,
+        peer::Encryption::Disable,
+      )
+    });
 
     let mut stream = TcpStream::connect_timeout(&addr, Duration::new(3, 0)).unwrap();
     let mut payload = peer::handshake::Handshake::new(infohash).serialize();
@@ -438,7 +320,9 @@ mod tests {
       Client::connect(&addr, infohash2),
       Err(Error::Network { .. })
     );
-    assert_matches!(handle.join().unwrap(), Err(Error::PeerHandshakeInfohash));
+    // The infohash is checked during the encryption handshake, before the
+    // peers exchange BitTorrent handshakes.
+    assert_matches!(handle.join().unwrap(), Err(Error::PeerEncryptionInfohash));
   }
 
   #[test]
@@ -466,7 +350,7 @@ mod tests {
     let mut c = Client::connect(&addr, infohash).unwrap();
     c.send_extension_handshake().unwrap();
     c.conn.recv().and_then(|msg| c.handle_msg(&msg)).unwrap();
-    let handshake = c.extension_handshake.unwrap();
+    let handshake = c.session.extension_handshake.unwrap();
     assert_eq!(
       handshake.version.unwrap(),
       format!("intermodal {}", consts::VERSION)
@@ -486,11 +370,7 @@ mod tests {
 
     let mut local = Client::connect(&addr, infohash).unwrap();
     assert_matches!(
-      local.ut_metadata_size(),
-      Err(Error::PeerNoExtendedHandshake)
-    );
-    assert_matches!(
-      local.ut_metadata_msg_id(),
+      local.session.sender(extended::UtMetadata::NAME).send(()),
       Err(Error::PeerNoExtendedHandshake)
     );
     local.send_extension_handshake().unwrap();
@@ -500,9 +380,81 @@ mod tests {
       .and_then(|msg| local.handle_msg(&msg))
       .unwrap();
     assert_matches!(
-      local.ut_metadata_size(),
-      Err(Error::PeerUtMetadataMetadataSizeNotKnown)
+      local.session.sender("ut_pex").send(()),
+      Err(Error::PeerExtensionNotSupported { name: "ut_pex" })
     );
+    assert_eq!(
+      local.session.extension_handshake.unwrap().metadata_size,
+      None
+    );
+    assert_matches!(handle.join().unwrap(), Ok(()));
+  }
+
+  #[derive(Debug)]
+  struct Echo(&'static str);
+
+  impl extended::Extension for Echo {
+    fn name(&self) -> &'static str {
+      self.0
+    }
+
+    fn handle_message(&mut self, payload: &[u8], sender: &mut extended::Sender) -> Result<()> {
+      sender.send(Message::from_bencode::<String>(payload)?)
+    }
+  }
+
+  #[test]
+  fn extension_ids_are_per_peer() {
+    let info = new_one_piece_info();
+    let infohash = info.infohash_lossy().unwrap();
+
+    let (handle, addr) = spawn_peer(infohash, |mut c| {
+      c.session.extensions.register(Echo("foo"));
+      c.session.extensions.register(Echo("echo"));
+      c.send_extension_handshake()?;
+      for _ in 0..2 {
+        let msg = c.conn.recv()?;
+        c.handle_msg(&msg)?;
+      }
+      Ok(())
+    });
+
+    let mut c = Client::connect(&addr, infohash).unwrap();
+    assert_eq!(c.session.extensions.register(Echo("echo")), 3);
+    c.send_extension_handshake().unwrap();
+    c.conn.recv().and_then(|msg| c.handle_msg(&msg)).unwrap();
+
+    // Sent to the peer's id for `echo`, which is 4.
+    c.session.sender("echo").send("bar").unwrap();
+    c.flush().unwrap();
+
+    // Echoed to our id for `echo`, which is 3.
+    let msg = c.conn.recv().unwrap();
+    let (id, payload) = msg.parse_extended_payload().unwrap();
+    assert_eq!(id, 3);
+    assert_eq!(payload, b"3:bar");
+
+    assert_matches!(handle.join().unwrap(), Ok(()));
+  }
+
+  #[test]
+  fn unknown_extension_message_ignored() {
+    let info = new_one_piece_info();
+    let infohash = info.infohash_lossy().unwrap();
+
+    let (handle, addr) = spawn_peer(infohash, |mut c| {
+      let msg = c.conn.recv()?;
+      c.handle_msg(&msg)
+    });
+
+    let mut c = Client::connect(&addr, infohash).unwrap();
+    c.conn
+      .send(&Message::Extended {
+        id: 200,
+        payload: b"garbage".to_vec(),
+      })
+      .unwrap();
+
     assert_matches!(handle.join().unwrap(), Ok(()));
   }
 
@@ -515,7 +467,7 @@ mod tests {
     let mut extended_handshake = extended::Handshake::new(); // no ut_metadata message id set
     extended_handshake.metadata_size = Some(1);
     c.conn
-      .send(&Message::new_extended(extended::Id::Handshake.into(), extended_handshake).unwrap())
+      .send(&Message::new_extended(extended::Handshake::ID, extended_handshake).unwrap())
       .unwrap();
 
     assert_matches!(
@@ -539,6 +491,30 @@ mod tests {
     );
   }
 
+  #[test]
+  fn metadata_size_too_large() {
+    let info = new_one_piece_info();
+    let infohash = info.infohash_lossy().unwrap();
+
+    let (join_handle, addr) = spawn_info_dict_fetcher(infohash);
+    let mut c = Client::connect(&addr, infohash).unwrap();
+    let extended_handshake = extended::Handshake {
+      metadata_size: Some(usize::MAX),
+      ..extended::Handshake::default()
+    };
+    c.conn
+      .send(&Message::new_extended(extended::Handshake::ID, extended_handshake).unwrap())
+      .unwrap();
+
+    assert_matches!(
+      join_handle.join().unwrap(),
+      Err(Error::PeerUtMetadataMetadataSizeTooLarge {
+        size: usize::MAX,
+        max: 16_777_216,
+      })
+    );
+  }
+
   #[test]
   fn fetch_info_one_piece() {
     let info = new_one_piece_info();
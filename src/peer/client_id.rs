@@ -0,0 +1,280 @@
+use crate::common::*;
+
+/// The client that generated a peer id, decoded according to the conventions
+/// described in BEP 20.
+#[derive(Debug, PartialEq)]
+pub(crate) enum ClientId {
+  /// `-XXVVVV-`, where `XX` identifies the client and `VVVV` is its version.
+  Azureus { code: [u8; 2], version: String },
+  /// `CVVVVV`, where `C` identifies the client and the version is padded with
+  /// dashes.
+  Shadow { code: u8, version: String },
+  /// `M1-2-3--`, used by the original mainline client.
+  Mainline { version: String },
+}
+
+impl ClientId {
+  /// Our own Azureus-style client code.
+  const IMDL_CODE: [u8; 2] = *b"IM";
+
+  const AZUREUS_CLIENTS: &'static [(&'static [u8; 2], &'static str)] = &[
+    (b"AG", "Ares"),
+    (b"AZ", "Vuze"),
+    (b"BB", "BitBuddy"),
+    (b"BC", "BitComet"),
+    (b"BF", "Bitflu"),
+    (b"BI", "BiglyBT"),
+    (b"BT", "BitTorrent"),
+    (b"DE", "Deluge"),
+    (b"FD", "Free Download Manager"),
+    (b"FW", "FrostWire"),
+    (b"HL", "Halite"),
+    (b"IM", "Intermodal"),
+    (b"KT", "KTorrent"),
+    (b"LT", "libTorrent"),
+    (b"lt", "libtorrent"),
+    (b"ML", "MLDonkey"),
+    (b"PI", "PicoTorrent"),
+    (b"qB", "qBittorrent"),
+    (b"SD", "Thunder"),
+    (b"TR", "Transmission"),
+    (b"TX", "Tixati"),
+    (b"UM", "µTorrent for Mac"),
+    (b"UT", "µTorrent"),
+    (b"UW", "µTorrent Web"),
+    (b"WD", "WebTorrent Desktop"),
+    (b"WW", "WebTorrent"),
+    (b"XL", "Xunlei"),
+  ];
+
+  const SHADOW_CLIENTS: &'static [(u8, &'static str)] = &[
+    (b'A', "ABC"),
+    (b'O', "Osprey Permaseed"),
+    (b'Q', "BTQueue"),
+    (b'R', "Tribler"),
+    (b'S', "Shadow"),
+    (b'T', "BitTornado"),
+    (b'U', "UPnP NAT Bit Torrent"),
+  ];
+
+  /// A fresh peer id identifying us, in Azureus style.
+  pub(crate) fn peer_id() -> [u8; 20] {
+    let mut peer_id: [u8; 20] = rand::rng().random();
+
+    let mut version = env!("CARGO_PKG_VERSION")
+      .split('.')
+      .map(|component| component.parse::<u32>().unwrap_or(0));
+
+    peer_id[0] = b'-';
+    peer_id[1..3].copy_from_slice(&Self::IMDL_CODE);
+    for byte in &mut peer_id[3..7] {
+      *byte = version
+        .next()
+        .and_then(|component| char::from_digit(component, 36))
+        .map_or(b'0', |c| c.to_ascii_uppercase() as u8);
+    }
+    peer_id[7] = b'-';
+
+    peer_id
+  }
+
+  pub(crate) fn from_peer_id(peer_id: &[u8; 20]) -> Option<Self> {
+    Self::azureus(peer_id)
+      .or_else(|| Self::mainline(peer_id))
+      .or_else(|| Self::shadow(peer_id))
+  }
+
+  fn azureus(peer_id: &[u8; 20]) -> Option<Self> {
+    if peer_id[0] != b'-' || peer_id[7] != b'-' {
+      return None;
+    }
+
+    let code = [peer_id[1], peer_id[2]];
+
+    if !code.iter().all(u8::is_ascii_alphabetic) {
+      return None;
+    }
+
+    let digits = peer_id[3..7]
+      .iter()
+      .map(|byte| char::from(*byte).to_digit(36))
+      .collect::<Option<Vec<u32>>>()?;
+
+    let version = if &code == b"TR" {
+      // Transmission uses `-TRMmmX-`, where `M` is the major version, `mm`
+      // the minor version, and a trailing `Z` or `X` marks a development
+      // build.
+      format!(
+        "{}.{}{}{}",
+        digits[0],
+        digits[1],
+        digits[2],
+        if digits[3] == 0 { "" } else { "+" },
+      )
+    } else {
+      Self::dotted(&digits)
+    };
+
+    Some(Self::Azureus { code, version })
+  }
+
+  fn mainline(peer_id: &[u8; 20]) -> Option<Self> {
+    if peer_id[0] != b'M' {
+      return None;
+    }
+
+    let version = str::from_utf8(&peer_id[1..8]).ok()?.trim_end_matches('-');
+
+    let components = version.split('-').collect::<Vec<&str>>();
+
+    if components.len() != 3
+      || !components
+        .iter()
+        .all(|component| !component.is_empty() && component.bytes().all(|b| b.is_ascii_digit()))
+    {
+      return None;
+    }
+
+    Some(Self::Mainline {
+      version: components.join("."),
+    })
+  }
+
+  fn shadow(peer_id: &[u8; 20]) -> Option<Self> {
+    let code = peer_id[0];
+
+    if !Self::SHADOW_CLIENTS.iter().any(|(c, _)| *c == code) {
+      return None;
+    }
+
+    let digits = peer_id[1..6]
+      .iter()
+      .map_while(|byte| Self::shadow_digit(*byte))
+      .collect::<Vec<u32>>();
+
+    // The version is followed by at least three dashes of padding.
+    if digits.is_empty() || !peer_id[1 + digits.len()..].starts_with(b"---") {
+      return None;
+    }
+
+    Some(Self::Shadow {
+      code,
+      version: digits
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<String>>()
+        .join("."),
+    })
+  }
+
+  fn shadow_digit(byte: u8) -> Option<u32> {
+    match byte {
+      b'0'..=b'9' => Some((byte - b'0').into()),
+      b'A'..=b'Z' => Some((byte - b'A' + 10).into()),
+      b'a'..=b'z' => Some((byte - b'a' + 36).into()),
+      b'.' => Some(62),
+      _ => None,
+    }
+  }
+
+  // Join version digits with dots, dropping a trailing zero build number.
+  fn dotted(digits: &[u32]) -> String {
+    let digits = match digits {
+      [rest @ .., 0] if rest.len() >= 2 => rest,
+      digits => digits,
+    };
+
+    digits
+      .iter()
+      .map(ToString::to_string)
+      .collect::<Vec<String>>()
+      .join(".")
+  }
+
+  fn name(&self) -> Cow<'static, str> {
+    match self {
+      Self::Azureus { code, .. } => Self::AZUREUS_CLIENTS
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map_or_else(
+          || format!("Unknown client `{}`", String::from_utf8_lossy(code)).into(),
+          |(_, name)| (*name).into(),
+        ),
+      Self::Shadow { code, .. } => Self::SHADOW_CLIENTS
+        .iter()
+        .find(|(c, _)| c == code)
+        .map_or("Unknown client".into(), |(_, name)| (*name).into()),
+      Self::Mainline { .. } => "BitTorrent".into(),
+    }
+  }
+}
+
+impl Display for ClientId {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    let version = match self {
+      Self::Azureus { version, .. } | Self::Shadow { version, .. } | Self::Mainline { version } => {
+        version
+      }
+    };
+
+    write!(f, "{} {}", self.name(), version)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn decode(prefix: &str) -> Option<String> {
+    let mut peer_id = [b'x'; 20];
+    peer_id[..prefix.len()].copy_from_slice(prefix.as_bytes());
+    ClientId::from_peer_id(&peer_id).map(|client_id| client_id.to_string())
+  }
+
+  #[test]
+  fn azureus() {
+    assert_eq!(decode("-qB4390-").unwrap(), "qBittorrent 4.3.9");
+    assert_eq!(decode("-DE13F0-").unwrap(), "Deluge 1.3.15");
+    assert_eq!(decode("-lt0D80-").unwrap(), "libtorrent 0.13.8");
+    assert_eq!(decode("-UT3550-").unwrap(), "µTorrent 3.5.5");
+    assert_eq!(decode("-AZ5761-").unwrap(), "Vuze 5.7.6.1");
+    assert_eq!(decode("-ZZ1000-").unwrap(), "Unknown client `ZZ` 1.0.0");
+    assert_eq!(decode("-qB43!0-"), None);
+  }
+
+  #[test]
+  fn transmission() {
+    assert_eq!(decode("-TR2940-").unwrap(), "Transmission 2.94");
+    assert_eq!(decode("-TR300Z-").unwrap(), "Transmission 3.00+");
+  }
+
+  #[test]
+  fn shadow() {
+    assert_eq!(decode("T03I-----").unwrap(), "BitTornado 0.3.18");
+    assert_eq!(decode("S58B-----").unwrap(), "Shadow 5.8.11");
+    assert_eq!(decode("T03I"), None);
+    assert_eq!(decode("X03I-----"), None);
+  }
+
+  #[test]
+  fn mainline() {
+    assert_eq!(decode("M4-3-6--").unwrap(), "BitTorrent 4.3.6");
+    assert_eq!(decode("M4-20-8-").unwrap(), "BitTorrent 4.20.8");
+    assert_eq!(decode("M4-x-6--"), None);
+  }
+
+  #[test]
+  fn unknown() {
+    assert_eq!(ClientId::from_peer_id(&[0; 20]), None);
+  }
+
+  #[test]
+  fn peer_id_round_trip() {
+    let peer_id = ClientId::peer_id();
+    assert_eq!(&peer_id[..3], b"-IM");
+    assert_eq!(
+      ClientId::from_peer_id(&peer_id).unwrap().to_string(),
+      format!("Intermodal {}", env!("CARGO_PKG_VERSION")),
+    );
+  }
+}
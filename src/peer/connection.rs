@@ -1,30 +1,93 @@
 use crate::common::*;
 
 use message::Message;
+use peer::cipher_stream::CipherStream;
 use peer::handshake::Handshake;
 use peer::message;
+use peer::{Encryption, Limits, Transport};
 
 #[derive(Debug)]
 pub struct Connection {
-  pub(crate) stream: TcpStream,
+  pub(crate) stream: CipherStream,
   pub(crate) handshake: Handshake,
+  limits: Limits,
+  bytes_read: u64,
+  established: Instant,
 }
 
 impl Connection {
+  #[cfg(test)]
   pub(crate) fn new(addr: &SocketAddr, infohash: Infohash) -> Result<Self> {
-    let mut stream =
-      TcpStream::connect_timeout(addr, Duration::new(3, 0)).context(error::Network)?;
-    stream
-      .set_read_timeout(Some(Duration::new(3, 0)))
-      .context(error::Network)?;
+    Self::connect(
+      addr,
+      infohash,
+      peer::handshake::IMDL_RESERVED_BYTES,
+      Encryption::default(),
+    )
+  }
+
+  /// Open a TCP connection to the peer at `addr`, for tests that play the
+  /// part of a remote peer. Outgoing connections are otherwise made by
+  /// `Wire`, which also handles uTP and falling back to plaintext.
+  #[cfg(test)]
+  pub(crate) fn connect(
+    addr: &SocketAddr,
+    infohash: Infohash,
+    reserved: [u8; 8],
+    encryption: Encryption,
+  ) -> Result<Self> {
+    let transport = Transport::connect_tcp(addr, Limits::default().connect_timeout)?;
 
-    Self::send_handshake(&mut stream, infohash)?;
+    let mut stream = if encryption == Encryption::Disable {
+      CipherStream::plaintext(transport)
+    } else {
+      CipherStream::initiate(transport, infohash, encryption)?
+    };
+
+    Self::send_handshake(&mut stream, Handshake::with_reserved(infohash, reserved))?;
     let handshake = Self::recv_handshake(&mut stream, infohash)?;
 
-    Ok(Self { stream, handshake })
+    Ok(Self::with_handshake(stream, handshake))
+  }
+
+  /// Complete the handshake on an inbound connection, advertising the
+  /// capabilities in `reserved`. The peer may open with an encryption
+  /// handshake, which is accepted unless `encryption` is `Disable`.
+  pub(crate) fn accept(
+    transport: Transport,
+    infohash: Infohash,
+    reserved: [u8; 8],
+    encryption: Encryption,
+  ) -> Result<Self> {
+    let mut stream = CipherStream::accept(transport, infohash, encryption)?;
+    let handshake = Self::recv_handshake(&mut stream, infohash)?;
+    Self::send_handshake(&mut stream, Handshake::with_reserved(infohash, reserved))?;
+    Ok(Self::with_handshake(stream, handshake))
+  }
+
+  fn with_handshake(stream: CipherStream, handshake: Handshake) -> Self {
+    Self {
+      stream,
+      handshake,
+      limits: Limits::default(),
+      bytes_read: Handshake::LENGTH.into_u64(),
+      established: Instant::now(),
+    }
+  }
+
+  /// Replace the default limits. The deadline, if any, is measured from when
+  /// the connection was established.
+  pub(crate) fn with_limits(mut self, limits: Limits) -> Self {
+    self.limits = limits;
+    self
+  }
+
+  #[cfg(test)]
+  pub(crate) fn limits(&self) -> Limits {
+    self.limits
   }
 
-  fn recv_handshake(stream: &mut TcpStream, infohash: Infohash) -> Result<Handshake> {
+  fn recv_handshake(stream: &mut CipherStream, infohash: Infohash) -> Result<Handshake> {
     let mut buf = [0u8; Handshake::LENGTH];
     stream.read_exact(&mut buf).context(error::Network)?;
     let handshake = Handshake::try_from(buf)?;
@@ -34,46 +97,100 @@ impl Connection {
     Ok(handshake)
   }
 
-  fn send_handshake(stream: &mut TcpStream, infohash: Infohash) -> Result<Handshake> {
-    let handshake = Handshake::new(infohash);
+  fn send_handshake(stream: &mut CipherStream, handshake: Handshake) -> Result<Handshake> {
     stream
       .write_all(&handshake.serialize()[..])
       .context(error::Network)?;
     Ok(handshake)
   }
 
+  /// Receive the next message. Keep-alives are returned as
+  /// `Message::KeepAlive`, and may be ignored by callers.
   pub(crate) fn recv(&mut self) -> Result<Message> {
-    // The message header is four bytes of message length, plus a one byte instruction.
-    let mut header = [0u8; 5];
-    self
-      .stream
-      .read_exact(&mut header)
-      .context(error::Network)?;
+    let mut prefix = [0u8; 4];
+    self.read_exact(&mut prefix)?;
 
-    let length = u32::from_be_bytes(
-      header[..4]
-        .try_into()
-        .invariant_unwrap("bound is checked by read_exact and the length of buf"),
-    );
+    let length = u32::from_be_bytes(prefix);
 
-    let payload = if length > 1 {
-      let mut payload = Vec::new();
-      (&self.stream)
-        .take((length - 1).into())
-        .read_to_end(&mut payload)
-        .context(error::Network)?;
-      Some(payload)
-    } else {
-      None
+    if length > self.limits.max_message_length {
+      return Err(Error::PeerMessageTooLarge {
+        length,
+        max: self.limits.max_message_length,
+      });
+    }
+
+    let mut buf = vec![0; length.into_usize()];
+    self.read_exact(&mut buf)?;
+
+    Message::deserialize(&buf)
+  }
+
+  // Read exactly `buf.len()` bytes, enforcing the byte limit and deadline.
+  fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+    self.bytes_read += buf.len().into_u64();
+
+    if let Some(max) = self.limits.max_bytes_read {
+      if self.bytes_read > max {
+        return Err(Error::PeerReadLimit { max });
+      }
+    }
+
+    let Some(deadline) = self
+      .limits
+      .deadline
+      .map(|deadline| self.established + deadline)
+    else {
+      return self.stream.read_exact(buf).context(error::Network);
     };
 
-    Ok(Message {
-      flavour: message::Flavour::from(header[4]),
-      payload,
-    })
+    // Shorten the read timeout so a peer that trickles data can't keep us
+    // waiting past the deadline.
+    let timeout = self.stream.read_timeout().context(error::Network)?;
+
+    let mut filled = 0;
+    while filled < buf.len() {
+      let remaining = deadline.saturating_duration_since(Instant::now());
+
+      if remaining.is_zero() {
+        return Err(Error::PeerDeadline);
+      }
+
+      self
+        .stream
+        .set_read_timeout(Some(
+          timeout.map_or(remaining, |timeout| timeout.min(remaining)),
+        ))
+        .context(error::Network)?;
+
+      match self.stream.read(&mut buf[filled..]) {
+        Ok(0) => {
+          return Err(Error::Network {
+            source: io::ErrorKind::UnexpectedEof.into(),
+          })
+        }
+        Ok(n) => filled += n,
+        Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+        Err(err)
+          if matches!(
+            err.kind(),
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+          ) && Instant::now() >= deadline =>
+        {
+          return Err(Error::PeerDeadline);
+        }
+        Err(source) => return Err(Error::Network { source }),
+      }
+    }
+
+    self
+      .stream
+      .set_read_timeout(timeout)
+      .context(error::Network)?;
+
+    Ok(())
   }
 
-  pub(crate) fn send(&mut self, msg: &message::Message) -> Result<()> {
+  pub(crate) fn send(&mut self, msg: &Message) -> Result<()> {
     self
       .stream
       .write_all(&msg.serialize()?)
@@ -84,10 +201,139 @@ impl Connection {
     self.handshake.supports_extension_protocol()
   }
 
+  pub(crate) fn supports_fast_extension(&self) -> bool {
+    self.handshake.supports_fast_extension()
+  }
+
   #[cfg(test)]
-  pub(crate) fn from(mut stream: TcpStream, infohash: Infohash) -> Result<Self> {
-    let handshake = Self::recv_handshake(&mut stream, infohash)?;
-    Self::send_handshake(&mut stream, infohash)?;
-    Ok(Self { stream, handshake })
+  pub(crate) fn from(stream: impl Into<Transport>, infohash: Infohash) -> Result<Self> {
+    Self::accept(
+      stream.into(),
+      infohash,
+      peer::handshake::IMDL_RESERVED_BYTES,
+      Encryption::default(),
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn infohash() -> Infohash {
+    Infohash::from([1; 20])
+  }
+
+  /// Spawn a hostile peer that completes the handshake, then writes `frames`
+  /// to the stream, pausing for `delay` between each.
+  fn spawn_hostile_peer(frames: Vec<Vec<u8>>, delay: Duration) -> SocketAddr {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+      let (stream, _) = listener.accept().unwrap();
+      let mut conn = Connection::from(stream, infohash()).unwrap();
+      for frame in frames {
+        if conn.stream.write_all(&frame).is_err() {
+          return;
+        }
+        thread::sleep(delay);
+      }
+      // Hold the connection open until the other side gives up.
+      let mut buf = [0; 1];
+      conn.stream.read_exact(&mut buf).ok();
+    });
+    addr
+  }
+
+  #[test]
+  fn oversized_message() {
+    let addr = spawn_hostile_peer(vec![vec![0xff, 0xff, 0xff, 0xff, 7]], Duration::ZERO);
+    let mut conn = Connection::new(&addr, infohash()).unwrap();
+    assert_matches!(
+      conn.recv(),
+      Err(Error::PeerMessageTooLarge {
+        length: u32::MAX,
+        max: 1_048_576,
+      })
+    );
+  }
+
+  #[test]
+  fn configured_message_limit() {
+    let frame = Message::Bitfield {
+      bitfield: vec![0; 100],
+    }
+    .serialize()
+    .unwrap();
+    let addr = spawn_hostile_peer(vec![frame.clone(), frame], Duration::ZERO);
+    let mut conn = Connection::new(&addr, infohash()).unwrap();
+    assert_matches!(conn.recv(), Ok(Message::Bitfield { .. }));
+    let mut conn = conn.with_limits(Limits {
+      max_message_length: 100,
+      ..Limits::default()
+    });
+    assert_matches!(
+      conn.recv(),
+      Err(Error::PeerMessageTooLarge {
+        length: 101,
+        max: 100
+      })
+    );
+  }
+
+  #[test]
+  fn read_limit() {
+    let frame = Message::Have { index: 0 }.serialize().unwrap();
+    let addr = spawn_hostile_peer(vec![frame; 100], Duration::ZERO);
+    let mut conn = Connection::new(&addr, infohash())
+      .unwrap()
+      .with_limits(Limits {
+        max_bytes_read: Some(Handshake::LENGTH.into_u64() + 9 * 10),
+        ..Limits::default()
+      });
+
+    for _ in 0..10 {
+      assert_matches!(conn.recv(), Ok(Message::Have { index: 0 }));
+    }
+
+    assert_matches!(conn.recv(), Err(Error::PeerReadLimit { .. }));
+  }
+
+  #[test]
+  fn deadline() {
+    let addr = spawn_hostile_peer(
+      vec![Message::KeepAlive.serialize().unwrap(); 100],
+      Duration::from_millis(50),
+    );
+    let mut conn = Connection::new(&addr, infohash())
+      .unwrap()
+      .with_limits(Limits {
+        deadline: Some(Duration::from_millis(500)),
+        ..Limits::default()
+      });
+
+    loop {
+      match conn.recv() {
+        Ok(Message::KeepAlive) => {}
+        Err(Error::PeerDeadline) => break,
+        other => panic!("unexpected result: {other:?}"),
+      }
+    }
+  }
+
+  #[test]
+  fn trickled_message() {
+    // Send a message one byte at a time, each well within the read timeout.
+    let frame = Message::Have { index: 0 }.serialize().unwrap();
+    let frames = frame.into_iter().map(|byte| vec![byte]).collect();
+    let addr = spawn_hostile_peer(frames, Duration::from_millis(200));
+    let mut conn = Connection::new(&addr, infohash())
+      .unwrap()
+      .with_limits(Limits {
+        deadline: Some(Duration::from_millis(500)),
+        ..Limits::default()
+      });
+
+    assert_matches!(conn.recv(), Err(Error::PeerDeadline));
   }
 }
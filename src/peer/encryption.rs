@@ -0,0 +1,33 @@
+use crate::common::*;
+
+/// Whether to use message stream encryption on peer connections.
+#[derive(Copy, Clone, Debug, Default, PartialEq, VariantNames, EnumString, IntoStaticStr)]
+#[strum(serialize_all = "kebab-case")]
+pub(crate) enum Encryption {
+  /// Encrypt outgoing connections, reconnecting without encryption if the
+  /// peer doesn't support it, and accept both encrypted and plaintext
+  /// incoming connections.
+  #[default]
+  Prefer,
+  /// Only use encrypted connections.
+  Require,
+  /// Only use plaintext connections.
+  Disable,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn variants() {
+    assert_eq!(Encryption::VARIANTS, &["prefer", "require", "disable"]);
+  }
+
+  #[test]
+  fn from_str() {
+    assert_eq!(Encryption::Prefer, "prefer".parse().unwrap());
+    assert_eq!(Encryption::Require, "require".parse().unwrap());
+    assert_eq!(Encryption::Disable, "disable".parse().unwrap());
+  }
+}
@@ -0,0 +1,328 @@
+use crate::common::*;
+
+use peer::handshake;
+use peer::{Encryption, Limits, Session, Wire};
+
+/// A non-blocking info dictionary fetch from a single peer, driven by a
+/// `Reactor`.
+#[derive(Debug)]
+pub(crate) struct Fetcher {
+  wire: Wire,
+  session: Session,
+}
+
+impl Fetcher {
+  pub(crate) fn new(
+    addr: SocketAddr,
+    infohash: Infohash,
+    limits: Limits,
+    encryption: Encryption,
+  ) -> Self {
+    Self::with_wire(Wire::new(
+      addr,
+      infohash,
+      handshake::IMDL_RESERVED_BYTES,
+      limits,
+      encryption,
+    ))
+  }
+
+  fn with_wire(wire: Wire) -> Self {
+    Self {
+      session: Session::new(wire.infohash(), wire.limits()),
+      wire,
+    }
+  }
+
+  /// Connect through `proxy`, if any.
+  pub(crate) fn via(self, proxy: Option<&Proxy>) -> Self {
+    Self {
+      wire: self.wire.via(proxy),
+      ..self
+    }
+  }
+
+  /// Try connecting over uTP before TCP, if `utp` is set.
+  pub(crate) fn with_utp(self, utp: bool) -> Self {
+    Self {
+      wire: self.wire.with_utp(utp),
+      ..self
+    }
+  }
+
+  pub(crate) fn addr(&self) -> SocketAddr {
+    self.wire.addr()
+  }
+
+  /// Our external address, if the peer told us what it is.
+  pub(crate) fn external_ip(&self) -> Option<IpAddr> {
+    self.session.extension_handshake.as_ref()?.yourip()
+  }
+
+  /// Trackers the peer told us about with BEP 28 tracker exchange.
+  pub(crate) fn trackers(&mut self) -> &[Url] {
+    self.session.tracker_exchange().trackers()
+  }
+
+  /// A retry of a fetch that failed with `error`, over TCP if a uTP
+  /// connection couldn't be opened, or in plaintext if the peer doesn't seem
+  /// to support encryption.
+  pub(crate) fn fallback(&self, error: &Error) -> Option<Self> {
+    self.wire.fallback(error).map(Self::with_wire)
+  }
+
+  fn queue_outbox(&mut self) -> Result<()> {
+    for msg in self.session.outbox() {
+      self.wire.send(&msg)?;
+    }
+    Ok(())
+  }
+
+  // Handle everything received so far, returning the info dictionary once it
+  // has arrived.
+  fn process(&mut self) -> Result<Option<Info>> {
+    if let Some(handshake) = self.wire.recv_handshake()? {
+      if !handshake.supports_extension_protocol() {
+        return Err(Error::PeerUtMetadataNotSupported);
+      }
+
+      self.session.send_extension_handshake()?;
+      self.queue_outbox()?;
+    }
+
+    while let Some(msg) = self.wire.recv()? {
+      self.session.handle_msg(&msg)?;
+      self.queue_outbox()?;
+
+      if let Some(info) = self.session.metadata_exchange().take_info() {
+        return Ok(Some(info));
+      }
+    }
+
+    Ok(None)
+  }
+}
+
+impl Task for Fetcher {
+  type Output = Info;
+
+  fn start(&mut self, now: Instant) -> Result<()> {
+    self.wire.start(now)?;
+    self.session.metadata_exchange().fetch();
+    Ok(())
+  }
+
+  fn source(&mut self) -> &mut dyn mio::event::Source {
+    self.wire.source()
+  }
+
+  fn interest(&self) -> mio::Interest {
+    self.wire.interest()
+  }
+
+  fn wakeup(&self) -> Option<Instant> {
+    self.wire.wakeup()
+  }
+
+  fn advance(&mut self, now: Instant) -> Result<Option<Info>> {
+    if !self.wire.pump(now)? {
+      return Ok(None);
+    }
+
+    if let Some(info) = self.process()? {
+      return Ok(Some(info));
+    }
+
+    if self.wire.eof() {
+      return Err(Error::Network {
+        source: io::ErrorKind::UnexpectedEof.into(),
+      });
+    }
+
+    self.wire.flush()?;
+
+    Ok(None)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use peer::Client;
+
+  use std::sync::atomic::{self, AtomicUsize};
+
+  fn info() -> Info {
+    Info {
+      private: None,
+      piece_length: Bytes(16 * 1024),
+      source: None,
+      name: "foo".into(),
+      pieces: PieceList::from_pieces(["foo"]),
+      mode: Mode::Single {
+        length: Bytes(1),
+        md5sum: None,
+        sha1: None,
+        sha256: None,
+      },
+      update_url: None,
+    }
+  }
+
+  fn fetch(fetchers: Vec<Fetcher>) -> Vec<Result<Info>> {
+    let mut reactor = Reactor::new(fetchers.len()).unwrap();
+    for fetcher in fetchers {
+      reactor.push(fetcher);
+    }
+    let mut results = Vec::new();
+    reactor
+      .run(|reactor, fetcher, result| {
+        if let Err(err) = &result {
+          if let Some(fetcher) = fetcher.fallback(err) {
+            reactor.push(fetcher);
+            return Ok(Control::Continue);
+          }
+        }
+        results.push(result);
+        Ok(Control::Continue)
+      })
+      .unwrap();
+    results
+  }
+
+  #[test]
+  fn fetch_info() {
+    let info = info();
+    let infohash = info.infohash_lossy().unwrap();
+    let (_, addr) = Client::spawn_info_dict_seeder(&info);
+    let results = fetch(vec![Fetcher::new(
+      addr,
+      infohash,
+      Limits::default(),
+      Encryption::default(),
+    )]);
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].as_ref().unwrap(), &info);
+  }
+
+  #[test]
+  fn fetch_info_over_utp() {
+    let info = info();
+    let infohash = info.infohash_lossy().unwrap();
+    let (_, addr) = Client::spawn_utp_info_dict_seeder(&info);
+    let results = fetch(vec![Fetcher::new(
+      addr,
+      infohash,
+      Limits::default(),
+      Encryption::default(),
+    )
+    .with_utp(true)]);
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].as_ref().unwrap(), &info);
+  }
+
+  #[test]
+  fn fetch_info_many_peers() {
+    let info = info();
+    let infohash = info.infohash_lossy().unwrap();
+    let fetchers = (0..10)
+      .map(|_| {
+        let (_, addr) = Client::spawn_info_dict_seeder(&info);
+        Fetcher::new(addr, infohash, Limits::default(), Encryption::default())
+      })
+      .collect();
+    for result in fetch(fetchers) {
+      assert_eq!(result.unwrap(), info);
+    }
+  }
+
+  #[test]
+  fn fetch_info_through_proxy() {
+    let info = info();
+    let infohash = info.infohash_lossy().unwrap();
+    let (server, proxy) = proxy::Server::spawn();
+
+    for scheme in ["socks5", "http"] {
+      let (_, addr) = Client::spawn_info_dict_seeder(&info);
+      let proxy = Proxy::from_url(&format!("{scheme}://{proxy}").parse().unwrap()).unwrap();
+      let results = fetch(vec![Fetcher::new(
+        addr,
+        infohash,
+        Limits::default(),
+        Encryption::default(),
+      )
+      .via(Some(&proxy))]);
+      assert_eq!(results[0].as_ref().unwrap(), &info);
+      assert_eq!(server.targets().last(), Some(&addr.into()));
+    }
+  }
+
+  #[test]
+  fn connection_refused() {
+    // Bind and immediately drop a listener to find a port nobody is
+    // listening on.
+    let addr = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+      .unwrap()
+      .local_addr()
+      .unwrap();
+    let results = fetch(vec![Fetcher::new(
+      addr,
+      Infohash::from([0; 20]),
+      Limits::default(),
+      Encryption::default(),
+    )]);
+    assert_matches!(results.as_slice(), [Err(Error::Network { .. })]);
+  }
+
+  // Accept connections, counting them, and hand each to `handle`.
+  fn listen(handle: fn(TcpStream)) -> (SocketAddr, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let connections = Arc::new(AtomicUsize::new(0));
+    let count = connections.clone();
+    thread::spawn(move || {
+      for stream in listener.incoming() {
+        count.fetch_add(1, atomic::Ordering::SeqCst);
+        thread::spawn(move || handle(stream.unwrap()));
+      }
+    });
+    (addr, connections)
+  }
+
+  #[test]
+  fn encryption_fallback() {
+    // A peer that closes connections without answering, as peers that don't
+    // support encryption do.
+    let (addr, connections) = listen(drop);
+
+    let results = fetch(vec![Fetcher::new(
+      addr,
+      Infohash::from([0; 20]),
+      Limits::default(),
+      Encryption::Prefer,
+    )]);
+    assert_matches!(results.as_slice(), [Err(Error::Network { .. })]);
+    assert_eq!(connections.load(atomic::Ordering::SeqCst), 2);
+  }
+
+  #[test]
+  fn deadline() {
+    // A peer that accepts the connection but never completes the handshake.
+    let (addr, connections) = listen(|_stream| thread::sleep(Duration::from_secs(5)));
+
+    let limits = Limits {
+      deadline: Some(Duration::from_millis(100)),
+      ..Limits::default()
+    };
+
+    let results = fetch(vec![Fetcher::new(
+      addr,
+      Infohash::from([0; 20]),
+      limits,
+      Encryption::default(),
+    )]);
+    assert_matches!(results.as_slice(), [Err(Error::PeerDeadline)]);
+    assert_eq!(connections.load(atomic::Ordering::SeqCst), 1);
+  }
+}
@@ -1,8 +1,33 @@
 use crate::common::*;
 
+use peer::ClientId;
+
 pub(crate) const HEADER: &[u8; 20] = b"\x13BitTorrent protocol";
 pub(crate) const SUPPORTS_EXTENSION_PROTOCOL: u8 = 0b0001_0000;
+pub(crate) const SUPPORTS_FAST_EXTENSION: u8 = 0b0000_0100;
+// Reserved bits assigned in BEP 4, as (byte, mask, description).
+const CAPABILITIES: &[(usize, u8, &str)] = &[
+  (0, 0b1000_0000, "Azureus Messaging Protocol"),
+  (5, SUPPORTS_EXTENSION_PROTOCOL, "Extension Protocol"),
+  (7, 0b0000_0001, "DHT"),
+  (7, 0b0000_0010, "XBT Peer Exchange"),
+  (7, SUPPORTS_FAST_EXTENSION, "Fast Extension"),
+  (7, 0b0000_1000, "NAT Traversal"),
+  (7, 0b0001_0000, "BitTorrent v2 Upgrade"),
+];
 pub(crate) const IMDL_RESERVED_BYTES: [u8; 8] = [0, 0, 0, 0, 0, SUPPORTS_EXTENSION_PROTOCOL, 0, 0];
+// Seeders also advertise the BEP 6 fast extension, since they always send
+// `HaveAll` or a bitfield immediately after the handshake.
+pub(crate) const IMDL_SEEDER_RESERVED_BYTES: [u8; 8] = [
+  0,
+  0,
+  0,
+  0,
+  0,
+  SUPPORTS_EXTENSION_PROTOCOL,
+  0,
+  SUPPORTS_FAST_EXTENSION,
+];
 
 #[derive(Debug)]
 pub(crate) struct Handshake {
@@ -14,11 +39,16 @@ pub(crate) struct Handshake {
 impl Handshake {
   pub(crate) const LENGTH: usize = 68;
 
+  #[cfg(test)]
   pub(crate) fn new(infohash: Infohash) -> Self {
+    Self::with_reserved(infohash, IMDL_RESERVED_BYTES)
+  }
+
+  pub(crate) fn with_reserved(infohash: Infohash, reserved: [u8; 8]) -> Self {
     Handshake {
-      peer_id: rand::rng().random(),
+      peer_id: ClientId::peer_id(),
       infohash: infohash.into(),
-      reserved: IMDL_RESERVED_BYTES,
+      reserved,
     }
   }
 
@@ -36,6 +66,19 @@ impl Handshake {
   pub fn supports_extension_protocol(&self) -> bool {
     self.reserved[5] & SUPPORTS_EXTENSION_PROTOCOL > 0
   }
+
+  pub fn supports_fast_extension(&self) -> bool {
+    self.reserved[7] & SUPPORTS_FAST_EXTENSION > 0
+  }
+
+  /// Descriptions of the capabilities advertised in the reserved bytes.
+  pub(crate) fn capabilities(&self) -> Vec<&'static str> {
+    CAPABILITIES
+      .iter()
+      .filter(|(byte, mask, _)| self.reserved[*byte] & mask > 0)
+      .map(|(_, _, description)| *description)
+      .collect()
+  }
 }
 
 impl TryFrom<[u8; Handshake::LENGTH]> for Handshake {
@@ -61,3 +104,18 @@ impl TryFrom<[u8; Handshake::LENGTH]> for Handshake {
     })
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn capabilities() {
+    let handshake =
+      Handshake::with_reserved(Infohash::from([0; 20]), [0, 0, 0, 0, 0, 0x10, 0, 0x05]);
+    assert_eq!(
+      handshake.capabilities(),
+      ["Extension Protocol", "DHT", "Fast Extension"]
+    );
+  }
+}
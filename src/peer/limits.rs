@@ -0,0 +1,47 @@
+use crate::common::*;
+
+/// Resource limits applied to a peer connection, protecting us from peers that
+/// send oversized messages, advertise enormous info dictionaries, or trickle
+/// data to keep a connection open indefinitely.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Limits {
+  /// Largest message, excluding the length prefix, that will be read.
+  pub(crate) max_message_length: u32,
+  /// Largest `metadata_size` a peer may advertise in its extension handshake.
+  pub(crate) max_metadata_size: usize,
+  /// Total number of bytes that will be read from the peer, if limited.
+  pub(crate) max_bytes_read: Option<u64>,
+  /// Time after which the connection is abandoned, if limited.
+  pub(crate) deadline: Option<Duration>,
+  /// Time allowed to establish an outgoing connection.
+  pub(crate) connect_timeout: Duration,
+}
+
+impl Limits {
+  /// Limits for connections accepted by a seeder, which last for as long as
+  /// the peer keeps downloading. Messages are bounded as usual, and so is the
+  /// total read, though loosely enough for a peer to request hundreds of
+  /// gigabytes. Peers that go silent are dropped by the seeder's read timeout
+  /// instead of a deadline.
+  pub(crate) fn seeding() -> Self {
+    Self {
+      max_bytes_read: Some(256 << 20),
+      deadline: None,
+      ..Self::default()
+    }
+  }
+}
+
+impl Default for Limits {
+  fn default() -> Self {
+    Self {
+      // Comfortably larger than a 128 KiB block or a bitfield for a torrent with
+      // millions of pieces.
+      max_message_length: 1 << 20,
+      max_metadata_size: 16 << 20,
+      max_bytes_read: Some(64 << 20),
+      deadline: Some(Duration::from_secs(60)),
+      connect_timeout: Duration::from_secs(3),
+    }
+  }
+}
@@ -1,31 +1,69 @@
 use crate::common::*;
 
+pub mod block;
 pub mod extended;
 pub mod flavour;
 
+pub(crate) use block::Block;
 pub(crate) use flavour::Flavour;
 
-#[derive(Debug)]
-pub(crate) struct Message {
-  pub(crate) flavour: Flavour,
-  pub(crate) payload: Option<Vec<u8>>,
+/// A peer wire protocol message, covering BEP 3, the BEP 6 fast extension, and
+/// BEP 10 extended messages.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Message {
+  KeepAlive,
+  Choke,
+  Unchoke,
+  Interested,
+  NotInterested,
+  Have {
+    index: u32,
+  },
+  Bitfield {
+    bitfield: Vec<u8>,
+  },
+  Request(Block),
+  Piece {
+    index: u32,
+    begin: u32,
+    block: Vec<u8>,
+  },
+  Cancel(Block),
+  Port {
+    port: u16,
+  },
+  Suggest {
+    index: u32,
+  },
+  HaveAll,
+  HaveNone,
+  Reject(Block),
+  AllowedFast {
+    index: u32,
+  },
+  Extended {
+    id: u8,
+    payload: Vec<u8>,
+  },
+  // Peers must ignore messages they don't understand, so unknown messages are
+  // passed through rather than treated as errors.
+  Unknown {
+    id: u8,
+    payload: Vec<u8>,
+  },
 }
 
 impl Message {
-  pub(crate) fn new(flavour: Flavour, payload: Option<Vec<u8>>) -> Self {
-    Message { flavour, payload }
-  }
-
   /// Create a new extended message. Since extended message ids for the same extension protocol may
   /// vary between peers, the id parameter must be determined from an extended handshake or prior
   /// knowledge.
   pub(crate) fn new_extended<T: serde::Serialize>(id: u8, p: T) -> Result<Self> {
-    let mut payload = vec![id];
-    payload.extend_from_slice(&Self::bencode(p)?);
-    Ok(Self::new(Flavour::Extended, Some(payload)))
+    Ok(Message::Extended {
+      id,
+      payload: Self::bencode(p)?,
+    })
   }
 
-  #[cfg(test)]
   // Create a new extended message but append `buf` to the bencoded message payload.
   pub(crate) fn new_extended_with_trailer<T: serde::Serialize>(
     id: u8,
@@ -33,42 +71,200 @@ impl Message {
     buf: &[u8],
   ) -> Result<Self> {
     let mut m = Self::new_extended(id, payload)?;
-    if let Some(p) = &mut m.payload {
-      p.extend_from_slice(buf);
+    if let Message::Extended { payload, .. } = &mut m {
+      payload.extend_from_slice(buf);
     }
     Ok(m)
   }
 
-  pub(crate) fn len(&self) -> usize {
-    1 + self.payload.as_ref().map_or(0, std::vec::Vec::len)
+  /// The message id, or `None` for keep-alives, which have no id.
+  pub(crate) fn id(&self) -> Option<u8> {
+    let flavour = match self {
+      Self::KeepAlive => return None,
+      Self::Unknown { id, .. } => return Some(*id),
+      Self::Choke => Flavour::Choke,
+      Self::Unchoke => Flavour::Unchoke,
+      Self::Interested => Flavour::Interested,
+      Self::NotInterested => Flavour::NotInterested,
+      Self::Have { .. } => Flavour::Have,
+      Self::Bitfield { .. } => Flavour::Bitfield,
+      Self::Request(_) => Flavour::Request,
+      Self::Piece { .. } => Flavour::Piece,
+      Self::Cancel(_) => Flavour::Cancel,
+      Self::Port { .. } => Flavour::Port,
+      Self::Suggest { .. } => Flavour::Suggest,
+      Self::HaveAll => Flavour::HaveAll,
+      Self::HaveNone => Flavour::HaveNone,
+      Self::Reject(_) => Flavour::Reject,
+      Self::AllowedFast { .. } => Flavour::AllowedFast,
+      Self::Extended { .. } => Flavour::Extended,
+    };
+
+    Some(flavour.into())
   }
 
   // Serialize the message to the BitTorrent wire format:
   //
   //   prefix  4 bytes
-  //   flavour 1 byte
+  //   id      1 byte
   //   payload x bytes
   //
   // where prefix is the network byte order encoding of `x + 1` into a u32.
+  // Keep-alives are a bare prefix of zero.
   pub(crate) fn serialize(&self) -> Result<Vec<u8>> {
-    // find a way to test this without blowing the stack.
-    let message_length = self.len().try_into().context(error::PeerMessagePayload)?;
-    let mut buf: Vec<u8> = u32::to_be_bytes(message_length).to_vec();
-    buf.push(self.flavour.into());
-    if let Some(p) = &self.payload {
-      buf.extend(p);
+    let mut buf = vec![0; 4];
+
+    if let Some(id) = self.id() {
+      buf.push(id);
     }
 
+    match self {
+      Self::KeepAlive
+      | Self::Choke
+      | Self::Unchoke
+      | Self::Interested
+      | Self::NotInterested
+      | Self::HaveAll
+      | Self::HaveNone => {}
+      Self::Have { index } | Self::Suggest { index } | Self::AllowedFast { index } => {
+        buf.extend_from_slice(&index.to_be_bytes());
+      }
+      Self::Bitfield { bitfield } => buf.extend_from_slice(bitfield),
+      Self::Request(block) | Self::Cancel(block) | Self::Reject(block) => block.serialize(&mut buf),
+      Self::Piece {
+        index,
+        begin,
+        block,
+      } => {
+        buf.extend_from_slice(&index.to_be_bytes());
+        buf.extend_from_slice(&begin.to_be_bytes());
+        buf.extend_from_slice(block);
+      }
+      Self::Port { port } => buf.extend_from_slice(&port.to_be_bytes()),
+      Self::Extended { id, payload } => {
+        buf.push(*id);
+        buf.extend_from_slice(payload);
+      }
+      Self::Unknown { payload, .. } => buf.extend_from_slice(payload),
+    }
+
+    let message_length: u32 = (buf.len() - 4)
+      .try_into()
+      .context(error::PeerMessagePayload)?;
+    buf[..4].copy_from_slice(&message_length.to_be_bytes());
+
     Ok(buf)
   }
 
-  pub(crate) fn parse_extended_payload(&self) -> Result<(extended::Id, &[u8])> {
-    if let Some(p) = &self.payload {
-      if !p.is_empty() {
-        return Ok((p[0].into(), &p[1..]));
+  /// Parse a message from its wire format, excluding the four byte length
+  /// prefix.
+  pub(crate) fn deserialize(buf: &[u8]) -> Result<Self> {
+    let Some((&id, payload)) = buf.split_first() else {
+      return Ok(Self::KeepAlive);
+    };
+
+    let Ok(flavour) = Flavour::try_from(id) else {
+      return Ok(Self::Unknown {
+        id,
+        payload: payload.to_vec(),
+      });
+    };
+
+    let length = |want: usize| {
+      if payload.len() == want {
+        Ok(())
+      } else {
+        Err(Error::PeerMessageLength {
+          flavour,
+          length: payload.len(),
+        })
+      }
+    };
+
+    let index = || -> Result<u32> {
+      length(4)?;
+      Ok(u32::from_be_bytes(
+        payload
+          .try_into()
+          .invariant_unwrap("payload length is checked"),
+      ))
+    };
+
+    let block = || -> Result<Block> {
+      length(Block::LENGTH)?;
+      Ok(Block::deserialize(
+        payload
+          .try_into()
+          .invariant_unwrap("payload length is checked"),
+      ))
+    };
+
+    let message = match flavour {
+      Flavour::Choke => length(0).map(|()| Self::Choke)?,
+      Flavour::Unchoke => length(0).map(|()| Self::Unchoke)?,
+      Flavour::Interested => length(0).map(|()| Self::Interested)?,
+      Flavour::NotInterested => length(0).map(|()| Self::NotInterested)?,
+      Flavour::HaveAll => length(0).map(|()| Self::HaveAll)?,
+      Flavour::HaveNone => length(0).map(|()| Self::HaveNone)?,
+      Flavour::Have => Self::Have { index: index()? },
+      Flavour::Suggest => Self::Suggest { index: index()? },
+      Flavour::AllowedFast => Self::AllowedFast { index: index()? },
+      Flavour::Bitfield => Self::Bitfield {
+        bitfield: payload.to_vec(),
+      },
+      Flavour::Request => Self::Request(block()?),
+      Flavour::Cancel => Self::Cancel(block()?),
+      Flavour::Reject => Self::Reject(block()?),
+      Flavour::Piece => {
+        if payload.len() < 8 {
+          return Err(Error::PeerMessageLength {
+            flavour,
+            length: payload.len(),
+          });
+        }
+        let (header, block) = payload.split_at(8);
+        Self::Piece {
+          index: u32::from_be_bytes(
+            header[0..4]
+              .try_into()
+              .invariant_unwrap("header is eight bytes"),
+          ),
+          begin: u32::from_be_bytes(
+            header[4..8]
+              .try_into()
+              .invariant_unwrap("header is eight bytes"),
+          ),
+          block: block.to_vec(),
+        }
+      }
+      Flavour::Port => {
+        length(2)?;
+        Self::Port {
+          port: u16::from_be_bytes(
+            payload
+              .try_into()
+              .invariant_unwrap("payload length is checked"),
+          ),
+        }
       }
+      Flavour::Extended => match payload.split_first() {
+        Some((&id, payload)) => Self::Extended {
+          id,
+          payload: payload.to_vec(),
+        },
+        None => return Err(Error::PeerMessageExtendedPayload),
+      },
+    };
+
+    Ok(message)
+  }
+
+  #[cfg(test)]
+  pub(crate) fn parse_extended_payload(&self) -> Result<(u8, &[u8])> {
+    match self {
+      Self::Extended { id, payload } => Ok((*id, payload)),
+      _ => Err(Error::PeerMessageExtendedPayload),
     }
-    Err(Error::PeerMessageExtendedPayload)
   }
 
   pub fn bencode<T: serde::Serialize>(msg: T) -> Result<Vec<u8>> {
@@ -84,25 +280,214 @@ impl Message {
 mod tests {
   use super::*;
 
+  fn block(index: u32, begin: u32, length: u32) -> Block {
+    Block {
+      index,
+      begin,
+      length,
+    }
+  }
+
+  fn case(message: Message, want: &[u8]) {
+    let have = message.serialize().unwrap();
+    assert_eq!(have, want, "{message:?} serialized incorrectly");
+    assert_eq!(Message::deserialize(&have[4..]).unwrap(), message);
+  }
+
+  #[test]
+  fn encoding() {
+    case(Message::KeepAlive, &[0, 0, 0, 0]);
+    case(Message::Choke, &[0, 0, 0, 1, 0]);
+    case(Message::Unchoke, &[0, 0, 0, 1, 1]);
+    case(Message::Interested, &[0, 0, 0, 1, 2]);
+    case(Message::NotInterested, &[0, 0, 0, 1, 3]);
+    case(Message::Have { index: 258 }, &[0, 0, 0, 5, 4, 0, 0, 1, 2]);
+    case(
+      Message::Bitfield {
+        bitfield: vec![0xff, 0x80],
+      },
+      &[0, 0, 0, 3, 5, 0xff, 0x80],
+    );
+    case(
+      Message::Request(block(1, 2, 3)),
+      &[0, 0, 0, 13, 6, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3],
+    );
+    case(
+      Message::Piece {
+        index: 1,
+        begin: 2,
+        block: b"abc".to_vec(),
+      },
+      &[0, 0, 0, 12, 7, 0, 0, 0, 1, 0, 0, 0, 2, b'a', b'b', b'c'],
+    );
+    case(
+      Message::Cancel(block(1, 2, 3)),
+      &[0, 0, 0, 13, 8, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3],
+    );
+    case(Message::Port { port: 6881 }, &[0, 0, 0, 3, 9, 0x1a, 0xe1]);
+    case(Message::Suggest { index: 7 }, &[0, 0, 0, 5, 13, 0, 0, 0, 7]);
+    case(Message::HaveAll, &[0, 0, 0, 1, 14]);
+    case(Message::HaveNone, &[0, 0, 0, 1, 15]);
+    case(
+      Message::Reject(block(1, 2, 3)),
+      &[0, 0, 0, 13, 16, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3],
+    );
+    case(
+      Message::AllowedFast { index: 7 },
+      &[0, 0, 0, 5, 17, 0, 0, 0, 7],
+    );
+    case(
+      Message::Extended {
+        id: 1,
+        payload: b"de".to_vec(),
+      },
+      &[0, 0, 0, 4, 20, 1, b'd', b'e'],
+    );
+    case(
+      Message::Unknown {
+        id: 100,
+        payload: vec![1, 2],
+      },
+      &[0, 0, 0, 3, 100, 1, 2],
+    );
+  }
+
+  #[test]
+  fn round_trip_random() {
+    fn bytes() -> Vec<u8> {
+      let mut rng = rand::rng();
+      let len = rng.random_range(0..64);
+      (0..len).map(|_| rng.random()).collect()
+    }
+
+    for _ in 0..1000 {
+      let message = match rand::rng().random_range(0..18) {
+        0 => Message::KeepAlive,
+        1 => Message::Choke,
+        2 => Message::Unchoke,
+        3 => Message::Interested,
+        4 => Message::NotInterested,
+        5 => Message::Have {
+          index: rand::rng().random(),
+        },
+        6 => Message::Bitfield { bitfield: bytes() },
+        7 => Message::Request(block(
+          rand::rng().random(),
+          rand::rng().random(),
+          rand::rng().random(),
+        )),
+        8 => Message::Piece {
+          index: rand::rng().random(),
+          begin: rand::rng().random(),
+          block: bytes(),
+        },
+        9 => Message::Cancel(block(
+          rand::rng().random(),
+          rand::rng().random(),
+          rand::rng().random(),
+        )),
+        10 => Message::Port {
+          port: rand::rng().random(),
+        },
+        11 => Message::Suggest {
+          index: rand::rng().random(),
+        },
+        12 => Message::HaveAll,
+        13 => Message::HaveNone,
+        14 => Message::Reject(block(
+          rand::rng().random(),
+          rand::rng().random(),
+          rand::rng().random(),
+        )),
+        15 => Message::AllowedFast {
+          index: rand::rng().random(),
+        },
+        16 => Message::Extended {
+          id: rand::rng().random(),
+          payload: bytes(),
+        },
+        _ => Message::Unknown {
+          id: rand::rng().random_range(21..=u8::MAX),
+          payload: bytes(),
+        },
+      };
+
+      let buf = message.serialize().unwrap();
+      let length = u32::from_be_bytes(buf[..4].try_into().unwrap());
+      assert_eq!(length.into_usize(), buf.len() - 4);
+      assert_eq!(Message::deserialize(&buf[4..]).unwrap(), message);
+    }
+  }
+
+  #[test]
+  fn deserialize_random_never_panics() {
+    let mut rng = rand::rng();
+
+    for _ in 0..1000 {
+      let len = rng.random_range(0..32);
+      let buf: Vec<u8> = (0..len).map(|_| rng.random()).collect();
+      Message::deserialize(&buf).ok();
+    }
+  }
+
+  #[test]
+  fn wrong_length() {
+    assert_matches!(
+      Message::deserialize(&[0, 1]),
+      Err(Error::PeerMessageLength {
+        flavour: Flavour::Choke,
+        length: 1,
+      })
+    );
+    assert_matches!(
+      Message::deserialize(&[4, 0, 0, 0]),
+      Err(Error::PeerMessageLength {
+        flavour: Flavour::Have,
+        length: 3,
+      })
+    );
+    assert_matches!(
+      Message::deserialize(&[6, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3, 0]),
+      Err(Error::PeerMessageLength {
+        flavour: Flavour::Request,
+        length: 13,
+      })
+    );
+    assert_matches!(
+      Message::deserialize(&[7, 0, 0, 0, 1, 0, 0, 0]),
+      Err(Error::PeerMessageLength {
+        flavour: Flavour::Piece,
+        length: 7,
+      })
+    );
+    assert_matches!(
+      Message::deserialize(&[9, 0]),
+      Err(Error::PeerMessageLength {
+        flavour: Flavour::Port,
+        length: 1,
+      })
+    );
+  }
+
   #[test]
   fn parse_extended_payload() {
-    let m = Message {
-      flavour: Flavour::Extended,
-      payload: Some(vec![1, 2, 3, 4]),
+    let m = Message::Extended {
+      id: 1,
+      payload: vec![2, 3, 4],
     };
     let (i, b) = m.parse_extended_payload().unwrap();
-    assert_eq!(i, extended::Id::UtMetadata);
+    assert_eq!(i, 1);
     assert_eq!(b, vec![2, 3, 4]);
   }
 
   #[test]
   fn parse_extended_payload_fail() {
-    let m = Message {
-      flavour: Flavour::Extended,
-      payload: None,
-    };
     assert_matches!(
-      m.parse_extended_payload(),
+      Message::deserialize(&[20]),
+      Err(Error::PeerMessageExtendedPayload)
+    );
+    assert_matches!(
+      Message::Choke.parse_extended_payload(),
       Err(Error::PeerMessageExtendedPayload)
     );
   }
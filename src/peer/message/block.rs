@@ -0,0 +1,40 @@
+use crate::common::*;
+
+/// A range of bytes within a piece, as carried by `Request`, `Cancel`, and
+/// `Reject` messages.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Block {
+  pub(crate) index: u32,
+  pub(crate) begin: u32,
+  pub(crate) length: u32,
+}
+
+impl Block {
+  pub(crate) const LENGTH: usize = 12;
+
+  pub(crate) fn serialize(self, buf: &mut Vec<u8>) {
+    buf.extend_from_slice(&self.index.to_be_bytes());
+    buf.extend_from_slice(&self.begin.to_be_bytes());
+    buf.extend_from_slice(&self.length.to_be_bytes());
+  }
+
+  pub(crate) fn deserialize(buf: &[u8; Self::LENGTH]) -> Self {
+    Block {
+      index: u32::from_be_bytes(
+        buf[0..4]
+          .try_into()
+          .invariant_unwrap("buf is Block::LENGTH bytes"),
+      ),
+      begin: u32::from_be_bytes(
+        buf[4..8]
+          .try_into()
+          .invariant_unwrap("buf is Block::LENGTH bytes"),
+      ),
+      length: u32::from_be_bytes(
+        buf[8..12]
+          .try_into()
+          .invariant_unwrap("buf is Block::LENGTH bytes"),
+      ),
+    }
+  }
+}
@@ -0,0 +1,24 @@
+use crate::common::*;
+
+use peer::message::extended::{Handshake, Sender};
+
+/// A BEP 10 extension. Extensions are registered with a `Registry`, which
+/// advertises each one under its name in the `m` dictionary of our extension
+/// handshake and routes messages sent to its local id back to it.
+pub(crate) trait Extension: Any + fmt::Debug + Send {
+  /// Name under which the extension is advertised, e.g. `ut_metadata`.
+  fn name(&self) -> &'static str;
+
+  /// Add extension-specific fields, such as `metadata_size`, to our
+  /// handshake.
+  fn extend_handshake(&self, _handshake: &mut Handshake) {}
+
+  /// Called when the peer's extension handshake arrives. `sender` addresses
+  /// messages using the id the peer assigned to this extension, if any.
+  fn handle_handshake(&mut self, _peer: &Handshake, _sender: &mut Sender) -> Result<()> {
+    Ok(())
+  }
+
+  /// Handle a message the peer sent to this extension's local id.
+  fn handle_message(&mut self, payload: &[u8], sender: &mut Sender) -> Result<()>;
+}
@@ -1,8 +1,5 @@
 use crate::common::*;
 
-use extended::ut_metadata;
-use peer::message::extended;
-
 // From BEP10:
 //
 // Handshake is a dictionary of supported extension messages which maps names of extensions to an
@@ -69,9 +66,20 @@ pub(crate) struct Handshake {
     with = "unwrap_or_skip"
   )]
   pub(crate) request_queue_size: Option<u64>,
+  // Set to 1 by peers that only upload, such as seeds, or clients that don't
+  // want any more pieces. (BEP 21)
+  #[serde(
+    default,
+    skip_serializing_if = "Option::is_none",
+    with = "unwrap_or_skip"
+  )]
+  pub(crate) upload_only: Option<u8>,
 }
 
 impl Handshake {
+  /// The extended message id of the handshake itself.
+  pub(crate) const ID: u8 = 0;
+
   pub(crate) fn new() -> Self {
     Handshake {
       message_ids: HashMap::new(),
@@ -80,6 +88,7 @@ impl Handshake {
       ipv4: vec![],
       ipv6: vec![],
       request_queue_size: None,
+      upload_only: None,
       yourip: vec![],
       version: Some(format!("intermodal {}", consts::VERSION)),
     }
@@ -92,13 +101,22 @@ impl Handshake {
   pub(crate) fn with_metadata_size(&mut self, size: usize) {
     self.metadata_size = Some(size);
   }
+
+  /// Our external address, as seen by the peer.
+  pub(crate) fn yourip(&self) -> Option<IpAddr> {
+    if let Ok(octets) = <[u8; 4]>::try_from(self.yourip.as_slice()) {
+      Some(Ipv4Addr::from(octets).into())
+    } else if let Ok(octets) = <[u8; 16]>::try_from(self.yourip.as_slice()) {
+      Some(Ipv6Addr::from(octets).into())
+    } else {
+      None
+    }
+  }
 }
 
 impl Default for Handshake {
   fn default() -> Self {
-    let mut handshake = Handshake::new();
-    handshake.with_message(String::from(ut_metadata::UtMetadata::NAME), 1);
-    handshake
+    Handshake::new()
   }
 }
 
@@ -106,14 +124,14 @@ impl Default for Handshake {
 mod tests {
   use super::*;
 
+  use peer::message::extended::UtMetadata;
+
   #[test]
   fn handshake_from_bencoded_handshake() {
     let payload = b"d1:ei0e1:md11:ut_metadatai255ee13:metadata_sizei1337e1:pi12345e4:reqqi2048e1:v18:intermodal v0.1.12e";
     let handshake: Handshake = bendy::serde::de::from_bytes(payload).unwrap();
     assert_eq!(
-      handshake
-        .message_ids
-        .get(&String::from(extended::Id::UtMetadata)),
+      handshake.message_ids.get(UtMetadata::NAME),
       Some(255).as_ref()
     );
     assert_eq!(handshake.metadata_size, Some(1337));
@@ -126,25 +144,43 @@ mod tests {
     let payload = b"d12:complete_agoi6e1:md11:lt_donthavei7e10:share_modei8e11:upload_onlyi3e12:ut_holepunchi4e11:ut_metadatai2e6:ut_pexi1eee";
     let handshake: Handshake = bendy::serde::de::from_bytes(payload).unwrap();
     assert_eq!(
-      handshake
-        .message_ids
-        .get(&String::from(extended::Id::UtMetadata)),
+      handshake.message_ids.get(UtMetadata::NAME),
       Some(2).as_ref()
     );
     assert_eq!(handshake.metadata_size, None);
   }
 
+  #[test]
+  fn handshake_with_upload_only() {
+    let payload = b"d1:md11:upload_onlyi3ee11:upload_onlyi1ee";
+    let handshake: Handshake = bendy::serde::de::from_bytes(payload).unwrap();
+    assert_eq!(handshake.upload_only, Some(1));
+    assert_eq!(Handshake::new().upload_only, None);
+  }
+
   #[test]
   fn handshake_with_yourip() {
     let payload = b"d1:ei0e1:md11:ut_metadatai255ee13:metadata_sizei1337e1:pi12345e4:reqqi2048e1:v18:intermodal v0.1.126:yourip4:z\xc7%\xcfee";
     let handshake: Handshake = bendy::serde::de::from_bytes(payload).unwrap();
     assert_eq!(
-      handshake
-        .message_ids
-        .get(&String::from(extended::Id::UtMetadata)),
+      handshake.message_ids.get(UtMetadata::NAME),
       Some(255).as_ref()
     );
     assert_eq!(handshake.yourip, vec![0x7a, 0xc7, 0x25, 0xcf]);
+    assert_eq!(
+      handshake.yourip(),
+      Some(Ipv4Addr::new(0x7a, 0xc7, 0x25, 0xcf).into())
+    );
+  }
+
+  #[test]
+  fn yourip() {
+    let mut handshake = Handshake::new();
+    assert_eq!(handshake.yourip(), None);
+    handshake.yourip = Ipv6Addr::LOCALHOST.octets().to_vec();
+    assert_eq!(handshake.yourip(), Some(Ipv6Addr::LOCALHOST.into()));
+    handshake.yourip = vec![1, 2, 3];
+    assert_eq!(handshake.yourip(), None);
   }
 
   #[test]
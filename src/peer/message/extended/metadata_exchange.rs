@@ -0,0 +1,176 @@
+use crate::common::*;
+
+use peer::message::extended::{ut_metadata::MsgType, Extension, Handshake, Sender, UtMetadata};
+use peer::message::Message;
+
+/// BEP 9 metadata exchange, over the `ut_metadata` extension. Fetches the info
+/// dictionary from a peer, and serves ours to peers that request it.
+#[derive(Debug)]
+pub(crate) struct MetadataExchange {
+  infohash: Infohash,
+  max_metadata_size: usize,
+  info_dict: Option<Vec<u8>>,
+  state: State,
+}
+
+#[derive(Debug, PartialEq)]
+enum State {
+  Idle,
+  Fetching {
+    metadata_size: Option<usize>,
+    buffer: Vec<u8>,
+  },
+  Fetched(Info),
+}
+
+impl MetadataExchange {
+  pub(crate) fn new(infohash: Infohash, max_metadata_size: usize) -> Self {
+    Self {
+      infohash,
+      max_metadata_size,
+      info_dict: None,
+      state: State::Idle,
+    }
+  }
+
+  /// Serve `info_dict` to peers that request it.
+  #[cfg(test)]
+  pub(crate) fn serve(&mut self, info_dict: Vec<u8>) {
+    self.info_dict = Some(info_dict);
+  }
+
+  /// Request the info dictionary once the peer's extension handshake arrives.
+  pub(crate) fn fetch(&mut self) {
+    self.state = State::Fetching {
+      metadata_size: None,
+      buffer: Vec::new(),
+    };
+  }
+
+  pub(crate) fn take_info(&mut self) -> Option<Info> {
+    match mem::replace(&mut self.state, State::Idle) {
+      State::Fetched(info) => Some(info),
+      state => {
+        self.state = state;
+        None
+      }
+    }
+  }
+
+  fn handle_data(&mut self, msg: &UtMetadata, payload: &[u8], sender: &mut Sender) -> Result<()> {
+    let State::Fetching {
+      metadata_size,
+      buffer,
+    } = &mut self.state
+    else {
+      return Ok(());
+    };
+
+    let metadata_size = metadata_size.ok_or(Error::PeerUtMetadataMetadataSizeNotKnown)?;
+
+    let piece = buffer.len() / UtMetadata::PIECE_LENGTH;
+    if msg.piece != piece {
+      return Err(Error::PeerUtMetadataWrongPiece);
+    }
+
+    // The ut_metadata::MsgType::Data payload splits into two parts,
+    // 1. a bencoded UtMetadata message,
+    // 2. the binary info_dict peice data.
+    // Their boundary is not delimited. Bencode the message to find the piece offset.
+    let piece_offset = Message::bencode(msg)?.len();
+    if payload[piece_offset..].len() > UtMetadata::PIECE_LENGTH {
+      return Err(Error::PeerUtMetadataPieceLength);
+    }
+    buffer.extend_from_slice(&payload[piece_offset..]);
+
+    match buffer.len().cmp(&metadata_size) {
+      Ordering::Equal => {
+        let info = Self::verify_info_dict(buffer, self.infohash)?;
+        self.state = State::Fetched(info);
+        Ok(())
+      }
+      Ordering::Less => sender.send(UtMetadata::request(piece + 1)),
+      Ordering::Greater => Err(Error::PeerUtMetadataInfoLength),
+    }
+  }
+
+  fn handle_request(&self, msg: &UtMetadata, sender: &mut Sender) -> Result<()> {
+    let Some(info_dict) = &self.info_dict else {
+      return sender.send(UtMetadata::reject(msg.piece));
+    };
+
+    let start = msg.piece.saturating_mul(UtMetadata::PIECE_LENGTH);
+
+    if start >= info_dict.len() {
+      return sender.send(UtMetadata::reject(msg.piece));
+    }
+
+    let end = info_dict.len().min(start + UtMetadata::PIECE_LENGTH);
+
+    sender.send_with_trailer(
+      UtMetadata::data(msg.piece, info_dict.len()),
+      &info_dict[start..end],
+    )
+  }
+
+  fn verify_info_dict(buf: &[u8], target: Infohash) -> Result<Info> {
+    let info =
+      bendy::serde::de::from_bytes::<Info>(buf).context(error::PeerUtMetadataInfoDeserialize)?;
+
+    let infohash = Infohash::from_bencoded_info_dict(
+      &bendy::serde::ser::to_bytes(&info).context(error::InfoSerialize)?,
+    );
+
+    if infohash == target {
+      Ok(info)
+    } else {
+      Err(Error::PeerUtMetadataWrongInfohash)
+    }
+  }
+}
+
+impl Extension for MetadataExchange {
+  fn name(&self) -> &'static str {
+    UtMetadata::NAME
+  }
+
+  fn extend_handshake(&self, handshake: &mut Handshake) {
+    if let Some(info_dict) = &self.info_dict {
+      handshake.with_metadata_size(info_dict.len());
+    }
+  }
+
+  fn handle_handshake(&mut self, peer: &Handshake, sender: &mut Sender) -> Result<()> {
+    let State::Fetching { metadata_size, .. } = &mut self.state else {
+      return Ok(());
+    };
+
+    // Drop the peer if we want info and the peer can't give it to us.
+    match peer.metadata_size {
+      None => return Err(Error::PeerUtMetadataMetadataSizeNotKnown),
+      Some(size) if size > self.max_metadata_size => {
+        return Err(Error::PeerUtMetadataMetadataSizeTooLarge {
+          size,
+          max: self.max_metadata_size,
+        })
+      }
+      Some(size) => *metadata_size = Some(size),
+    }
+
+    if !sender.supported() {
+      return Err(Error::PeerUtMetadataNotSupported);
+    }
+
+    sender.send(UtMetadata::request(0))
+  }
+
+  fn handle_message(&mut self, payload: &[u8], sender: &mut Sender) -> Result<()> {
+    let msg: UtMetadata = Message::from_bencode(payload)?;
+
+    match msg.msg_type.into() {
+      MsgType::Data => self.handle_data(&msg, payload, sender),
+      MsgType::Request => self.handle_request(&msg, sender),
+      MsgType::Reject => Ok(()),
+    }
+  }
+}
@@ -1,9 +1,15 @@
-use crate::common::*;
-
+pub(crate) use extension::Extension;
 pub(crate) use handshake::Handshake;
-pub(crate) use id::Id;
+pub(crate) use metadata_exchange::MetadataExchange;
+pub(crate) use registry::Registry;
+pub(crate) use sender::Sender;
+pub(crate) use tracker_exchange::TrackerExchange;
 pub(crate) use ut_metadata::UtMetadata;
 
+pub(crate) mod extension;
 pub(crate) mod handshake;
-pub(crate) mod id;
+pub(crate) mod metadata_exchange;
+pub(crate) mod registry;
+pub(crate) mod sender;
+pub(crate) mod tracker_exchange;
 pub(crate) mod ut_metadata;
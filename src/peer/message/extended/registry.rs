@@ -0,0 +1,131 @@
+use crate::common::*;
+
+use peer::message::extended::{Extension, Handshake};
+
+/// The set of extensions we support. Each extension is assigned a local id,
+/// starting at 1, since 0 is reserved for the extension handshake itself.
+#[derive(Debug, Default)]
+pub(crate) struct Registry {
+  extensions: Vec<Box<dyn Extension>>,
+}
+
+impl Registry {
+  pub(crate) fn register(&mut self, extension: impl Extension) -> u8 {
+    assert!(
+      self.local_id(extension.name()).is_none(),
+      "extension `{}` registered twice",
+      extension.name(),
+    );
+    self.extensions.push(Box::new(extension));
+    Self::id(self.extensions.len() - 1)
+  }
+
+  pub(crate) fn local_id(&self, name: &str) -> Option<u8> {
+    self
+      .extensions
+      .iter()
+      .position(|extension| extension.name() == name)
+      .map(Self::id)
+  }
+
+  fn id(index: usize) -> u8 {
+    u8::try_from(index + 1).invariant_unwrap("fewer than 256 extensions are registered")
+  }
+
+  /// Our extension handshake, advertising every registered extension.
+  pub(crate) fn handshake(&self) -> Handshake {
+    let mut handshake = Handshake::new();
+    for (i, extension) in self.extensions.iter().enumerate() {
+      handshake.with_message(extension.name().into(), Self::id(i));
+      extension.extend_handshake(&mut handshake);
+    }
+    handshake
+  }
+
+  /// The extension with local id `id`, if any.
+  pub(crate) fn get_mut(&mut self, id: u8) -> Option<&mut dyn Extension> {
+    let i = usize::from(id).checked_sub(1)?;
+    Some(self.extensions.get_mut(i)?.as_mut())
+  }
+
+  /// The registered extension of type `T`, if any.
+  pub(crate) fn find_mut<T: Extension>(&mut self) -> Option<&mut T> {
+    self
+      .extensions
+      .iter_mut()
+      .find_map(|extension| (extension.as_mut() as &mut dyn Any).downcast_mut())
+  }
+
+  pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = &mut dyn Extension> {
+    self.extensions.iter_mut().map(AsMut::as_mut)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use peer::message::extended::Sender;
+
+  #[derive(Debug)]
+  struct Dummy(&'static str);
+
+  impl Extension for Dummy {
+    fn name(&self) -> &'static str {
+      self.0
+    }
+
+    fn extend_handshake(&self, handshake: &mut Handshake) {
+      handshake.request_queue_size = Some(self.0.len().into_u64());
+    }
+
+    fn handle_message(&mut self, _payload: &[u8], _sender: &mut Sender) -> Result<()> {
+      Ok(())
+    }
+  }
+
+  #[test]
+  fn ids() {
+    let mut registry = Registry::default();
+    assert_eq!(registry.register(Dummy("foo")), 1);
+    assert_eq!(registry.register(Dummy("quux")), 2);
+    assert_eq!(registry.local_id("foo"), Some(1));
+    assert_eq!(registry.local_id("quux"), Some(2));
+    assert_eq!(registry.local_id("bar"), None);
+    assert!(registry.get_mut(0).is_none());
+    assert_eq!(registry.get_mut(1).unwrap().name(), "foo");
+    assert_eq!(registry.get_mut(2).unwrap().name(), "quux");
+    assert!(registry.get_mut(3).is_none());
+  }
+
+  #[test]
+  #[should_panic(expected = "extension `foo` registered twice")]
+  fn duplicate() {
+    let mut registry = Registry::default();
+    registry.register(Dummy("foo"));
+    registry.register(Dummy("foo"));
+  }
+
+  #[test]
+  fn handshake() {
+    let mut registry = Registry::default();
+    registry.register(Dummy("foo"));
+    registry.register(Dummy("quux"));
+    let handshake = registry.handshake();
+    assert_eq!(
+      handshake.message_ids,
+      [("foo".to_owned(), 1), ("quux".to_owned(), 2)]
+        .into_iter()
+        .collect()
+    );
+    assert_eq!(handshake.request_queue_size, Some(4));
+  }
+
+  #[test]
+  fn find() {
+    let mut registry = Registry::default();
+    assert!(registry.find_mut::<Dummy>().is_none());
+    registry.register(Dummy("foo"));
+    assert_eq!(registry.find_mut::<Dummy>().unwrap().0, "foo");
+  }
+}
@@ -0,0 +1,56 @@
+use crate::common::*;
+
+use peer::message::{extended::Handshake, Message};
+
+/// Queues messages for a single extension. Peers choose their own ids for
+/// each extension, so outgoing messages are addressed using the id from the
+/// peer's extension handshake rather than our local id.
+pub(crate) struct Sender<'a> {
+  outbox: &'a mut Vec<Message>,
+  peer: Option<&'a Handshake>,
+  name: &'static str,
+}
+
+impl<'a> Sender<'a> {
+  pub(crate) fn new(
+    outbox: &'a mut Vec<Message>,
+    peer: Option<&'a Handshake>,
+    name: &'static str,
+  ) -> Self {
+    Self { outbox, peer, name }
+  }
+
+  /// The id the peer assigned to the extension. An id of zero means the
+  /// extension is disabled.
+  fn id(&self) -> Result<u8> {
+    let Some(peer) = self.peer else {
+      return Err(Error::PeerNoExtendedHandshake);
+    };
+
+    match peer.message_ids.get(self.name) {
+      Some(&id) if id != 0 => Ok(id),
+      _ => Err(Error::PeerExtensionNotSupported { name: self.name }),
+    }
+  }
+
+  pub(crate) fn supported(&self) -> bool {
+    self.id().is_ok()
+  }
+
+  pub(crate) fn send<T: Serialize>(&mut self, payload: T) -> Result<()> {
+    let msg = Message::new_extended(self.id()?, payload)?;
+    self.outbox.push(msg);
+    Ok(())
+  }
+
+  /// Send `payload` followed by `trailer`, which is not bencoded.
+  pub(crate) fn send_with_trailer<T: Serialize>(
+    &mut self,
+    payload: T,
+    trailer: &[u8],
+  ) -> Result<()> {
+    let msg = Message::new_extended_with_trailer(self.id()?, payload, trailer)?;
+    self.outbox.push(msg);
+    Ok(())
+  }
+}
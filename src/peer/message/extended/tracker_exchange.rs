@@ -0,0 +1,215 @@
+use crate::common::*;
+
+use peer::message::extended::{Extension, Sender};
+use peer::message::Message;
+
+/// BEP 28 tracker exchange, over the `lt_tex` extension. Collects the tracker
+/// URLs that a peer tells us about, up to `MAX_TRACKERS`, ignoring those that
+/// name hosts on our own network.
+#[derive(Debug, Default)]
+pub(crate) struct TrackerExchange {
+  trackers: Vec<Url>,
+  received: bool,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub(crate) struct LtTex {
+  #[serde(default)]
+  pub(crate) added: Vec<String>,
+}
+
+impl TrackerExchange {
+  pub(crate) const NAME: &'static str = "lt_tex";
+
+  /// The most trackers kept from a single peer.
+  pub(crate) const MAX_TRACKERS: usize = 20;
+
+  /// Trackers the peer has told us about, in the order they were received.
+  pub(crate) fn trackers(&self) -> &[Url] {
+    &self.trackers
+  }
+
+  /// Whether the peer has sent us a tracker list.
+  pub(crate) fn received(&self) -> bool {
+    self.received
+  }
+
+  // Whether `url`'s host may be on the public internet. Hosts given by name
+  // are checked again once they have been resolved.
+  fn is_public(url: &Url) -> bool {
+    let Some(host) = url.host_str() else {
+      return false;
+    };
+
+    // Hosts in URLs with schemes like `udp` aren't parsed, so addresses are
+    // parsed here.
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+
+    if let Ok(ip) = host.parse::<IpAddr>() {
+      return is_public(ip);
+    }
+
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+
+    host != "localhost" && !host.ends_with(".localhost")
+  }
+}
+
+impl Extension for TrackerExchange {
+  fn name(&self) -> &'static str {
+    Self::NAME
+  }
+
+  fn handle_message(&mut self, payload: &[u8], _sender: &mut Sender) -> Result<()> {
+    let msg: LtTex = Message::from_bencode(payload)?;
+
+    self.received = true;
+
+    // Peers may send anything, so only keep URLs that could be trackers.
+    for tracker in msg.added {
+      let Ok(url) = Url::parse(&tracker) else {
+        continue;
+      };
+
+      if self.trackers.len() >= Self::MAX_TRACKERS {
+        break;
+      }
+
+      if matches!(url.scheme(), "http" | "https" | "udp")
+        && Self::is_public(&url)
+        && !self.trackers.contains(&url)
+      {
+        self.trackers.push(url);
+      }
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn handle(tex: &mut TrackerExchange, msg: &LtTex) -> Result<()> {
+    let mut outbox = Vec::new();
+    let mut sender = Sender::new(&mut outbox, None, TrackerExchange::NAME);
+    tex.handle_message(&Message::bencode(msg).unwrap(), &mut sender)?;
+    assert!(outbox.is_empty());
+    Ok(())
+  }
+
+  #[test]
+  fn bencode() {
+    let msg = LtTex {
+      added: vec!["udp://foo:1337".into()],
+    };
+    let bytes = Message::bencode(&msg).unwrap();
+    assert_eq!(bytes, b"d5:addedl14:udp://foo:1337ee");
+    assert_eq!(Message::from_bencode::<LtTex>(&bytes).unwrap(), msg);
+    assert_eq!(
+      Message::from_bencode::<LtTex>(b"de").unwrap(),
+      LtTex { added: Vec::new() }
+    );
+  }
+
+  #[test]
+  fn collects_trackers() {
+    let mut tex = TrackerExchange::default();
+    assert!(!tex.received());
+
+    handle(
+      &mut tex,
+      &LtTex {
+        added: vec![
+          "udp://foo:1337".into(),
+          "http://bar/announce".into(),
+          "not a url".into(),
+          "file:///etc/passwd".into(),
+          "udp://foo:1337".into(),
+        ],
+      },
+    )
+    .unwrap();
+
+    handle(
+      &mut tex,
+      &LtTex {
+        added: vec!["https://baz/announce".into(), "http://bar/announce".into()],
+      },
+    )
+    .unwrap();
+
+    assert!(tex.received());
+    assert_eq!(
+      tex.trackers(),
+      [
+        Url::parse("udp://foo:1337").unwrap(),
+        Url::parse("http://bar/announce").unwrap(),
+        Url::parse("https://baz/announce").unwrap(),
+      ]
+    );
+  }
+
+  #[test]
+  fn ignores_local_trackers() {
+    let mut tex = TrackerExchange::default();
+
+    handle(
+      &mut tex,
+      &LtTex {
+        added: vec![
+          "udp://127.0.0.1:1337".into(),
+          "http://192.168.1.1/announce".into(),
+          "http://169.254.169.254/latest".into(),
+          "udp://[::1]:1337".into(),
+          "udp://[fe80::1]:1337".into(),
+          "http://localhost/announce".into(),
+          "http://foo.localhost./announce".into(),
+          "udp://1.2.3.4:1337".into(),
+        ],
+      },
+    )
+    .unwrap();
+
+    assert_eq!(tex.trackers(), [Url::parse("udp://1.2.3.4:1337").unwrap()]);
+  }
+
+  #[test]
+  fn max_trackers() {
+    let mut tex = TrackerExchange::default();
+
+    handle(
+      &mut tex,
+      &LtTex {
+        added: (0..100)
+          .map(|i| format!("udp://tracker{i}.example.com:1337"))
+          .collect(),
+      },
+    )
+    .unwrap();
+
+    handle(
+      &mut tex,
+      &LtTex {
+        added: vec!["udp://more.example.com:1337".into()],
+      },
+    )
+    .unwrap();
+
+    assert_eq!(tex.trackers().len(), TrackerExchange::MAX_TRACKERS);
+    assert_eq!(
+      tex.trackers()[0],
+      Url::parse("udp://tracker0.example.com:1337").unwrap()
+    );
+  }
+
+  #[test]
+  fn malformed() {
+    let mut tex = TrackerExchange::default();
+    let mut outbox = Vec::new();
+    let mut sender = Sender::new(&mut outbox, None, TrackerExchange::NAME);
+    assert!(tex.handle_message(b"i1e", &mut sender).is_err());
+    assert!(!tex.received());
+  }
+}
@@ -31,7 +31,6 @@ impl UtMetadata {
     }
   }
 
-  #[cfg(test)]
   pub(crate) fn data(piece: usize, total_size: usize) -> Self {
     Self {
       msg_type: MsgType::Data.into(),
@@ -39,6 +38,14 @@ impl UtMetadata {
       total_size: Some(total_size),
     }
   }
+
+  pub(crate) fn reject(piece: usize) -> Self {
+    Self {
+      msg_type: MsgType::Reject.into(),
+      piece,
+      total_size: None,
+    }
+  }
 }
 
 impl From<MsgType> for u8 {
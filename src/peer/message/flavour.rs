@@ -1,3 +1,5 @@
+// Message ids from BEP 3 (`Choke` through `Port`), BEP 6 (`Suggest` through
+// `AllowedFast`), and BEP 10 (`Extended`).
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub(crate) enum Flavour {
   Choke = 0,
@@ -9,30 +11,39 @@ pub(crate) enum Flavour {
   Request,
   Piece,
   Cancel,
+  Port,
 
-  HaveAll = 14,
-  HaveNone = 15,
-  Extended = 20,
+  Suggest = 13,
+  HaveAll,
+  HaveNone,
+  Reject,
+  AllowedFast,
 
-  Bad = 255,
+  Extended = 20,
 }
 
-impl From<u8> for Flavour {
-  fn from(i: u8) -> Self {
+impl TryFrom<u8> for Flavour {
+  type Error = u8;
+
+  fn try_from(i: u8) -> Result<Self, u8> {
     match i {
-      0x00 => Flavour::Choke,
-      0x01 => Flavour::Unchoke,
-      0x02 => Flavour::Interested,
-      0x03 => Flavour::NotInterested,
-      0x04 => Flavour::Have,
-      0x05 => Flavour::Bitfield,
-      0x06 => Flavour::Request,
-      0x07 => Flavour::Piece,
-      0x08 => Flavour::Cancel,
-      0x0e => Flavour::HaveAll,
-      0x0f => Flavour::HaveNone,
-      0x14 => Flavour::Extended,
-      _ => Flavour::Bad,
+      0x00 => Ok(Flavour::Choke),
+      0x01 => Ok(Flavour::Unchoke),
+      0x02 => Ok(Flavour::Interested),
+      0x03 => Ok(Flavour::NotInterested),
+      0x04 => Ok(Flavour::Have),
+      0x05 => Ok(Flavour::Bitfield),
+      0x06 => Ok(Flavour::Request),
+      0x07 => Ok(Flavour::Piece),
+      0x08 => Ok(Flavour::Cancel),
+      0x09 => Ok(Flavour::Port),
+      0x0d => Ok(Flavour::Suggest),
+      0x0e => Ok(Flavour::HaveAll),
+      0x0f => Ok(Flavour::HaveNone),
+      0x10 => Ok(Flavour::Reject),
+      0x11 => Ok(Flavour::AllowedFast),
+      0x14 => Ok(Flavour::Extended),
+      _ => Err(i),
     }
   }
 }
@@ -49,10 +60,27 @@ impl From<Flavour> for u8 {
       Flavour::Request => 0x06,
       Flavour::Piece => 0x07,
       Flavour::Cancel => 0x08,
+      Flavour::Port => 0x09,
+      Flavour::Suggest => 0x0d,
       Flavour::HaveAll => 0x0e,
       Flavour::HaveNone => 0x0f,
+      Flavour::Reject => 0x10,
+      Flavour::AllowedFast => 0x11,
       Flavour::Extended => 0x14,
-      Flavour::Bad => 0xff,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn round_trip() {
+    for i in 0..=u8::MAX {
+      if let Ok(flavour) = Flavour::try_from(i) {
+        assert_eq!(u8::from(flavour), i);
+      }
     }
   }
 }
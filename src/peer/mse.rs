@@ -0,0 +1,525 @@
+use crate::common::*;
+
+use num_bigint::BigUint;
+use peer::handshake::HEADER;
+use peer::{Encryption, Rc4};
+
+// From BEP 8 and the MSE specification:
+//
+// 1 A->B: Diffie Hellman Ya, PadA
+// 2 B->A: Diffie Hellman Yb, PadB
+// 3 A->B: HASH('req1', S), HASH('req2', SKEY) xor HASH('req3', S),
+//         ENCRYPT(VC, crypto_provide, len(PadC), PadC, len(IA)), ENCRYPT(IA)
+// 4 B->A: ENCRYPT(VC, crypto_select, len(padD), padD), ENCRYPT2(Payload Stream)
+// 5 A->B: ENCRYPT2(Payload Stream)
+//
+// `S` is the shared Diffie-Hellman secret, `SKEY` is the infohash, and `VC`
+// is eight zero bytes, used to verify that both sides derived the same keys.
+
+const PRIME: &[u8; 96] = b"\
+  \xFF\xFF\xFF\xFF\xFF\xFF\xFF\xFF\xC9\x0F\xDA\xA2\x21\x68\xC2\x34\xC4\xC6\x62\x8B\x80\xDC\x1C\xD1\
+  \x29\x02\x4E\x08\x8A\x67\xCC\x74\x02\x0B\xBE\xA6\x3B\x13\x9B\x22\x51\x4A\x08\x79\x8E\x34\x04\xDD\
+  \xEF\x95\x19\xB3\xCD\x3A\x43\x1B\x30\x2B\x0A\x6D\xF2\x5F\x14\x37\x4F\xE1\x35\x6D\x6D\x51\xC2\x45\
+  \xE4\x85\xB5\x76\x62\x5E\x7E\xC6\xF4\x4C\x42\xE9\xA6\x3A\x36\x21\x00\x00\x00\x00\x00\x09\x05\x63";
+
+const GENERATOR: u32 = 2;
+
+const KEY_LENGTH: usize = 96;
+
+const MAX_PAD_LENGTH: usize = 512;
+
+const VC: [u8; 8] = [0; 8];
+
+const CRYPTO_PLAINTEXT: u32 = 0x01;
+const CRYPTO_RC4: u32 = 0x02;
+
+/// The ciphers used once an encrypted connection is established.
+#[derive(Debug)]
+pub(crate) struct Cipher {
+  pub(crate) encrypt: Rc4,
+  pub(crate) decrypt: Rc4,
+}
+
+/// The result of a successful encryption handshake.
+#[derive(Debug)]
+pub(crate) struct Established {
+  /// Ciphers for the rest of the connection, or `None` if the peers agreed to
+  /// continue in plaintext.
+  pub(crate) cipher: Option<Cipher>,
+  /// Decrypted initial payload sent by the initiator, which is the start of
+  /// the `BitTorrent` stream.
+  pub(crate) payload: Vec<u8>,
+}
+
+#[derive(Debug)]
+enum State {
+  // Initiator
+  AwaitPeerKey,
+  AwaitVc { marker: [u8; 8] },
+  AwaitSelect,
+  AwaitPadD { length: usize, select: u32 },
+  // Receiver
+  AwaitInitiatorKey,
+  AwaitReq1 { marker: [u8; 20] },
+  AwaitReq2,
+  AwaitProvide,
+  AwaitPadC { length: usize, provide: u32 },
+  AwaitIa { length: usize, provide: u32 },
+}
+
+/// A message stream encryption handshake, independent of how bytes move over
+/// the wire. Bytes received from the peer are passed to `advance`, which
+/// consumes what it needs and produces bytes to send in response.
+#[derive(Debug)]
+pub(crate) struct Handshake {
+  infohash: Infohash,
+  encryption: Encryption,
+  private_key: BigUint,
+  secret: Option<[u8; KEY_LENGTH]>,
+  cipher: Option<Cipher>,
+  state: State,
+}
+
+impl Handshake {
+  /// Start a handshake on an outgoing connection, returning the handshake and
+  /// the bytes to send to the peer.
+  pub(crate) fn initiate(infohash: Infohash, encryption: Encryption) -> (Self, Vec<u8>) {
+    let handshake = Self::new(infohash, encryption, State::AwaitPeerKey);
+    let output = handshake.public_key_and_pad();
+    (handshake, output)
+  }
+
+  /// Respond to a handshake on an incoming connection.
+  pub(crate) fn respond(infohash: Infohash, encryption: Encryption) -> Self {
+    Self::new(infohash, encryption, State::AwaitInitiatorKey)
+  }
+
+  /// Whether `buf`, the start of an incoming connection, is a plaintext
+  /// `BitTorrent` handshake rather than the start of an encryption handshake.
+  pub(crate) fn is_plaintext(buf: &[u8]) -> bool {
+    buf.starts_with(HEADER)
+  }
+
+  fn new(infohash: Infohash, encryption: Encryption, state: State) -> Self {
+    let private_key: [u8; 20] = rand::rng().random();
+
+    Self {
+      infohash,
+      encryption,
+      private_key: BigUint::from_bytes_be(&private_key),
+      secret: None,
+      cipher: None,
+      state,
+    }
+  }
+
+  fn public_key_and_pad(&self) -> Vec<u8> {
+    let public_key = BigUint::from(GENERATOR).modpow(&self.private_key, &Self::prime());
+    let mut output = Self::pad_key(&public_key).to_vec();
+    output.extend(Self::random_pad());
+    output
+  }
+
+  fn prime() -> BigUint {
+    BigUint::from_bytes_be(PRIME)
+  }
+
+  fn pad_key(key: &BigUint) -> [u8; KEY_LENGTH] {
+    let bytes = key.to_bytes_be();
+    let mut padded = [0; KEY_LENGTH];
+    padded[KEY_LENGTH - bytes.len()..].copy_from_slice(&bytes);
+    padded
+  }
+
+  fn random_pad() -> Vec<u8> {
+    let mut rng = rand::rng();
+    let length = rng.random_range(0..=MAX_PAD_LENGTH);
+    (0..length).map(|_| rng.random()).collect()
+  }
+
+  fn hash(parts: &[&[u8]]) -> [u8; 20] {
+    let mut sha1 = Sha1::new();
+    for part in parts {
+      sha1.update(part);
+    }
+    sha1.digest().bytes()
+  }
+
+  fn compute_secret(&mut self, peer_key: &[u8]) -> [u8; KEY_LENGTH] {
+    let peer_key = BigUint::from_bytes_be(peer_key);
+    let secret = Self::pad_key(&peer_key.modpow(&self.private_key, &Self::prime()));
+    self.secret = Some(secret);
+    secret
+  }
+
+  // The initiator encrypts with `keyA` and the receiver with `keyB`.
+  fn ciphers(&self, initiator: bool) -> Cipher {
+    let secret = self
+      .secret
+      .invariant_unwrap("secret is computed before ciphers");
+    let infohash = <[u8; 20]>::from(self.infohash);
+    let key_a = Rc4::new(&Self::hash(&[b"keyA", &secret, &infohash]));
+    let key_b = Rc4::new(&Self::hash(&[b"keyB", &secret, &infohash]));
+
+    if initiator {
+      Cipher {
+        encrypt: key_a,
+        decrypt: key_b,
+      }
+    } else {
+      Cipher {
+        encrypt: key_b,
+        decrypt: key_a,
+      }
+    }
+  }
+
+  fn cipher(&mut self) -> &mut Cipher {
+    self
+      .cipher
+      .as_mut()
+      .invariant_unwrap("cipher is created before it is used")
+  }
+
+  // Remove and decrypt the first `n` bytes of `input`, if they have arrived.
+  fn take(&mut self, input: &mut Vec<u8>, n: usize) -> Option<Vec<u8>> {
+    if input.len() < n {
+      return None;
+    }
+
+    let mut buf = input.drain(..n).collect::<Vec<u8>>();
+    self.cipher().decrypt.apply(&mut buf);
+    Some(buf)
+  }
+
+  // Find `marker` within the first `MAX_PAD_LENGTH` bytes of `input`, and
+  // remove everything up to and including it. Returns `false` if more bytes
+  // are needed.
+  fn sync(input: &mut Vec<u8>, marker: &[u8]) -> Result<bool> {
+    if let Some(i) = input
+      .windows(marker.len())
+      .position(|window| window == marker)
+    {
+      input.drain(..i + marker.len());
+      return Ok(true);
+    }
+
+    if input.len() >= MAX_PAD_LENGTH + marker.len() {
+      return Err(Error::PeerEncryptionSync);
+    }
+
+    Ok(false)
+  }
+
+  fn read_u16(buf: &[u8]) -> usize {
+    u16::from_be_bytes([buf[0], buf[1]]).into()
+  }
+
+  fn read_pad_length(buf: &[u8]) -> Result<usize> {
+    let length = Self::read_u16(buf);
+
+    if length > MAX_PAD_LENGTH {
+      return Err(Error::PeerEncryptionPadding { length });
+    }
+
+    Ok(length)
+  }
+
+  fn provide(&self) -> u32 {
+    match self.encryption {
+      Encryption::Require => CRYPTO_RC4,
+      Encryption::Prefer | Encryption::Disable => CRYPTO_RC4 | CRYPTO_PLAINTEXT,
+    }
+  }
+
+  fn select(&self, provide: u32) -> Result<u32> {
+    if provide & CRYPTO_RC4 > 0 {
+      Ok(CRYPTO_RC4)
+    } else if provide & CRYPTO_PLAINTEXT > 0 && self.encryption != Encryption::Require {
+      Ok(CRYPTO_PLAINTEXT)
+    } else {
+      Err(Error::PeerEncryptionMethod)
+    }
+  }
+
+  /// Consume bytes received from the peer from the front of `input`, and
+  /// append bytes to send to `output`. Returns the established connection
+  /// once the handshake is complete, after which any bytes remaining in
+  /// `input` belong to the `BitTorrent` stream, still encrypted if a cipher was
+  /// selected.
+  pub(crate) fn advance(
+    &mut self,
+    input: &mut Vec<u8>,
+    output: &mut Vec<u8>,
+  ) -> Result<Option<Established>> {
+    loop {
+      match self.state {
+        State::AwaitPeerKey => {
+          if input.len() < KEY_LENGTH {
+            return Ok(None);
+          }
+
+          let peer_key = input.drain(..KEY_LENGTH).collect::<Vec<u8>>();
+          let secret = self.compute_secret(&peer_key);
+          let infohash = <[u8; 20]>::from(self.infohash);
+
+          output.extend(Self::hash(&[b"req1", &secret]));
+
+          let req2 = Self::hash(&[b"req2", &infohash]);
+          let req3 = Self::hash(&[b"req3", &secret]);
+          output.extend(req2.iter().zip(req3).map(|(a, b)| a ^ b));
+
+          let mut cipher = self.ciphers(true);
+
+          // We send no padding and no initial payload, so the BitTorrent
+          // handshake follows once the handshake completes.
+          let mut buf = VC.to_vec();
+          buf.extend(self.provide().to_be_bytes());
+          buf.extend(0u16.to_be_bytes());
+          buf.extend(0u16.to_be_bytes());
+          cipher.encrypt.apply(&mut buf);
+          output.extend(buf);
+
+          // The receiver's first encrypted bytes are `VC`, so they can be
+          // predicted and used to find the end of its padding.
+          let mut marker = VC;
+          cipher.decrypt.clone().apply(&mut marker);
+
+          self.cipher = Some(cipher);
+          self.state = State::AwaitVc { marker };
+        }
+        State::AwaitVc { marker } => {
+          if !Self::sync(input, &marker)? {
+            return Ok(None);
+          }
+
+          let mut vc = VC;
+          self.cipher().decrypt.apply(&mut vc);
+          self.state = State::AwaitSelect;
+        }
+        State::AwaitSelect => {
+          let Some(buf) = self.take(input, 6) else {
+            return Ok(None);
+          };
+
+          let select = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]);
+
+          if select.count_ones() != 1 || select & self.provide() == 0 {
+            return Err(Error::PeerEncryptionMethod);
+          }
+
+          self.state = State::AwaitPadD {
+            length: Self::read_pad_length(&buf[4..])?,
+            select,
+          };
+        }
+        State::AwaitPadD { length, select } => {
+          if self.take(input, length).is_none() {
+            return Ok(None);
+          }
+
+          return Ok(Some(self.establish(select, Vec::new())));
+        }
+        State::AwaitInitiatorKey => {
+          if input.len() < KEY_LENGTH {
+            return Ok(None);
+          }
+
+          let peer_key = input.drain(..KEY_LENGTH).collect::<Vec<u8>>();
+          let secret = self.compute_secret(&peer_key);
+
+          output.extend(self.public_key_and_pad());
+
+          self.state = State::AwaitReq1 {
+            marker: Self::hash(&[b"req1", &secret]),
+          };
+        }
+        State::AwaitReq1 { marker } => {
+          if !Self::sync(input, &marker)? {
+            return Ok(None);
+          }
+
+          self.state = State::AwaitReq2;
+        }
+        State::AwaitReq2 => {
+          if input.len() < 20 {
+            return Ok(None);
+          }
+
+          let secret = self
+            .secret
+            .invariant_unwrap("secret is computed before req2");
+          let req3 = Self::hash(&[b"req3", &secret]);
+          let req2 = input
+            .drain(..20)
+            .zip(req3)
+            .map(|(a, b)| a ^ b)
+            .collect::<Vec<u8>>();
+
+          if req2 != Self::hash(&[b"req2", &<[u8; 20]>::from(self.infohash)]) {
+            return Err(Error::PeerEncryptionInfohash);
+          }
+
+          self.cipher = Some(self.ciphers(false));
+          self.state = State::AwaitProvide;
+        }
+        State::AwaitProvide => {
+          let Some(buf) = self.take(input, 14) else {
+            return Ok(None);
+          };
+
+          if buf[..8] != VC {
+            return Err(Error::PeerEncryptionSync);
+          }
+
+          self.state = State::AwaitPadC {
+            provide: u32::from_be_bytes([buf[8], buf[9], buf[10], buf[11]]),
+            length: Self::read_pad_length(&buf[12..])?,
+          };
+        }
+        State::AwaitPadC { length, provide } => {
+          let Some(buf) = self.take(input, length + 2) else {
+            return Ok(None);
+          };
+
+          self.state = State::AwaitIa {
+            length: Self::read_u16(&buf[length..]),
+            provide,
+          };
+        }
+        State::AwaitIa { length, provide } => {
+          let Some(payload) = self.take(input, length) else {
+            return Ok(None);
+          };
+
+          let select = self.select(provide)?;
+
+          let mut buf = VC.to_vec();
+          buf.extend(select.to_be_bytes());
+          buf.extend(0u16.to_be_bytes());
+          self.cipher().encrypt.apply(&mut buf);
+          output.extend(buf);
+
+          return Ok(Some(self.establish(select, payload)));
+        }
+      }
+    }
+  }
+
+  fn establish(&mut self, select: u32, payload: Vec<u8>) -> Established {
+    Established {
+      cipher: if select == CRYPTO_RC4 {
+        self.cipher.take()
+      } else {
+        None
+      },
+      payload,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn infohash() -> Infohash {
+    Infohash::from([7; 20])
+  }
+
+  // Run a handshake between an initiator and a receiver, passing bytes in
+  // uneven chunks to exercise partial reads. Stops at the first error.
+  fn handshake(
+    initiator: Encryption,
+    receiver: Encryption,
+    receiver_infohash: Infohash,
+  ) -> (Option<Result<Established>>, Option<Result<Established>>) {
+    let (mut a, mut a_to_b) = Handshake::initiate(infohash(), initiator);
+    let mut b = Handshake::respond(receiver_infohash, receiver);
+    let mut b_to_a = Vec::new();
+
+    let mut a_input = Vec::new();
+    let mut b_input = Vec::new();
+    let mut a_done = None;
+    let mut b_done = None;
+
+    while a_done.is_none() || b_done.is_none() {
+      let n = a_to_b.len().min(7);
+      b_input.extend(a_to_b.drain(..n));
+      if b_done.is_none() {
+        match b.advance(&mut b_input, &mut b_to_a) {
+          Ok(Some(established)) => b_done = Some(Ok(established)),
+          Ok(None) => {}
+          Err(err) => return (a_done, Some(Err(err))),
+        }
+      }
+
+      let n = b_to_a.len().min(11);
+      a_input.extend(b_to_a.drain(..n));
+      if a_done.is_none() {
+        match a.advance(&mut a_input, &mut a_to_b) {
+          Ok(Some(established)) => a_done = Some(Ok(established)),
+          Ok(None) => {}
+          Err(err) => return (Some(Err(err)), b_done),
+        }
+      }
+    }
+
+    (a_done, b_done)
+  }
+
+  #[test]
+  fn rc4() {
+    let (a, b) = handshake(Encryption::Prefer, Encryption::Prefer, infohash());
+    let mut a = a.unwrap().unwrap().cipher.unwrap();
+    let mut b = b.unwrap().unwrap().cipher.unwrap();
+
+    let mut buf = *b"hello";
+    a.encrypt.apply(&mut buf);
+    assert_ne!(&buf, b"hello");
+    b.decrypt.apply(&mut buf);
+    assert_eq!(&buf, b"hello");
+
+    let mut buf = *b"goodbye";
+    b.encrypt.apply(&mut buf);
+    a.decrypt.apply(&mut buf);
+    assert_eq!(&buf, b"goodbye");
+  }
+
+  #[test]
+  fn wrong_infohash() {
+    let (_, b) = handshake(
+      Encryption::Prefer,
+      Encryption::Prefer,
+      Infohash::from([8; 20]),
+    );
+    assert_matches!(b, Some(Err(Error::PeerEncryptionInfohash)));
+  }
+
+  #[test]
+  fn select() {
+    let b = Handshake::respond(infohash(), Encryption::Prefer);
+    assert_eq!(b.select(CRYPTO_PLAINTEXT | CRYPTO_RC4).unwrap(), CRYPTO_RC4);
+    assert_eq!(b.select(CRYPTO_PLAINTEXT).unwrap(), CRYPTO_PLAINTEXT);
+    assert_matches!(b.select(0), Err(Error::PeerEncryptionMethod));
+
+    let b = Handshake::respond(infohash(), Encryption::Require);
+    assert_matches!(b.select(CRYPTO_PLAINTEXT), Err(Error::PeerEncryptionMethod));
+  }
+
+  #[test]
+  fn sync_gives_up() {
+    let mut input = vec![0; MAX_PAD_LENGTH + 19];
+    assert!(!Handshake::sync(&mut input, &[1; 20]).unwrap());
+    input.push(0);
+    assert_matches!(
+      Handshake::sync(&mut input, &[1; 20]),
+      Err(Error::PeerEncryptionSync)
+    );
+  }
+
+  #[test]
+  fn is_plaintext() {
+    assert!(Handshake::is_plaintext(HEADER));
+    assert!(!Handshake::is_plaintext(&[0; 20]));
+  }
+}
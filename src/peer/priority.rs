@@ -0,0 +1,143 @@
+use crate::common::*;
+
+/// The BEP 40 canonical priority of a connection between `a` and `b`. Both
+/// ends of a connection compute the same priority, and peers that prefer
+/// connections with higher priority form a well-connected swarm rather than
+/// clusters. Returns `None` if the addresses aren't in the same family.
+pub(crate) fn priority(a: SocketAddr, b: SocketAddr) -> Option<u32> {
+  if a.ip() == b.ip() {
+    let (low, high) = sort(a.port(), b.port());
+    return Some(crc32c(&[low.to_be_bytes(), high.to_be_bytes()].concat()));
+  }
+
+  let (a, b) = match (a.ip(), b.ip()) {
+    (IpAddr::V4(a), IpAddr::V4(b)) => mask(&a.octets(), &b.octets(), 2),
+    (IpAddr::V6(a), IpAddr::V6(b)) => mask(&a.octets(), &b.octets(), 6),
+    _ => return None,
+  };
+
+  let (low, high) = sort(a, b);
+
+  Some(crc32c(&[low, high].concat()))
+}
+
+// Mask both addresses, leaving the first `prefix` bytes unmasked. If the
+// addresses share those bytes, the next byte is also left unmasked, and if
+// they share that byte too, so is the one after it.
+fn mask(a: &[u8], b: &[u8], prefix: usize) -> (Vec<u8>, Vec<u8>) {
+  let shared = a.iter().zip(b).take_while(|(a, b)| a == b).count();
+
+  let unmasked = prefix + shared.saturating_sub(prefix - 1).min(2);
+
+  let apply = |address: &[u8]| {
+    address
+      .iter()
+      .enumerate()
+      .map(|(i, byte)| if i < unmasked { *byte } else { byte & 0x55 })
+      .collect()
+  };
+
+  (apply(a), apply(b))
+}
+
+fn sort<T: Ord>(a: T, b: T) -> (T, T) {
+  if a <= b {
+    (a, b)
+  } else {
+    (b, a)
+  }
+}
+
+// CRC-32C, using the Castagnoli polynomial.
+fn crc32c(bytes: &[u8]) -> u32 {
+  const POLYNOMIAL: u32 = 0x82f6_3b78;
+
+  let mut crc = !0;
+
+  for byte in bytes {
+    crc ^= u32::from(*byte);
+    for _ in 0..8 {
+      crc = if crc & 1 == 1 {
+        (crc >> 1) ^ POLYNOMIAL
+      } else {
+        crc >> 1
+      };
+    }
+  }
+
+  !crc
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn addr(s: &str) -> SocketAddr {
+    s.parse().unwrap()
+  }
+
+  #[test]
+  fn crc32c_check_value() {
+    assert_eq!(crc32c(b"123456789"), 0xe306_9283);
+  }
+
+  #[test]
+  fn different_subnets() {
+    assert_eq!(
+      priority(addr("123.213.32.10:0"), addr("98.76.54.32:0")),
+      Some(0xec2d_7224)
+    );
+  }
+
+  #[test]
+  fn same_subnet() {
+    assert_eq!(
+      priority(addr("123.213.32.10:0"), addr("123.213.32.234:0")),
+      Some(0x9956_8189)
+    );
+  }
+
+  #[test]
+  fn symmetric() {
+    for (a, b) in [
+      ("1.2.3.4:5", "6.7.8.9:10"),
+      ("1.2.3.4:5", "1.2.8.9:10"),
+      ("1.2.3.4:5", "1.2.3.9:10"),
+      ("1.2.3.4:5", "1.2.3.4:10"),
+      ("[2001:db8::1]:1", "[2001:db9::1]:2"),
+      ("[2001:db8::1]:1", "[2001:db8::2]:2"),
+    ] {
+      assert_eq!(priority(addr(a), addr(b)), priority(addr(b), addr(a)));
+      assert!(priority(addr(a), addr(b)).is_some());
+    }
+  }
+
+  #[test]
+  fn ports() {
+    assert_eq!(
+      priority(addr("1.2.3.4:1"), addr("1.2.3.4:2")),
+      Some(crc32c(&[0, 1, 0, 2]))
+    );
+  }
+
+  #[test]
+  fn masks() {
+    assert_eq!(
+      mask(&[1, 2, 3, 4], &[5, 6, 7, 8], 2),
+      (vec![1, 2, 1, 4], vec![5, 6, 5, 0])
+    );
+    assert_eq!(
+      mask(&[1, 2, 3, 4], &[1, 2, 7, 8], 2),
+      (vec![1, 2, 3, 4], vec![1, 2, 7, 0])
+    );
+    assert_eq!(
+      mask(&[1, 2, 3, 4], &[1, 2, 3, 8], 2),
+      (vec![1, 2, 3, 4], vec![1, 2, 3, 8])
+    );
+  }
+
+  #[test]
+  fn mixed_families() {
+    assert_eq!(priority(addr("1.2.3.4:1"), addr("[::1]:1")), None);
+  }
+}
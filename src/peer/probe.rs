@@ -0,0 +1,601 @@
+use crate::common::*;
+
+use peer::handshake::{self, Handshake};
+use peer::message::{extended, Message};
+use peer::{Encryption, Limits, Session, Wire};
+
+/// Connects to a peer and records what it tells us about itself: its peer id,
+/// reserved bits, extension handshake, and which pieces it has. Driven by a
+/// `Reactor`.
+#[derive(Debug)]
+pub(crate) struct Probe {
+  wire: Wire,
+  session: Session,
+  piece_count: usize,
+  handshake: Option<Handshake>,
+  pieces: Option<Vec<bool>>,
+  settle: Option<Instant>,
+  exchange_trackers: bool,
+}
+
+/// Everything a probed peer told us about itself.
+#[derive(Debug)]
+pub(crate) struct Report {
+  pub(crate) addr: SocketAddr,
+  pub(crate) handshake: Handshake,
+  pub(crate) extension_handshake: Option<extended::Handshake>,
+  /// Which pieces the peer has, or `None` if it never said.
+  pub(crate) pieces: Option<Vec<bool>>,
+  /// Trackers the peer told us about with BEP 28 tracker exchange.
+  pub(crate) trackers: Vec<Url>,
+}
+
+impl Probe {
+  // Peers with no pieces may send no bitfield at all, and peers may ignore the
+  // extension protocol despite advertising it, so stop waiting after this long.
+  const SETTLE: Duration = Duration::from_secs(5);
+
+  // We also advertise the fast extension, so that peers that support it send
+  // `HaveAll` or `HaveNone` instead of leaving us to guess.
+  const RESERVED_BYTES: [u8; 8] = [
+    0,
+    0,
+    0,
+    0,
+    0,
+    handshake::SUPPORTS_EXTENSION_PROTOCOL,
+    0,
+    handshake::SUPPORTS_FAST_EXTENSION,
+  ];
+
+  pub(crate) fn new(
+    addr: SocketAddr,
+    infohash: Infohash,
+    piece_count: usize,
+    limits: Limits,
+    encryption: Encryption,
+  ) -> Self {
+    Self::with_wire(
+      Wire::new(addr, infohash, Self::RESERVED_BYTES, limits, encryption),
+      piece_count,
+    )
+  }
+
+  fn with_wire(wire: Wire, piece_count: usize) -> Self {
+    Self {
+      session: Session::new(wire.infohash(), wire.limits()),
+      wire,
+      piece_count,
+      handshake: None,
+      pieces: None,
+      settle: None,
+      exchange_trackers: false,
+    }
+  }
+
+  /// Also wait for the peer's tracker list, if it supports tracker exchange.
+  pub(crate) fn exchanging_trackers(self) -> Self {
+    Self {
+      exchange_trackers: true,
+      ..self
+    }
+  }
+
+  /// Connect through `proxy`, if any.
+  pub(crate) fn via(self, proxy: Option<&Proxy>) -> Self {
+    Self {
+      wire: self.wire.via(proxy),
+      ..self
+    }
+  }
+
+  /// Try connecting over uTP before TCP, if `utp` is set.
+  pub(crate) fn with_utp(self, utp: bool) -> Self {
+    Self {
+      wire: self.wire.with_utp(utp),
+      ..self
+    }
+  }
+
+  /// A retry of a probe that failed with `error`, over TCP if a uTP
+  /// connection couldn't be opened, or in plaintext if the peer doesn't seem
+  /// to support encryption.
+  pub(crate) fn fallback(&self, error: &Error) -> Option<Self> {
+    self.wire.fallback(error).map(|wire| Self {
+      exchange_trackers: self.exchange_trackers,
+      ..Self::with_wire(wire, self.piece_count)
+    })
+  }
+
+  pub(crate) fn addr(&self) -> SocketAddr {
+    self.wire.addr()
+  }
+
+  fn queue_outbox(&mut self) -> Result<()> {
+    for msg in self.session.outbox() {
+      self.wire.send(&msg)?;
+    }
+    Ok(())
+  }
+
+  fn handle_handshake(&mut self, handshake: Handshake, now: Instant) -> Result<()> {
+    // Peers that negotiate the fast extension expect us to say what we have
+    // before anything else.
+    if handshake.supports_fast_extension() {
+      self.wire.send(&Message::HaveNone)?;
+    }
+
+    if handshake.supports_extension_protocol() {
+      self.session.send_extension_handshake()?;
+      self.queue_outbox()?;
+    }
+
+    self.handshake = Some(handshake);
+    self.settle = Some(now + Self::SETTLE);
+
+    Ok(())
+  }
+
+  fn handle_msg(&mut self, msg: &Message) -> Result<()> {
+    match msg {
+      Message::Bitfield { bitfield } => {
+        let expected = self.piece_count.div_ceil(8);
+
+        if bitfield.len() != expected {
+          return Err(Error::PeerBitfieldLength {
+            length: bitfield.len(),
+            expected,
+          });
+        }
+
+        self.pieces = Some(
+          (0..self.piece_count)
+            .map(|i| bitfield[i / 8] & (0x80 >> (i % 8)) > 0)
+            .collect(),
+        );
+      }
+      Message::HaveAll => self.pieces = Some(vec![true; self.piece_count]),
+      Message::HaveNone => self.pieces = Some(vec![false; self.piece_count]),
+      Message::Have { index } => {
+        let piece_count = self.piece_count;
+        let pieces = self.pieces.get_or_insert_with(|| vec![false; piece_count]);
+
+        // Out of range indices are ignored, since they tell us nothing.
+        if let Some(have) = pieces.get_mut(index.into_usize()) {
+          *have = true;
+        }
+      }
+      _ => {}
+    }
+
+    self.session.handle_msg(msg)?;
+    self.queue_outbox()
+  }
+
+  // Whether we've heard everything we're waiting for.
+  fn complete(&mut self) -> bool {
+    let Some(handshake) = &self.handshake else {
+      return false;
+    };
+
+    if self.pieces.is_none() {
+      return false;
+    }
+
+    if !handshake.supports_extension_protocol() {
+      return true;
+    }
+
+    let Some(extension_handshake) = &self.session.extension_handshake else {
+      return false;
+    };
+
+    let supports_tracker_exchange = extension_handshake
+      .message_ids
+      .get(extended::TrackerExchange::NAME)
+      .is_some_and(|&id| id != 0);
+
+    !(self.exchange_trackers
+      && supports_tracker_exchange
+      && !self.session.tracker_exchange().received())
+  }
+
+  fn report(&mut self) -> Option<Report> {
+    Some(Report {
+      addr: self.wire.addr(),
+      handshake: self.handshake.take()?,
+      extension_handshake: self.session.extension_handshake.take(),
+      pieces: self.pieces.take(),
+      trackers: self.session.tracker_exchange().trackers().to_vec(),
+    })
+  }
+}
+
+impl Task for Probe {
+  type Output = Report;
+
+  fn start(&mut self, now: Instant) -> Result<()> {
+    self.wire.start(now)
+  }
+
+  fn source(&mut self) -> &mut dyn mio::event::Source {
+    self.wire.source()
+  }
+
+  fn interest(&self) -> mio::Interest {
+    self.wire.interest()
+  }
+
+  fn wakeup(&self) -> Option<Instant> {
+    self.wire.wakeup().into_iter().chain(self.settle).min()
+  }
+
+  fn advance(&mut self, now: Instant) -> Result<Option<Report>> {
+    if self.settle.is_some_and(|settle| now >= settle) {
+      return Ok(self.report());
+    }
+
+    if !self.wire.pump(now)? {
+      return Ok(None);
+    }
+
+    if let Some(handshake) = self.wire.recv_handshake()? {
+      self.handle_handshake(handshake, now)?;
+    }
+
+    while let Some(msg) = self.wire.recv()? {
+      self.handle_msg(&msg)?;
+    }
+
+    if self.complete() || (self.wire.eof() && self.handshake.is_some()) {
+      return Ok(self.report());
+    }
+
+    if self.wire.eof() {
+      return Err(Error::Network {
+        source: io::ErrorKind::UnexpectedEof.into(),
+      });
+    }
+
+    self.wire.flush()?;
+
+    Ok(None)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use peer::connection::Connection;
+
+  use std::sync::mpsc;
+
+  fn probe(piece_count: usize, peer: impl FnOnce(Connection) + Send + 'static) -> Result<Report> {
+    probe_with(piece_count, false, peer)
+  }
+
+  fn probe_with(
+    piece_count: usize,
+    exchange_trackers: bool,
+    peer: impl FnOnce(Connection) + Send + 'static,
+  ) -> Result<Report> {
+    let infohash = Infohash::from([0; 20]);
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    thread::spawn(move || {
+      let (stream, _) = listener.accept().unwrap();
+      let conn = Connection::accept(
+        stream.into(),
+        infohash,
+        handshake::IMDL_SEEDER_RESERVED_BYTES,
+        Encryption::default(),
+      )
+      .unwrap();
+      peer(conn);
+    });
+
+    let mut probe = Probe::new(
+      addr,
+      infohash,
+      piece_count,
+      Limits::default(),
+      Encryption::default(),
+    );
+
+    if exchange_trackers {
+      probe = probe.exchanging_trackers();
+    }
+
+    run(probe)
+  }
+
+  // Run `probe`, falling back as `peers` does.
+  fn run(probe: Probe) -> Result<Report> {
+    let mut reactor = Reactor::new(1).unwrap();
+    reactor.push(probe);
+
+    let mut report = None;
+    reactor
+      .run(|reactor, probe, result| {
+        if let Err(err) = &result {
+          if let Some(probe) = probe.fallback(err) {
+            reactor.push(probe);
+            return Ok(Control::Continue);
+          }
+        }
+        report = Some(result);
+        Ok(Control::Continue)
+      })
+      .unwrap();
+
+    report.unwrap()
+  }
+
+  // Accept connections using `encryption`, reporting whether each attempt
+  // succeeded and was encrypted, and tell each successfully connected probe
+  // that we have every piece.
+  fn listen(encryption: Encryption) -> (SocketAddr, mpsc::Receiver<Result<bool>>) {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+      for stream in listener.incoming() {
+        let result = Connection::accept(
+          stream.unwrap().into(),
+          Infohash::from([0; 20]),
+          handshake::IMDL_SEEDER_RESERVED_BYTES,
+          encryption,
+        );
+
+        match result {
+          Ok(conn) => {
+            tx.send(Ok(conn.stream.is_encrypted())).unwrap();
+            seed(conn);
+          }
+          Err(err) => tx.send(Err(err)).unwrap(),
+        }
+      }
+    });
+    (addr, rx)
+  }
+
+  // Tell the probe on `conn` that we have every piece, and wait for it to
+  // hang up.
+  fn seed(mut conn: Connection) {
+    conn.send(&Message::HaveAll).ok();
+    extension_handshake(&mut conn, Some(1));
+    while conn.recv().is_ok() {}
+  }
+
+  fn probe_encrypted(addr: SocketAddr, encryption: Encryption) -> Result<Report> {
+    run(Probe::new(
+      addr,
+      Infohash::from([0; 20]),
+      3,
+      Limits::default(),
+      encryption,
+    ))
+  }
+
+  fn extension_handshake(conn: &mut Connection, upload_only: Option<u8>) {
+    let handshake = extended::Handshake {
+      upload_only,
+      ..extended::Handshake::new()
+    };
+    conn
+      .send(&Message::new_extended(extended::Handshake::ID, handshake).unwrap())
+      .unwrap();
+  }
+
+  #[test]
+  fn over_utp() {
+    let listener = utp::Listener::bind((Ipv4Addr::LOCALHOST, 0).into()).unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+      let (stream, _) = listener.accept().unwrap();
+      seed(
+        Connection::accept(
+          stream.into(),
+          Infohash::from([0; 20]),
+          handshake::IMDL_SEEDER_RESERVED_BYTES,
+          Encryption::default(),
+        )
+        .unwrap(),
+      );
+    });
+
+    let report = run(
+      Probe::new(
+        addr,
+        Infohash::from([0; 20]),
+        3,
+        Limits::default(),
+        Encryption::default(),
+      )
+      .with_utp(true),
+    )
+    .unwrap();
+
+    assert_eq!(report.pieces, Some(vec![true; 3]));
+  }
+
+  #[test]
+  fn utp_tcp_fallback() {
+    let (addr, rx) = listen(Encryption::Prefer);
+
+    let report = run(
+      Probe::new(
+        addr,
+        Infohash::from([0; 20]),
+        3,
+        Limits::default(),
+        Encryption::default(),
+      )
+      .with_utp(true),
+    )
+    .unwrap();
+
+    assert_eq!(report.pieces, Some(vec![true; 3]));
+    assert!(rx.recv().unwrap().unwrap());
+  }
+
+  #[test]
+  fn encryption_preferred() {
+    let (addr, rx) = listen(Encryption::Prefer);
+    probe_encrypted(addr, Encryption::Prefer).unwrap();
+    assert!(rx.recv().unwrap().unwrap());
+  }
+
+  #[test]
+  fn encryption_required() {
+    let (addr, rx) = listen(Encryption::Require);
+    probe_encrypted(addr, Encryption::Require).unwrap();
+    assert!(rx.recv().unwrap().unwrap());
+  }
+
+  #[test]
+  fn encryption_disabled() {
+    let (addr, rx) = listen(Encryption::Prefer);
+    probe_encrypted(addr, Encryption::Disable).unwrap();
+    assert!(!rx.recv().unwrap().unwrap());
+  }
+
+  #[test]
+  fn encryption_fallback() {
+    let (addr, rx) = listen(Encryption::Disable);
+    probe_encrypted(addr, Encryption::Prefer).unwrap();
+    assert_matches!(rx.recv().unwrap(), Err(Error::PeerHandshakeHeader));
+    assert!(!rx.recv().unwrap().unwrap());
+  }
+
+  #[test]
+  fn encryption_required_by_initiator() {
+    let (addr, rx) = listen(Encryption::Disable);
+    assert_matches!(
+      probe_encrypted(addr, Encryption::Require),
+      Err(Error::Network { .. })
+    );
+    assert_matches!(rx.recv().unwrap(), Err(Error::PeerHandshakeHeader));
+    assert_matches!(rx.try_recv(), Err(mpsc::TryRecvError::Empty));
+  }
+
+  #[test]
+  fn encryption_required_by_receiver() {
+    let (addr, rx) = listen(Encryption::Require);
+    assert_matches!(
+      probe_encrypted(addr, Encryption::Disable),
+      Err(Error::Network { .. })
+    );
+    assert_matches!(rx.recv().unwrap(), Err(Error::PeerEncryptionRequired));
+  }
+
+  #[test]
+  fn have_all() {
+    let report = probe(3, |mut conn| {
+      assert_eq!(conn.recv().unwrap(), Message::HaveNone);
+      conn.send(&Message::HaveAll).unwrap();
+      extension_handshake(&mut conn, Some(1));
+      thread::sleep(Duration::from_secs(1));
+    })
+    .unwrap();
+
+    assert_eq!(report.pieces, Some(vec![true; 3]));
+    assert_eq!(report.extension_handshake.unwrap().upload_only, Some(1));
+    assert!(report.handshake.supports_fast_extension());
+    assert_eq!(&report.handshake.peer_id[..3], b"-IM");
+  }
+
+  #[test]
+  fn bitfield_and_have() {
+    let report = probe(10, |mut conn| {
+      conn
+        .send(&Message::Bitfield {
+          bitfield: vec![0b1010_0000, 0b0100_0000],
+        })
+        .unwrap();
+      conn.send(&Message::Have { index: 1 }).unwrap();
+      extension_handshake(&mut conn, None);
+      thread::sleep(Duration::from_secs(1));
+    })
+    .unwrap();
+
+    assert_eq!(
+      report.pieces.unwrap(),
+      [true, true, true, false, false, false, false, false, false, true]
+    );
+    assert_eq!(report.extension_handshake.unwrap().upload_only, None);
+  }
+
+  #[test]
+  fn bitfield_length() {
+    let result = probe(10, |mut conn| {
+      conn
+        .send(&Message::Bitfield {
+          bitfield: vec![0b1010_0000],
+        })
+        .unwrap();
+      thread::sleep(Duration::from_secs(1));
+    });
+
+    assert_matches!(
+      result,
+      Err(Error::PeerBitfieldLength {
+        length: 1,
+        expected: 2,
+      })
+    );
+  }
+
+  #[test]
+  fn disconnect_after_handshake() {
+    let report = probe(3, |mut conn| {
+      assert_eq!(conn.recv().unwrap(), Message::HaveNone);
+      assert_matches!(conn.recv().unwrap(), Message::Extended { .. });
+    })
+    .unwrap();
+    assert_eq!(report.pieces, None);
+    assert!(report.extension_handshake.is_none());
+  }
+
+  #[test]
+  fn tracker_exchange() {
+    let report = probe_with(3, true, |mut conn| {
+      assert_eq!(conn.recv().unwrap(), Message::HaveNone);
+
+      let Message::Extended { id: 0, payload } = conn.recv().unwrap() else {
+        panic!("expected extension handshake");
+      };
+      let ours: extended::Handshake = Message::from_bencode(&payload).unwrap();
+      let tex = ours.message_ids[extended::TrackerExchange::NAME];
+
+      conn.send(&Message::HaveAll).unwrap();
+      let mut handshake = extended::Handshake::new();
+      handshake.with_message(extended::TrackerExchange::NAME.into(), 7);
+      conn
+        .send(&Message::new_extended(extended::Handshake::ID, handshake).unwrap())
+        .unwrap();
+
+      // The probe should wait for the tracker list.
+      thread::sleep(Duration::from_millis(500));
+
+      conn
+        .send(
+          &Message::new_extended(
+            tex,
+            extended::tracker_exchange::LtTex {
+              added: vec!["udp://foo:1337".into()],
+            },
+          )
+          .unwrap(),
+        )
+        .unwrap();
+
+      thread::sleep(Duration::from_secs(1));
+    })
+    .unwrap();
+
+    assert_eq!(report.trackers, [Url::parse("udp://foo:1337").unwrap()]);
+  }
+}
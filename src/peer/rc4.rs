@@ -0,0 +1,76 @@
+use crate::common::*;
+
+/// The RC4 stream cipher, as used by message stream encryption. RC4 is weak,
+/// and MSE only uses it to obfuscate traffic, not to secure it.
+#[derive(Clone)]
+pub(crate) struct Rc4 {
+  state: [u8; 256],
+  i: u8,
+  j: u8,
+}
+
+impl Rc4 {
+  /// MSE discards the start of each keystream, since early RC4 output is
+  /// correlated with the key.
+  const DISCARD: usize = 1024;
+
+  pub(crate) fn new(key: &[u8]) -> Self {
+    Self::with_discard(key, Self::DISCARD)
+  }
+
+  fn with_discard(key: &[u8], discard: usize) -> Self {
+    let mut state = [0; 256];
+    for (byte, value) in state.iter_mut().zip(0..=u8::MAX) {
+      *byte = value;
+    }
+
+    let mut j = 0u8;
+    for i in 0..state.len() {
+      j = j.wrapping_add(state[i]).wrapping_add(key[i % key.len()]);
+      state.swap(i, j.into());
+    }
+
+    let mut rc4 = Self { state, i: 0, j: 0 };
+    rc4.apply(&mut vec![0; discard]);
+    rc4
+  }
+
+  /// Encrypt or decrypt `buf` in place.
+  pub(crate) fn apply(&mut self, buf: &mut [u8]) {
+    for byte in buf {
+      self.i = self.i.wrapping_add(1);
+      self.j = self.j.wrapping_add(self.state[usize::from(self.i)]);
+      self.state.swap(self.i.into(), self.j.into());
+      let k = self.state[usize::from(self.i)].wrapping_add(self.state[usize::from(self.j)]);
+      *byte ^= self.state[usize::from(k)];
+    }
+  }
+}
+
+impl fmt::Debug for Rc4 {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    f.debug_struct("Rc4").finish_non_exhaustive()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn keystream() {
+    // The widely published test vector for key `Key`.
+    let mut buf = *b"Plaintext";
+    Rc4::with_discard(b"Key", 0).apply(&mut buf);
+    assert_eq!(buf, [0xbb, 0xf3, 0x16, 0xe8, 0xd9, 0x40, 0xaf, 0x0a, 0xd3]);
+  }
+
+  #[test]
+  fn round_trip() {
+    let mut buf = *b"hello";
+    Rc4::new(b"foo").apply(&mut buf);
+    assert_ne!(&buf, b"hello");
+    Rc4::new(b"foo").apply(&mut buf);
+    assert_eq!(&buf, b"hello");
+  }
+}
@@ -0,0 +1,351 @@
+use crate::common::*;
+
+use message::{extended, Block, Message};
+use peer::connection::Connection;
+use peer::handshake;
+use peer::message;
+use peer::{Encryption, Limits, Transport};
+
+/// Serves piece data from a `PieceStore` to a single remote peer.
+#[derive(Debug)]
+pub(crate) struct Seeder {
+  conn: Connection,
+  store: Arc<PieceStore>,
+  choking: bool,
+}
+
+impl Seeder {
+  /// Largest block a peer may request. Most clients request 16 KiB blocks, but
+  /// some ask for more.
+  pub(crate) const MAX_BLOCK_LENGTH: u32 = 128 * 1024;
+
+  // Peers send a keep-alive at least every two minutes, so a peer that has
+  // been silent for longer than this is gone.
+  const READ_TIMEOUT: Duration = Duration::from_secs(180);
+
+  pub(crate) fn accept(
+    mut stream: Transport,
+    infohash: Infohash,
+    store: Arc<PieceStore>,
+    encryption: Encryption,
+  ) -> Result<Self> {
+    stream
+      .set_read_timeout(Some(Self::READ_TIMEOUT))
+      .context(error::Network)?;
+
+    let conn = Connection::accept(
+      stream,
+      infohash,
+      handshake::IMDL_SEEDER_RESERVED_BYTES,
+      encryption,
+    )?
+    .with_limits(Limits::seeding());
+
+    Ok(Seeder {
+      conn,
+      store,
+      choking: true,
+    })
+  }
+
+  /// Announce that we have every piece, then serve requests until the peer
+  /// disconnects, which isn't an error, or misbehaves, which is.
+  pub(crate) fn serve(mut self) -> Result<()> {
+    self.send_have()?;
+    self.send_extension_handshake()?;
+
+    loop {
+      let msg = match self.conn.recv() {
+        Ok(msg) => msg,
+        Err(Error::Network { source })
+          if matches!(
+            source.kind(),
+            io::ErrorKind::UnexpectedEof | io::ErrorKind::ConnectionReset
+          ) =>
+        {
+          return Ok(());
+        }
+        Err(err) => return Err(err),
+      };
+
+      self.handle_msg(&msg)?;
+    }
+  }
+
+  fn send_have(&mut self) -> Result<()> {
+    if self.conn.supports_fast_extension() {
+      return self.conn.send(&Message::HaveAll);
+    }
+
+    let piece_count = self.store.piece_count().into_usize();
+    let mut bitfield = vec![0xff; piece_count.div_ceil(8)];
+    // Spare bits at the end of the bitfield must be cleared.
+    let spare = bitfield.len() * 8 - piece_count;
+    if let Some(last) = bitfield.last_mut() {
+      *last <<= spare;
+    }
+
+    self.conn.send(&Message::Bitfield { bitfield })
+  }
+
+  // Tell peers that support the extension protocol who we are, and that we
+  // won't be requesting anything from them.
+  fn send_extension_handshake(&mut self) -> Result<()> {
+    if !self.conn.supports_extension_protocol() {
+      return Ok(());
+    }
+
+    let handshake = extended::Handshake {
+      upload_only: Some(1),
+      ..extended::Handshake::new()
+    };
+
+    self
+      .conn
+      .send(&Message::new_extended(extended::Handshake::ID, handshake)?)
+  }
+
+  fn handle_msg(&mut self, msg: &Message) -> Result<()> {
+    match msg {
+      // Every interested peer is unchoked, since we have nothing to download
+      // and so no reason to prefer one peer over another.
+      Message::Interested => self.set_choking(false),
+      Message::NotInterested => self.set_choking(true),
+      Message::Request(block) => self.handle_request(*block),
+      _ => Ok(()),
+    }
+  }
+
+  fn set_choking(&mut self, choking: bool) -> Result<()> {
+    if self.choking == choking {
+      return Ok(());
+    }
+
+    self.choking = choking;
+
+    self.conn.send(if choking {
+      &Message::Choke
+    } else {
+      &Message::Unchoke
+    })
+  }
+
+  fn handle_request(&mut self, block: Block) -> Result<()> {
+    if self.choking
+      || block.length > Self::MAX_BLOCK_LENGTH
+      || !self.store.contains(block.index, block.begin, block.length)
+    {
+      // Peers without the fast extension don't understand rejections, and
+      // must infer from being choked that their requests were dropped.
+      if self.conn.supports_fast_extension() {
+        return self.conn.send(&Message::Reject(block));
+      }
+      return Ok(());
+    }
+
+    let data = self.store.read(block.index, block.begin, block.length)?;
+
+    self.conn.send(&Message::Piece {
+      index: block.index,
+      begin: block.begin,
+      block: data,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn spawn_seeder(tempdir: &TempDir) -> (thread::JoinHandle<Result<()>>, SocketAddr, Infohash) {
+    let contents = "abcdefghij".repeat(4000);
+    let path = tempdir.path().join("foo");
+    fs::write(&path, &contents).unwrap();
+
+    let info = Info {
+      private: None,
+      piece_length: Bytes::kib() * 16,
+      name: "foo".into(),
+      source: None,
+      pieces: PieceList::from_pieces(contents.as_bytes().chunks(16 * 1024)),
+      mode: Mode::Single {
+        md5sum: None,
+        sha1: None,
+        sha256: None,
+        length: Bytes::from(contents.len().into_u64()),
+      },
+      update_url: None,
+    };
+
+    let infohash = info.infohash_lossy().unwrap();
+    let store = Arc::new(PieceStore::new(&info, &path).unwrap());
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let handle = thread::spawn(move || {
+      let (stream, _) = listener.accept().unwrap();
+      Seeder::accept(stream.into(), infohash, store, Encryption::default())?.serve()
+    });
+
+    (handle, addr, infohash)
+  }
+
+  fn block(index: u32, begin: u32, length: u32) -> Block {
+    Block {
+      index,
+      begin,
+      length,
+    }
+  }
+
+  fn recv_extension_handshake(conn: &mut Connection) -> extended::Handshake {
+    match conn.recv().unwrap() {
+      Message::Extended { id, payload } if id == extended::Handshake::ID => {
+        Message::from_bencode(&payload).unwrap()
+      }
+      msg => panic!("expected extension handshake, got {msg:?}"),
+    }
+  }
+
+  fn request(index: u32, begin: u32, length: u32) -> Message {
+    Message::Request(block(index, begin, length))
+  }
+
+  #[test]
+  fn bitfield() {
+    let tempdir = temptree! {};
+    let (_, addr, infohash) = spawn_seeder(&tempdir);
+    let mut conn = Connection::new(&addr, infohash).unwrap();
+    assert_eq!(
+      conn.recv().unwrap(),
+      Message::Bitfield {
+        bitfield: vec![0b1110_0000]
+      }
+    );
+    recv_extension_handshake(&mut conn);
+  }
+
+  #[test]
+  fn have_all() {
+    let tempdir = temptree! {};
+    let (_, addr, infohash) = spawn_seeder(&tempdir);
+    let mut conn = Connection::connect(
+      &addr,
+      infohash,
+      handshake::IMDL_SEEDER_RESERVED_BYTES,
+      Encryption::default(),
+    )
+    .unwrap();
+    assert_eq!(conn.recv().unwrap(), Message::HaveAll);
+    assert_eq!(recv_extension_handshake(&mut conn).upload_only, Some(1));
+  }
+
+  #[test]
+  fn disconnect() {
+    let tempdir = temptree! {};
+    let (handle, addr, infohash) = spawn_seeder(&tempdir);
+    let mut conn = Connection::new(&addr, infohash).unwrap();
+    assert_matches!(conn.recv().unwrap(), Message::Bitfield { .. });
+    recv_extension_handshake(&mut conn);
+    drop(conn);
+    handle.join().unwrap().unwrap();
+  }
+
+  #[test]
+  fn limits() {
+    let tempdir = temptree! {};
+    let (handle, addr, infohash) = spawn_seeder(&tempdir);
+    let mut conn = Connection::new(&addr, infohash).unwrap();
+    conn
+      .send(&Message::Bitfield {
+        bitfield: vec![0; 2 << 20],
+      })
+      .ok();
+    assert_matches!(
+      handle.join().unwrap(),
+      Err(Error::PeerMessageTooLarge { .. })
+    );
+  }
+
+  #[test]
+  fn serve_piece() {
+    let tempdir = temptree! {};
+    let (_, addr, infohash) = spawn_seeder(&tempdir);
+    let mut conn = Connection::new(&addr, infohash).unwrap();
+    assert_matches!(conn.recv().unwrap(), Message::Bitfield { .. });
+    recv_extension_handshake(&mut conn);
+
+    conn.send(&Message::Interested).unwrap();
+    assert_eq!(conn.recv().unwrap(), Message::Unchoke);
+
+    conn.send(&request(2, 2, 5)).unwrap();
+    assert_eq!(
+      conn.recv().unwrap(),
+      Message::Piece {
+        index: 2,
+        begin: 2,
+        block: b"abcde".to_vec(),
+      }
+    );
+  }
+
+  #[test]
+  fn choked_request_rejected() {
+    let tempdir = temptree! {};
+    let (_, addr, infohash) = spawn_seeder(&tempdir);
+    let mut conn = Connection::connect(
+      &addr,
+      infohash,
+      handshake::IMDL_SEEDER_RESERVED_BYTES,
+      Encryption::default(),
+    )
+    .unwrap();
+    assert_eq!(conn.recv().unwrap(), Message::HaveAll);
+    recv_extension_handshake(&mut conn);
+
+    conn.send(&request(0, 0, 16)).unwrap();
+    assert_eq!(conn.recv().unwrap(), Message::Reject(block(0, 0, 16)));
+
+    conn.send(&Message::Interested).unwrap();
+    assert_eq!(conn.recv().unwrap(), Message::Unchoke);
+
+    conn
+      .send(&request(0, 0, Seeder::MAX_BLOCK_LENGTH + 1))
+      .unwrap();
+    assert_eq!(
+      conn.recv().unwrap(),
+      Message::Reject(block(0, 0, Seeder::MAX_BLOCK_LENGTH + 1))
+    );
+
+    conn.send(&request(3, 0, 1)).unwrap();
+    assert_eq!(conn.recv().unwrap(), Message::Reject(block(3, 0, 1)));
+  }
+
+  #[test]
+  fn malformed_request() {
+    let tempdir = temptree! {};
+    let (handle, addr, infohash) = spawn_seeder(&tempdir);
+    let mut conn = Connection::new(&addr, infohash).unwrap();
+    conn.stream.write_all(&[0, 0, 0, 4, 6, 0, 0, 0]).unwrap();
+    assert_matches!(
+      handle.join().unwrap(),
+      Err(Error::PeerMessageLength {
+        flavour: message::Flavour::Request,
+        length: 3,
+      })
+    );
+  }
+
+  #[test]
+  fn keep_alive() {
+    let tempdir = temptree! {};
+    let (_, addr, infohash) = spawn_seeder(&tempdir);
+    let mut conn = Connection::new(&addr, infohash).unwrap();
+    assert_matches!(conn.recv().unwrap(), Message::Bitfield { .. });
+    recv_extension_handshake(&mut conn);
+
+    conn.send(&Message::KeepAlive).unwrap();
+    conn.send(&Message::Interested).unwrap();
+    assert_eq!(conn.recv().unwrap(), Message::Unchoke);
+  }
+}
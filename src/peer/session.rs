@@ -0,0 +1,107 @@
+use crate::common::*;
+
+use message::extended;
+use message::Message;
+use peer::message;
+use peer::Limits;
+
+/// Protocol state for a single peer, independent of how bytes move over the
+/// wire. Messages produced while handling incoming messages are queued in an
+/// outbox, which the owner of the connection is responsible for sending.
+#[derive(Debug)]
+pub(crate) struct Session {
+  pub(crate) extensions: extended::Registry,
+  pub(crate) extension_handshake: Option<extended::Handshake>,
+  outbox: Vec<Message>,
+}
+
+impl Session {
+  pub(crate) fn new(infohash: Infohash, limits: Limits) -> Self {
+    let mut extensions = extended::Registry::default();
+
+    extensions.register(extended::MetadataExchange::new(
+      infohash,
+      limits.max_metadata_size,
+    ));
+
+    extensions.register(extended::TrackerExchange::default());
+
+    Self {
+      extensions,
+      extension_handshake: None,
+      outbox: Vec::new(),
+    }
+  }
+
+  /// Queue our extension handshake, advertising every registered extension.
+  pub(crate) fn send_extension_handshake(&mut self) -> Result<()> {
+    self.outbox.push(Message::new_extended(
+      extended::Handshake::ID,
+      self.extensions.handshake(),
+    )?);
+    Ok(())
+  }
+
+  pub(crate) fn metadata_exchange(&mut self) -> &mut extended::MetadataExchange {
+    self
+      .extensions
+      .find_mut()
+      .invariant_unwrap("metadata exchange is always registered")
+  }
+
+  pub(crate) fn tracker_exchange(&mut self) -> &mut extended::TrackerExchange {
+    self
+      .extensions
+      .find_mut()
+      .invariant_unwrap("tracker exchange is always registered")
+  }
+
+  /// Take the messages queued since the last call.
+  pub(crate) fn outbox(&mut self) -> Vec<Message> {
+    mem::take(&mut self.outbox)
+  }
+
+  pub(crate) fn handle_msg(&mut self, msg: &Message) -> Result<()> {
+    match msg {
+      Message::Extended { id, payload } => self.handle_extended(*id, payload),
+      _ => Ok(()),
+    }
+  }
+
+  fn handle_extended(&mut self, id: u8, payload: &[u8]) -> Result<()> {
+    if id == extended::Handshake::ID {
+      return self.handle_extension_handshake(payload);
+    }
+
+    // Ignore messages sent to ids we didn't advertise.
+    let Some(extension) = self.extensions.get_mut(id) else {
+      return Ok(());
+    };
+
+    let mut sender = extended::Sender::new(
+      &mut self.outbox,
+      self.extension_handshake.as_ref(),
+      extension.name(),
+    );
+
+    extension.handle_message(payload, &mut sender)
+  }
+
+  fn handle_extension_handshake(&mut self, payload: &[u8]) -> Result<()> {
+    let handshake: extended::Handshake = Message::from_bencode(payload)?;
+
+    for extension in self.extensions.iter_mut() {
+      let mut sender = extended::Sender::new(&mut self.outbox, Some(&handshake), extension.name());
+      extension.handle_handshake(&handshake, &mut sender)?;
+    }
+
+    self.extension_handshake.replace(handshake);
+
+    Ok(())
+  }
+
+  #[cfg(test)]
+  pub(crate) fn sender(&mut self, name: &'static str) -> extended::Sender<'_> {
+    extended::Sender::new(&mut self.outbox, self.extension_handshake.as_ref(), name)
+  }
+}
@@ -0,0 +1,80 @@
+use crate::common::*;
+
+/// The stream underlying a peer connection.
+#[derive(Debug)]
+pub(crate) enum Transport {
+  Tcp(TcpStream),
+  Utp(utp::Stream),
+}
+
+impl Transport {
+  #[cfg(test)]
+  pub(crate) fn connect_tcp(addr: &SocketAddr, timeout: Duration) -> Result<Self> {
+    let stream = TcpStream::connect_timeout(addr, timeout).context(error::Network)?;
+    stream
+      .set_read_timeout(Some(timeout))
+      .context(error::Network)?;
+    Ok(Self::Tcp(stream))
+  }
+
+  pub(crate) fn peer_addr(&self) -> io::Result<SocketAddr> {
+    match self {
+      Self::Tcp(stream) => stream.peer_addr(),
+      Self::Utp(stream) => Ok(stream.peer_addr()),
+    }
+  }
+
+  pub(crate) fn read_timeout(&self) -> io::Result<Option<Duration>> {
+    match self {
+      Self::Tcp(stream) => stream.read_timeout(),
+      Self::Utp(stream) => Ok(stream.read_timeout()),
+    }
+  }
+
+  pub(crate) fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+    match self {
+      Self::Tcp(stream) => stream.set_read_timeout(timeout),
+      Self::Utp(stream) => {
+        stream.set_read_timeout(timeout);
+        Ok(())
+      }
+    }
+  }
+}
+
+impl From<TcpStream> for Transport {
+  fn from(stream: TcpStream) -> Self {
+    Self::Tcp(stream)
+  }
+}
+
+impl From<utp::Stream> for Transport {
+  fn from(stream: utp::Stream) -> Self {
+    Self::Utp(stream)
+  }
+}
+
+impl Read for Transport {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    match self {
+      Self::Tcp(stream) => stream.read(buf),
+      Self::Utp(stream) => stream.read(buf),
+    }
+  }
+}
+
+impl Write for Transport {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    match self {
+      Self::Tcp(stream) => stream.write(buf),
+      Self::Utp(stream) => stream.write(buf),
+    }
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    match self {
+      Self::Tcp(stream) => stream.flush(),
+      Self::Utp(stream) => stream.flush(),
+    }
+  }
+}
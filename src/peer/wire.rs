@@ -0,0 +1,480 @@
+use crate::common::*;
+
+use mio::net::TcpStream;
+use peer::handshake::Handshake;
+use peer::message::Message;
+use peer::mse::{self, Cipher};
+use peer::{Encryption, Limits};
+
+/// A non-blocking outgoing peer connection. Handles connecting, over TCP or
+/// over uTP relayed by `utp::bridge`, possibly through a proxy, encryption,
+/// exchanging handshakes, and framing messages, leaving what to do with them
+/// to the task that owns it.
+#[derive(Debug)]
+pub(crate) struct Wire {
+  addr: SocketAddr,
+  infohash: Infohash,
+  reserved: [u8; 8],
+  limits: Limits,
+  encryption: Encryption,
+  utp: bool,
+  proxy: Option<Proxy>,
+  tunnel: Option<proxy::Tunnel>,
+  mse: Option<mse::Handshake>,
+  cipher: Option<Cipher>,
+  stream: Option<TcpStream>,
+  started: Option<Instant>,
+  established: Option<Instant>,
+  handshake_received: bool,
+  read_buf: Vec<u8>,
+  write_buf: Vec<u8>,
+  bytes_read: u64,
+  eof: bool,
+}
+
+impl Wire {
+  const READ_CHUNK: usize = 16 * 1024;
+
+  pub(crate) fn new(
+    addr: SocketAddr,
+    infohash: Infohash,
+    reserved: [u8; 8],
+    limits: Limits,
+    encryption: Encryption,
+  ) -> Self {
+    Self {
+      addr,
+      infohash,
+      reserved,
+      limits,
+      encryption,
+      utp: false,
+      proxy: None,
+      tunnel: None,
+      mse: None,
+      cipher: None,
+      stream: None,
+      started: None,
+      established: None,
+      handshake_received: false,
+      read_buf: Vec::new(),
+      write_buf: Vec::new(),
+      bytes_read: 0,
+      eof: false,
+    }
+  }
+
+  /// Connect through `proxy`, if any. Proxies only carry TCP, so this
+  /// disables uTP.
+  pub(crate) fn via(self, proxy: Option<&Proxy>) -> Self {
+    Self {
+      utp: self.utp && proxy.is_none(),
+      proxy: proxy.cloned(),
+      ..self
+    }
+  }
+
+  /// Try connecting over uTP first, falling back to TCP if the peer doesn't
+  /// answer. Ignored when connecting through a proxy.
+  pub(crate) fn with_utp(self, utp: bool) -> Self {
+    Self {
+      utp: utp && self.proxy.is_none(),
+      ..self
+    }
+  }
+
+  pub(crate) fn addr(&self) -> SocketAddr {
+    self.addr
+  }
+
+  pub(crate) fn infohash(&self) -> Infohash {
+    self.infohash
+  }
+
+  pub(crate) fn limits(&self) -> Limits {
+    self.limits
+  }
+
+  /// A replacement for this connection after it fails with `error`: over
+  /// TCP, if a uTP connection couldn't be opened, or in plaintext, if
+  /// encryption was preferred and the peer closed the connection or answered
+  /// with something other than an encryption handshake, which is how peers
+  /// that don't support encryption usually respond to it.
+  pub(crate) fn fallback(&self, error: &Error) -> Option<Self> {
+    if self.utp && self.established.is_none() {
+      return Some(
+        Self::new(
+          self.addr,
+          self.infohash,
+          self.reserved,
+          self.limits,
+          self.encryption,
+        )
+        .with_utp(false),
+      );
+    }
+
+    if self.encryption != Encryption::Prefer
+      || self.established.is_none()
+      || self.mse.is_none()
+      || !Self::refused_encryption(error)
+    {
+      return None;
+    }
+
+    Some(
+      Self::new(
+        self.addr,
+        self.infohash,
+        self.reserved,
+        self.limits,
+        Encryption::Disable,
+      )
+      .with_utp(self.utp)
+      .via(self.proxy.as_ref()),
+    )
+  }
+
+  // Whether `error`, during the encryption handshake, shows that the peer
+  // doesn't support encryption, unlike running out of time or exceeding the
+  // read limit.
+  fn refused_encryption(error: &Error) -> bool {
+    match error {
+      Error::Network { source } => matches!(
+        source.kind(),
+        io::ErrorKind::UnexpectedEof
+          | io::ErrorKind::ConnectionReset
+          | io::ErrorKind::ConnectionAborted
+          | io::ErrorKind::BrokenPipe
+      ),
+      Error::PeerEncryptionMethod | Error::PeerEncryptionSync => true,
+      _ => false,
+    }
+  }
+
+  /// Begin connecting, over uTP if enabled, and queue the start of the proxy
+  /// handshake, the encryption handshake, or our `BitTorrent` handshake,
+  /// whichever comes first.
+  pub(crate) fn start(&mut self, now: Instant) -> Result<()> {
+    self.stream = Some(if self.utp {
+      utp::bridge(self.addr, self.limits.connect_timeout).context(error::Network)?
+    } else {
+      let addr = self.proxy.as_ref().map_or(self.addr, Proxy::addr);
+      TcpStream::connect(addr).context(error::Network)?
+    });
+    self.started = Some(now);
+
+    if let Some(proxy) = &self.proxy {
+      let (tunnel, output) = proxy.tunnel(self.addr.into());
+      self.tunnel = Some(tunnel);
+      self.write_buf.extend(output);
+    } else {
+      self.begin();
+    }
+
+    Ok(())
+  }
+
+  // Queue either the start of the encryption handshake or our `BitTorrent`
+  // handshake.
+  fn begin(&mut self) {
+    if self.encryption == Encryption::Disable {
+      self.queue_handshake();
+    } else {
+      let (mse, output) = mse::Handshake::initiate(self.infohash, self.encryption);
+      self.mse = Some(mse);
+      self.write_buf.extend(output);
+    }
+  }
+
+  fn queue_handshake(&mut self) {
+    self.queue(&Handshake::with_reserved(self.infohash, self.reserved).serialize());
+  }
+
+  // Append `bytes` to the write buffer, encrypting them if necessary.
+  fn queue(&mut self, bytes: &[u8]) {
+    let start = self.write_buf.len();
+    self.write_buf.extend_from_slice(bytes);
+    if let Some(cipher) = &mut self.cipher {
+      cipher.encrypt.apply(&mut self.write_buf[start..]);
+    }
+  }
+
+  pub(crate) fn source(&mut self) -> &mut dyn mio::event::Source {
+    self.stream()
+  }
+
+  pub(crate) fn interest(&self) -> mio::Interest {
+    if self.established.is_none() || !self.write_buf.is_empty() {
+      mio::Interest::READABLE | mio::Interest::WRITABLE
+    } else {
+      mio::Interest::READABLE
+    }
+  }
+
+  /// When the connection times out: the connect timeout while connecting and
+  /// again while negotiating with the proxy and encryption, and the deadline,
+  /// if any, after that.
+  pub(crate) fn wakeup(&self) -> Option<Instant> {
+    match self.established {
+      Some(established) if self.tunnel.is_some() || self.mse.is_some() => {
+        let timeout = established + self.limits.connect_timeout;
+        Some(
+          self
+            .limits
+            .deadline
+            .map_or(timeout, |deadline| timeout.min(established + deadline)),
+        )
+      }
+      Some(established) => self.limits.deadline.map(|deadline| established + deadline),
+      None => self
+        .started
+        .map(|started| started + self.limits.connect_timeout),
+    }
+  }
+
+  /// Time out, write what we can, and read what is available. Returns false
+  /// while the connection is still being established.
+  pub(crate) fn pump(&mut self, now: Instant) -> Result<bool> {
+    if self.wakeup().is_some_and(|wakeup| now >= wakeup) {
+      return Err(if self.established.is_some() {
+        Error::PeerDeadline
+      } else {
+        Error::Network {
+          source: io::ErrorKind::TimedOut.into(),
+        }
+      });
+    }
+
+    if !self.connected(now)? {
+      return Ok(false);
+    }
+
+    self.flush()?;
+    self.fill()?;
+    self.dig()?;
+    self.negotiate()?;
+
+    Ok(true)
+  }
+
+  // Advance the proxy handshake, if one is in progress, and begin talking to
+  // the peer once the tunnel is open.
+  fn dig(&mut self) -> Result<()> {
+    let Some(tunnel) = &mut self.tunnel else {
+      return Ok(());
+    };
+
+    if tunnel
+      .advance(&mut self.read_buf, &mut self.write_buf)?
+      .is_none()
+    {
+      return self.flush();
+    }
+
+    self.tunnel = None;
+    self.begin();
+    self.flush()
+  }
+
+  // Advance the encryption handshake, if one is in progress, and queue our
+  // BitTorrent handshake once it completes.
+  fn negotiate(&mut self) -> Result<()> {
+    let Some(mse) = &mut self.mse else {
+      return Ok(());
+    };
+
+    let Some(established) = mse.advance(&mut self.read_buf, &mut self.write_buf)? else {
+      return self.flush();
+    };
+
+    self.mse = None;
+    self.cipher = established.cipher;
+
+    // Whatever is left arrived after the encryption handshake.
+    if let Some(cipher) = &mut self.cipher {
+      cipher.decrypt.apply(&mut self.read_buf);
+    }
+
+    self.queue_handshake();
+    self.flush()
+  }
+
+  /// The peer's handshake, once it has been received. Returns `None` both
+  /// before it arrives and after it has been returned.
+  pub(crate) fn recv_handshake(&mut self) -> Result<Option<Handshake>> {
+    if self.handshake_received || self.tunnel.is_some() || self.mse.is_some() {
+      return Ok(None);
+    }
+
+    let Some(buf) = self.read_buf.get(..Handshake::LENGTH) else {
+      return Ok(None);
+    };
+
+    let handshake = Handshake::try_from(
+      <[u8; Handshake::LENGTH]>::try_from(buf).invariant_unwrap("length is checked"),
+    )?;
+
+    if Infohash::from(handshake.infohash) != self.infohash {
+      return Err(Error::PeerHandshakeInfohash);
+    }
+
+    self.read_buf.drain(..Handshake::LENGTH);
+    self.handshake_received = true;
+
+    Ok(Some(handshake))
+  }
+
+  /// The next complete message in the read buffer, if any.
+  pub(crate) fn recv(&mut self) -> Result<Option<Message>> {
+    if !self.handshake_received {
+      return Ok(None);
+    }
+
+    let Some(prefix) = self.read_buf.get(..4) else {
+      return Ok(None);
+    };
+
+    let length = u32::from_be_bytes(prefix.try_into().invariant_unwrap("length is checked"));
+
+    if length > self.limits.max_message_length {
+      return Err(Error::PeerMessageTooLarge {
+        length,
+        max: self.limits.max_message_length,
+      });
+    }
+
+    let end = 4 + length.into_usize();
+
+    let Some(buf) = self.read_buf.get(4..end) else {
+      return Ok(None);
+    };
+
+    let msg = Message::deserialize(buf)?;
+    self.read_buf.drain(..end);
+
+    Ok(Some(msg))
+  }
+
+  /// Queue `msg`, to be written by the next `flush` or `pump`.
+  pub(crate) fn send(&mut self, msg: &Message) -> Result<()> {
+    self.queue(&msg.serialize()?);
+    Ok(())
+  }
+
+  /// Whether the peer has closed the connection.
+  pub(crate) fn eof(&self) -> bool {
+    self.eof
+  }
+
+  fn stream(&mut self) -> &mut TcpStream {
+    self
+      .stream
+      .as_mut()
+      .invariant_unwrap("stream is opened by start")
+  }
+
+  // Returns true once the non-blocking connect has completed, and, over uTP,
+  // the bridge has reported that the uTP connection is open.
+  fn connected(&mut self, now: Instant) -> Result<bool> {
+    if self.established.is_some() {
+      return Ok(true);
+    }
+
+    let utp = self.utp;
+    let stream = self.stream();
+
+    if let Some(source) = stream.take_error().context(error::Network)? {
+      return Err(Error::Network { source });
+    }
+
+    match stream.peer_addr() {
+      Ok(_) if utp => {
+        let mut ready = [0];
+        match stream.read(&mut ready) {
+          Ok(1) if ready[0] == utp::READY => {
+            self.established = Some(now);
+            Ok(true)
+          }
+          Ok(_) => Err(Error::Network {
+            source: io::ErrorKind::ConnectionRefused.into(),
+          }),
+          Err(err)
+            if matches!(
+              err.kind(),
+              io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted
+            ) =>
+          {
+            Ok(false)
+          }
+          Err(source) => Err(Error::Network { source }),
+        }
+      }
+      Ok(_) => {
+        self.established = Some(now);
+        Ok(true)
+      }
+      Err(err)
+        if matches!(
+          err.kind(),
+          io::ErrorKind::NotConnected | io::ErrorKind::WouldBlock
+        ) =>
+      {
+        Ok(false)
+      }
+      Err(source) => Err(Error::Network { source }),
+    }
+  }
+
+  pub(crate) fn flush(&mut self) -> Result<()> {
+    while !self.write_buf.is_empty() {
+      let stream = self
+        .stream
+        .as_mut()
+        .invariant_unwrap("stream is opened by start");
+
+      match stream.write(&self.write_buf) {
+        Ok(n) => {
+          self.write_buf.drain(..n);
+        }
+        Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+        Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+        Err(source) => return Err(Error::Network { source }),
+      }
+    }
+
+    Ok(())
+  }
+
+  // Read until the socket would block, enforcing the byte limit.
+  fn fill(&mut self) -> Result<()> {
+    let mut buf = [0; Self::READ_CHUNK];
+
+    loop {
+      match self.stream().read(&mut buf) {
+        Ok(0) => {
+          self.eof = true;
+          return Ok(());
+        }
+        Ok(n) => {
+          self.bytes_read += n.into_u64();
+
+          if let Some(max) = self.limits.max_bytes_read {
+            if self.bytes_read > max {
+              return Err(Error::PeerReadLimit { max });
+            }
+          }
+
+          if let Some(cipher) = &mut self.cipher {
+            cipher.decrypt.apply(&mut buf[..n]);
+          }
+
+          self.read_buf.extend_from_slice(&buf[..n]);
+        }
+        Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+        Err(source) => return Err(Error::Network { source }),
+      }
+    }
+  }
+}
@@ -24,7 +24,7 @@ impl PieceLengthPicker {
     if content_size == Bytes::from(0u64) {
       0
     } else {
-      (content_size / piece_length).max(1)
+      content_size.count().div_ceil(piece_length.count())
     }
   }
 
@@ -0,0 +1,179 @@
+use crate::common::*;
+
+/// How to pick a piece length for content of a given size. Every strategy
+/// picks a power of two of at least 16 KiB.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub(crate) enum PieceLengthStrategy {
+  /// Intermodal's usual choice, from `PieceLengthPicker::from_content_size`.
+  Auto,
+  /// As `Auto`, but no more than 8 MiB, since some older clients reject
+  /// larger pieces.
+  ClientCompat,
+  /// The smallest piece length whose piece list is no larger than the given
+  /// size.
+  MaxMetainfoSize(Bytes),
+  /// The smallest piece length that yields no more than the given number of
+  /// pieces.
+  TargetPieceCount(u64),
+}
+
+impl PieceLengthStrategy {
+  const CLIENT_COMPAT_MAX: u64 = 8 << 20;
+  const MIN_EXPONENT: u32 = 14;
+  // The largest power of two that fits in the `u32` that
+  // `Bytes::as_piece_length` requires.
+  const MAX_EXPONENT: u32 = 31;
+
+  pub(crate) fn pick(self, content_size: Bytes) -> Bytes {
+    match self {
+      Self::Auto => PieceLengthPicker::from_content_size(content_size),
+      Self::ClientCompat => {
+        PieceLengthPicker::from_content_size(content_size).min(Bytes(Self::CLIENT_COMPAT_MAX))
+      }
+      Self::MaxMetainfoSize(max) => Self::smallest(|piece_length| {
+        PieceLengthPicker::metainfo_size(content_size, piece_length) <= max
+      }),
+      Self::TargetPieceCount(count) => Self::smallest(|piece_length| {
+        PieceLengthPicker::piece_count(content_size, piece_length) <= count
+      }),
+    }
+  }
+
+  // The smallest power of two in range for which `acceptable` holds, or the
+  // largest if it holds for none.
+  fn smallest(acceptable: impl Fn(Bytes) -> bool) -> Bytes {
+    (Self::MIN_EXPONENT..=Self::MAX_EXPONENT)
+      .map(|exponent| Bytes(1 << exponent))
+      .find(|piece_length| acceptable(*piece_length))
+      .unwrap_or(Bytes(1 << Self::MAX_EXPONENT))
+  }
+}
+
+impl FromStr for PieceLengthStrategy {
+  type Err = Error;
+
+  fn from_str(text: &str) -> Result<Self, Self::Err> {
+    let (name, value) = match text.split_once(':') {
+      Some((name, value)) => (name, Some(value)),
+      None => (text, None),
+    };
+
+    match (name, value) {
+      ("auto", None) => Ok(Self::Auto),
+      ("client-compat", None) => Ok(Self::ClientCompat),
+      ("max-metainfo-size", Some(value)) => Ok(Self::MaxMetainfoSize(value.parse()?)),
+      ("target-piece-count", Some(value)) => value
+        .parse()
+        .ok()
+        .filter(|count| *count > 0)
+        .map(Self::TargetPieceCount)
+        .ok_or_else(|| Error::PieceLengthStrategy { text: text.into() }),
+      _ => Err(Error::PieceLengthStrategy { text: text.into() }),
+    }
+  }
+}
+
+impl Display for PieceLengthStrategy {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    match self {
+      Self::Auto => write!(f, "auto"),
+      Self::ClientCompat => write!(f, "client-compat"),
+      Self::MaxMetainfoSize(max) => write!(f, "max-metainfo-size:{max}"),
+      Self::TargetPieceCount(count) => write!(f, "target-piece-count:{count}"),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse() {
+    assert_eq!(
+      "auto".parse::<PieceLengthStrategy>().unwrap(),
+      PieceLengthStrategy::Auto
+    );
+    assert_eq!(
+      "client-compat".parse::<PieceLengthStrategy>().unwrap(),
+      PieceLengthStrategy::ClientCompat
+    );
+    assert_eq!(
+      "max-metainfo-size:1mib"
+        .parse::<PieceLengthStrategy>()
+        .unwrap(),
+      PieceLengthStrategy::MaxMetainfoSize(Bytes::mib())
+    );
+    assert_eq!(
+      "target-piece-count:1000"
+        .parse::<PieceLengthStrategy>()
+        .unwrap(),
+      PieceLengthStrategy::TargetPieceCount(1000)
+    );
+
+    for text in [
+      "foo",
+      "auto:1",
+      "target-piece-count",
+      "target-piece-count:0",
+      "target-piece-count:x",
+    ] {
+      assert!(
+        matches!(
+          text.parse::<PieceLengthStrategy>(),
+          Err(Error::PieceLengthStrategy { .. })
+        ),
+        "{text}"
+      );
+    }
+
+    assert_matches!(
+      "max-metainfo-size:x".parse::<PieceLengthStrategy>(),
+      Err(Error::ByteParse { .. })
+    );
+  }
+
+  #[test]
+  fn target_piece_count() {
+    let strategy = PieceLengthStrategy::TargetPieceCount(1000);
+    assert_eq!(strategy.pick(Bytes::mib()), Bytes::kib() * 16);
+    assert_eq!(strategy.pick(Bytes::mib() * 1000), Bytes::mib());
+    assert_eq!(strategy.pick(Bytes::mib() * 1001), Bytes::mib() * 2);
+  }
+
+  #[test]
+  fn max_metainfo_size() {
+    let strategy = PieceLengthStrategy::MaxMetainfoSize(Bytes::kib() * 20);
+    assert_eq!(strategy.pick(Bytes::mib() * 1024), Bytes::mib());
+    assert_eq!(strategy.pick(Bytes::mib() * 1025), Bytes::mib() * 2);
+  }
+
+  #[test]
+  fn client_compat() {
+    let size = Bytes::from(1u64 << 50);
+    assert_eq!(PieceLengthStrategy::Auto.pick(size), Bytes::mib() * 16);
+    assert_eq!(
+      PieceLengthStrategy::ClientCompat.pick(size),
+      Bytes::mib() * 8
+    );
+    assert_eq!(
+      PieceLengthStrategy::ClientCompat.pick(Bytes::mib()),
+      PieceLengthStrategy::Auto.pick(Bytes::mib())
+    );
+  }
+
+  #[test]
+  fn display() {
+    for (text, display) in [
+      ("auto", "auto"),
+      ("client-compat", "client-compat"),
+      ("max-metainfo-size:1mib", "max-metainfo-size:1 MiB"),
+      ("target-piece-count:10", "target-piece-count:10"),
+    ] {
+      assert_eq!(
+        text.parse::<PieceLengthStrategy>().unwrap().to_string(),
+        display
+      );
+    }
+  }
+}
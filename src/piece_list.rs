@@ -20,6 +20,17 @@ impl PieceList {
     self.piece_hashes.push(digest);
   }
 
+  pub(crate) fn extend(&mut self, other: &PieceList) {
+    self.piece_hashes.extend_from_slice(&other.piece_hashes);
+  }
+
+  /// The pieces in `range`, which must be in bounds.
+  pub(crate) fn slice(&self, range: Range<usize>) -> PieceList {
+    Self {
+      piece_hashes: self.piece_hashes[range].to_vec(),
+    }
+  }
+
   #[cfg(test)]
   pub(crate) fn from_pieces<I, B>(pieces: I) -> Self
   where
@@ -0,0 +1,167 @@
+use crate::common::*;
+
+/// Read-only access to torrent content by piece, mapping piece offsets onto the
+/// files described by the torrent's `Mode`.
+#[derive(Debug)]
+pub(crate) struct PieceStore {
+  files: Vec<(PathBuf, u64)>,
+  piece_length: u64,
+  piece_count: u32,
+  content_size: u64,
+}
+
+impl PieceStore {
+  pub(crate) fn new(info: &Info, base: &Path) -> Result<Self> {
+    let files = match &info.mode {
+      Mode::Single { length, .. } => vec![(base.to_owned(), length.count())],
+      Mode::Multiple { files } => files
+        .iter()
+        .map(|file| (file.path.absolute(base), file.length.count()))
+        .collect(),
+    };
+
+    let piece_count = info
+      .pieces
+      .count()
+      .try_into()
+      .context(error::PieceStoreTooManyPieces)?;
+
+    Ok(Self {
+      files,
+      piece_length: info.piece_length.as_piece_length()?.into(),
+      piece_count,
+      content_size: info.content_size().count(),
+    })
+  }
+
+  pub(crate) fn piece_count(&self) -> u32 {
+    self.piece_count
+  }
+
+  /// The size of piece `index`, or `None` if there is no such piece. All
+  /// pieces are `piece_length` bytes long, except the last, which may be
+  /// shorter.
+  pub(crate) fn piece_size(&self, index: u32) -> Option<u64> {
+    if index >= self.piece_count {
+      return None;
+    }
+
+    let start = u64::from(index) * self.piece_length;
+
+    Some((self.content_size - start).min(self.piece_length))
+  }
+
+  /// Whether `length` bytes starting at `begin` lie within piece `index`.
+  pub(crate) fn contains(&self, index: u32, begin: u32, length: u32) -> bool {
+    match self.piece_size(index) {
+      Some(size) => u64::from(begin) + u64::from(length) <= size,
+      None => false,
+    }
+  }
+
+  pub(crate) fn read(&self, index: u32, begin: u32, length: u32) -> Result<Vec<u8>> {
+    if !self.contains(index, begin, length) {
+      return Err(Error::PieceStoreRange {
+        index,
+        begin,
+        length,
+      });
+    }
+
+    let mut offset = u64::from(index) * self.piece_length + u64::from(begin);
+    let mut buffer = vec![0; length.into_usize()];
+    let mut filled = 0;
+    let mut file_start = 0;
+
+    for (path, file_length) in &self.files {
+      let file_end = file_start + file_length;
+
+      if offset < file_end {
+        let available = file_end - offset;
+        let want = (buffer.len() - filled).min(available.try_into().unwrap_or(usize::MAX));
+
+        let mut file = File::open(path).context(error::Filesystem { path })?;
+        file
+          .seek(SeekFrom::Start(offset - file_start))
+          .context(error::Filesystem { path })?;
+        file
+          .read_exact(&mut buffer[filled..filled + want])
+          .context(error::Filesystem { path })?;
+
+        filled += want;
+        offset += want.into_u64();
+
+        if filled == buffer.len() {
+          break;
+        }
+      }
+
+      file_start = file_end;
+    }
+
+    Ok(buffer)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn store(tempdir: &TempDir, piece_length: u64, files: &[(&str, &str)]) -> PieceStore {
+    let files = files
+      .iter()
+      .map(|(name, contents)| {
+        let path = tempdir.path().join(name);
+        fs::write(&path, contents).unwrap();
+        (path, contents.len().into_u64())
+      })
+      .collect::<Vec<(PathBuf, u64)>>();
+
+    let content_size = files.iter().map(|(_, length)| length).sum::<u64>();
+
+    PieceStore {
+      piece_count: content_size.div_ceil(piece_length).try_into().unwrap(),
+      files,
+      piece_length,
+      content_size,
+    }
+  }
+
+  #[test]
+  fn piece_size() {
+    let tempdir = temptree! {};
+    let store = store(&tempdir, 4, &[("a", "abcdefghij")]);
+    assert_eq!(store.piece_count(), 3);
+    assert_eq!(store.piece_size(0), Some(4));
+    assert_eq!(store.piece_size(2), Some(2));
+    assert_eq!(store.piece_size(3), None);
+  }
+
+  #[test]
+  fn read_within_file() {
+    let tempdir = temptree! {};
+    let store = store(&tempdir, 4, &[("a", "abcdefghij")]);
+    assert_eq!(store.read(1, 1, 3).unwrap(), b"fgh");
+  }
+
+  #[test]
+  fn read_across_files() {
+    let tempdir = temptree! {};
+    let store = store(
+      &tempdir,
+      4,
+      &[("a", "abc"), ("b", ""), ("c", "def"), ("d", "ghij")],
+    );
+    assert_eq!(store.read(0, 0, 4).unwrap(), b"abcd");
+    assert_eq!(store.read(1, 0, 4).unwrap(), b"efgh");
+    assert_eq!(store.read(2, 0, 2).unwrap(), b"ij");
+  }
+
+  #[test]
+  fn read_out_of_range() {
+    let tempdir = temptree! {};
+    let store = store(&tempdir, 4, &[("a", "abcdefghij")]);
+    assert_matches!(store.read(2, 1, 2), Err(Error::PieceStoreRange { .. }));
+    assert_matches!(store.read(3, 0, 1), Err(Error::PieceStoreRange { .. }));
+  }
+}
@@ -0,0 +1,354 @@
+use crate::common::*;
+
+/// Settings for one of several torrents created from a single hashing pass,
+/// given as `NAME:KEY=VALUE,…`. Settings that are absent are taken from the
+/// other arguments to `torrent create`, and settings with empty values are
+/// cleared. Each `announce-tier` setting adds a tier, like `--announce-tier`.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Profile {
+  pub(crate) name: String,
+  pub(crate) announce: Setting<Url>,
+  pub(crate) announce_tiers: Setting<Vec<Vec<String>>>,
+  pub(crate) comment: Setting<String>,
+  pub(crate) output: Option<OutputTarget>,
+  pub(crate) private: Option<bool>,
+  pub(crate) source: Setting<String>,
+}
+
+/// An optional value in a profile, which may be taken from the other
+/// arguments, cleared, or replaced.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Setting<T> {
+  Inherit,
+  Clear,
+  Set(T),
+}
+
+impl<T> Setting<T> {
+  pub(crate) fn or(self, inherited: Option<T>) -> Option<T> {
+    match self {
+      Self::Inherit => inherited,
+      Self::Clear => None,
+      Self::Set(value) => Some(value),
+    }
+  }
+}
+
+impl Profile {
+  /// The announce tiers of this profile's torrent, given the tiers from the
+  /// other arguments. A profile with its own `announce` URL doesn't inherit
+  /// tiers, since they're most likely for a different tracker.
+  pub(crate) fn announce_list(&self, inherited: &[Vec<String>]) -> Vec<Vec<String>> {
+    match (&self.announce_tiers, &self.announce) {
+      (Setting::Set(tiers), _) => tiers.clone(),
+      (Setting::Inherit, Setting::Inherit | Setting::Clear) => inherited.to_vec(),
+      (Setting::Clear, _) | (Setting::Inherit, Setting::Set(_)) => Vec::new(),
+    }
+  }
+
+  /// Load profiles from the file at `path`, which contains one profile per
+  /// line. Blank lines and lines beginning with `#` are ignored.
+  pub(crate) fn load(path: &Path) -> Result<Vec<Self>> {
+    let text = fs::read_to_string(path).context(error::Filesystem { path })?;
+
+    let mut profiles = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+      let line = line.trim();
+
+      if line.is_empty() || line.starts_with('#') {
+        continue;
+      }
+
+      profiles.push(
+        line
+          .parse()
+          .context(error::ProfileFile { path, line: i + 1 })?,
+      );
+    }
+
+    Ok(profiles)
+  }
+
+  /// Where to write this profile's torrent if it has no `output` setting,
+  /// given where the torrent would be written without profiles.
+  pub(crate) fn default_output(&self, output: &OutputTarget) -> Result<OutputTarget> {
+    match output {
+      OutputTarget::Stdout => Err(Error::ProfileOutputRequired {
+        profile: self.name.clone(),
+      }),
+      OutputTarget::Path(path) => {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let stem = file_name.strip_suffix(".torrent").unwrap_or(&file_name);
+        Ok(OutputTarget::Path(
+          path.with_file_name(format!("{stem}.{}.torrent", self.name)),
+        ))
+      }
+    }
+  }
+
+  // Split `text` on commas not escaped with a backslash, so that values can
+  // contain commas, and unescape each part.
+  fn split(text: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+      match c {
+        '\\' => {
+          if let Some(c) = chars.next() {
+            parts
+              .last_mut()
+              .invariant_unwrap("parts is not empty")
+              .push(c);
+          }
+        }
+        ',' => parts.push(String::new()),
+        c => parts
+          .last_mut()
+          .invariant_unwrap("parts is not empty")
+          .push(c),
+      }
+    }
+
+    parts
+  }
+
+  fn text(value: &str) -> Setting<String> {
+    if value.is_empty() {
+      Setting::Clear
+    } else {
+      Setting::Set(value.to_owned())
+    }
+  }
+}
+
+impl FromStr for Profile {
+  type Err = ProfileParseError;
+
+  fn from_str(text: &str) -> Result<Self, Self::Err> {
+    let (name, settings) = text.split_once(':').unwrap_or((text, ""));
+
+    if name.is_empty() {
+      return Err(ProfileParseError::NameMissing { text: text.into() });
+    }
+
+    if !name
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+      return Err(ProfileParseError::NameInvalid { name: name.into() });
+    }
+
+    let mut profile = Self {
+      name: name.into(),
+      announce: Setting::Inherit,
+      announce_tiers: Setting::Inherit,
+      comment: Setting::Inherit,
+      output: None,
+      private: None,
+      source: Setting::Inherit,
+    };
+
+    if settings.is_empty() {
+      return Ok(profile);
+    }
+
+    for setting in Self::split(settings) {
+      let Some((key, value)) = setting.split_once('=') else {
+        return Err(ProfileParseError::ValueMissing { setting });
+      };
+
+      match key {
+        "announce" => {
+          profile.announce = if value.is_empty() {
+            Setting::Clear
+          } else {
+            Setting::Set(
+              value
+                .parse()
+                .context(profile_parse_error::Announce { text: value })?,
+            )
+          };
+        }
+        "announce-tier" => {
+          if value.is_empty() {
+            profile.announce_tiers = Setting::Clear;
+            continue;
+          }
+
+          let tier = value.split(',').map(str::to_owned).collect::<Vec<String>>();
+
+          for announce in &tier {
+            announce
+              .parse::<Url>()
+              .context(profile_parse_error::Announce { text: announce })?;
+          }
+
+          match &mut profile.announce_tiers {
+            Setting::Set(tiers) => tiers.push(tier),
+            _ => profile.announce_tiers = Setting::Set(vec![tier]),
+          }
+        }
+        "comment" => profile.comment = Self::text(value),
+        "output" => {
+          profile.output = Some(
+            OutputTarget::try_from(OsStr::new(value))
+              .map_err(|_| ProfileParseError::OutputEmpty)?,
+          );
+        }
+        "private" => {
+          profile.private = Some(match value {
+            "true" => true,
+            "false" => false,
+            _ => return Err(ProfileParseError::Private { text: value.into() }),
+          });
+        }
+        "source" => profile.source = Self::text(value),
+        _ => return Err(ProfileParseError::KeyUnknown { key: key.into() }),
+      }
+    }
+
+    Ok(profile)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(text: &str) -> Result<Profile, ProfileParseError> {
+    text.parse()
+  }
+
+  #[test]
+  fn name_only() {
+    assert_eq!(
+      parse("foo").unwrap(),
+      Profile {
+        name: "foo".into(),
+        announce: Setting::Inherit,
+        announce_tiers: Setting::Inherit,
+        comment: Setting::Inherit,
+        output: None,
+        private: None,
+        source: Setting::Inherit,
+      }
+    );
+  }
+
+  #[test]
+  fn settings() {
+    assert_eq!(
+      parse(
+        "foo:announce=https://example.com/announce,comment=a\\, b,output=-,private=true,source=FOO"
+      )
+      .unwrap(),
+      Profile {
+        name: "foo".into(),
+        announce: Setting::Set("https://example.com/announce".parse().unwrap()),
+        announce_tiers: Setting::Inherit,
+        comment: Setting::Set("a, b".into()),
+        output: Some(OutputTarget::Stdout),
+        private: Some(true),
+        source: Setting::Set("FOO".into()),
+      }
+    );
+  }
+
+  #[test]
+  fn cleared() {
+    let profile = parse("foo:announce=,announce-tier=,comment=,source=").unwrap();
+    assert_eq!(profile.announce, Setting::Clear);
+    assert_eq!(profile.announce_tiers, Setting::Clear);
+    assert_eq!(profile.comment, Setting::Clear);
+    assert_eq!(profile.source, Setting::Clear);
+  }
+
+  #[test]
+  fn announce_tiers() {
+    let profile = parse(
+      "foo:announce-tier=udp://a.example:1\\,udp://b.example:2,announce-tier=udp://c.example:3",
+    )
+    .unwrap();
+    assert_eq!(
+      profile.announce_tiers,
+      Setting::Set(vec![
+        vec!["udp://a.example:1".into(), "udp://b.example:2".into()],
+        vec!["udp://c.example:3".into()],
+      ])
+    );
+  }
+
+  #[test]
+  fn announce_list() {
+    let inherited = [vec!["udp://a.example:1".to_owned()]];
+
+    assert_eq!(parse("foo").unwrap().announce_list(&inherited), inherited);
+    assert_eq!(
+      parse("foo:announce=").unwrap().announce_list(&inherited),
+      inherited
+    );
+    assert!(parse("foo:announce=https://b.example/announce")
+      .unwrap()
+      .announce_list(&inherited)
+      .is_empty());
+    assert!(parse("foo:announce-tier=")
+      .unwrap()
+      .announce_list(&inherited)
+      .is_empty());
+    assert_eq!(
+      parse("foo:announce=https://b.example/announce,announce-tier=udp://c.example:3")
+        .unwrap()
+        .announce_list(&inherited),
+      [vec!["udp://c.example:3".to_owned()]]
+    );
+  }
+
+  #[test]
+  fn errors() {
+    assert_matches!(
+      parse(":source=x"),
+      Err(ProfileParseError::NameMissing { .. })
+    );
+    assert_matches!(parse("a/b"), Err(ProfileParseError::NameInvalid { .. }));
+    assert_matches!(
+      parse("foo:source"),
+      Err(ProfileParseError::ValueMissing { .. })
+    );
+    assert_matches!(
+      parse("foo:bar=baz"),
+      Err(ProfileParseError::KeyUnknown { .. })
+    );
+    assert_matches!(
+      parse("foo:announce=x"),
+      Err(ProfileParseError::Announce { .. })
+    );
+    assert_matches!(
+      parse("foo:announce-tier=udp://a.example:1\\,x"),
+      Err(ProfileParseError::Announce { .. })
+    );
+    assert_matches!(
+      parse("foo:private=1"),
+      Err(ProfileParseError::Private { .. })
+    );
+    assert_matches!(parse("foo:output="), Err(ProfileParseError::OutputEmpty));
+  }
+
+  #[test]
+  fn default_output() {
+    let profile = parse("foo").unwrap();
+
+    assert_eq!(
+      profile
+        .default_output(&OutputTarget::Path("dir/bar.torrent".into()))
+        .unwrap(),
+      OutputTarget::Path("dir/bar.foo.torrent".into())
+    );
+
+    assert_matches!(
+      profile.default_output(&OutputTarget::Stdout),
+      Err(Error::ProfileOutputRequired { .. })
+    );
+  }
+}
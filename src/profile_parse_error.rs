@@ -0,0 +1,26 @@
+use crate::common::*;
+
+#[derive(Debug, Snafu)]
+#[snafu(context(suffix(false)), visibility(pub(crate)))]
+pub(crate) enum ProfileParseError {
+  #[snafu(display("Failed to parse announce URL `{}`: {}", text, source))]
+  Announce {
+    text: String,
+    source: url::ParseError,
+  },
+  #[snafu(display("Unknown profile setting `{}`", key))]
+  KeyUnknown { key: String },
+  #[snafu(display(
+    "Invalid profile name `{}`: names may only contain ASCII letters, digits, `-`, and `_`",
+    name
+  ))]
+  NameInvalid { name: String },
+  #[snafu(display("Profile name missing: `{}`", text))]
+  NameMissing { text: String },
+  #[snafu(display("Profile output must not be empty"))]
+  OutputEmpty,
+  #[snafu(display("Profile setting `private` must be `true` or `false`, not `{}`", text))]
+  Private { text: String },
+  #[snafu(display("Profile setting `{}` has no `=VALUE`", setting))]
+  ValueMissing { setting: String },
+}
@@ -0,0 +1,134 @@
+use super::*;
+
+/// A SOCKS5 UDP relay. Datagrams sent to the relay start with a header
+/// naming their destination, datagrams received from it start with one
+/// naming their source, and the relay stays open for as long as the control
+/// connection does.
+#[derive(Debug)]
+pub(crate) struct Association {
+  // Closing the control connection closes the relay.
+  _control: TcpStream,
+  relay: SocketAddr,
+}
+
+impl Association {
+  const HEADER_PREFIX: [u8; 3] = [0, 0, 0];
+
+  pub(crate) fn new(proxy: &Proxy, timeout: Duration) -> Result<Self> {
+    if proxy.kind == Kind::Http {
+      return Err(Error::ProxyUdp);
+    }
+
+    let addr = proxy.addr;
+
+    let mut control =
+      TcpStream::connect_timeout(&addr, timeout).context(error::ProxyNetwork { addr })?;
+    control
+      .set_read_timeout(Some(timeout))
+      .context(error::ProxyNetwork { addr })?;
+    control
+      .set_write_timeout(Some(timeout))
+      .context(error::ProxyNetwork { addr })?;
+
+    // We don't know which address our datagrams will come from, which the
+    // unspecified address tells the proxy.
+    let (mut tunnel, mut output) = Tunnel::new(
+      proxy,
+      Command::UdpAssociate,
+      SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)).into(),
+    );
+
+    let mut input = Vec::new();
+    let mut buf = [0; 512];
+
+    let relay = loop {
+      control
+        .write_all(&output)
+        .context(error::ProxyNetwork { addr })?;
+      output.clear();
+
+      let n = control
+        .read(&mut buf)
+        .context(error::ProxyNetwork { addr })?;
+
+      if n == 0 {
+        return Err(Error::ProxyResponse);
+      }
+
+      input.extend_from_slice(&buf[..n]);
+
+      if let Some(relay) = tunnel.advance(&mut input, &mut output)? {
+        break relay;
+      }
+    };
+
+    // Datagrams must be sent to an address, not a domain.
+    let relay = relay.socket_addr().ok_or(Error::ProxyResponse)?;
+
+    // A relay bound to every interface is reachable at the proxy's address.
+    let relay = if relay.ip().is_unspecified() {
+      SocketAddr::new(addr.ip(), relay.port())
+    } else {
+      relay
+    };
+
+    Ok(Self {
+      _control: control,
+      relay,
+    })
+  }
+
+  /// Where datagrams should be sent.
+  pub(crate) fn relay(&self) -> SocketAddr {
+    self.relay
+  }
+
+  /// Prefix `payload` with a header asking the relay to send it to `target`,
+  /// which the relay looks up if it is a domain.
+  pub(crate) fn wrap(target: &HostPort, payload: &[u8]) -> Result<Vec<u8>> {
+    let mut datagram = Self::HEADER_PREFIX.to_vec();
+    write_socks5_addr(&mut datagram, target)?;
+    datagram.extend_from_slice(payload);
+    Ok(datagram)
+  }
+
+  /// Split a datagram from the relay into its source and payload. Fragments
+  /// and malformed headers are dropped, like lost datagrams.
+  pub(crate) fn unwrap(datagram: &[u8]) -> Option<(HostPort, &[u8])> {
+    let rest = datagram.strip_prefix(&Self::HEADER_PREFIX)?;
+    let (source, length) = read_socks5_addr(rest).ok()??;
+    Some((source, &rest[length..]))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn wrap_unwrap() {
+    let target = "1.2.3.4:6969".parse().unwrap();
+    let datagram = Association::wrap(&target, b"foo").unwrap();
+    assert_eq!(datagram, b"\0\0\0\x01\x01\x02\x03\x04\x1b\x39foo");
+    assert_eq!(
+      Association::unwrap(&datagram),
+      Some((target, b"foo".as_slice()))
+    );
+  }
+
+  #[test]
+  fn fragments_dropped() {
+    let mut datagram = Association::wrap(&"1.2.3.4:6969".parse().unwrap(), b"foo").unwrap();
+    datagram[2] = 1;
+    assert_eq!(Association::unwrap(&datagram), None);
+  }
+
+  #[test]
+  fn http_proxy() {
+    let proxy = Proxy::from_url(&"http://127.0.0.1:3128".parse().unwrap()).unwrap();
+    assert_matches!(
+      proxy.associate(Duration::from_secs(1)),
+      Err(Error::ProxyUdp)
+    );
+  }
+}
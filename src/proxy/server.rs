@@ -0,0 +1,195 @@
+use super::*;
+
+use std::net::UdpSocket;
+use std::sync::atomic::{self, AtomicBool};
+use std::sync::Mutex;
+
+/// A loopback proxy for tests, which speaks SOCKS5, without authentication,
+/// and HTTP CONNECT on the same port, and records every target it is asked
+/// to reach. Domains are looked up by the server, like a real proxy would.
+pub(crate) struct Server {
+  targets: Arc<Mutex<Vec<HostPort>>>,
+}
+
+impl Server {
+  pub(crate) fn spawn() -> (Self, SocketAddr) {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let targets = Arc::new(Mutex::new(Vec::new()));
+
+    let server = Self {
+      targets: targets.clone(),
+    };
+
+    thread::spawn(move || {
+      for stream in listener.incoming() {
+        let targets = targets.clone();
+        thread::spawn(move || Self::serve(stream.unwrap(), &targets));
+      }
+    });
+
+    (server, addr)
+  }
+
+  /// Targets of CONNECT requests and relayed datagrams, in the order they
+  /// were requested.
+  pub(crate) fn targets(&self) -> Vec<HostPort> {
+    self.targets.lock().unwrap().clone()
+  }
+
+  fn serve(stream: TcpStream, targets: &Mutex<Vec<HostPort>>) {
+    let mut version = [0];
+    stream.peek(&mut version).unwrap();
+
+    if version[0] == 5 {
+      Self::socks5(stream, targets);
+    } else {
+      Self::http(stream, targets);
+    }
+  }
+
+  fn socks5(mut stream: TcpStream, targets: &Mutex<Vec<HostPort>>) {
+    let mut greeting = [0; 2];
+    stream.read_exact(&mut greeting).unwrap();
+    let mut methods = vec![0; greeting[1].into()];
+    stream.read_exact(&mut methods).unwrap();
+
+    if !methods.contains(&0) {
+      stream.write_all(&[5, 0xFF]).unwrap();
+      return;
+    }
+
+    stream.write_all(&[5, 0]).unwrap();
+
+    let mut request = [0; 5];
+    stream.read_exact(&mut request).unwrap();
+    let mut addr = request[3..].to_vec();
+    addr.resize(
+      match request[3] {
+        SOCKS5_IPV4 => 7,
+        SOCKS5_DOMAIN => 2 + usize::from(request[4]) + 2,
+        _ => 19,
+      },
+      0,
+    );
+    stream.read_exact(&mut addr[2..]).unwrap();
+    let (target, _) = read_socks5_addr(&addr).unwrap().unwrap();
+
+    match request[1] {
+      1 => {
+        targets.lock().unwrap().push(target.clone());
+
+        let Ok(upstream) = TcpStream::connect(target) else {
+          stream.write_all(&[5, 5, 0, 1, 0, 0, 0, 0, 0, 0]).unwrap();
+          return;
+        };
+
+        let mut reply = vec![5, 0, 0];
+        write_socks5_addr(&mut reply, &upstream.local_addr().unwrap().into()).unwrap();
+        stream.write_all(&reply).unwrap();
+
+        Self::splice(stream, upstream);
+      }
+      3 => {
+        let relay = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+
+        let mut reply = vec![5, 0, 0];
+        write_socks5_addr(&mut reply, &relay.local_addr().unwrap().into()).unwrap();
+        stream.write_all(&reply).unwrap();
+
+        let closed = AtomicBool::new(false);
+
+        thread::scope(|scope| {
+          scope.spawn(|| Self::relay(&relay, &closed, targets));
+
+          // The association ends when the control connection closes.
+          let _ = stream.read(&mut [0]);
+          closed.store(true, atomic::Ordering::Relaxed);
+        });
+      }
+      _ => stream.write_all(&[5, 7, 0, 1, 0, 0, 0, 0, 0, 0]).unwrap(),
+    }
+  }
+
+  fn relay(relay: &UdpSocket, closed: &AtomicBool, targets: &Mutex<Vec<HostPort>>) {
+    relay
+      .set_read_timeout(Some(Duration::from_millis(50)))
+      .unwrap();
+
+    let mut client = None;
+    let mut buf = [0; 8192];
+
+    while !closed.load(atomic::Ordering::Relaxed) {
+      let Ok((n, source)) = relay.recv_from(&mut buf) else {
+        continue;
+      };
+
+      // The first datagram comes from the client, and so do all later
+      // datagrams from the same address.
+      if client.is_none() || client == Some(source) {
+        client = Some(source);
+
+        if let Some((target, payload)) = Association::unwrap(&buf[..n]) {
+          // The relay is bound to an IPv4 address, so it can only reach
+          // IPv4 targets.
+          let addr = target
+            .to_socket_addrs()
+            .unwrap()
+            .find(SocketAddr::is_ipv4)
+            .unwrap();
+          targets.lock().unwrap().push(target);
+          relay.send_to(payload, addr).unwrap();
+        }
+      } else if let Some(client) = client {
+        relay
+          .send_to(
+            &Association::wrap(&source.into(), &buf[..n]).unwrap(),
+            client,
+          )
+          .unwrap();
+      }
+    }
+  }
+
+  fn http(mut stream: TcpStream, targets: &Mutex<Vec<HostPort>>) {
+    let mut head = Vec::new();
+    let mut byte = [0];
+    while !head.ends_with(b"\r\n\r\n") {
+      stream.read_exact(&mut byte).unwrap();
+      head.push(byte[0]);
+    }
+
+    let head = String::from_utf8(head).unwrap();
+    let mut words = head.split_whitespace();
+    assert_eq!(words.next(), Some("CONNECT"));
+    let target = words.next().unwrap().parse::<HostPort>().unwrap();
+
+    targets.lock().unwrap().push(target.clone());
+
+    let Ok(upstream) = TcpStream::connect(target) else {
+      stream
+        .write_all(b"HTTP/1.1 502 Bad Gateway\r\n\r\n")
+        .unwrap();
+      return;
+    };
+
+    stream
+      .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+      .unwrap();
+
+    Self::splice(stream, upstream);
+  }
+
+  // Copy bytes in both directions until both sides are done.
+  fn splice(client: TcpStream, upstream: TcpStream) {
+    let copy = |mut from: TcpStream, mut to: TcpStream| {
+      let _ = io::copy(&mut from, &mut to);
+      let _ = to.shutdown(std::net::Shutdown::Write);
+    };
+
+    let (client_read, upstream_read) = (client.try_clone().unwrap(), upstream.try_clone().unwrap());
+
+    thread::spawn(move || copy(client_read, upstream));
+    copy(upstream_read, client);
+  }
+}
@@ -0,0 +1,362 @@
+use crate::common::*;
+
+use mio::{Events, Interest, Poll, Token};
+
+/// Drives many `Task`s on a single thread, polling their sockets for readiness
+/// instead of blocking on each one in turn. At most `max_active` tasks run at
+/// once, and the remainder wait in a queue until a slot is free.
+pub(crate) struct Reactor<T: Task> {
+  poll: Poll,
+  max_active: usize,
+  queue: VecDeque<T>,
+  slots: Vec<Option<Slot<T>>>,
+}
+
+struct Slot<T> {
+  task: T,
+  interest: Interest,
+}
+
+impl<T: Task> Reactor<T> {
+  pub(crate) fn new(max_active: usize) -> Result<Self> {
+    Ok(Self {
+      poll: Poll::new().context(error::Reactor)?,
+      max_active: max_active.max(1),
+      queue: VecDeque::new(),
+      slots: Vec::new(),
+    })
+  }
+
+  pub(crate) fn push(&mut self, task: T) {
+    self.queue.push_back(task);
+  }
+
+  fn active(&self) -> usize {
+    self.slots.iter().flatten().count()
+  }
+
+  /// Run until every task has finished, or `handle` returns `Control::Stop`.
+  /// `handle` is called with each task and its result as it finishes, and may
+  /// push new tasks. Tasks still running or queued when the reactor stops are
+  /// dropped, closing their sockets.
+  pub(crate) fn run<F>(&mut self, mut handle: F) -> Result<()>
+  where
+    F: FnMut(&mut Self, T, Result<T::Output>) -> Result<Control>,
+  {
+    let mut events = Events::with_capacity(1024);
+    let mut finished = Vec::new();
+
+    loop {
+      self.start_queued(&mut finished);
+
+      if finished.is_empty() {
+        if self.active() == 0 {
+          return Ok(());
+        }
+
+        self.poll(&mut events, &mut finished)?;
+      }
+
+      for (task, result) in finished.drain(..) {
+        if handle(self, task, result)? == Control::Stop {
+          self.queue.clear();
+          self.slots.clear();
+          return Ok(());
+        }
+      }
+    }
+  }
+
+  fn start_queued(&mut self, finished: &mut Vec<(T, Result<T::Output>)>) {
+    while self.active() < self.max_active {
+      let Some(mut task) = self.queue.pop_front() else {
+        break;
+      };
+
+      if let Err(err) = task.start(Instant::now()) {
+        finished.push((task, Err(err)));
+        continue;
+      }
+
+      let i = if let Some(i) = self.slots.iter().position(Option::is_none) {
+        i
+      } else {
+        self.slots.push(None);
+        self.slots.len() - 1
+      };
+
+      let interest = task.interest();
+
+      if let Err(source) = self
+        .poll
+        .registry()
+        .register(task.source(), Token(i), interest)
+      {
+        finished.push((task, Err(Error::Reactor { source })));
+        continue;
+      }
+
+      self.slots[i] = Some(Slot { task, interest });
+    }
+  }
+
+  fn poll(
+    &mut self,
+    events: &mut Events,
+    finished: &mut Vec<(T, Result<T::Output>)>,
+  ) -> Result<()> {
+    let now = Instant::now();
+
+    let timeout = self
+      .slots
+      .iter()
+      .flatten()
+      .filter_map(|slot| slot.task.wakeup())
+      .min()
+      .map(|wakeup| wakeup.saturating_duration_since(now));
+
+    match self.poll.poll(events, timeout) {
+      Ok(()) => {}
+      Err(err) if err.kind() == io::ErrorKind::Interrupted => return Ok(()),
+      Err(source) => return Err(Error::Reactor { source }),
+    }
+
+    let now = Instant::now();
+
+    for event in events.iter() {
+      self.advance(event.token().0, now, finished);
+    }
+
+    for i in 0..self.slots.len() {
+      let due = self.slots[i]
+        .as_ref()
+        .and_then(|slot| slot.task.wakeup())
+        .is_some_and(|wakeup| wakeup <= now);
+
+      if due {
+        self.advance(i, now, finished);
+      }
+    }
+
+    Ok(())
+  }
+
+  fn advance(&mut self, i: usize, now: Instant, finished: &mut Vec<(T, Result<T::Output>)>) {
+    // Events may arrive for tasks that have already finished.
+    let Some(slot) = self.slots.get_mut(i).and_then(Option::as_mut) else {
+      return;
+    };
+
+    let result = match slot.task.advance(now) {
+      Ok(None) => {
+        let interest = slot.task.interest();
+
+        if interest == slot.interest {
+          return;
+        }
+
+        slot.interest = interest;
+
+        match self
+          .poll
+          .registry()
+          .reregister(slot.task.source(), Token(i), interest)
+        {
+          Ok(()) => return,
+          Err(source) => Err(Error::Reactor { source }),
+        }
+      }
+      Ok(Some(output)) => Ok(output),
+      Err(err) => Err(err),
+    };
+
+    if let Some(mut slot) = self.slots[i].take() {
+      self.poll.registry().deregister(slot.task.source()).ok();
+      finished.push((slot.task, result));
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::{cell::Cell, net::UdpSocket, rc::Rc};
+
+  /// Waits for a datagram, giving up after `timeout`.
+  struct Recv {
+    socket: mio::net::UdpSocket,
+    timeout: Duration,
+    deadline: Option<Instant>,
+    active: Rc<Cell<usize>>,
+    max_active: Rc<Cell<usize>>,
+  }
+
+  impl Recv {
+    fn new(timeout: Duration) -> Self {
+      Self {
+        socket: mio::net::UdpSocket::bind((Ipv4Addr::LOCALHOST, 0).into()).unwrap(),
+        timeout,
+        deadline: None,
+        active: Rc::default(),
+        max_active: Rc::default(),
+      }
+    }
+  }
+
+  impl Task for Recv {
+    type Output = Vec<u8>;
+
+    fn start(&mut self, now: Instant) -> Result<()> {
+      self.deadline = Some(now + self.timeout);
+      self.active.set(self.active.get() + 1);
+      self
+        .max_active
+        .set(self.max_active.get().max(self.active.get()));
+      Ok(())
+    }
+
+    fn source(&mut self) -> &mut dyn mio::event::Source {
+      &mut self.socket
+    }
+
+    fn interest(&self) -> Interest {
+      Interest::READABLE
+    }
+
+    fn wakeup(&self) -> Option<Instant> {
+      self.deadline
+    }
+
+    fn advance(&mut self, now: Instant) -> Result<Option<Vec<u8>>> {
+      let mut buf = [0; 64];
+      let result = match self.socket.recv(&mut buf) {
+        Ok(n) => Ok(Some(buf[..n].to_vec())),
+        Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+          if self.deadline.is_some_and(|deadline| now >= deadline) {
+            Err(Error::PeerDeadline)
+          } else {
+            return Ok(None);
+          }
+        }
+        Err(source) => Err(Error::Network { source }),
+      };
+      self.active.set(self.active.get() - 1);
+      result
+    }
+  }
+
+  #[test]
+  fn readiness() {
+    let task = Recv::new(Duration::from_secs(10));
+    let addr = task.socket.local_addr().unwrap();
+
+    let mut reactor = Reactor::new(1).unwrap();
+    reactor.push(task);
+
+    thread::spawn(move || {
+      thread::sleep(Duration::from_millis(50));
+      UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))
+        .unwrap()
+        .send_to(b"hello", addr)
+        .unwrap();
+    });
+
+    let start = Instant::now();
+    let mut received = Vec::new();
+    reactor
+      .run(|_, _, result| {
+        received.push(result.unwrap());
+        Ok(Control::Continue)
+      })
+      .unwrap();
+
+    assert_eq!(received, [b"hello"]);
+    assert!(start.elapsed() < Duration::from_secs(5));
+  }
+
+  #[test]
+  fn deadline() {
+    let mut reactor = Reactor::new(10).unwrap();
+    for _ in 0..3 {
+      reactor.push(Recv::new(Duration::from_millis(50)));
+    }
+
+    let start = Instant::now();
+    let mut results = Vec::new();
+    reactor
+      .run(|_, _, result| {
+        results.push(result);
+        Ok(Control::Continue)
+      })
+      .unwrap();
+
+    assert_eq!(results.len(), 3);
+    for result in results {
+      assert_matches!(result, Err(Error::PeerDeadline));
+    }
+    assert!(start.elapsed() < Duration::from_secs(5));
+  }
+
+  #[test]
+  fn bounded_concurrency() {
+    let active = Rc::new(Cell::new(0));
+    let max_active = Rc::new(Cell::new(0));
+
+    let mut reactor = Reactor::new(3).unwrap();
+    for _ in 0..10 {
+      let mut task = Recv::new(Duration::from_millis(10));
+      task.active = active.clone();
+      task.max_active = max_active.clone();
+      reactor.push(task);
+    }
+
+    let mut finished = 0;
+    reactor
+      .run(|_, _, _| {
+        finished += 1;
+        Ok(Control::Continue)
+      })
+      .unwrap();
+
+    assert_eq!(finished, 10);
+    assert_eq!(max_active.get(), 3);
+  }
+
+  #[test]
+  fn stop() {
+    let mut reactor = Reactor::new(2).unwrap();
+    for _ in 0..10 {
+      reactor.push(Recv::new(Duration::from_millis(10)));
+    }
+
+    let mut finished = 0;
+    reactor
+      .run(|_, _, _| {
+        finished += 1;
+        Ok(Control::Stop)
+      })
+      .unwrap();
+
+    assert_eq!(finished, 1);
+  }
+
+  #[test]
+  fn push_from_handler() {
+    let mut reactor = Reactor::new(2).unwrap();
+    reactor.push(Recv::new(Duration::from_millis(10)));
+
+    let mut finished = 0;
+    reactor
+      .run(|reactor, _, _| {
+        finished += 1;
+        if finished < 5 {
+          reactor.push(Recv::new(Duration::from_millis(10)));
+        }
+        Ok(Control::Continue)
+      })
+      .unwrap();
+
+    assert_eq!(finished, 5);
+  }
+}
@@ -29,6 +29,35 @@ impl From<sha1_smol::Digest> for Sha1Digest {
   }
 }
 
+// Serialized as a raw 20-byte string, like the `sha1` key of BEP 47.
+impl Serialize for Sha1Digest {
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: Serializer,
+  {
+    serde_bytes::Bytes::new(&self.bytes).serialize(serializer)
+  }
+}
+
+impl<'de> Deserialize<'de> for Sha1Digest {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    let bytes = serde_bytes::ByteBuf::deserialize(deserializer)?.into_vec();
+
+    let bytes = bytes.try_into().map_err(|bytes: Vec<u8>| {
+      D::Error::custom(format!(
+        "SHA-1 digest length {} is not {}",
+        bytes.len(),
+        Self::LENGTH
+      ))
+    })?;
+
+    Ok(Self { bytes })
+  }
+}
+
 impl Display for Sha1Digest {
   fn fmt(&self, f: &mut Formatter) -> fmt::Result {
     for byte in &self.bytes {
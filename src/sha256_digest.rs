@@ -0,0 +1,97 @@
+use crate::common::*;
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub(crate) struct Sha256Digest {
+  bytes: [u8; Self::LENGTH],
+}
+
+impl Sha256Digest {
+  pub(crate) const LENGTH: usize = 32;
+
+  #[cfg(test)]
+  pub(crate) fn from_data(data: impl AsRef<[u8]>) -> Self {
+    ring::digest::digest(&ring::digest::SHA256, data.as_ref()).into()
+  }
+}
+
+impl From<ring::digest::Digest> for Sha256Digest {
+  fn from(digest: ring::digest::Digest) -> Self {
+    Self {
+      bytes: digest
+        .as_ref()
+        .try_into()
+        .invariant_unwrap("SHA-256 digests are 32 bytes"),
+    }
+  }
+}
+
+// Serialized as a raw 32-byte string, like the `sha1` key of BEP 47.
+impl Serialize for Sha256Digest {
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: Serializer,
+  {
+    serde_bytes::Bytes::new(&self.bytes).serialize(serializer)
+  }
+}
+
+impl<'de> Deserialize<'de> for Sha256Digest {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    let bytes = serde_bytes::ByteBuf::deserialize(deserializer)?.into_vec();
+
+    let bytes = bytes.try_into().map_err(|bytes: Vec<u8>| {
+      D::Error::custom(format!(
+        "SHA-256 digest length {} is not {}",
+        bytes.len(),
+        Self::LENGTH
+      ))
+    })?;
+
+    Ok(Self { bytes })
+  }
+}
+
+impl Display for Sha256Digest {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    for byte in &self.bytes {
+      write!(f, "{byte:02x}")?;
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn display() {
+    assert_eq!(
+      Sha256Digest::from_data("abc").to_string(),
+      "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+  }
+
+  #[test]
+  fn round_trip() {
+    let digest = Sha256Digest::from_data("abc");
+
+    let bytes = bendy::serde::ser::to_bytes(&digest).unwrap();
+
+    assert_eq!(&bytes[..3], b"32:");
+
+    assert_eq!(
+      bendy::serde::de::from_bytes::<Sha256Digest>(&bytes).unwrap(),
+      digest
+    );
+  }
+
+  #[test]
+  fn length_mismatch() {
+    assert!(bendy::serde::de::from_bytes::<Sha256Digest>(b"3:abc").is_err());
+  }
+}
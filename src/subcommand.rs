@@ -1,19 +1,28 @@
 use crate::common::*;
 
+mod cache;
 mod completions;
+mod config;
+mod serve_webseed;
 mod torrent;
 
 #[derive(StructOpt)]
 pub(crate) enum Subcommand {
+  Cache(cache::Cache),
   Torrent(torrent::Torrent),
   Completions(completions::Completions),
+  Config(config::Config),
+  ServeWebseed(serve_webseed::ServeWebseed),
 }
 
 impl Subcommand {
   pub(crate) fn run(self, env: &mut Env, options: &Options) -> Result<(), Error> {
     match self {
+      Self::Cache(cache) => cache.run(env, options),
       Self::Torrent(torrent) => torrent.run(env, options),
       Self::Completions(completions) => completions.run(env),
+      Self::Config(config) => config.run(env, options),
+      Self::ServeWebseed(serve_webseed) => serve_webseed.run(env, options),
     }
   }
 }
@@ -0,0 +1,21 @@
+use crate::common::*;
+
+mod prune;
+
+#[derive(StructOpt)]
+#[structopt(
+  help_message(consts::HELP_MESSAGE),
+  version_message(consts::VERSION_MESSAGE),
+  about("Subcommands related to the hash cache.")
+)]
+pub(crate) enum Cache {
+  Prune(prune::Prune),
+}
+
+impl Cache {
+  pub(crate) fn run(self, env: &mut Env, options: &Options) -> Result<(), Error> {
+    match self {
+      Self::Prune(prune) => prune.run(env, options),
+    }
+  }
+}
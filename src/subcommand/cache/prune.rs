@@ -0,0 +1,89 @@
+use crate::common::*;
+
+#[derive(StructOpt)]
+#[structopt(
+  help_message(consts::HELP_MESSAGE),
+  version_message(consts::VERSION_MESSAGE),
+  about("Remove hash cache entries for files that have changed or no longer exist.")
+)]
+pub(crate) struct Prune {
+  #[structopt(long = "all", help = "Remove all hash cache entries.")]
+  all: bool,
+}
+
+impl Prune {
+  pub(crate) fn run(self, env: &mut Env, options: &Options) -> Result<(), Error> {
+    let cache = options.hash_cache(env)?.ok_or(Error::HashCacheMissing)?;
+
+    let pruned = cache.prune(self.all)?;
+
+    errln!(
+      env,
+      "Removed {} hash cache entries, kept {}.",
+      pruned.removed,
+      pruned.kept
+    )?;
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn cache_required() {
+    let mut env = test_env! {
+      args: [
+        "cache",
+        "prune",
+      ],
+      tree: {},
+    };
+
+    assert_matches!(env.run(), Err(Error::HashCacheMissing));
+  }
+
+  #[test]
+  fn prune() {
+    let mut env = test_env! {
+      args: [
+        "--hash-cache",
+        "cache",
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--piece-length",
+        "16KiB",
+        "--announce",
+        "udp://127.0.0.1:1",
+      ],
+      tree: {
+        foo: {},
+      },
+    };
+
+    env.write("foo/a", "a".repeat(32 * 1024));
+    env.write("foo/b", "b".repeat(32 * 1024));
+
+    env.assert_ok();
+
+    env.remove_file("foo/a");
+
+    let mut prune_env = test_env! {
+      args: [
+        "--hash-cache",
+        env.resolve("cache").unwrap(),
+        "cache",
+        "prune",
+      ],
+      tree: {},
+    };
+
+    prune_env.assert_ok();
+
+    assert_eq!(prune_env.err(), "Removed 1 hash cache entries, kept 1.\n");
+  }
+}
@@ -0,0 +1,21 @@
+use crate::common::*;
+
+mod show;
+
+#[derive(StructOpt)]
+#[structopt(
+  help_message(consts::HELP_MESSAGE),
+  version_message(consts::VERSION_MESSAGE),
+  about("Subcommands related to the configuration file.")
+)]
+pub(crate) enum Config {
+  Show(show::Show),
+}
+
+impl Config {
+  pub(crate) fn run(self, env: &mut Env, options: &Options) -> Result<(), Error> {
+    match self {
+      Self::Show(show) => show.run(env, options),
+    }
+  }
+}
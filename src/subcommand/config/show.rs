@@ -0,0 +1,190 @@
+use crate::common::*;
+
+#[derive(StructOpt)]
+#[structopt(
+  help_message(consts::HELP_MESSAGE),
+  version_message(consts::VERSION_MESSAGE),
+  about(
+    "Print the defaults that `torrent create` will use, after applying any preset. Environment \
+         variables in announce URLs are checked but not expanded, so that passkeys aren't printed."
+  )
+)]
+pub(crate) struct Show {
+  #[structopt(
+    long = "preset",
+    value_name = "NAME",
+    help = "Show the defaults used with `torrent create --preset NAME`."
+  )]
+  preset: Option<String>,
+}
+
+impl Show {
+  pub(crate) fn run(self, env: &mut Env, options: &Options) -> Result<(), Error> {
+    let config = options.config(env)?;
+
+    match config.path() {
+      Some(path) => errln!(env, "Using configuration file `{}`.", path.display())?,
+      None => errln!(env, "No configuration file found.")?,
+    }
+
+    config.defaults(self.preset.as_deref(), |name| {
+      env.var(name).map(str::to_owned)
+    })?;
+
+    let mut table = toml::Table::new();
+    table.insert(
+      "create".into(),
+      config.table(self.preset.as_deref())?.into(),
+    );
+
+    out!(
+      env,
+      "{}",
+      toml::to_string(&table).invariant_unwrap("tables serialize")
+    )?;
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn no_config() {
+    let mut env = test_env! {
+      args: [
+        "config",
+        "show",
+      ],
+      tree: {},
+    };
+
+    env.assert_ok();
+
+    assert_eq!(env.out(), "[create]\n");
+    assert_eq!(env.err(), "No configuration file found.\n");
+  }
+
+  #[test]
+  fn preset() {
+    let mut env = test_env! {
+      args: [
+        "--config",
+        "config.toml",
+        "config",
+        "show",
+        "--preset",
+        "foo",
+      ],
+      tree: {
+        "config.toml": "
+          [create]
+          allow = [\"small-piece-length\"]
+          source = \"BAR\"
+
+          [preset.foo]
+          announce = \"https://example.com/announce\"
+          private = true
+        ",
+      },
+    };
+
+    env.assert_ok();
+
+    assert_eq!(
+      env.out(),
+      "[create]
+allow = [\"small-piece-length\"]
+announce = \"https://example.com/announce\"
+private = true
+source = \"BAR\"
+"
+    );
+  }
+
+  #[test]
+  fn variables_unexpanded() {
+    let mut env = test_env! {
+      args: [
+        "--config",
+        "config.toml",
+        "config",
+        "show",
+      ],
+      vars: {
+        "PASSKEY": "hunter2",
+      },
+      tree: {
+        "config.toml": "
+          [create]
+          announce = \"https://example.com/${PASSKEY}/announce\"
+        ",
+      },
+    };
+
+    env.assert_ok();
+
+    assert_eq!(
+      env.out(),
+      "[create]\nannounce = \"https://example.com/${PASSKEY}/announce\"\n"
+    );
+  }
+
+  #[test]
+  fn variable_unset() {
+    let mut env = test_env! {
+      args: [
+        "--config",
+        "config.toml",
+        "config",
+        "show",
+      ],
+      tree: {
+        "config.toml": "
+          [create]
+          announce = \"https://example.com/${PASSKEY}/announce\"
+        ",
+      },
+    };
+
+    assert_matches!(
+      env.run(),
+      Err(Error::ConfigVariable { variable, .. }) if variable == "PASSKEY"
+    );
+  }
+
+  #[test]
+  fn preset_unknown() {
+    let mut env = test_env! {
+      args: [
+        "config",
+        "show",
+        "--preset",
+        "foo",
+      ],
+      tree: {},
+    };
+
+    assert_matches!(
+      env.run(),
+      Err(Error::ConfigPresetUnknown { preset }) if preset == "foo"
+    );
+  }
+
+  #[test]
+  fn config_missing() {
+    let mut env = test_env! {
+      args: [
+        "--config",
+        "config.toml",
+        "config",
+        "show",
+      ],
+      tree: {},
+    };
+
+    assert_matches!(env.run(), Err(Error::Filesystem { .. }));
+  }
+}
@@ -0,0 +1,249 @@
+use crate::common::*;
+
+use std::num::NonZeroUsize;
+
+use serve_webseed_step::ServeWebseedStep;
+
+mod serve_webseed_step;
+
+#[derive(StructOpt)]
+#[structopt(
+  help_message(consts::HELP_MESSAGE),
+  version_message(consts::VERSION_MESSAGE),
+  about("Serve torrent content over HTTP to web seed clients, as described in BEP 19.")
+)]
+#[cfg_attr(test, structopt(setting = AppSettings::ColorNever))]
+pub(crate) struct ServeWebseed {
+  #[structopt(
+    long = "content",
+    short = "c",
+    value_name = "PATH",
+    empty_values(false),
+    parse(from_os_str),
+    help = "Serve torrent content from `PATH`. Defaults to the `name` field of the torrent info \
+            dictionary."
+  )]
+  content: Option<PathBuf>,
+  #[structopt(
+    long = "listen",
+    short = "l",
+    value_name = "ADDR",
+    default_value = "0.0.0.0:8080",
+    help = "Accept HTTP connections on `ADDR`. A single-file torrent's content is served at \
+            `/NAME`, and a multi-file torrent's files at `/NAME/PATH`, so torrents whose \
+            `url-list` is `http://HOST:PORT/` find it."
+  )]
+  listen: SocketAddr,
+  #[structopt(
+    long = "max-connections",
+    value_name = "N",
+    default_value = "50",
+    help = "Serve at most `N` connections at once. Further connections wait to be served until \
+            another closes."
+  )]
+  max_connections: NonZeroUsize,
+  #[structopt(
+    long = "torrent",
+    short = "t",
+    value_name = "TORRENT",
+    empty_values(false),
+    parse(try_from_os_str = InputTarget::try_from_os_str),
+    help = "Serve the content described by the metainfo in `TORRENT`. If `TORRENT` is `-`, read \
+            metainfo from standard input."
+  )]
+  torrent: InputTarget,
+  #[structopt(
+    long = "verify",
+    help = "Verify content before serving it, and refuse to serve it unless all pieces and \
+            files are good."
+  )]
+  verify: bool,
+}
+
+impl ServeWebseed {
+  pub(crate) fn run(self, env: &mut Env, options: &Options) -> Result<(), Error> {
+    ServeWebseedStep::Loading {
+      metainfo: &self.torrent,
+    }
+    .print(env)?;
+
+    let input = env.read(self.torrent.clone())?;
+
+    let metainfo = Metainfo::from_input(&input)?;
+
+    let content = self.content.clone().unwrap_or_else(|| match &self.torrent {
+      InputTarget::Path(path) => path.join("..").join(&metainfo.info.name).lexiclean(),
+      InputTarget::Stdin => PathBuf::from(&metainfo.info.name),
+    });
+
+    if self.verify {
+      ServeWebseedStep::Verifying { content: &content }.print(env)?;
+
+      let progress_bar = if env.err().is_styled_term() && !options.quiet {
+        let style = ProgressStyle::default_bar()
+          .template(consts::PROGRESS_STYLE)
+          .tick_chars(consts::TICK_CHARS)
+          .progress_chars(consts::PROGRESS_CHARS);
+
+        Some(ProgressBar::new(metainfo.content_size().count()).with_style(style))
+      } else {
+        None
+      };
+
+      let status = metainfo.verify(&env.resolve(&content)?, None, progress_bar, None)?;
+
+      if !status.good() {
+        status.print(env)?;
+        return Err(Error::Verify);
+      }
+    }
+
+    let server = Arc::new(webseed::Server::new(&metainfo.info, &env.resolve(content)?));
+
+    let listener =
+      TcpListener::bind(self.listen).context(error::WebseedListen { addr: self.listen })?;
+
+    let addr = listener
+      .local_addr()
+      .context(error::WebseedListen { addr: self.listen })?;
+
+    let workers = server.spawn_workers(self.max_connections);
+
+    ServeWebseedStep::Serving { addr }.print(env)?;
+
+    for stream in listener.incoming() {
+      let stream = match stream {
+        Ok(stream) => stream,
+        Err(err) => {
+          errln!(env, "Failed to accept connection: {}", err)?;
+          continue;
+        }
+      };
+
+      if !options.quiet {
+        if let Ok(peer) = stream.peer_addr() {
+          errln!(env, "Accepted connection from `{}`.", peer)?;
+        }
+      }
+
+      workers
+        .send(stream)
+        .map_err(|_| Error::internal("Web seed workers exited"))?;
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn torrent_required() {
+    test_env! {
+      args: [
+        "serve-webseed",
+      ],
+      tree: {
+      },
+      matches: Err(Error::Clap { .. }),
+    };
+  }
+
+  #[test]
+  fn listen_invalid() {
+    test_env! {
+      args: [
+        "serve-webseed",
+        "--torrent",
+        "foo.torrent",
+        "--listen",
+        "foo",
+      ],
+      tree: {
+      },
+      matches: Err(Error::Clap { .. }),
+    };
+  }
+
+  #[test]
+  fn verify_content_corrupt() -> Result<()> {
+    let mut create_env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--announce",
+        "udp://127.0.0.1:1",
+      ],
+      tree: {
+        foo: {
+          a: "abc",
+          d: "efg",
+        },
+      },
+    };
+
+    create_env.assert_ok();
+
+    create_env.write("foo/a", "xyz");
+
+    let torrent = create_env.resolve("foo.torrent")?;
+
+    let mut serve_env = test_env! {
+      args: [
+        "serve-webseed",
+        "--torrent",
+        &torrent,
+        "--listen",
+        "127.0.0.1:0",
+        "--verify",
+      ],
+      tree: {},
+    };
+
+    assert_matches!(serve_env.run(), Err(Error::Verify));
+
+    Ok(())
+  }
+
+  #[test]
+  fn listen_in_use() -> Result<()> {
+    let mut create_env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--announce",
+        "udp://127.0.0.1:1",
+      ],
+      tree: {
+        foo: "abc",
+      },
+    };
+
+    create_env.assert_ok();
+
+    let torrent = create_env.resolve("foo.torrent")?;
+
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+
+    let mut serve_env = test_env! {
+      args: [
+        "serve-webseed",
+        "--torrent",
+        &torrent,
+        "--listen",
+        listener.local_addr().unwrap().to_string(),
+      ],
+      tree: {},
+    };
+
+    assert_matches!(serve_env.run(), Err(Error::WebseedListen { .. }));
+
+    Ok(())
+  }
+}
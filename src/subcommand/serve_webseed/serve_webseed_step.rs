@@ -0,0 +1,40 @@
+use crate::common::*;
+
+#[derive(Clone, Copy)]
+pub(crate) enum ServeWebseedStep<'a> {
+  Loading { metainfo: &'a InputTarget },
+  Verifying { content: &'a Path },
+  Serving { addr: SocketAddr },
+}
+
+impl Step for ServeWebseedStep<'_> {
+  fn n(&self) -> usize {
+    match self {
+      Self::Loading { .. } => 1,
+      Self::Verifying { .. } => 2,
+      Self::Serving { .. } => 3,
+    }
+  }
+
+  fn symbol(&self) -> &str {
+    match self {
+      Self::Loading { .. } => "\u{1F4BE}",
+      Self::Verifying { .. } => "\u{1F9EE}",
+      Self::Serving { .. } => "\u{1F310}",
+    }
+  }
+
+  fn total() -> usize {
+    3
+  }
+
+  fn write_message(&self, write: &mut dyn Write) -> io::Result<()> {
+    match self {
+      Self::Loading { metainfo } => write!(write, "Loading metainfo from {metainfo}…"),
+      Self::Verifying { content } => {
+        write!(write, "Verifying pieces from `{}`…", content.display())
+      }
+      Self::Serving { addr } => write!(write, "Serving on `http://{addr}`…"),
+    }
+  }
+}
@@ -5,7 +5,10 @@ mod create;
 mod dump;
 mod from_link;
 mod link;
+mod lsd;
+mod peers;
 mod piece_length;
+mod seed;
 mod show;
 mod stats;
 mod verify;
@@ -22,8 +25,11 @@ pub(crate) enum Torrent {
   Dump(dump::Dump),
   FromLink(from_link::FromLink),
   Link(link::Link),
+  Lsd(lsd::Lsd),
+  Peers(peers::Peers),
   #[structopt(alias = "piece-size")]
   PieceLength(piece_length::PieceLength),
+  Seed(seed::Seed),
   Show(show::Show),
   Stats(stats::Stats),
   Verify(verify::Verify),
@@ -32,12 +38,15 @@ pub(crate) enum Torrent {
 impl Torrent {
   pub(crate) fn run(self, env: &mut Env, options: &Options) -> Result<(), Error> {
     match self {
-      Self::Announce(announce) => announce.run(env),
+      Self::Announce(announce) => announce.run(env, options),
       Self::Create(create) => create.run(env, options),
       Self::Dump(dump) => dump.run(env),
       Self::FromLink(from_link) => from_link.run(env, options),
       Self::Link(link) => link.run(env),
+      Self::Lsd(lsd) => lsd.run(env, options),
+      Self::Peers(peers) => peers.run(env, options),
       Self::PieceLength(piece_length) => piece_length.run(env),
+      Self::Seed(seed) => seed.run(env, options),
       Self::Show(show) => show.run(env),
       Self::Stats(stats) => stats.run(env, options),
       Self::Verify(verify) => verify.run(env, options),
@@ -1,4 +1,7 @@
 use crate::common::*;
+use job::{Job, Outcome};
+
+mod job;
 
 const INPUT_HELP: &str =
   "Read torrent metainfo from `INPUT`. If `INPUT` is `-`, read metainfo from standard input.";
@@ -14,6 +17,20 @@ const INPUT_POSITIONAL: &str = "<INPUT>";
   about("Announce a .torrent file.")
 )]
 pub(crate) struct Announce {
+  #[structopt(
+    long = "blocklist",
+    value_name = "FILE",
+    parse(from_os_str),
+    help = "Ignore peers with addresses in the blocklist `FILE`, which may contain eMule \
+            `ipfilter.dat` ranges, PeerGuardian `.p2p` ranges, and CIDR blocks."
+  )]
+  blocklist: Option<PathBuf>,
+  #[structopt(
+    long = "exchange-trackers",
+    help = "Ask the peers that trackers return for more trackers with BEP 28 tracker exchange, \
+            and announce to those too. Ignored if the torrent is private."
+  )]
+  exchange_trackers: bool,
   #[structopt(
     name = INPUT_FLAG,
     long = "input",
@@ -34,10 +51,19 @@ pub(crate) struct Announce {
     help = INPUT_HELP,
   )]
   input_positional: Option<InputTarget>,
+  #[structopt(
+    long = "tracker-timeout",
+    value_name = "SECONDS",
+    help = "Resend requests to trackers that have not responded within `SECONDS`, giving up after \
+            three attempts. Defaults to 3 seconds."
+  )]
+  tracker_timeout: Option<u64>,
 }
 
 impl Announce {
-  pub(crate) fn run(self, env: &mut Env) -> Result<(), Error> {
+  const MAX_CONCURRENT_ANNOUNCES: usize = 50;
+
+  pub(crate) fn run(self, env: &mut Env, options: &Options) -> Result<(), Error> {
     let target = xor_args(
       "input_flag",
       self.input_flag.as_ref(),
@@ -48,8 +74,17 @@ impl Announce {
     let input = env.read(target)?;
     let infohash = Infohash::from_input(&input)?;
     let metainfo = Metainfo::from_input(&input)?;
-    let mut peers = HashSet::new();
+    let blocklist = match &self.blocklist {
+      Some(path) => Blocklist::load(&env.resolve(path)?)?,
+      None => Blocklist::default(),
+    };
+    let proxy = options.proxy(env)?;
+    let timeout = self
+      .tracker_timeout
+      .map_or(tracker::Announcer::TIMEOUT, Duration::from_secs);
+    let mut reactor = Reactor::new(Self::MAX_CONCURRENT_ANNOUNCES)?;
     let mut usable_trackers = 0;
+    let mut trackers = HashSet::new();
 
     for tracker_url in metainfo.trackers() {
       let tracker_url = match tracker_url {
@@ -60,8 +95,10 @@ impl Announce {
         }
       };
 
-      let client = match tracker::Client::from_url(&tracker_url) {
-        Ok(client) => client,
+      trackers.insert(tracker_url.clone());
+
+      let job = match Self::job(&tracker_url, infohash, timeout, proxy.as_ref()) {
+        Ok(job) => job,
         Err(err) => {
           errln!(env, "Couldn't build tracker client. {}", err)?;
           continue;
@@ -69,22 +106,119 @@ impl Announce {
       };
 
       usable_trackers += 1;
-      match client.announce_exchange(&infohash) {
-        Ok(peer_list) => peers.extend(peer_list),
-        Err(err) => errln!(env, "Announce failed: {}", err)?,
-      }
+      reactor.push(job);
     }
 
+    // BEP 28 tracker exchange must not be used with private torrents.
+    let exchange_trackers = self.exchange_trackers && metainfo.info.private != Some(true);
+    let piece_count = metainfo.info.pieces.count();
+
+    let mut peers = HashSet::new();
+    let mut blocked = HashSet::new();
+    let mut learned = HashSet::new();
+    reactor.run(|reactor, job, result| {
+      match (job, result) {
+        (Job::Announce(_), Ok(Outcome::Peers(mut list))) => {
+          blocked.extend(blocklist.filter(&mut list));
+
+          for addr in list {
+            if peers.insert(addr) && exchange_trackers {
+              reactor.push(Job::Probe(
+                peer::Probe::new(
+                  addr,
+                  infohash,
+                  piece_count,
+                  peer::Limits::default(),
+                  peer::Encryption::default(),
+                )
+                .exchanging_trackers()
+                .via(proxy.as_ref()),
+              ));
+            }
+          }
+        }
+        (Job::WebSocket(announcer), Ok(Outcome::Swarm(swarm))) => {
+          errln!(env, "Tracker `{}` reports {}.", announcer.url(), swarm)?;
+        }
+        (Job::Announce(_) | Job::WebSocket(_), Err(err)) => {
+          errln!(env, "Announce failed: {}", err)?;
+        }
+        (Job::Probe(_), Ok(Outcome::Report(report))) => {
+          for tracker_url in report.trackers {
+            if !trackers.insert(tracker_url.clone()) {
+              continue;
+            }
+
+            if !options.quiet {
+              errln!(env, "Learned tracker `{}` from peer.", tracker_url)?;
+            }
+
+            // Peers may name any host, so only announce to public addresses.
+            if let Ok(resolver) = tracker::Resolver::new(&tracker_url, infohash, timeout) {
+              learned.insert(tracker_url);
+              reactor.push(Job::Resolve(resolver.public_only().via(proxy.as_ref())));
+            }
+          }
+        }
+        (Job::Resolve(_), Ok(Outcome::Announcer(announcer))) => {
+          reactor.push(Job::Announce(announcer));
+        }
+        (Job::Resolve(resolver), Err(err)) => {
+          if !options.quiet || !learned.contains(resolver.url()) {
+            errln!(
+              env,
+              "Couldn't resolve tracker `{}`: {}",
+              resolver.url(),
+              err
+            )?;
+          }
+        }
+        (Job::Probe(probe), Err(err)) => {
+          if let Some(probe) = probe.fallback(&err) {
+            reactor.push(Job::Probe(probe));
+          }
+        }
+        (Job::Announce(_) | Job::Probe(_) | Job::Resolve(_) | Job::WebSocket(_), Ok(_)) => {}
+      }
+      Ok(Control::Continue)
+    })?;
+
     if usable_trackers == 0 {
       return Err(Error::MetainfoMissingTrackers);
     }
 
+    if self.blocklist.is_some() && !options.quiet {
+      errln!(env, "Blocklist filtered {} peers.", blocked.len())?;
+    }
+
     for peer in &peers {
       outln!(env, "{}", peer)?;
     }
 
     Ok(())
   }
+
+  /// Build the job that announces to `tracker_url`, using the WebSocket
+  /// protocol for `ws://` and `wss://` URLs, and UDP otherwise.
+  fn job(
+    tracker_url: &Url,
+    infohash: Infohash,
+    timeout: Duration,
+    proxy: Option<&Proxy>,
+  ) -> Result<Job> {
+    if tracker::WebSocketAnnouncer::supports(tracker_url) {
+      Ok(Job::WebSocket(tracker::WebSocketAnnouncer::from_url(
+        tracker_url,
+        infohash,
+        timeout,
+        proxy,
+      )?))
+    } else {
+      Ok(Job::Resolve(
+        tracker::Resolver::new(tracker_url, infohash, timeout)?.via(proxy),
+      ))
+    }
+  }
 }
 
 #[cfg(test)]
@@ -110,6 +244,8 @@ mod tests {
         mode: Mode::Single {
           length: Bytes(2 * 16 * 1024),
           md5sum: None,
+          sha1: None,
+          sha256: None,
         },
         update_url: None,
       },
@@ -217,6 +353,8 @@ mod tests {
         mode: Mode::Single {
           length: Bytes(2 * 16 * 1024),
           md5sum: None,
+          sha1: None,
+          sha256: None,
         },
         update_url: None,
       },
@@ -227,7 +365,7 @@ mod tests {
     assert_eq!(
       env.err(),
       format!(
-        "Couldn't build tracker client. Cannot connect to tracker `{https_tracker_url}`: only UDP trackers are supported\n",
+        "Couldn't build tracker client. Cannot connect to tracker `{https_tracker_url}`: only UDP and WebSocket trackers are supported\n",
       )
     );
   }
@@ -261,6 +399,8 @@ mod tests {
         mode: Mode::Single {
           length: Bytes(2 * 16 * 1024),
           md5sum: None,
+          sha1: None,
+          sha256: None,
         },
         update_url: None,
       },
@@ -268,4 +408,176 @@ mod tests {
     env.write("test.torrent", metainfo.serialize().unwrap());
     assert_matches!(env.run(), Err(Error::MetainfoMissingTrackers));
   }
+
+  #[test]
+  fn blocklist() {
+    let mut metainfo = new_dummy_metainfo();
+    let infohash = metainfo.infohash_lossy().unwrap();
+    let peers = HashSet::from([
+      SocketAddr::from((Ipv4Addr::new(1, 2, 3, 4), 1)),
+      SocketAddr::from((Ipv4Addr::new(5, 6, 7, 8), 2)),
+    ]);
+    let (_, addr) = tracker::Daemon::spawn_with_records(HashMap::from([(infohash.into(), peers)]));
+    metainfo.announce = Some(format!("udp://{addr}"));
+
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "announce",
+        "test.torrent",
+        "--blocklist",
+        "list.p2p",
+      ],
+      tree: {
+        "list.p2p": "Bad Actors:1.2.3.0-1.2.3.255\n",
+      },
+    };
+    env.write("test.torrent", metainfo.serialize().unwrap());
+    env.assert_ok();
+
+    assert_eq!(env.out(), "5.6.7.8:2\n");
+    assert_eq!(env.err(), "Blocklist filtered 1 peers.\n");
+  }
+
+  #[test]
+  fn blocklist_quiet() {
+    let mut metainfo = new_dummy_metainfo();
+    let infohash = metainfo.infohash_lossy().unwrap();
+    let peers = HashSet::from([
+      SocketAddr::from((Ipv4Addr::new(1, 2, 3, 4), 1)),
+      SocketAddr::from((Ipv4Addr::new(5, 6, 7, 8), 2)),
+    ]);
+    let (_, addr) = tracker::Daemon::spawn_with_records(HashMap::from([(infohash.into(), peers)]));
+    metainfo.announce = Some(format!("udp://{addr}"));
+
+    let mut env = test_env! {
+      args: [
+        "--quiet",
+        "torrent",
+        "announce",
+        "test.torrent",
+        "--blocklist",
+        "list.p2p",
+      ],
+      tree: {
+        "list.p2p": "Bad Actors, Inc:1.2.3.0-1.2.3.255\n",
+      },
+    };
+    env.write("test.torrent", metainfo.serialize().unwrap());
+    env.assert_ok();
+
+    assert_eq!(env.out(), "5.6.7.8:2\n");
+    assert_eq!(env.err(), "");
+  }
+
+  #[test]
+  fn blocklist_invalid() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "announce",
+        "test.torrent",
+        "--blocklist",
+        "list.txt",
+      ],
+      tree: {
+        "list.txt": "1.2.3.4\nfoo\n",
+      },
+    };
+    env.write("test.torrent", new_dummy_metainfo().serialize().unwrap());
+    assert_matches!(env.run(), Err(Error::BlocklistParse { line: 2, .. }));
+  }
+
+  #[test]
+  fn proxy() {
+    let mut metainfo = new_dummy_metainfo();
+    let infohash = metainfo.infohash_lossy().unwrap();
+    let peer = SocketAddr::from((Ipv4Addr::new(1, 2, 3, 4), 1));
+    let (_, addr) = tracker::Daemon::spawn_with_records(HashMap::from([(
+      infohash.into(),
+      HashSet::from([peer]),
+    )]));
+    metainfo.announce = Some(format!("udp://{addr}"));
+
+    let (server, proxy) = proxy::Server::spawn();
+
+    let mut env = test_env! {
+      args: [
+        "--proxy",
+        format!("socks5://{proxy}"),
+        "torrent",
+        "announce",
+        "test.torrent",
+      ],
+      tree: {},
+    };
+    env.write("test.torrent", metainfo.serialize().unwrap());
+    env.assert_ok();
+
+    assert_eq!(env.out(), "1.2.3.4:1\n");
+    assert!(server.targets().contains(&addr.into()));
+  }
+
+  #[test]
+  fn web_socket() {
+    let mut metainfo = new_dummy_metainfo();
+    let (daemon, addr) = tracker::WebSocketDaemon::spawn(tracker::Swarm {
+      complete: 3,
+      incomplete: 5,
+      downloaded: Some(8),
+    });
+    let tracker_url = format!("ws://{addr}/announce");
+    metainfo.announce = Some(tracker_url.clone());
+
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "announce",
+        "test.torrent",
+      ],
+      tree: {},
+    };
+    env.write("test.torrent", metainfo.serialize().unwrap());
+    env.assert_ok();
+
+    assert_eq!(env.out(), "");
+    assert_eq!(
+      env.err(),
+      format!("Tracker `{tracker_url}` reports 3 seeders, 5 leechers, 8 downloads.\n"),
+    );
+    assert_eq!(daemon.requests().len(), 2);
+  }
+
+  #[test]
+  fn proxy_invalid() {
+    let mut env = test_env! {
+      args: [
+        "--proxy",
+        "ftp://127.0.0.1:21",
+        "torrent",
+        "announce",
+        "test.torrent",
+      ],
+      tree: {},
+    };
+    env.write("test.torrent", new_dummy_metainfo().serialize().unwrap());
+    assert_matches!(env.run(), Err(Error::ProxyUrl { .. }));
+  }
+
+  #[test]
+  fn proxy_variable_invalid() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "announce",
+        "test.torrent",
+      ],
+      vars: {
+        "IMDL_PROXY": "foo",
+      },
+      tree: {},
+    };
+    env.write("test.torrent", new_dummy_metainfo().serialize().unwrap());
+    assert_matches!(env.run(), Err(Error::ProxyUrlParse { text, .. }) if text == "foo");
+  }
 }
@@ -0,0 +1,68 @@
+use crate::common::*;
+
+/// The work `announce` performs on its event loop: announcing to trackers to
+/// discover peers or, for WebSocket trackers, the size of the swarm, and,
+/// with tracker exchange, probing those peers for more trackers and resolving
+/// the addresses of the trackers they tell us about.
+pub(crate) enum Job {
+  Announce(tracker::Announcer),
+  Probe(peer::Probe),
+  Resolve(tracker::Resolver),
+  WebSocket(tracker::WebSocketAnnouncer),
+}
+
+pub(crate) enum Outcome {
+  Announcer(tracker::Announcer),
+  Peers(Vec<SocketAddr>),
+  Report(peer::Report),
+  Swarm(tracker::Swarm),
+}
+
+impl Task for Job {
+  type Output = Outcome;
+
+  fn start(&mut self, now: Instant) -> Result<()> {
+    match self {
+      Self::Announce(announcer) => announcer.start(now),
+      Self::Probe(probe) => probe.start(now),
+      Self::Resolve(resolver) => resolver.start(now),
+      Self::WebSocket(announcer) => announcer.start(now),
+    }
+  }
+
+  fn source(&mut self) -> &mut dyn mio::event::Source {
+    match self {
+      Self::Announce(announcer) => announcer.source(),
+      Self::Probe(probe) => probe.source(),
+      Self::Resolve(resolver) => resolver.source(),
+      Self::WebSocket(announcer) => announcer.source(),
+    }
+  }
+
+  fn interest(&self) -> mio::Interest {
+    match self {
+      Self::Announce(announcer) => announcer.interest(),
+      Self::Probe(probe) => probe.interest(),
+      Self::Resolve(resolver) => resolver.interest(),
+      Self::WebSocket(announcer) => announcer.interest(),
+    }
+  }
+
+  fn wakeup(&self) -> Option<Instant> {
+    match self {
+      Self::Announce(announcer) => announcer.wakeup(),
+      Self::Probe(probe) => probe.wakeup(),
+      Self::Resolve(resolver) => resolver.wakeup(),
+      Self::WebSocket(announcer) => announcer.wakeup(),
+    }
+  }
+
+  fn advance(&mut self, now: Instant) -> Result<Option<Outcome>> {
+    match self {
+      Self::Announce(announcer) => Ok(announcer.advance(now)?.map(Outcome::Peers)),
+      Self::Probe(probe) => Ok(probe.advance(now)?.map(Outcome::Report)),
+      Self::Resolve(resolver) => Ok(resolver.advance(now)?.map(Outcome::Announcer)),
+      Self::WebSocket(announcer) => Ok(announcer.advance(now)?.map(Outcome::Swarm)),
+    }
+  }
+}
@@ -1,7 +1,9 @@
 use crate::common::*;
+use create_batch::CreateBatch;
 use create_content::CreateContent;
 use create_step::CreateStep;
 
+mod create_batch;
 mod create_content;
 mod create_step;
 
@@ -22,6 +24,19 @@ const INPUT_POSITIONAL: &str = "<INPUT>";
   about("Create a .torrent file.")
 )]
 pub(crate) struct Create {
+  #[structopt(
+    long = "add",
+    value_name = "SRC[:DEST]",
+    conflicts_with_all = &[INPUT_FLAG, INPUT_POSITIONAL, "files-from", "from-archive"],
+    requires = "name",
+    help = "Add the file or directory at `SRC` to the torrent at `DEST`, instead of reading \
+            torrent contents from `INPUT`. `DEST` defaults to the filename of `SRC`. May be given \
+            more than once to combine several sources into one torrent, and directories added at \
+            the same `DEST` are merged. It is an error for two sources to add the same file. \
+            Requires `--name`. To verify the torrent, pass the same `--add` arguments to `imdl \
+            torrent verify`."
+  )]
+  adds: Vec<Mapping>,
   #[structopt(
     long = "announce",
     short = "a",
@@ -60,6 +75,34 @@ pub(crate) struct Create {
             https://github.com/bittorrent/bittorrent.org/issues/82"
   )]
   announce_tiers: Vec<String>,
+  #[structopt(
+    long = "batch",
+    value_name = "DIR",
+    empty_values(false),
+    parse(from_os_str),
+    conflicts_with_all = &[
+      INPUT_FLAG,
+      INPUT_POSITIONAL,
+      "adds",
+      "files-from",
+      "from-archive",
+      "name",
+      "output",
+      "open",
+      "print-magnet-link",
+      "profiles",
+      "profile-file",
+      "show",
+    ],
+    requires = "output-dir",
+    help = "Create a torrent for each file and directory in `DIR`, named after it and saved to \
+            `--output-dir`. Entries are hashed in parallel, and every other setting, including \
+            `--glob`, lints, and piece length picking, applies to each entry as it would to \
+            `INPUT`. Hidden and junk entries are skipped unless `--include-hidden` or \
+            `--include-junk` are given. Entries whose `.torrent` file already exists are skipped \
+            unless `--force` is given. Prints a table of each entry's name, status, and infohash."
+  )]
+  batch: Option<PathBuf>,
   #[structopt(
     long = "comment",
     short = "c",
@@ -90,6 +133,28 @@ Examples:
     help = "Skip writing `.torrent` file to disk."
   )]
   dry_run: bool,
+  #[structopt(
+    long = "file-checksum",
+    value_name = "ALGORITHM",
+    possible_values = FileChecksum::VARIANTS,
+    help = "Include an `ALGORITHM` checksum of each file in the torrent, as a `sha1` key for \
+            `sha1`, as described in BEP 47, or a `sha256` key for `sha256`. May be given more \
+            than once. Checksums are computed while hashing pieces, so files are only read once."
+  )]
+  file_checksums: Vec<FileChecksum>,
+  #[structopt(
+    long = "files-from",
+    value_name = "FILE",
+    empty_values(false),
+    parse(try_from_os_str = InputTarget::try_from_os_str),
+    conflicts_with_all = &["from-archive", "globs"],
+    help = "Include exactly the files listed in `FILE`, instead of searching `INPUT` for files. \
+            If `FILE` is `-`, read the list from standard input. Paths are relative to `INPUT`, \
+            and are separated by NUL bytes if the list contains any, and by newlines otherwise. \
+            Files are kept in the order listed unless `--sort-by` or `--reproducible` is given. \
+            Listed files are included even if they are hidden or junk."
+  )]
+  files_from: Option<InputTarget>,
   #[structopt(
     long = "follow-symlinks",
     short = "F",
@@ -103,6 +168,15 @@ Examples:
     help = "Overwrite the destination `.torrent` file, if it exists."
   )]
   force: bool,
+  #[structopt(
+    long = "from-archive",
+    help = "Treat `INPUT` as a `.tar`, `.tar.gz`, or `.zip` archive, and create a multi-file \
+            torrent from its entries without extracting them. `--glob`, `--include-hidden`, \
+            `--include-junk`, and `--sort-by` apply to archive entries as they would to files. \
+            With `--input -`, reads an archive, such as a tar stream, from standard input. The \
+            name of the torrent defaults to the archive's filename without its extension."
+  )]
+  from_archive: bool,
   #[structopt(
     long = "glob",
     short = "g",
@@ -128,7 +202,7 @@ Examples:
     name = INPUT_POSITIONAL,
     value_name = "INPUT",
     empty_values = false,
-    required_unless = INPUT_FLAG,
+    required_unless_one = &[INPUT_FLAG, "adds", "batch"],
     conflicts_with = INPUT_FLAG,
     parse(try_from_os_str = InputTarget::try_from_os_str),
     help = INPUT_HELP,
@@ -149,6 +223,16 @@ Examples:
     help = "Print created torrent `magnet:` URL to standard output"
   )]
   print_magnet_link: bool,
+  #[structopt(
+    long = "manifest",
+    value_name = "PATH",
+    empty_values(false),
+    parse(from_os_str),
+    requires = "batch",
+    help = "Write a JSON object to `PATH` mapping the name of each torrent created or skipped by \
+            `--batch` to its `infohash` and `magnet` link."
+  )]
+  manifest: Option<PathBuf>,
   #[structopt(
     long = "md5",
     short = "M",
@@ -166,6 +250,18 @@ Examples:
     required_if(INPUT_POSITIONAL, "-")
   )]
   name: Option<String>,
+  #[structopt(
+    long = "no-announce",
+    conflicts_with = "announce",
+    help = "Ignore the announce URL and announce tiers given in the configuration file. Announce \
+            tiers may still be given with `--announce-tier`."
+  )]
+  no_announce: bool,
+  #[structopt(
+    long = "no-cache",
+    help = "Read and hash every file, even if `--hash-cache` is given, and don't update the cache."
+  )]
+  no_cache: bool,
   #[structopt(
     long = "no-created-by",
     help = "Do not populate `created by` key of generated torrent with imdl version information."
@@ -176,6 +272,12 @@ Examples:
     help = "Do not populate `creation date` key of generated torrent with current time."
   )]
   no_creation_date: bool,
+  #[structopt(
+    long = "no-private",
+    conflicts_with = "private",
+    help = "Don't set the `private` flag, even if the configuration file sets it."
+  )]
+  no_private: bool,
   #[structopt(
     long = "open",
     short = "O",
@@ -218,6 +320,16 @@ Sort in ascending order by size, break ties in descending path order:
             when `--input -`.",
   )]
   output: Option<OutputTarget>,
+  #[structopt(
+    long = "output-dir",
+    value_name = "DIR",
+    empty_values(false),
+    parse(from_os_str),
+    requires = "batch",
+    help = "Save the `.torrent` files created by `--batch` to `DIR`, which is created if it does \
+            not exist."
+  )]
+  output_dir: Option<PathBuf>,
   #[structopt(
     long = "peer",
     value_name = "PEER",
@@ -232,6 +344,36 @@ Sort in ascending order by size, break ties in descending path order:
     help = "Set piece length to `BYTES`. Accepts SI units, e.g. kib, mib, and gib."
   )]
   piece_length: Option<Bytes>,
+  #[structopt(
+    long = "piece-length-strategy",
+    value_name = "STRATEGY",
+    conflicts_with = "piece-length",
+    help = "Pick a piece length according to `STRATEGY` when content is not read from standard \
+            input. Defaults to `auto`. All strategies pick a power of two of at \
+            least 16 KiB:
+
+`auto`: Intermodal's usual choice, between 16 KiB and 16 MiB, growing with content size.
+
+`client-compat`: As `auto`, but no more than 8 MiB, since some older clients reject larger \
+            pieces.
+
+`max-metainfo-size:BYTES`: The smallest piece length for which the piece list is no larger than \
+            `BYTES`.
+
+`target-piece-count:N`: The smallest piece length that yields no more than `N` pieces.
+
+Run `imdl torrent piece-length PATH` to compare piece lengths for the content at `PATH`."
+  )]
+  piece_length_strategy: Option<PieceLengthStrategy>,
+  #[structopt(
+    long = "preset",
+    value_name = "NAME",
+    help = "Use the defaults in the `[preset.NAME]` table of the configuration file, which take \
+            precedence over those in its `[create]` table. Arguments take precedence over both, \
+            with `--allow`, `--announce-tier`, and `--sort-by` replacing the configured lists \
+            rather than adding to them."
+  )]
+  preset: Option<String>,
   #[structopt(
     long = "private",
     short = "P",
@@ -242,6 +384,45 @@ Sort in ascending order by size, break ties in descending path order:
             more information."
   )]
   private: bool,
+  #[structopt(
+    long = "profile",
+    value_name = "PROFILE",
+    help = "Create an additional torrent according to `PROFILE`, sharing a single hashing pass \
+            with the other torrents created. `PROFILE` should be of the form \
+            `NAME:KEY=VALUE,…`, with `KEY` being one of `announce`, `announce-tier`, `comment`, \
+            `output`, `private`, or `source`. Settings that are not given are taken from the \
+            other arguments, and settings with empty values are cleared. Each `announce-tier` \
+            adds a tier, and profiles with their own `announce` URL only get the tiers they \
+            give. Commas in values may be escaped with a backslash. `output` defaults to the \
+            torrent's usual output, with `.NAME` inserted before the `.torrent` extension. When \
+            profiles are given, only the profiles' torrents are created:
+
+    --profile alpha:announce=https://alpha.example/announce,source=ALPHA,private=true
+    --profile beta:announce=https://beta.example/announce,source=BETA,comment=Hi
+    --profile gamma:announce-tier=udp://a.example:80\\,udp://b.example:80,output=gamma.torrent"
+  )]
+  profiles: Vec<Profile>,
+  #[structopt(
+    long = "profile-file",
+    value_name = "PATH",
+    empty_values(false),
+    parse(from_os_str),
+    help = "Read profiles from `PATH`, one per line, in the same form as the argument to \
+            `--profile`. Blank lines and lines beginning with `#` are ignored."
+  )]
+  profile_file: Option<PathBuf>,
+  #[structopt(
+    long = "reproducible",
+    conflicts_with = "sort-by",
+    help = "Create the same `.torrent` file from the same content, wherever and whenever it is \
+            created. Sets `creation date` to `SOURCE_DATE_EPOCH` if it is set, and omits it \
+            otherwise, sets `created by` to the imdl version without the git commit hash, sorts \
+            files by path, and converts file names to Unicode Normalization Form C, so that names \
+            composed differently by different platforms match. Files with converted names can \
+            only be verified or seeded on filesystems that treat differently composed names as \
+            the same, such as those of macOS, unless they are renamed."
+  )]
+  reproducible: bool,
   #[structopt(
     long = "show",
     short = "S",
@@ -277,13 +458,29 @@ Sort in ascending order by size, break ties in descending path order:
 }
 
 impl Create {
-  pub(crate) fn run(self, env: &mut Env, options: &Options) -> Result<(), Error> {
-    let input = xor_args(
-      "input_positional",
-      self.input_positional.as_ref(),
-      "input_flag",
-      self.input_flag.as_ref(),
-    )?;
+  pub(crate) fn run(mut self, env: &mut Env, options: &Options) -> Result<(), Error> {
+    let defaults = options
+      .config(env)?
+      .defaults(self.preset.as_deref(), |name| {
+        env.var(name).map(str::to_owned)
+      })?;
+
+    self.apply(defaults);
+
+    if self.reproducible {
+      self.sort_by.clear();
+    }
+
+    let input = if self.adds.is_empty() && self.batch.is_none() {
+      Some(xor_args(
+        "input_positional",
+        self.input_positional.as_ref(),
+        "input_flag",
+        self.input_flag.as_ref(),
+      )?)
+    } else {
+      None
+    };
 
     let mut linter = Linter::new();
     linter.allow(self.allowed_lints.iter().copied());
@@ -301,12 +498,19 @@ impl Create {
       announce_list.push(tier);
     }
 
-    if linter.is_denied(Lint::PrivateTrackerless) && self.private && self.announce.is_none() {
+    if linter.is_denied(Lint::PrivateTrackerless)
+      && self.private
+      && self.announce.is_none()
+      && self.profiles.is_empty()
+      && self.profile_file.is_none()
+    {
       return Err(Error::PrivateTrackerless);
     }
 
     let creation_date = if self.no_creation_date {
       None
+    } else if self.reproducible {
+      Self::source_date_epoch(env.var("SOURCE_DATE_EPOCH"))?
     } else {
       Some(
         SystemTime::now()
@@ -315,162 +519,415 @@ impl Create {
       )
     };
 
-    CreateStep::Searching { input: &input }.print(env)?;
-
-    let content = CreateContent::from_create(&self, &input, env)?;
+    let created_by = if self.no_created_by {
+      None
+    } else if self.reproducible {
+      Some(String::from(consts::CREATED_BY_REPRODUCIBLE))
+    } else {
+      Some(String::from(consts::CREATED_BY_DEFAULT))
+    };
 
-    let mut output = content.output.resolve(env)?;
+    let common = Common {
+      announce_list,
+      created_by,
+      creation_date,
+    };
 
-    if content.piece_length.count() == 0 {
-      return Err(Error::PieceLengthZero);
+    if let Some(batch) = &self.batch {
+      return CreateBatch::run(&self, batch, &linter, &common, env, options);
     }
 
-    if linter.is_denied(Lint::UnevenPieceLength) && !content.piece_length.count().is_power_of_two()
-    {
-      return Err(Error::PieceLengthUneven {
-        bytes: content.piece_length,
-      });
+    CreateStep::Searching {
+      input: input.as_ref(),
     }
+    .print(env)?;
 
-    if linter.is_denied(Lint::SmallPieceLength) && content.piece_length.count() < 16 * 1024 {
-      return Err(Error::PieceLengthSmall);
-    }
+    let content = CreateContent::from_create(&self, input.as_ref(), env)?;
+
+    #[cfg(test)]
+    let layout = content.files.as_ref().and_then(Files::laid_out).cloned();
+
+    let mut output = content.output.resolve(env)?;
+
+    Self::check_piece_length(&linter, content.piece_length)?;
 
     if let OutputTarget::Path(path) = &mut output {
       if path.is_dir() {
         path.push(format!("{}.torrent", content.name));
       }
+    }
 
-      if !self.force && path.exists() {
-        return Err(Error::OutputExists { path: path.clone() });
+    let mut profiles = self.profiles.clone();
+
+    if let Some(path) = &self.profile_file {
+      profiles.extend(Profile::load(&env.resolve(path)?)?);
+    }
+
+    let mut names = HashSet::new();
+    for profile in &profiles {
+      if !names.insert(&profile.name) {
+        return Err(Error::ProfileDuplicate {
+          name: profile.name.clone(),
+        });
       }
     }
 
-    let private = if self.private { Some(true) } else { None };
+    let targets = if profiles.is_empty() {
+      vec![Target {
+        display: content.output.clone(),
+        output,
+        announce: self.announce.clone(),
+        announce_list: common.announce_list.clone(),
+        comment: self.comment.clone(),
+        private: self.private,
+        source: self.source.clone(),
+      }]
+    } else {
+      profiles
+        .into_iter()
+        .map(|profile| {
+          let mut output = match &profile.output {
+            Some(output) => output.resolve(env)?,
+            None => profile.default_output(&output)?,
+          };
+
+          if let OutputTarget::Path(path) = &mut output {
+            if path.is_dir() {
+              path.push(format!("{}.{}.torrent", content.name, profile.name));
+            }
+          }
+
+          // Show paths relative to the working directory, like the
+          // arguments they came from.
+          let display = match &output {
+            OutputTarget::Path(path) => {
+              OutputTarget::Path(path.strip_prefix(env.dir()).unwrap_or(path).to_owned())
+            }
+            OutputTarget::Stdout => OutputTarget::Stdout,
+          };
+
+          Ok(Target {
+            display,
+            output,
+            announce_list: profile.announce_list(&common.announce_list),
+            announce: profile.announce.or(self.announce.clone()),
+            comment: profile.comment.or(self.comment.clone()),
+            private: profile.private.unwrap_or(self.private),
+            source: profile.source.or(self.source.clone()),
+          })
+        })
+        .collect::<Result<Vec<Target>>>()?
+    };
 
-    let created_by = if self.no_created_by {
+    let mut outputs = Vec::new();
+    for target in &targets {
+      if outputs.contains(&&target.output) {
+        return Err(Error::ProfileOutputConflict {
+          output: target.display.clone(),
+        });
+      }
+      outputs.push(&target.output);
+
+      if linter.is_denied(Lint::PrivateTrackerless) && target.private && target.announce.is_none() {
+        return Err(Error::PrivateTrackerless);
+      }
+
+      if let OutputTarget::Path(path) = &target.output {
+        if !self.force && path.exists() {
+          return Err(Error::OutputExists { path: path.clone() });
+        }
+      }
+    }
+
+    let cache = if self.no_cache {
       None
     } else {
-      Some(String::from(consts::CREATED_BY_DEFAULT))
+      options.hash_cache(env)?
     };
 
     CreateStep::Hashing.print(env)?;
 
     let hasher = Hasher::new(
-      self.md5sum,
+      FileChecksum::kinds(self.md5sum, &self.file_checksums),
       content.piece_length.as_piece_length()?.into_usize(),
       if env.err().is_styled_term() && !options.quiet {
         Some(content.progress_bar)
       } else {
         None
       },
-    );
+    )
+    .caching(cache.clone());
 
-    let (mode, pieces) = if let Some(files) = content.files {
+    let (mut mode, pieces) = if let Some(files) = content.files {
       hasher.hash_files(&files)?
     } else {
       hasher.hash_stdin(&mut env.input())?
     };
 
-    CreateStep::Writing {
-      output: &content.output,
+    if let Some(err) = cache.as_ref().and_then(HashCache::take_failure) {
+      errln!(env, "Couldn't update hash cache: {}", err)?;
+    }
+
+    let (name, converted) = if self.reproducible {
+      let name = nfc(&content.name);
+      let converted = Self::normalize(&mut mode)? || name != content.name;
+      (name, converted)
+    } else {
+      (content.name.clone(), false)
+    };
+
+    // Files are looked up by the names in the torrent, so on filesystems
+    // that don't normalize names, like most on Linux, the original files
+    // can't be found under their converted names.
+    if converted && !options.quiet {
+      errln!(
+        env,
+        "Converted file names to Unicode Normalization Form C. Verifying or seeding this \
+         torrent requires files with the converted names, unless the filesystem treats \
+         differently composed names as the same."
+      )?;
+    }
+
+    // Every target shares the pieces and mode from the single hashing pass
+    // above, and differs only in the settings of its profile.
+    for target in targets {
+      CreateStep::Writing {
+        output: &target.display,
+      }
+      .print(env)?;
+
+      let metainfo = self.metainfo(
+        &common,
+        &target,
+        name.clone(),
+        content.piece_length,
+        mode.clone(),
+        pieces.clone(),
+      );
+
+      let bytes = metainfo.serialize()?;
+
+      if !self.dry_run {
+        self.write(env, &target.output, &bytes)?;
+      }
+
+      #[cfg(test)]
+      {
+        // Archive entries aren't files that can be verified, files added
+        // with `--add` are found through the layout, and files with
+        // converted names can't be found at all.
+        let base = match (&input, self.from_archive) {
+          _ if converted => None,
+          (Some(InputTarget::Path(path)), false) => Some(env.resolve(path)?),
+          (None, _) => Some(env.dir().to_owned()),
+          _ => None,
+        };
+
+        if let Some(base) = base {
+          let deserialized = bendy::serde::de::from_bytes::<Metainfo>(&bytes).unwrap();
+
+          assert_eq!(deserialized, metainfo);
+
+          let status = metainfo.verify(&base, layout.as_ref(), None, None)?;
+
+          status.print(env)?;
+
+          if !status.good() {
+            return Err(Error::Verify);
+          }
+        }
+      }
+
+      if self.show {
+        // We just created this torrent, so no extra fields have been discarded.
+        TorrentSummary::from_metainfo_lossy(metainfo.clone())?.write(env)?;
+      }
+
+      if self.print_magnet_link {
+        // We just created this torrent, so no extra fields have been discarded.
+        let mut link = MagnetLink::from_metainfo_lossy(&metainfo)?;
+        for peer in &self.peers {
+          link.add_peer(peer.clone());
+        }
+        outln!(env, "{}", link)?;
+      }
+
+      if let OutputTarget::Path(path) = &target.output {
+        if self.open {
+          Platform::open_file(path)?;
+        }
+      }
+    }
+
+    errln!(env, "\u{2728}\u{2728} Done! \u{2728}\u{2728}")?;
+
+    Ok(())
+  }
+
+  fn check_piece_length(linter: &Linter, piece_length: Bytes) -> Result<()> {
+    if piece_length.count() == 0 {
+      return Err(Error::PieceLengthZero);
     }
-    .print(env)?;
 
+    if linter.is_denied(Lint::UnevenPieceLength) && !piece_length.count().is_power_of_two() {
+      return Err(Error::PieceLengthUneven {
+        bytes: piece_length,
+      });
+    }
+
+    if linter.is_denied(Lint::SmallPieceLength) && piece_length.count() < 16 * 1024 {
+      return Err(Error::PieceLengthSmall);
+    }
+
+    Ok(())
+  }
+
+  // Convert file paths to Unicode Normalization Form C for `--reproducible`,
+  // failing if two paths differ only in how they are composed. Returns
+  // whether any path was changed.
+  fn normalize(mode: &mut Mode) -> Result<bool> {
+    let mut converted = false;
+
+    if let Mode::Multiple { files } = mode {
+      let mut paths = BTreeSet::new();
+
+      for file in files {
+        let normalized = file.path.normalized();
+        converted |= normalized != file.path;
+        file.path = normalized;
+
+        if !paths.insert(file.path.clone()) {
+          return Err(Error::ReproducibleCollision {
+            path: file.path.clone(),
+          });
+        }
+      }
+    }
+
+    Ok(converted)
+  }
+
+  fn metainfo(
+    &self,
+    common: &Common,
+    target: &Target,
+    name: String,
+    piece_length: Bytes,
+    mode: Mode,
+    pieces: PieceList,
+  ) -> Metainfo {
     let info = Info {
-      name: content.name,
-      piece_length: content.piece_length,
-      source: self.source,
-      update_url: self.update_url,
+      name,
+      piece_length,
+      source: target.source.clone(),
+      update_url: self.update_url.clone(),
       mode,
       pieces,
-      private,
+      private: if target.private { Some(true) } else { None },
     };
 
-    let metainfo = Metainfo {
-      comment: self.comment,
+    Metainfo {
+      comment: target.comment.clone(),
       encoding: Some(consts::ENCODING_UTF8.to_owned()),
-      announce: self.announce.map(|url| url.to_string()),
-      announce_list: if announce_list.is_empty() {
+      announce: target.announce.as_ref().map(ToString::to_string),
+      announce_list: if target.announce_list.is_empty() {
         None
       } else {
-        Some(announce_list)
+        Some(target.announce_list.clone())
       },
       nodes: if self.dht_nodes.is_empty() {
         None
       } else {
-        Some(self.dht_nodes)
+        Some(self.dht_nodes.clone())
       },
-      creation_date,
-      created_by,
+      creation_date: common.creation_date,
+      created_by: common.created_by.clone(),
       info,
-    };
-
-    let bytes = metainfo.serialize()?;
-
-    if !self.dry_run {
-      match &output {
-        OutputTarget::Path(path) => {
-          let mut open_options = fs::OpenOptions::new();
+    }
+  }
 
-          if self.force {
-            open_options.write(true).create(true).truncate(true);
-          } else {
-            open_options.write(true).create_new(true);
-          }
+  fn write(&self, env: &mut Env, output: &OutputTarget, bytes: &[u8]) -> Result<()> {
+    match output {
+      OutputTarget::Path(path) => {
+        let mut open_options = fs::OpenOptions::new();
 
-          open_options
-            .open(path)
-            .and_then(|mut file| file.write_all(&bytes))
-            .context(error::Filesystem { path })?;
+        if self.force {
+          open_options.write(true).create(true).truncate(true);
+        } else {
+          open_options.write(true).create_new(true);
         }
-        OutputTarget::Stdout => env.out_mut().write_all(&bytes).context(error::Stdout)?,
+
+        open_options
+          .open(path)
+          .and_then(|mut file| file.write_all(bytes))
+          .context(error::Filesystem { path })?;
       }
+      OutputTarget::Stdout => env.out_mut().write_all(bytes).context(error::Stdout)?,
     }
 
-    #[cfg(test)]
-    {
-      if let InputTarget::Path(path) = &input {
-        let deserialized = bendy::serde::de::from_bytes::<Metainfo>(&bytes).unwrap();
-
-        assert_eq!(deserialized, metainfo);
-
-        let status = metainfo.verify(&env.resolve(path)?, None)?;
+    Ok(())
+  }
 
-        status.print(env)?;
+  // Parse the value of `SOURCE_DATE_EPOCH`, treating an empty value as unset,
+  // as described at https://reproducible-builds.org/specs/source-date-epoch/.
+  fn source_date_epoch(value: Option<&str>) -> Result<Option<u64>> {
+    match value {
+      None | Some("") => Ok(None),
+      Some(text) => text
+        .parse()
+        .map(Some)
+        .map_err(|_| Error::SourceDateEpoch { text: text.into() }),
+    }
+  }
 
-        if !status.good() {
-          return Err(Error::Verify);
-        }
-      }
+  // Fill in settings not given as arguments from the configuration file.
+  fn apply(&mut self, defaults: config::Defaults) {
+    if self.allowed_lints.is_empty() {
+      self.allowed_lints = defaults.allow;
     }
 
-    errln!(env, "\u{2728}\u{2728} Done! \u{2728}\u{2728}")?;
+    if self.announce.is_none() && !self.no_announce {
+      self.announce = defaults.announce;
+    }
 
-    if self.show {
-      // We just created this torrent, so no extra fields have been discarded.
-      TorrentSummary::from_metainfo_lossy(metainfo.clone())?.write(env)?;
+    if self.announce_tiers.is_empty() && !self.no_announce {
+      self.announce_tiers = defaults.announce_tiers;
     }
 
-    if self.print_magnet_link {
-      // We just created this torrent, so no extra fields have been discarded.
-      let mut link = MagnetLink::from_metainfo_lossy(&metainfo)?;
-      for peer in self.peers {
-        link.add_peer(peer);
-      }
-      outln!(env, "{}", link)?;
+    if !self.no_private {
+      self.private |= defaults.private;
     }
 
-    if let OutputTarget::Path(path) = output {
-      if self.open {
-        Platform::open_file(&path)?;
-      }
+    if self.sort_by.is_empty() {
+      self.sort_by = defaults.sort_by;
     }
 
-    Ok(())
+    if self.source.is_none() {
+      self.source = defaults.source;
+    }
   }
 }
 
+/// The parts of the metainfo that are the same for every torrent created,
+/// unless a profile says otherwise.
+struct Common {
+  announce_list: Vec<Vec<String>>,
+  created_by: Option<String>,
+  creation_date: Option<u64>,
+}
+
+/// Where to write one of the torrents created, and the settings that may
+/// differ between them.
+struct Target {
+  display: OutputTarget,
+  output: OutputTarget,
+  announce: Option<Url>,
+  announce_list: Vec<Vec<String>>,
+  comment: Option<String>,
+  private: bool,
+  source: Option<String>,
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -1174,6 +1631,8 @@ mod tests {
       Mode::Single {
         length: Bytes(3),
         md5sum: None,
+        sha1: None,
+        sha256: None,
       }
     );
   }
@@ -1205,6 +1664,8 @@ mod tests {
       Mode::Single {
         length: Bytes(4),
         md5sum: None,
+        sha1: None,
+        sha256: None,
       }
     );
   }
@@ -1236,6 +1697,8 @@ mod tests {
       Mode::Single {
         length: Bytes(4),
         md5sum: None,
+        sha1: None,
+        sha256: None,
       }
     );
   }
@@ -1274,11 +1737,15 @@ mod tests {
             path: FilePath::from_components(&["bar"]),
             length: Bytes(4),
             md5sum: Some(Md5Digest::from_data("5678")),
+            sha1: None,
+            sha256: None,
           },
           FileInfo {
             path: FilePath::from_components(&["foo"]),
             length: Bytes(4),
             md5sum: Some(Md5Digest::from_data("1234")),
+            sha1: None,
+            sha256: None,
           },
         ],
       }
@@ -1308,6 +1775,8 @@ mod tests {
       Mode::Single {
         length: Bytes(3),
         md5sum: None,
+        sha1: None,
+        sha256: None,
       }
     );
   }
@@ -1342,6 +1811,8 @@ mod tests {
       Mode::Single {
         length: Bytes(3),
         md5sum: None,
+        sha1: None,
+        sha256: None,
       }
     );
   }
@@ -1369,6 +1840,8 @@ mod tests {
       Mode::Single {
         length: Bytes(0),
         md5sum: None,
+        sha1: None,
+        sha256: None,
       }
     );
   }
@@ -1422,6 +1895,8 @@ mod tests {
           &[FileInfo {
             length: Bytes(3),
             md5sum: Some(Md5Digest::from_hex("37b51d194a7513e45b56f6524f2d51f2")),
+            sha1: None,
+            sha256: None,
             path: FilePath::from_components(&["bar"]),
           },]
         );
@@ -1457,6 +1932,8 @@ mod tests {
           &[FileInfo {
             length: Bytes(3),
             md5sum: None,
+            sha1: None,
+            sha256: None,
             path: FilePath::from_components(&["bar"]),
           },]
         );
@@ -1466,7 +1943,7 @@ mod tests {
   }
 
   #[test]
-  fn multiple_three_files() {
+  fn file_checksum() {
     let mut env = test_env! {
       args: [
         "torrent",
@@ -1475,48 +1952,35 @@ mod tests {
         "foo",
         "--announce",
         "http://bar",
-        "--md5"
+        "--file-checksum",
+        "sha1",
+        "--file-checksum",
+        "sha256",
       ],
       tree: {
         foo: {
-          a: "abc",
-          x: "xyz",
-          h: "hij",
+          bar: "bar",
         },
       },
     };
     env.assert_ok();
     let metainfo = env.load_metainfo("foo.torrent");
-    assert_eq!(metainfo.info.pieces, PieceList::from_pieces(["abchijxyz"]));
-    match metainfo.info.mode {
-      Mode::Multiple { files } => {
-        assert_eq!(
-          files,
-          &[
-            FileInfo {
-              length: Bytes(3),
-              md5sum: Some(Md5Digest::from_hex("900150983cd24fb0d6963f7d28e17f72")),
-              path: FilePath::from_components(&["a"]),
-            },
-            FileInfo {
-              length: Bytes(3),
-              md5sum: Some(Md5Digest::from_hex("857c4402ad934005eae4638a93812bf7")),
-              path: FilePath::from_components(&["h"]),
-            },
-            FileInfo {
-              length: Bytes(3),
-              md5sum: Some(Md5Digest::from_hex("d16fb36f0911f878998c136191af705e")),
-              path: FilePath::from_components(&["x"]),
-            },
-          ]
-        );
+    assert_eq!(
+      metainfo.info.mode,
+      Mode::Multiple {
+        files: vec![FileInfo {
+          length: Bytes(3),
+          md5sum: None,
+          sha1: Some(Sha1Digest::from_data("bar")),
+          sha256: Some(Sha256Digest::from_data("bar")),
+          path: FilePath::from_components(&["bar"]),
+        }],
       }
-      Mode::Single { .. } => panic!("Expected multi-file torrent"),
-    }
+    );
   }
 
   #[test]
-  fn uneven_piece_length() {
+  fn file_checksum_single() {
     let mut env = test_env! {
       args: [
         "torrent",
@@ -1525,21 +1989,29 @@ mod tests {
         "foo",
         "--announce",
         "http://bar",
-        "--piece-length",
-        "17KiB",
+        "--md5",
+        "--file-checksum",
+        "sha256",
       ],
       tree: {
-        foo: {},
+        foo: "bar",
       },
     };
-    assert_matches!(
-      env.run(),
-      Err(Error::PieceLengthUneven { bytes }) if bytes.0 == 17 * 1024
+    env.assert_ok();
+    let metainfo = env.load_metainfo("foo.torrent");
+    assert_eq!(
+      metainfo.info.mode,
+      Mode::Single {
+        length: Bytes(3),
+        md5sum: Some(Md5Digest::from_data("bar")),
+        sha1: None,
+        sha256: Some(Sha256Digest::from_data("bar")),
+      }
     );
   }
 
   #[test]
-  fn uneven_piece_length_allow() {
+  fn file_checksum_raw_bytes() {
     let mut env = test_env! {
       args: [
         "torrent",
@@ -1548,41 +2020,40 @@ mod tests {
         "foo",
         "--announce",
         "http://bar",
-        "--piece-length",
-        "17KiB",
-        "--allow",
-        "uneven-piece-length",
+        "--file-checksum",
+        "sha1",
       ],
       tree: {
-        foo: {},
+        foo: "bar",
       },
     };
     env.assert_ok();
-    env.load_metainfo("foo.torrent");
+    let bytes = fs::read(env.resolve("foo.torrent").unwrap()).unwrap();
+    let mut want = b"4:sha120:".to_vec();
+    want.extend_from_slice(&Sha1Digest::from_data("bar").bytes());
+    assert!(bytes.windows(want.len()).any(|window| window == want));
   }
 
   #[test]
-  fn zero_piece_length() {
-    let mut env = test_env! {
+  fn file_checksum_invalid() {
+    test_env! {
       args: [
         "torrent",
         "create",
         "--input",
         "foo",
-        "--announce",
-        "http://bar",
-        "--piece-length",
-        "0",
+        "--file-checksum",
+        "md5",
       ],
       tree: {
-        foo: {},
+        foo: "bar",
       },
+      matches: Err(Error::Clap { .. }),
     };
-    assert_matches!(env.run(), Err(Error::PieceLengthZero));
   }
 
   #[test]
-  fn small_piece_length() {
+  fn multiple_three_files() {
     let mut env = test_env! {
       args: [
         "torrent",
@@ -1591,13 +2062,135 @@ mod tests {
         "foo",
         "--announce",
         "http://bar",
-        "--piece-length",
-        "8KiB",
+        "--md5"
       ],
       tree: {
-        foo: "",
-      },
-    };
+        foo: {
+          a: "abc",
+          x: "xyz",
+          h: "hij",
+        },
+      },
+    };
+    env.assert_ok();
+    let metainfo = env.load_metainfo("foo.torrent");
+    assert_eq!(metainfo.info.pieces, PieceList::from_pieces(["abchijxyz"]));
+    match metainfo.info.mode {
+      Mode::Multiple { files } => {
+        assert_eq!(
+          files,
+          &[
+            FileInfo {
+              length: Bytes(3),
+              md5sum: Some(Md5Digest::from_hex("900150983cd24fb0d6963f7d28e17f72")),
+              sha1: None,
+              sha256: None,
+              path: FilePath::from_components(&["a"]),
+            },
+            FileInfo {
+              length: Bytes(3),
+              md5sum: Some(Md5Digest::from_hex("857c4402ad934005eae4638a93812bf7")),
+              sha1: None,
+              sha256: None,
+              path: FilePath::from_components(&["h"]),
+            },
+            FileInfo {
+              length: Bytes(3),
+              md5sum: Some(Md5Digest::from_hex("d16fb36f0911f878998c136191af705e")),
+              sha1: None,
+              sha256: None,
+              path: FilePath::from_components(&["x"]),
+            },
+          ]
+        );
+      }
+      Mode::Single { .. } => panic!("Expected multi-file torrent"),
+    }
+  }
+
+  #[test]
+  fn uneven_piece_length() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--announce",
+        "http://bar",
+        "--piece-length",
+        "17KiB",
+      ],
+      tree: {
+        foo: {},
+      },
+    };
+    assert_matches!(
+      env.run(),
+      Err(Error::PieceLengthUneven { bytes }) if bytes.0 == 17 * 1024
+    );
+  }
+
+  #[test]
+  fn uneven_piece_length_allow() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--announce",
+        "http://bar",
+        "--piece-length",
+        "17KiB",
+        "--allow",
+        "uneven-piece-length",
+      ],
+      tree: {
+        foo: {},
+      },
+    };
+    env.assert_ok();
+    env.load_metainfo("foo.torrent");
+  }
+
+  #[test]
+  fn zero_piece_length() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--announce",
+        "http://bar",
+        "--piece-length",
+        "0",
+      ],
+      tree: {
+        foo: {},
+      },
+    };
+    assert_matches!(env.run(), Err(Error::PieceLengthZero));
+  }
+
+  #[test]
+  fn small_piece_length() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--announce",
+        "http://bar",
+        "--piece-length",
+        "8KiB",
+      ],
+      tree: {
+        foo: "",
+      },
+    };
     assert_matches!(env.run(), Err(Error::PieceLengthSmall));
   }
 
@@ -1993,11 +2586,15 @@ Content Size  9 bytes
             FileInfo {
               length: Bytes(3),
               md5sum: Some(Md5Digest::from_hex("37b51d194a7513e45b56f6524f2d51f2")),
+              sha1: None,
+              sha256: None,
               path: FilePath::from_components(&["bar"]),
             },
             FileInfo {
               length: Bytes(3),
               md5sum: Some(Md5Digest::from_hex("73feffa4b7f6bb68e44cf984c85f6e88")),
+              sha1: None,
+              sha256: None,
               path: FilePath::from_components(&["dir", "baz"]),
             },
           ]
@@ -2904,6 +3501,8 @@ Content Size  9 bytes
       Mode::Single {
         length: Bytes(5),
         md5sum: Some(Md5Digest::from_data("hello")),
+        sha1: None,
+        sha256: None,
       }
     );
   }
@@ -3106,6 +3705,8 @@ Content Size  9 bytes
       Mode::Single {
         length: Bytes(5),
         md5sum: Some(Md5Digest::from_data("hello")),
+        sha1: None,
+        sha256: None,
       }
     );
 
@@ -3193,4 +3794,1683 @@ Content Size  9 bytes
       Some("https://www.a_real_url.com/".parse().unwrap())
     );
   }
+
+  #[test]
+  fn hash_cache_reused() {
+    let mut env = test_env! {
+      args: [
+        "--hash-cache",
+        "cache",
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--piece-length",
+        "16KiB",
+        "--md5",
+        "--force",
+      ],
+      tree: {
+        foo: {},
+      },
+    };
+
+    // Sizes that leave every file but the first unaligned with piece
+    // boundaries, so cached pieces must be stitched together with partial
+    // pieces read from each file's head and tail.
+    env.write("foo/a", "a".repeat(20_000));
+    env.write("foo/b", "b".repeat(40_000));
+    env.write("foo/c", "c".repeat(5_000));
+    env.write("foo/d", "d".repeat(50_000));
+
+    env.assert_ok();
+
+    let first = env.load_metainfo("foo.torrent");
+
+    assert_eq!(
+      fs::read_dir(env.resolve("cache").unwrap()).unwrap().count(),
+      3
+    );
+
+    env.assert_ok();
+
+    assert_eq!(env.load_metainfo("foo.torrent"), first);
+  }
+
+  #[test]
+  fn hash_cache_used() {
+    let mut env = test_env! {
+      args: [
+        "--hash-cache",
+        "cache",
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--piece-length",
+        "16KiB",
+        "--force",
+      ],
+      tree: {},
+    };
+
+    env.write("foo", "a".repeat(32 * 1024));
+
+    env.assert_ok();
+
+    // Replace the cached hashes with wrong ones, which the torrent created
+    // next will contain, and so fail the verification done in tests.
+    let cache = HashCache::open(&env.resolve("cache").unwrap()).unwrap();
+    let key = HashCache::key(&env.resolve("foo").unwrap(), 16 * 1024, 0).unwrap();
+    cache
+      .insert(
+        key,
+        PieceList::from_pieces(["x", "y"]),
+        Checksums::default(),
+      )
+      .unwrap();
+
+    assert_matches!(env.run(), Err(Error::Verify));
+  }
+
+  #[test]
+  fn hash_cache_disabled() {
+    let mut env = test_env! {
+      args: [
+        "--hash-cache",
+        "cache",
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--piece-length",
+        "16KiB",
+        "--no-cache",
+      ],
+      tree: {},
+    };
+
+    env.write("foo", "a".repeat(32 * 1024));
+
+    env.assert_ok();
+
+    assert!(!env.resolve("cache").unwrap().exists());
+  }
+
+  #[test]
+  fn profiles() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--announce",
+        "https://default.example/announce",
+        "--comment",
+        "hello",
+        "--profile",
+        "alpha:announce=https://alpha.example/announce,source=ALPHA,private=true",
+        "--profile",
+        "beta:source=BETA,comment=",
+      ],
+      tree: {
+        foo: "abc",
+      },
+    };
+
+    env.assert_ok();
+
+    assert!(!env.resolve("foo.torrent").unwrap().exists());
+
+    let alpha = env.load_metainfo("foo.alpha.torrent");
+    assert_eq!(
+      alpha.announce.as_deref(),
+      Some("https://alpha.example/announce")
+    );
+    assert_eq!(alpha.comment.as_deref(), Some("hello"));
+    assert_eq!(alpha.info.source.as_deref(), Some("ALPHA"));
+    assert_eq!(alpha.info.private, Some(true));
+
+    let beta = env.load_metainfo("foo.beta.torrent");
+    assert_eq!(
+      beta.announce.as_deref(),
+      Some("https://default.example/announce")
+    );
+    assert_eq!(beta.comment, None);
+    assert_eq!(beta.info.source.as_deref(), Some("BETA"));
+    assert_eq!(beta.info.private, None);
+
+    assert_eq!(alpha.info.pieces, beta.info.pieces);
+    assert_eq!(alpha.info.mode, beta.info.mode);
+  }
+
+  #[test]
+  fn profile_output() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--profile",
+        "alpha:output=out/alpha.torrent",
+        "--profile",
+        "beta:output=out",
+      ],
+      tree: {
+        foo: "abc",
+        out: {},
+      },
+    };
+
+    env.assert_ok();
+
+    env.load_metainfo("out/alpha.torrent");
+    env.load_metainfo("out/foo.beta.torrent");
+  }
+
+  #[test]
+  fn profile_output_stdout() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--output",
+        "-",
+        "--profile",
+        "alpha",
+      ],
+      tree: {
+        foo: "abc",
+      },
+    };
+
+    assert_matches!(
+      env.run(),
+      Err(Error::ProfileOutputRequired { profile }) if profile == "alpha"
+    );
+  }
+
+  #[test]
+  fn profile_file() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--profile-file",
+        "profiles.txt",
+        "--profile",
+        "gamma",
+      ],
+      tree: {
+        foo: "abc",
+        "profiles.txt": "# Trackers\n\nalpha:source=ALPHA\nbeta:comment=a\\, b\n",
+      },
+    };
+
+    env.assert_ok();
+
+    assert_eq!(
+      env
+        .load_metainfo("foo.alpha.torrent")
+        .info
+        .source
+        .as_deref(),
+      Some("ALPHA")
+    );
+    assert_eq!(
+      env.load_metainfo("foo.beta.torrent").comment.as_deref(),
+      Some("a, b")
+    );
+    env.load_metainfo("foo.gamma.torrent");
+  }
+
+  #[test]
+  fn profile_file_invalid() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--profile-file",
+        "profiles.txt",
+      ],
+      tree: {
+        foo: "abc",
+        "profiles.txt": "alpha\nbeta:private=yes\n",
+      },
+    };
+
+    assert_matches!(
+      env.run(),
+      Err(Error::ProfileFile {
+        line: 2,
+        source: ProfileParseError::Private { .. },
+        ..
+      })
+    );
+  }
+
+  #[test]
+  fn profile_duplicate() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--profile",
+        "alpha",
+        "--profile",
+        "alpha:source=A",
+      ],
+      tree: {
+        foo: "abc",
+      },
+    };
+
+    assert_matches!(env.run(), Err(Error::ProfileDuplicate { name }) if name == "alpha");
+  }
+
+  #[test]
+  fn profile_announce_tiers() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--announce",
+        "https://default.example/announce",
+        "--announce-tier",
+        "https://default.example/announce,https://mirror.example/announce",
+        "--profile",
+        "alpha",
+        "--profile",
+        "beta:announce=https://beta.example/announce",
+        "--profile",
+        "gamma:announce=https://gamma.example/announce,announce-tier=https://gamma.example/announce\\,https://backup.example/announce",
+        "--profile",
+        "delta:announce-tier=",
+      ],
+      tree: {
+        foo: "abc",
+      },
+    };
+
+    env.assert_ok();
+
+    assert_eq!(
+      env.load_metainfo("foo.alpha.torrent").announce_list,
+      Some(vec![vec![
+        "https://default.example/announce".into(),
+        "https://mirror.example/announce".into(),
+      ]])
+    );
+    assert_eq!(env.load_metainfo("foo.beta.torrent").announce_list, None);
+    assert_eq!(
+      env.load_metainfo("foo.gamma.torrent").announce_list,
+      Some(vec![vec![
+        "https://gamma.example/announce".into(),
+        "https://backup.example/announce".into(),
+      ]])
+    );
+    assert_eq!(env.load_metainfo("foo.delta.torrent").announce_list, None);
+  }
+
+  #[test]
+  fn profile_output_conflict() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--profile",
+        "alpha:output=out.torrent",
+        "--profile",
+        "beta:output=./out.torrent",
+      ],
+      tree: {
+        foo: "abc",
+      },
+    };
+
+    assert_matches!(env.run(), Err(Error::ProfileOutputConflict { .. }));
+    assert!(!env.resolve("out.torrent").unwrap().exists());
+  }
+
+  #[test]
+  fn profile_output_conflict_stdout() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--profile",
+        "alpha:output=-",
+        "--profile",
+        "beta:output=-",
+      ],
+      tree: {
+        foo: "abc",
+      },
+    };
+
+    assert_matches!(
+      env.run(),
+      Err(Error::ProfileOutputConflict {
+        output: OutputTarget::Stdout
+      })
+    );
+  }
+
+  #[test]
+  fn profile_private_trackerless() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--private",
+        "--profile",
+        "alpha:announce=https://alpha.example/announce",
+        "--profile",
+        "beta",
+      ],
+      tree: {
+        foo: "abc",
+      },
+    };
+
+    assert_matches!(env.run(), Err(Error::PrivateTrackerless));
+  }
+
+  #[test]
+  fn config_defaults() {
+    let mut env = test_env! {
+      args: [
+        "--config",
+        "config.toml",
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+      ],
+      tree: {
+        foo: "abc",
+        "config.toml": "
+          [create]
+          announce = \"https://example.com/announce\"
+          announce-tier = [[\"udp://a.example.com:1\", \"udp://b.example.com:1\"]]
+          private = true
+          source = \"FOO\"
+        ",
+      },
+    };
+
+    env.assert_ok();
+
+    let metainfo = env.load_metainfo("foo.torrent");
+    assert_eq!(
+      metainfo.announce.as_deref(),
+      Some("https://example.com/announce")
+    );
+    assert_eq!(
+      metainfo.announce_list,
+      Some(vec![vec![
+        "udp://a.example.com:1".into(),
+        "udp://b.example.com:1".into(),
+      ]])
+    );
+    assert_eq!(metainfo.info.private, Some(true));
+    assert_eq!(metainfo.info.source.as_deref(), Some("FOO"));
+  }
+
+  #[test]
+  fn config_overridden() {
+    let mut env = test_env! {
+      args: [
+        "--config",
+        "config.toml",
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--no-announce",
+        "--no-private",
+      ],
+      tree: {
+        foo: "abc",
+        "config.toml": "
+          [create]
+          announce = \"https://example.com/announce\"
+          announce-tier = [[\"udp://a.example.com:1\"]]
+          private = true
+        ",
+      },
+    };
+
+    env.assert_ok();
+
+    let metainfo = env.load_metainfo("foo.torrent");
+    assert_eq!(metainfo.announce, None);
+    assert_eq!(metainfo.announce_list, None);
+    assert_eq!(metainfo.info.private, None);
+  }
+
+  #[test]
+  fn no_private_conflicts_with_private() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--private",
+        "--no-private",
+      ],
+      tree: {
+        foo: "abc",
+      },
+    };
+
+    assert_matches!(env.run(), Err(Error::Clap { .. }));
+  }
+
+  #[test]
+  fn no_announce_keeps_tiers() {
+    let mut env = test_env! {
+      args: [
+        "--config",
+        "config.toml",
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--no-announce",
+        "--announce-tier",
+        "udp://b.example.com:1",
+      ],
+      tree: {
+        foo: "abc",
+        "config.toml": "
+          [create]
+          announce = \"https://example.com/announce\"
+        ",
+      },
+    };
+
+    env.assert_ok();
+
+    let metainfo = env.load_metainfo("foo.torrent");
+    assert_eq!(metainfo.announce, None);
+    assert_eq!(
+      metainfo.announce_list,
+      Some(vec![vec!["udp://b.example.com:1".into()]])
+    );
+  }
+
+  #[test]
+  fn config_preset() {
+    let mut env = test_env! {
+      args: [
+        "--config",
+        "config.toml",
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--preset",
+        "bar",
+        "--source",
+        "BAZ",
+      ],
+      tree: {
+        foo: "abc",
+        "config.toml": "
+          [create]
+          announce = \"https://example.com/announce\"
+          source = \"FOO\"
+
+          [preset.bar]
+          announce = \"https://example.net/announce\"
+          source = \"BAR\"
+        ",
+      },
+    };
+
+    env.assert_ok();
+
+    let metainfo = env.load_metainfo("foo.torrent");
+    assert_eq!(
+      metainfo.announce.as_deref(),
+      Some("https://example.net/announce")
+    );
+    assert_eq!(metainfo.info.source.as_deref(), Some("BAZ"));
+  }
+
+  #[test]
+  fn config_variables() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+      ],
+      vars: {
+        "IMDL_CONFIG": "config.toml",
+        "PASSKEY": "hunter2",
+      },
+      tree: {
+        foo: "abc",
+        "config.toml": "
+          [create]
+          announce = \"https://example.com/${PASSKEY}/announce\"
+        ",
+      },
+    };
+
+    env.assert_ok();
+
+    let metainfo = env.load_metainfo("foo.torrent");
+    assert_eq!(
+      metainfo.announce.as_deref(),
+      Some("https://example.com/hunter2/announce")
+    );
+  }
+
+  #[test]
+  fn config_preset_unknown() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--preset",
+        "bar",
+      ],
+      tree: {
+        foo: "abc",
+      },
+    };
+
+    assert_matches!(
+      env.run(),
+      Err(Error::ConfigPresetUnknown { preset }) if preset == "bar"
+    );
+  }
+
+  #[test]
+  fn config_invalid() {
+    let mut env = test_env! {
+      args: [
+        "--config",
+        "config.toml",
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+      ],
+      tree: {
+        foo: "abc",
+        "config.toml": "[create]\nannouce = \"https://example.com/announce\"\n",
+      },
+    };
+
+    assert_matches!(env.run(), Err(Error::ConfigParse { .. }));
+  }
+
+  #[test]
+  fn reproducible() {
+    let create = || {
+      let mut env = test_env! {
+        args: [
+          "torrent",
+          "create",
+          "--input",
+          "foo",
+          "--announce",
+          "http://bar",
+          "--reproducible",
+        ],
+        tree: {
+          foo: {
+            a: "abc",
+            b: {
+              c: "def",
+            },
+            "\u{e9}": "ghi",
+          },
+        },
+      };
+
+      env.assert_ok();
+
+      fs::read(env.resolve("foo.torrent").unwrap()).unwrap()
+    };
+
+    let bytes = create();
+
+    assert_eq!(bytes, create());
+
+    let metainfo = bendy::serde::de::from_bytes::<Metainfo>(&bytes).unwrap();
+    assert_eq!(
+      metainfo.created_by.as_deref(),
+      Some(consts::CREATED_BY_REPRODUCIBLE)
+    );
+  }
+
+  #[test]
+  fn reproducible_composition() {
+    let create = |name: &str| {
+      let mut env = TestEnvBuilder::new()
+        .arg_slice(&[
+          "imdl",
+          "torrent",
+          "create",
+          "--input",
+          "foo",
+          "--announce",
+          "http://bar",
+          "--reproducible",
+        ])
+        .build();
+
+      env.create_dir("foo");
+      env.create_dir(format!("foo/{name}"));
+      env.write(format!("foo/{name}/a"), "abc");
+      env.write(format!("foo/{name}.txt"), "def");
+
+      env.assert_ok();
+
+      (
+        fs::read(env.resolve("foo.torrent").unwrap()).unwrap(),
+        env.err(),
+      )
+    };
+
+    let (composed, err) = create("\u{e9}");
+    assert!(!err.contains("Normalization Form C"));
+
+    let (decomposed, err) = create("e\u{301}");
+    assert!(err.contains("Converted file names to Unicode Normalization Form C."));
+
+    assert_eq!(composed, decomposed);
+  }
+
+  #[test]
+  fn reproducible_sort_by_conflict() {
+    test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--reproducible",
+        "--sort-by",
+        "size",
+      ],
+      tree: {},
+      matches: Err(Error::Clap { .. }),
+    };
+  }
+
+  #[test]
+  fn reproducible_collision() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--announce",
+        "http://bar",
+        "--reproducible",
+      ],
+      tree: {
+        foo: {
+          "e\u{301}": "abc",
+          "\u{e9}": "def",
+        },
+      },
+    };
+
+    assert_matches!(env.run(), Err(Error::ReproducibleCollision { .. }));
+  }
+
+  #[test]
+  fn reproducible_creation_date() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--announce",
+        "http://bar",
+        "--reproducible",
+      ],
+      vars: {
+        "SOURCE_DATE_EPOCH": "1000",
+      },
+      tree: {
+        foo: "abc",
+      },
+    };
+
+    env.assert_ok();
+
+    let metainfo = env.load_metainfo("foo.torrent");
+    assert_eq!(metainfo.creation_date, Some(1000));
+  }
+
+  #[test]
+  fn source_date_epoch() {
+    assert_eq!(Create::source_date_epoch(None).unwrap(), None);
+    assert_eq!(Create::source_date_epoch(Some("")).unwrap(), None);
+    assert_eq!(Create::source_date_epoch(Some("1000")).unwrap(), Some(1000));
+    assert_matches!(
+      Create::source_date_epoch(Some("x")),
+      Err(Error::SourceDateEpoch { text }) if text == "x"
+    );
+  }
+
+  #[test]
+  fn piece_length_strategy() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--announce",
+        "http://bar",
+        "--piece-length-strategy",
+        "target-piece-count:1",
+      ],
+      tree: {
+        foo: "",
+      },
+    };
+
+    env.write("foo", "a".repeat(20 * 1024));
+
+    env.assert_ok();
+
+    let metainfo = env.load_metainfo("foo.torrent");
+    assert_eq!(metainfo.info.piece_length, Bytes::kib() * 32);
+  }
+
+  #[test]
+  fn piece_length_strategy_conflict() {
+    test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--piece-length",
+        "32kib",
+        "--piece-length-strategy",
+        "auto",
+      ],
+      tree: {},
+      matches: Err(Error::Clap { .. }),
+    };
+  }
+
+  #[test]
+  fn from_archive() {
+    let files = [
+      ("a", "abc"),
+      ("b/c", "defg"),
+      (".hidden", "x"),
+      ("Thumbs.db", "junk"),
+    ];
+
+    let mut dir_env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--announce",
+        "http://bar",
+        "--md5",
+        "--no-creation-date",
+      ],
+      tree: {
+        foo: {
+          a: "abc",
+          b: {
+            c: "defg",
+          },
+          ".hidden": "x",
+          "Thumbs.db": "junk",
+        },
+      },
+    };
+    dir_env.assert_ok();
+    let want = dir_env.load_metainfo("foo.torrent");
+
+    for (filename, bytes) in [
+      ("foo.tar", crate::archive::tests::tar(&files, false)),
+      ("foo.tar.gz", crate::archive::tests::tar(&files, true)),
+      ("foo.zip", crate::archive::tests::zip(&files)),
+    ] {
+      let mut env = test_env! {
+        args: [
+          "torrent",
+          "create",
+          "--input",
+          filename,
+          "--from-archive",
+          "--announce",
+          "http://bar",
+          "--md5",
+          "--no-creation-date",
+        ],
+        tree: {},
+      };
+      env.write(filename, bytes);
+      env.assert_ok();
+      assert_eq!(env.load_metainfo("foo.torrent"), want, "{filename}");
+    }
+  }
+
+  #[test]
+  fn from_archive_filters() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo.tar",
+        "--from-archive",
+        "--announce",
+        "http://bar",
+        "--glob",
+        "!c",
+        "--include-hidden",
+        "--sort-by",
+        "size:descending",
+      ],
+      tree: {},
+    };
+    env.write(
+      "foo.tar",
+      crate::archive::tests::tar(&[("a", "abc"), (".b", "defgh"), ("c", "x")], false),
+    );
+    env.assert_ok();
+    let metainfo = env.load_metainfo("foo.torrent");
+    assert_eq!(metainfo.info.pieces, PieceList::from_pieces(["defghabc"]));
+    assert_eq!(
+      metainfo.info.mode,
+      Mode::Multiple {
+        files: vec![
+          FileInfo {
+            length: Bytes(5),
+            md5sum: None,
+            sha1: None,
+            sha256: None,
+            path: FilePath::from_components(&[".b"]),
+          },
+          FileInfo {
+            length: Bytes(3),
+            md5sum: None,
+            sha1: None,
+            sha256: None,
+            path: FilePath::from_components(&["a"]),
+          },
+        ],
+      }
+    );
+  }
+
+  #[test]
+  fn from_archive_stdin() {
+    let files = [("b/c", "defg"), ("a", "abc")];
+
+    for gzip in [false, true] {
+      let mut env = test_env! {
+        args: [
+          "torrent",
+          "create",
+          "--input",
+          "-",
+          "--from-archive",
+          "--announce",
+          "http://bar",
+          "--name",
+          "foo",
+          "--output",
+          "foo.torrent",
+        ],
+        input: crate::archive::tests::tar(&files, gzip),
+        tree: {},
+      };
+      env.assert_ok();
+      let metainfo = env.load_metainfo("foo.torrent");
+      assert_eq!(metainfo.info.name, "foo");
+      assert_eq!(metainfo.info.pieces, PieceList::from_pieces(["abcdefg"]));
+      assert_eq!(
+        metainfo.info.mode,
+        Mode::Multiple {
+          files: vec![
+            FileInfo {
+              length: Bytes(3),
+              md5sum: None,
+              sha1: None,
+              sha256: None,
+              path: FilePath::from_components(&["a"]),
+            },
+            FileInfo {
+              length: Bytes(4),
+              md5sum: None,
+              sha1: None,
+              sha256: None,
+              path: FilePath::from_components(&["b", "c"]),
+            },
+          ],
+        }
+      );
+    }
+  }
+
+  #[test]
+  fn from_archive_unsafe_path() {
+    test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "-",
+        "--from-archive",
+        "--announce",
+        "http://bar",
+        "--name",
+        "foo",
+        "--output",
+        "foo.torrent",
+      ],
+      input: crate::archive::tests::zip(&[("../a", "abc")]),
+      tree: {},
+      matches: Err(Error::PathComponent { .. }),
+    };
+  }
+
+  #[test]
+  fn files_from() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--announce",
+        "http://bar",
+        "--files-from",
+        "list",
+      ],
+      tree: {
+        foo: {
+          a: "abc",
+          b: {
+            c: "de",
+          },
+          ".hidden": "f",
+          unlisted: "g",
+        },
+        list: "./b/c\n.hidden\na\n",
+      },
+    };
+    env.assert_ok();
+    let metainfo = env.load_metainfo("foo.torrent");
+    assert_eq!(metainfo.info.pieces, PieceList::from_pieces(["defabc"]));
+    assert_eq!(
+      metainfo.info.mode,
+      Mode::Multiple {
+        files: vec![
+          FileInfo {
+            length: Bytes(2),
+            md5sum: None,
+            sha1: None,
+            sha256: None,
+            path: FilePath::from_components(&["b", "c"]),
+          },
+          FileInfo {
+            length: Bytes(1),
+            md5sum: None,
+            sha1: None,
+            sha256: None,
+            path: FilePath::from_components(&[".hidden"]),
+          },
+          FileInfo {
+            length: Bytes(3),
+            md5sum: None,
+            sha1: None,
+            sha256: None,
+            path: FilePath::from_components(&["a"]),
+          },
+        ],
+      }
+    );
+  }
+
+  #[test]
+  fn files_from_sort_by() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--announce",
+        "http://bar",
+        "--files-from",
+        "-",
+        "--sort-by",
+        "path",
+      ],
+      input: "b\0a\0",
+      tree: {
+        foo: {
+          a: "abc",
+          b: "de",
+        },
+      },
+    };
+    env.assert_ok();
+    let metainfo = env.load_metainfo("foo.torrent");
+    assert_eq!(metainfo.info.pieces, PieceList::from_pieces(["abcde"]));
+  }
+
+  #[test]
+  fn files_from_reproducible() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--announce",
+        "http://bar",
+        "--files-from",
+        "-",
+        "--reproducible",
+      ],
+      input: "b\0a\0",
+      tree: {
+        foo: {
+          a: "abc",
+          b: "de",
+        },
+      },
+    };
+    env.assert_ok();
+    let metainfo = env.load_metainfo("foo.torrent");
+    assert_eq!(metainfo.info.pieces, PieceList::from_pieces(["abcde"]));
+  }
+
+  #[test]
+  fn files_from_missing() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--announce",
+        "http://bar",
+        "--files-from",
+        "list",
+      ],
+      tree: {
+        foo: {
+          a: "abc",
+          b: {},
+        },
+        list: "a\nb\n",
+      },
+    };
+
+    assert_matches!(
+      env.run(),
+      Err(Error::FilesFromMissing { path, .. }) if path == FilePath::from_components(&["b"])
+    );
+  }
+
+  #[test]
+  fn files_from_duplicate() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--announce",
+        "http://bar",
+        "--files-from",
+        "list",
+      ],
+      tree: {
+        foo: {
+          a: "abc",
+        },
+        list: "a\n./a\n",
+      },
+    };
+
+    assert_matches!(
+      env.run(),
+      Err(Error::FilesFromDuplicate { path }) if path == FilePath::from_components(&["a"])
+    );
+  }
+
+  #[test]
+  fn files_from_input_stdin() {
+    test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "-",
+        "--announce",
+        "http://bar",
+        "--name",
+        "foo",
+        "--output",
+        "foo.torrent",
+        "--files-from",
+        "list",
+      ],
+      tree: {
+        list: "a\n",
+      },
+      matches: Err(Error::FilesFromInputStdin),
+    };
+  }
+
+  #[test]
+  fn files_from_glob_conflict() {
+    test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--files-from",
+        "list",
+        "--glob",
+        "a",
+      ],
+      tree: {},
+      matches: Err(Error::Clap { .. }),
+    };
+  }
+
+  #[test]
+  fn add() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--announce",
+        "http://bar",
+        "--name",
+        "combined",
+        "--add",
+        "foo:x",
+        "--add",
+        "bar/y.txt:x/y",
+        "--add",
+        "baz",
+      ],
+      tree: {
+        foo: {
+          a: "abc",
+        },
+        bar: {
+          "y.txt": "de",
+        },
+        baz: {
+          c: "f",
+        },
+      },
+    };
+    env.assert_ok();
+    let metainfo = env.load_metainfo("combined.torrent");
+    assert_eq!(metainfo.info.name, "combined");
+    assert_eq!(metainfo.info.pieces, PieceList::from_pieces(["fabcde"]));
+    assert_eq!(
+      metainfo.info.mode,
+      Mode::Multiple {
+        files: vec![
+          FileInfo {
+            length: Bytes(1),
+            md5sum: None,
+            sha1: None,
+            sha256: None,
+            path: FilePath::from_components(&["baz", "c"]),
+          },
+          FileInfo {
+            length: Bytes(3),
+            md5sum: None,
+            sha1: None,
+            sha256: None,
+            path: FilePath::from_components(&["x", "a"]),
+          },
+          FileInfo {
+            length: Bytes(2),
+            md5sum: None,
+            sha1: None,
+            sha256: None,
+            path: FilePath::from_components(&["x", "y"]),
+          },
+        ],
+      }
+    );
+  }
+
+  #[test]
+  fn add_merge() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--announce",
+        "http://bar",
+        "--name",
+        "merged",
+        "--add",
+        "a:d",
+        "--add",
+        "b:d",
+      ],
+      tree: {
+        a: {
+          x: "abc",
+        },
+        b: {
+          y: "de",
+        },
+      },
+    };
+    env.assert_ok();
+    let metainfo = env.load_metainfo("merged.torrent");
+    assert_eq!(metainfo.file_paths(), ["d/x".to_owned(), "d/y".to_owned()]);
+  }
+
+  #[test]
+  fn add_conflict() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--announce",
+        "http://bar",
+        "--name",
+        "merged",
+        "--add",
+        "a:d",
+        "--add",
+        "b:d",
+      ],
+      tree: {
+        a: {
+          x: "abc",
+        },
+        b: {
+          x: "de",
+        },
+      },
+    };
+    assert_matches!(
+      env.run(),
+      Err(Error::AddConflict { path, .. }) if path == FilePath::from_components(&["d", "x"])
+    );
+  }
+
+  #[test]
+  fn add_file_directory_conflict() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--announce",
+        "http://bar",
+        "--name",
+        "merged",
+        "--add",
+        "a:d",
+        "--add",
+        "f:d",
+      ],
+      tree: {
+        a: {
+          x: "abc",
+        },
+        f: "de",
+      },
+    };
+    assert_matches!(
+      env.run(),
+      Err(Error::AddConflict { path, .. }) if path == FilePath::from_components(&["d"])
+    );
+  }
+
+  #[test]
+  fn add_requires_name() {
+    test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--add",
+        "foo",
+      ],
+      tree: {},
+      matches: Err(Error::Clap { .. }),
+    };
+  }
+
+  #[test]
+  fn add_input_conflict() {
+    test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--name",
+        "foo",
+        "--add",
+        "bar",
+      ],
+      tree: {},
+      matches: Err(Error::Clap { .. }),
+    };
+  }
+
+  #[test]
+  fn add_invalid_destination() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--name",
+        "foo",
+        "--add",
+        "bar:../baz",
+      ],
+      tree: {},
+    };
+    assert_matches!(env.run(), Err(Error::Clap { .. }));
+  }
+
+  #[test]
+  fn batch() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--batch",
+        "dir",
+        "--output-dir",
+        "out",
+        "--announce",
+        "http://bar",
+        "--manifest",
+        "manifest.json",
+      ],
+      tree: {
+        dir: {
+          a: {
+            x: "abc",
+            y: "de",
+          },
+          "b.txt": "fgh",
+          ".hidden": "i",
+          "Thumbs.db": "j",
+        },
+      },
+    };
+    env.assert_ok();
+
+    let a = env.load_metainfo("out/a.torrent");
+    assert_eq!(a.info.name, "a");
+    assert_eq!(a.info.pieces, PieceList::from_pieces(["abcde"]));
+    assert_eq!(a.file_paths(), ["x".to_owned(), "y".to_owned()]);
+
+    let b = env.load_metainfo("out/b.txt.torrent");
+    assert_eq!(b.info.name, "b.txt");
+    assert_eq!(b.info.pieces, PieceList::from_pieces(["fgh"]));
+
+    assert!(!env.resolve("out/.hidden.torrent").unwrap().exists());
+    assert!(!env.resolve("out/Thumbs.db.torrent").unwrap().exists());
+
+    let a_infohash = a.infohash_lossy().unwrap();
+    let b_infohash = b.infohash_lossy().unwrap();
+
+    assert_eq!(
+      env.out(),
+      format!(
+        "Name   Status   Infohash\n\
+         a      created  {a_infohash}\n\
+         b.txt  created  {b_infohash}\n"
+      )
+    );
+
+    let manifest =
+      serde_json::from_str::<serde_json::Value>(&env.read_to_string("manifest.json")).unwrap();
+
+    assert_eq!(
+      manifest,
+      serde_json::json!({
+        "a": {
+          "infohash": a_infohash.to_string(),
+          "magnet": MagnetLink::from_metainfo_lossy(&a).unwrap().to_string(),
+        },
+        "b.txt": {
+          "infohash": b_infohash.to_string(),
+          "magnet": MagnetLink::from_metainfo_lossy(&b).unwrap().to_string(),
+        },
+      })
+    );
+  }
+
+  #[test]
+  fn batch_skip_existing() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--batch",
+        "dir",
+        "--output-dir",
+        "out",
+        "--announce",
+        "http://bar",
+      ],
+      tree: {
+        dir: {
+          a: "abc",
+        },
+        out: {},
+      },
+    };
+    env.assert_ok();
+
+    let before = env.load_metainfo("out/a.torrent");
+
+    env.write("dir/a", "xyz");
+    env.write("dir/b", "de");
+
+    env.assert_ok();
+
+    assert_eq!(env.load_metainfo("out/a.torrent"), before);
+
+    let b = env.load_metainfo("out/b.torrent");
+
+    assert!(env.out().ends_with(&format!(
+      "Name  Status   Infohash\n\
+       a     skipped  {}\n\
+       b     created  {}\n",
+      before.infohash_lossy().unwrap(),
+      b.infohash_lossy().unwrap(),
+    )));
+  }
+
+  #[test]
+  fn batch_force() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--batch",
+        "dir",
+        "--output-dir",
+        "out",
+        "--announce",
+        "http://bar",
+        "--force",
+      ],
+      tree: {
+        dir: {
+          a: "abc",
+        },
+        out: {
+          "a.torrent": "garbage",
+        },
+      },
+    };
+    env.assert_ok();
+
+    assert_eq!(
+      env.load_metainfo("out/a.torrent").info.pieces,
+      PieceList::from_pieces(["abc"])
+    );
+  }
+
+  #[test]
+  fn batch_failure() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--batch",
+        "dir",
+        "--output-dir",
+        "out",
+        "--announce",
+        "http://bar",
+      ],
+      tree: {
+        dir: {
+          a: "abc",
+          b: "de",
+        },
+        out: {
+          "a.torrent": "garbage",
+        },
+      },
+    };
+
+    assert_matches!(
+      env.run(),
+      Err(Error::BatchFailed {
+        failed: 1,
+        total: 2
+      })
+    );
+
+    assert!(env.err().starts_with("Failed to create torrent for `a`: "));
+
+    let b = env.load_metainfo("out/b.torrent");
+
+    assert_eq!(
+      env.out(),
+      format!(
+        "Name  Status   Infohash\n\
+         a     failed\n\
+         b     created  {}\n",
+        b.infohash_lossy().unwrap(),
+      )
+    );
+  }
+
+  #[test]
+  fn batch_lints() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--batch",
+        "dir",
+        "--output-dir",
+        "out",
+        "--announce",
+        "http://bar",
+        "--piece-length",
+        "1kib",
+      ],
+      tree: {
+        dir: {
+          a: "abc",
+        },
+      },
+    };
+
+    assert_matches!(env.run(), Err(Error::BatchFailed { failed: 1, .. }));
+
+    assert_eq!(
+      env.err(),
+      format!(
+        "Failed to create torrent for `a`: {}\n",
+        Error::PieceLengthSmall
+      )
+    );
+  }
+
+  #[test]
+  fn batch_requires_output_dir() {
+    test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--batch",
+        "dir",
+      ],
+      tree: {},
+      matches: Err(Error::Clap { .. }),
+    };
+  }
+
+  #[test]
+  fn batch_input_conflict() {
+    test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--batch",
+        "dir",
+        "--output-dir",
+        "out",
+        "--input",
+        "foo",
+      ],
+      tree: {},
+      matches: Err(Error::Clap { .. }),
+    };
+  }
 }
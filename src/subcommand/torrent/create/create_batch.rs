@@ -0,0 +1,399 @@
+use crate::common::*;
+
+use super::{Common, Create, CreateContent, Target};
+
+use std::{
+  num::NonZeroUsize,
+  panic,
+  sync::{
+    atomic::{self, AtomicUsize},
+    mpsc,
+  },
+};
+
+/// Creates a torrent for each entry of a directory, for `--batch`. Entries
+/// are walked and hashed in parallel, and each entry's torrent is written as
+/// soon as it has been hashed. Entries are reported on in order of name once
+/// every entry has been hashed.
+pub(crate) struct CreateBatch<'a> {
+  common: &'a Common,
+  create: &'a Create,
+  linter: &'a Linter,
+}
+
+struct Entry {
+  name: String,
+  output: PathBuf,
+  root: PathBuf,
+  skip: bool,
+}
+
+struct Hashed {
+  mode: Mode,
+  piece_length: Bytes,
+  pieces: PieceList,
+}
+
+/// What is reported about an entry whose torrent was created or skipped.
+struct Outcome {
+  infohash: String,
+  magnet: String,
+  status: &'static str,
+}
+
+#[derive(Serialize)]
+struct ManifestEntry {
+  infohash: String,
+  magnet: String,
+}
+
+impl<'a> CreateBatch<'a> {
+  pub(crate) fn run(
+    create: &'a Create,
+    batch: &Path,
+    linter: &'a Linter,
+    common: &'a Common,
+    env: &mut Env,
+    options: &Options,
+  ) -> Result<()> {
+    let batch = env.resolve(batch)?;
+
+    let output_dir = env.resolve(
+      create
+        .output_dir
+        .as_ref()
+        .ok_or_else(|| Error::internal("Expected `--output-dir` to be set with `--batch`."))?,
+    )?;
+
+    fs::create_dir_all(&output_dir).context(error::Filesystem { path: &output_dir })?;
+
+    let entries = Self::entries(create, &batch, &output_dir)?;
+
+    let cache = if create.no_cache {
+      None
+    } else {
+      options.hash_cache(env)?
+    };
+
+    let pending = entries
+      .iter()
+      .enumerate()
+      .filter(|(_, entry)| !entry.skip)
+      .map(|(i, _)| i)
+      .collect::<Vec<usize>>();
+
+    let progress_bar = if env.err().is_styled_term() && !options.quiet {
+      let style = ProgressStyle::default_bar()
+        .template("{spinner:.green} ⟪{elapsed_precise}⟫ ⟦{wide_bar:.cyan}⟧ {pos}/{len} ⟨{eta}⟩")
+        .tick_chars(consts::TICK_CHARS)
+        .progress_chars(consts::PROGRESS_CHARS);
+
+      Some(ProgressBar::new(pending.len().into_u64()).with_style(style))
+    } else {
+      None
+    };
+
+    let create_batch = Self {
+      common,
+      create,
+      linter,
+    };
+
+    let mut outcomes = entries
+      .iter()
+      .map(|entry| {
+        entry.skip.then(|| {
+          Self::load(env, &entry.output).and_then(|metainfo| Outcome::new(&metainfo, "skipped"))
+        })
+      })
+      .collect::<Vec<Option<Result<Outcome>>>>();
+
+    create_batch.hash_all(
+      &entries,
+      &pending,
+      cache.as_ref(),
+      progress_bar.as_ref(),
+      |i, hashed| {
+        outcomes[i] = Some(
+          hashed
+            .and_then(|hashed| create_batch.write(env, &entries[i], hashed))
+            .and_then(|metainfo| Outcome::new(&metainfo, "created")),
+        );
+      },
+    );
+
+    if let Some(progress_bar) = progress_bar {
+      progress_bar.finish_and_clear();
+    }
+
+    if let Some(err) = cache.as_ref().and_then(HashCache::take_failure) {
+      errln!(env, "Couldn't update hash cache: {}", err)?;
+    }
+
+    let mut rows = vec![[
+      "Name".to_owned(),
+      "Status".to_owned(),
+      "Infohash".to_owned(),
+    ]];
+
+    let mut manifest = BTreeMap::new();
+
+    let mut failed = 0;
+
+    for (entry, outcome) in entries.iter().zip(outcomes) {
+      match outcome.ok_or_else(|| Error::internal("Batch entry was not hashed"))? {
+        Ok(outcome) => {
+          rows.push([
+            entry.name.clone(),
+            outcome.status.to_owned(),
+            outcome.infohash.clone(),
+          ]);
+
+          manifest.insert(
+            entry.name.clone(),
+            ManifestEntry {
+              infohash: outcome.infohash,
+              magnet: outcome.magnet,
+            },
+          );
+        }
+        Err(error) => {
+          failed += 1;
+
+          errln!(
+            env,
+            "Failed to create torrent for `{}`: {}",
+            entry.name,
+            error
+          )?;
+
+          rows.push([entry.name.clone(), "failed".to_owned(), String::new()]);
+        }
+      }
+    }
+
+    let mut widths = [0; 3];
+    for row in &rows {
+      for (width, cell) in widths.iter_mut().zip(row) {
+        *width = (*width).max(UnicodeWidthStr::width(cell.as_str()));
+      }
+    }
+
+    for row in rows {
+      let line = row
+        .iter()
+        .zip(widths)
+        .map(|(cell, width)| format!("{cell:width$}"))
+        .collect::<Vec<String>>()
+        .join("  ");
+
+      outln!(env, "{}", line.trim_end())?;
+    }
+
+    if let Some(path) = &create.manifest {
+      let path = env.resolve(path)?;
+
+      let json = serde_json::to_string(&manifest).context(error::JsonSerialize)?;
+
+      fs::write(&path, format!("{json}\n")).context(error::Filesystem { path: &path })?;
+    }
+
+    if failed > 0 {
+      return Err(Error::BatchFailed {
+        failed,
+        total: entries.len(),
+      });
+    }
+
+    errln!(env, "\u{2728}\u{2728} Done! \u{2728}\u{2728}")?;
+
+    Ok(())
+  }
+
+  // The entries of `batch`, in order of name, skipping hidden and junk
+  // entries, symlinks that aren't followed, and `output_dir`.
+  fn entries(create: &Create, batch: &Path, output_dir: &Path) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+
+    for result in fs::read_dir(batch).context(error::Filesystem { path: batch })? {
+      let dir_entry = result.context(error::Filesystem { path: batch })?;
+
+      let root = dir_entry.path();
+
+      if root == output_dir {
+        continue;
+      }
+
+      let file_type = dir_entry
+        .file_type()
+        .context(error::Filesystem { path: &root })?;
+
+      if file_type.is_symlink() && !create.follow_symlinks {
+        continue;
+      }
+
+      let filename = dir_entry.file_name();
+
+      let name = filename
+        .to_str()
+        .ok_or_else(|| Error::FilenameDecode {
+          filename: PathBuf::from(&filename),
+        })?
+        .to_owned();
+
+      if !create.include_hidden && name.starts_with('.') {
+        continue;
+      }
+
+      if !create.include_junk && Walker::is_junk(&name) {
+        continue;
+      }
+
+      let output = output_dir.join(format!("{name}.torrent"));
+
+      entries.push(Entry {
+        skip: !create.force && output.exists(),
+        name,
+        output,
+        root,
+      });
+    }
+
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(entries)
+  }
+
+  // Hash the entries at each of `pending` on as many threads as there are
+  // CPUs, calling `handle` on this thread with each entry's index and result
+  // as soon as it has been hashed.
+  fn hash_all(
+    &self,
+    entries: &[Entry],
+    pending: &[usize],
+    cache: Option<&HashCache>,
+    progress_bar: Option<&ProgressBar>,
+    mut handle: impl FnMut(usize, Result<Hashed>),
+  ) {
+    let jobs = thread::available_parallelism()
+      .map_or(1, NonZeroUsize::get)
+      .min(pending.len());
+
+    let next = AtomicUsize::new(0);
+
+    thread::scope(|scope| {
+      let (tx, rx) = mpsc::channel();
+
+      let workers = (0..jobs)
+        .map(|_| {
+          let tx = tx.clone();
+          let next = &next;
+          scope.spawn(move || {
+            while let Some(&i) = pending.get(next.fetch_add(1, atomic::Ordering::Relaxed)) {
+              let hashed = self.hash(&entries[i].root, cache.cloned());
+
+              if let Some(progress_bar) = progress_bar {
+                progress_bar.inc(1);
+              }
+
+              if tx.send((i, hashed)).is_err() {
+                break;
+              }
+            }
+          })
+        })
+        .collect::<Vec<thread::ScopedJoinHandle<()>>>();
+
+      // Only the workers' senders remain, so receiving ends once they have
+      // all finished.
+      drop(tx);
+
+      for (i, hashed) in rx {
+        handle(i, hashed);
+      }
+
+      for worker in workers {
+        worker
+          .join()
+          .unwrap_or_else(|payload| panic::resume_unwind(payload));
+      }
+    });
+  }
+
+  fn hash(&self, root: &Path, cache: Option<HashCache>) -> Result<Hashed> {
+    let files = CreateContent::walker(self.create, root)?.files()?;
+
+    let piece_length = CreateContent::piece_length(self.create, &files);
+
+    Create::check_piece_length(self.linter, piece_length)?;
+
+    let (mut mode, pieces) = Hasher::new(
+      FileChecksum::kinds(self.create.md5sum, &self.create.file_checksums),
+      piece_length.as_piece_length()?.into_usize(),
+      None,
+    )
+    .caching(cache)
+    .hash_files(&files)?;
+
+    if self.create.reproducible {
+      Create::normalize(&mut mode)?;
+    }
+
+    Ok(Hashed {
+      mode,
+      piece_length,
+      pieces,
+    })
+  }
+
+  fn write(&self, env: &mut Env, entry: &Entry, hashed: Hashed) -> Result<Metainfo> {
+    let output = OutputTarget::Path(entry.output.clone());
+
+    let target = Target {
+      display: output.clone(),
+      output,
+      announce: self.create.announce.clone(),
+      announce_list: self.common.announce_list.clone(),
+      comment: self.create.comment.clone(),
+      private: self.create.private,
+      source: self.create.source.clone(),
+    };
+
+    let name = if self.create.reproducible {
+      nfc(&entry.name)
+    } else {
+      entry.name.clone()
+    };
+
+    let metainfo = self.create.metainfo(
+      self.common,
+      &target,
+      name,
+      hashed.piece_length,
+      hashed.mode,
+      hashed.pieces,
+    );
+
+    if !self.create.dry_run {
+      self
+        .create
+        .write(env, &target.output, &metainfo.serialize()?)?;
+    }
+
+    Ok(metainfo)
+  }
+
+  fn load(env: &mut Env, path: &Path) -> Result<Metainfo> {
+    Metainfo::from_input(&env.read(InputTarget::Path(path.to_owned()))?)
+  }
+}
+
+impl Outcome {
+  fn new(metainfo: &Metainfo, status: &'static str) -> Result<Self> {
+    Ok(Self {
+      infohash: metainfo.infohash_lossy()?.to_string(),
+      magnet: MagnetLink::from_metainfo_lossy(metainfo)?.to_string(),
+      status,
+    })
+  }
+}
@@ -11,54 +11,47 @@ pub(crate) struct CreateContent {
 }
 
 impl CreateContent {
-  pub(crate) fn from_create(create: &Create, input: &InputTarget, env: &mut Env) -> Result<Self> {
+  pub(crate) fn from_create(
+    create: &Create,
+    input: Option<&InputTarget>,
+    env: &mut Env,
+  ) -> Result<Self> {
     match input {
-      InputTarget::Path(path) => {
-        let spinner = if env.err().is_styled_term() {
-          let style = ProgressStyle::default_spinner()
-            .template("{spinner:.green} {msg:.bold}…")
-            .tick_chars(consts::TICK_CHARS);
+      Some(InputTarget::Path(path)) => {
+        let spinner = Self::spinner(env);
 
-          Some(ProgressBar::new_spinner().with_style(style))
-        } else {
-          None
-        };
-
-        let files = Walker::new(&env.resolve(path)?)
-          .include_junk(create.include_junk)
-          .include_hidden(create.include_hidden)
-          .ignore(create.ignore)
-          .follow_symlinks(create.follow_symlinks)
-          .sort_by(create.sort_by.clone())
-          .globs(&create.globs)?
-          .spinner(spinner)
-          .files()?;
+        let resolved = env.resolve(path)?;
 
-        let piece_length = create
-          .piece_length
-          .unwrap_or_else(|| PieceLengthPicker::from_content_size(files.total_size()));
+        let walker = Self::walker(create, &resolved)?
+          .list(Self::files_from(create, env)?)
+          .spinner(spinner);
 
-        let style = ProgressStyle::default_bar()
-          .template(consts::PROGRESS_STYLE)
-          .tick_chars(consts::TICK_CHARS)
-          .progress_chars(consts::PROGRESS_CHARS);
+        let files = if create.from_archive {
+          walker.archive(Archive::open(&resolved)?)
+        } else {
+          walker.files()?
+        };
 
-        let progress_bar = ProgressBar::new(files.total_size().count()).with_style(style);
+        let piece_length = Self::piece_length(create, &files);
 
-        let resolved = env.resolve(path)?;
+        let progress_bar = Self::progress_bar(&files);
 
         let filename = resolved
           .file_name()
           .ok_or_else(|| Error::FilenameExtract { path: path.clone() })?;
 
-        let name = match &create.name {
-          Some(name) => name.clone(),
-          None => filename
-            .to_str()
-            .ok_or_else(|| Error::FilenameDecode {
-              filename: PathBuf::from(filename),
-            })?
-            .to_owned(),
+        let name = if let Some(name) = &create.name {
+          name.clone()
+        } else {
+          let filename = filename.to_str().ok_or_else(|| Error::FilenameDecode {
+            filename: PathBuf::from(filename),
+          })?;
+
+          if create.from_archive {
+            Archive::stem(filename).to_owned()
+          } else {
+            filename.to_owned()
+          }
         };
 
         let output = create
@@ -75,15 +68,36 @@ impl CreateContent {
         })
       }
 
-      InputTarget::Stdin => {
-        let files = None;
-        let piece_length = create.piece_length.unwrap_or(Bytes::kib() * 256);
+      Some(InputTarget::Stdin) => {
+        if create.files_from.is_some() {
+          return Err(Error::FilesFromInputStdin);
+        }
 
-        let style = ProgressStyle::default_bar()
-          .template("{spinner:.green} ⟪{elapsed_precise}⟫ {binary_bytes} ⟨{binary_bytes_per_sec}⟩")
-          .tick_chars(consts::TICK_CHARS);
+        let (files, piece_length, progress_bar) = if create.from_archive {
+          let archive = Archive::spool(&mut env.input())?;
 
-        let progress_bar = ProgressBar::new_spinner().with_style(style);
+          let files = Self::walker(create, Path::new("-"))?.archive(archive);
+
+          let piece_length = Self::piece_length(create, &files);
+
+          let progress_bar = Self::progress_bar(&files);
+
+          (Some(files), piece_length, progress_bar)
+        } else {
+          let piece_length = create.piece_length.unwrap_or(Bytes::kib() * 256);
+
+          let style = ProgressStyle::default_bar()
+            .template(
+              "{spinner:.green} ⟪{elapsed_precise}⟫ {binary_bytes} ⟨{binary_bytes_per_sec}⟩",
+            )
+            .tick_chars(consts::TICK_CHARS);
+
+          (
+            None,
+            piece_length,
+            ProgressBar::new_spinner().with_style(style),
+          )
+        };
 
         let name = create
           .name
@@ -103,9 +117,133 @@ impl CreateContent {
           output,
         })
       }
+
+      None => {
+        let layout = Layout::new(&create.adds, env)?;
+
+        let files = Self::walker(create, env.dir())?
+          .spinner(Self::spinner(env))
+          .layout(layout)?;
+
+        let piece_length = Self::piece_length(create, &files);
+
+        let progress_bar = Self::progress_bar(&files);
+
+        let name = create
+          .name
+          .clone()
+          .ok_or_else(|| Error::internal("Expected `--name` to be set with `--add`."))?;
+
+        let output = create
+          .output
+          .clone()
+          .unwrap_or_else(|| OutputTarget::Path(PathBuf::from(format!("{name}.torrent"))));
+
+        Ok(Self {
+          files: Some(files),
+          piece_length,
+          progress_bar,
+          name,
+          output,
+        })
+      }
     }
   }
 
+  fn spinner(env: &mut Env) -> Option<ProgressBar> {
+    if env.err().is_styled_term() {
+      let style = ProgressStyle::default_spinner()
+        .template("{spinner:.green} {msg:.bold}…")
+        .tick_chars(consts::TICK_CHARS);
+
+      Some(ProgressBar::new_spinner().with_style(style))
+    } else {
+      None
+    }
+  }
+
+  pub(crate) fn walker(create: &Create, root: &Path) -> Result<Walker> {
+    Walker::new(root)
+      .include_junk(create.include_junk)
+      .include_hidden(create.include_hidden)
+      .ignore(create.ignore)
+      .follow_symlinks(create.follow_symlinks)
+      .normalize(create.reproducible)
+      .sort_by(create.sort_by.clone())
+      .globs(&create.globs)
+  }
+
+  fn files_from(create: &Create, env: &mut Env) -> Result<Option<Vec<FilePath>>> {
+    match &create.files_from {
+      Some(source) => Ok(Some(Self::parse_list(&env.read(source.clone())?.data)?)),
+      None => Ok(None),
+    }
+  }
+
+  // Parse a file list, whose paths are separated by NUL bytes if there are
+  // any, so that paths may contain newlines, and by newlines otherwise.
+  fn parse_list(data: &[u8]) -> Result<Vec<FilePath>> {
+    let separator = if data.contains(&0) { 0 } else { b'\n' };
+
+    let mut paths = Vec::new();
+    let mut seen = BTreeSet::new();
+
+    for entry in data.split(|byte| *byte == separator) {
+      let entry = if separator == b'\n' {
+        entry.strip_suffix(b"\r").unwrap_or(entry)
+      } else {
+        entry
+      };
+
+      if entry.is_empty() {
+        continue;
+      }
+
+      let text = str::from_utf8(entry).context(error::FilesFromDecode)?;
+
+      // Lists made with `find .` begin each path with `./`.
+      let relative = Path::new(text)
+        .components()
+        .filter(|component| *component != path::Component::CurDir)
+        .collect::<PathBuf>();
+
+      if relative.components().count() == 0 {
+        return Err(Error::PathComponent {
+          path: text.into(),
+          component: path::Component::CurDir.as_os_str().into(),
+        });
+      }
+
+      let path = FilePath::from_relative_path(&relative)?;
+
+      if !seen.insert(path.clone()) {
+        return Err(Error::FilesFromDuplicate { path });
+      }
+
+      paths.push(path);
+    }
+
+    Ok(paths)
+  }
+
+  pub(crate) fn piece_length(create: &Create, files: &Files) -> Bytes {
+    create.piece_length.unwrap_or_else(|| {
+      create
+        .piece_length_strategy
+        .unwrap_or(PieceLengthStrategy::Auto)
+        .pick(files.total_size())
+    })
+  }
+
+  fn progress_bar(files: &Files) -> ProgressBar {
+    let style = ProgressStyle::default_bar()
+      .template(consts::PROGRESS_STYLE)
+      .tick_chars(consts::TICK_CHARS)
+      .progress_chars(consts::PROGRESS_CHARS);
+
+    ProgressBar::new(files.total_size().count()).with_style(style)
+  }
+
   fn torrent_path(input: &Path, name: &str) -> PathBuf {
     input.join("..").lexiclean().join(format!("{name}.torrent"))
   }
@@ -148,4 +286,36 @@ mod tests {
     case(".", "foo", Path::new("..").join("foo.torrent"));
     case("..", "foo", Path::new("..").join("..").join("foo.torrent"));
   }
+
+  #[test]
+  fn parse_list() {
+    let paths = |data: &[u8]| {
+      CreateContent::parse_list(data)
+        .unwrap()
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<String>>()
+    };
+
+    assert_eq!(paths(b"b\n./a/c\r\n\n"), ["b", "a/c"]);
+    assert_eq!(paths(b"b\na\0c\0"), ["b\na", "c"]);
+    assert!(paths(b"").is_empty());
+
+    assert_matches!(
+      CreateContent::parse_list(b"a\nb\n./a"),
+      Err(Error::FilesFromDuplicate { path }) if path == FilePath::from_components(&["a"])
+    );
+    assert_matches!(
+      CreateContent::parse_list(b"../a"),
+      Err(Error::PathComponent { .. })
+    );
+    assert_matches!(
+      CreateContent::parse_list(b"."),
+      Err(Error::PathComponent { .. })
+    );
+    assert_matches!(
+      CreateContent::parse_list(b"\xff"),
+      Err(Error::FilesFromDecode { .. })
+    );
+  }
 }
@@ -2,7 +2,7 @@ use crate::common::*;
 
 #[derive(Clone, Copy)]
 pub(crate) enum CreateStep<'a> {
-  Searching { input: &'a InputTarget },
+  Searching { input: Option<&'a InputTarget> },
   Hashing,
   Writing { output: &'a OutputTarget },
 }
@@ -31,8 +31,13 @@ impl Step for CreateStep<'_> {
   fn write_message(&self, write: &mut dyn Write) -> io::Result<()> {
     match self {
       Self::Searching { input } => match input {
-        InputTarget::Path(path) => write!(write, "Searching `{}` for files…", path.display()),
-        InputTarget::Stdin => write!(write, "Creating single-file torrent from standard input…"),
+        Some(InputTarget::Path(path)) => {
+          write!(write, "Searching `{}` for files…", path.display())
+        }
+        Some(InputTarget::Stdin) => {
+          write!(write, "Creating single-file torrent from standard input…")
+        }
+        None => write!(write, "Searching `--add` sources for files…"),
       },
 
       Self::Hashing => write!(write, "Hashing pieces…"),
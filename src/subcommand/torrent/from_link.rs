@@ -1,5 +1,7 @@
 use crate::common::*;
-use rayon::prelude::*;
+use job::{Job, Outcome};
+
+mod job;
 
 const URI_HELP: &str = "Generate a torrent file from a magnet URI";
 
@@ -14,6 +16,20 @@ const INPUT_HELP: &str = "The magnet URI.";
   about(URI_HELP)
 )]
 pub(crate) struct FromLink {
+  #[structopt(
+    long = "add-exchanged-trackers",
+    help = "Add trackers learned from peers with BEP 28 tracker exchange to a new tier of the \
+            announce list. Ignored if the torrent is private."
+  )]
+  add_exchanged_trackers: bool,
+  #[structopt(
+    long = "blocklist",
+    value_name = "FILE",
+    parse(from_os_str),
+    help = "Ignore peers with addresses in the blocklist `FILE`, which may contain eMule \
+            `ipfilter.dat` ranges, PeerGuardian `.p2p` ranges, and CIDR blocks."
+  )]
+  blocklist: Option<PathBuf>,
   #[structopt(
     name = INPUT_FLAG,
     long = "input",
@@ -32,6 +48,61 @@ pub(crate) struct FromLink {
     help = INPUT_HELP,
   )]
   input_positional: Option<MagnetLink>,
+  #[structopt(
+    long = "connect-timeout",
+    value_name = "SECONDS",
+    help = "Give up on connecting to a peer after `SECONDS`. Defaults to 3 seconds."
+  )]
+  connect_timeout: Option<u64>,
+  #[structopt(
+    long = "encryption",
+    value_name = "MODE",
+    default_value = "prefer",
+    possible_values = peer::Encryption::VARIANTS,
+    help = "Use message stream encryption on peer connections. `prefer` tries an encrypted \
+            connection first and falls back to plaintext, `require` only uses encrypted \
+            connections, and `disable` only uses plaintext connections."
+  )]
+  encryption: peer::Encryption,
+  #[structopt(
+    long = "exchange-trackers",
+    help = "Announce to the trackers that peers tell us about with BEP 28 tracker exchange, to \
+            find more peers. Trackers on loopback, private, and link-local addresses are ignored."
+  )]
+  exchange_trackers: bool,
+  #[structopt(
+    long = "lsd",
+    help = "Also listen for BEP 14 announcements from peers on the local network, waiting up to \
+            ten seconds for them even if every tracker has failed. Ignored with `--proxy`, \
+            since local announcements bypass it."
+  )]
+  lsd: bool,
+  #[structopt(
+    long = "max-connections",
+    value_name = "N",
+    default_value = "50",
+    help = "Communicate with at most `N` trackers and peers at once."
+  )]
+  max_connections: usize,
+  #[structopt(
+    long = "max-message-size",
+    value_name = "BYTES",
+    help = "Disconnect from peers that send a message larger than `BYTES`. Defaults to 1 MiB."
+  )]
+  max_message_size: Option<Bytes>,
+  #[structopt(
+    long = "max-metadata-size",
+    value_name = "BYTES",
+    help = "Disconnect from peers that advertise an info dictionary larger than `BYTES`. \
+            Defaults to 16 MiB."
+  )]
+  max_metadata_size: Option<Bytes>,
+  #[structopt(
+    long = "max-peer-bytes",
+    value_name = "BYTES",
+    help = "Disconnect from peers after reading `BYTES` from them. Defaults to 64 MiB."
+  )]
+  max_peer_bytes: Option<Bytes>,
   #[structopt(
     long = "output",
     short = "o",
@@ -42,6 +113,26 @@ pub(crate) struct FromLink {
     help = "Save `.torrent` file to `TARGET`; if omitted, the parameter is set to `./${INFOHASH}.torrent`."
   )]
   output: Option<PathBuf>,
+  #[structopt(
+    long = "peer-deadline",
+    value_name = "SECONDS",
+    help = "Disconnect from peers that have not sent the info dictionary within `SECONDS`. \
+            Defaults to 60 seconds."
+  )]
+  peer_deadline: Option<u64>,
+  #[structopt(
+    long = "tracker-timeout",
+    value_name = "SECONDS",
+    help = "Resend requests to trackers that have not responded within `SECONDS`, giving up after \
+            three attempts. Defaults to 3 seconds."
+  )]
+  tracker_timeout: Option<u64>,
+  #[structopt(
+    long = "utp",
+    help = "Try connecting to peers over uTP before TCP. Peers that don't answer over uTP are \
+            retried over TCP once the connect timeout expires. Ignored with `--proxy`."
+  )]
+  utp: bool,
 }
 
 impl FromLink {
@@ -55,46 +146,217 @@ impl FromLink {
 
     let infohash = link.infohash;
 
+    let blocklist = match &self.blocklist {
+      Some(path) => Blocklist::load(&env.resolve(path)?)?,
+      None => Blocklist::default(),
+    };
+
     if !options.quiet {
       errln!(env, "Sending announce to all trackers.")?;
     }
 
-    let (tx, rx) = channel();
-    link.trackers.par_iter().for_each_with(tx, |s, x| {
-      let Ok(c) = tracker::Client::from_url(x) else {
-        return;
-      };
-      if let Ok(list) = c.announce_exchange(&infohash) {
-        for p in list {
-          s.send(p).ok();
+    let proxy = options.proxy(env)?;
+
+    let tracker_timeout = self
+      .tracker_timeout
+      .map_or(tracker::Announcer::TIMEOUT, Duration::from_secs);
+
+    let mut reactor = Reactor::new(self.max_connections)?;
+
+    let mut trackers = link.trackers.iter().cloned().collect::<HashSet<Url>>();
+    let mut exchanged = Vec::new();
+
+    let mut announcing = 0;
+    for tracker_url in &link.trackers {
+      if let Ok(resolver) = tracker::Resolver::new(tracker_url, infohash, tracker_timeout) {
+        reactor.push(Job::Resolve(resolver.via(proxy.as_ref())));
+        announcing += 1;
+      }
+    }
+
+    // Local announcements are multicast on the local network, which would
+    // bypass the proxy.
+    if self.lsd && proxy.is_none() {
+      reactor.push(Job::Discover(lsd::Searcher::new(
+        infohash,
+        lsd::Searcher::TIMEOUT,
+      )));
+    }
+
+    // Peers are contacted as soon as any tracker or local announcement names
+    // them, in BEP 40 priority order once a peer tells us our external
+    // address. With `--exchange-trackers`, trackers that peers tell us about
+    // are resolved and announced to as soon as we learn of them, and
+    // everything still in flight is cancelled once one of the peers sends the
+    // info dict.
+    let mut peers = HashSet::new();
+    let mut blocked = HashSet::new();
+    let mut candidates = peer::Candidates::default();
+    let mut fetching = 0;
+
+    // Peers given in the magnet link with `x.pe` parameters.
+    let mut link_peers = link
+      .peers
+      .iter()
+      .filter_map(|peer| peer.to_socket_addrs().ok()) // this may cause DNS look-ups!
+      .flatten()
+      .collect::<Vec<SocketAddr>>();
+    blocked.extend(blocklist.filter(&mut link_peers));
+    for addr in link_peers {
+      if peers.insert(addr) {
+        candidates.push(addr);
+      }
+    }
+    self.fill(
+      &mut reactor,
+      &mut candidates,
+      &mut fetching,
+      infohash,
+      proxy.as_ref(),
+    );
+
+    // Peers only known from local announcements, which must not be trusted
+    // with private torrents.
+    let mut local_peers = HashSet::new();
+    // Peers returned by trackers, which may also be known from elsewhere.
+    let mut tracker_peers = HashSet::new();
+    let mut info = None;
+    reactor.run(|reactor, job, result| {
+      match (job, result) {
+        (Job::Announce(_), result) => {
+          if let Ok(Outcome::Peers(mut list)) = result {
+            blocked.extend(blocklist.filter(&mut list));
+
+            for addr in list {
+              tracker_peers.insert(addr);
+              local_peers.remove(&addr);
+              if peers.insert(addr) {
+                candidates.push(addr);
+              }
+            }
+          }
+
+          announcing -= 1;
+
+          if announcing == 0 && !options.quiet {
+            errln!(env, "Trackers returned {} peers.", tracker_peers.len())?;
+          }
+        }
+        (Job::Resolve(_), Ok(Outcome::Announcer(announcer))) => {
+          reactor.push(Job::Announce(announcer));
         }
+        (Job::Resolve(resolver), result) => {
+          if let Err(err) = result {
+            if !options.quiet {
+              errln!(
+                env,
+                "Couldn't resolve tracker `{}`: {}",
+                resolver.url(),
+                err
+              )?;
+            }
+          }
+
+          announcing -= 1;
+
+          if announcing == 0 && !options.quiet {
+            errln!(env, "Trackers returned {} peers.", tracker_peers.len())?;
+          }
+        }
+        (Job::Discover(searcher), Ok(Outcome::Peers(mut list))) => {
+          if !list.is_empty() {
+            blocked.extend(blocklist.filter(&mut list));
+
+            for addr in list {
+              if peers.insert(addr) {
+                local_peers.insert(addr);
+                candidates.push(addr);
+              }
+            }
+
+            reactor.push(Job::Discover(searcher));
+          }
+        }
+        (Job::Fetch(mut fetcher), result) => {
+          if let Some(ip) = fetcher.external_ip() {
+            candidates.set_external(ip);
+          }
+
+          for tracker_url in fetcher.trackers() {
+            if !trackers.insert(tracker_url.clone()) {
+              continue;
+            }
+
+            if !options.quiet {
+              errln!(env, "Learned tracker `{}` from peer.", tracker_url)?;
+            }
+
+            exchanged.push(tracker_url.clone());
+
+            if !self.exchange_trackers {
+              continue;
+            }
+
+            // Peers may name any host, so only announce to public addresses.
+            if let Ok(resolver) = tracker::Resolver::new(tracker_url, infohash, tracker_timeout) {
+              reactor.push(Job::Resolve(resolver.public_only().via(proxy.as_ref())));
+              announcing += 1;
+            }
+          }
+
+          match result {
+            Ok(Outcome::Info(received))
+              if received.private != Some(true) || !local_peers.contains(&fetcher.addr()) =>
+            {
+              info = Some(received);
+              return Ok(Control::Stop);
+            }
+            // A fallback takes over the failed fetch's place.
+            Err(err) => match fetcher.fallback(&err) {
+              Some(fallback) => reactor.push(Job::Fetch(fallback)),
+              None => fetching -= 1,
+            },
+            Ok(_) => fetching -= 1,
+          }
+        }
+        (Job::Discover(_), _) => {}
       }
-    });
 
-    let peers: HashSet<_> = rx.iter().collect();
+      self.fill(
+        reactor,
+        &mut candidates,
+        &mut fetching,
+        infohash,
+        proxy.as_ref(),
+      );
 
-    if !options.quiet {
-      errln!(env, "Trackers returned {} peers.", peers.len())?;
+      Ok(Control::Continue)
+    })?;
+
+    if self.blocklist.is_some() && !options.quiet {
+      errln!(env, "Blocklist filtered {} peers.", blocked.len())?;
     }
 
-    let info = peers.par_iter().find_map_any(|addr| {
-      peer::Client::connect(addr, infohash)
-        .ok()
-        .and_then(|c| c.fetch_info_dict().ok())
-    });
+    let Some(info) = info else {
+      return Err(Error::FromLinkNoInfo);
+    };
 
-    let metainfo = match info {
-      Some(info) => Metainfo {
-        announce: None,
-        announce_list: Some(vec![link.trackers.iter().map(Url::to_string).collect()]),
-        nodes: None,
-        comment: None,
-        created_by: None,
-        creation_date: None,
-        encoding: None,
-        info,
-      },
-      None => return Err(Error::FromLinkNoInfo),
+    let mut announce_list = vec![link.trackers.iter().map(Url::to_string).collect()];
+
+    // BEP 28 tracker exchange must not be used with private torrents.
+    if self.add_exchanged_trackers && !exchanged.is_empty() && info.private != Some(true) {
+      announce_list.push(exchanged.iter().map(Url::to_string).collect());
+    }
+
+    let metainfo = Metainfo {
+      announce: None,
+      announce_list: Some(announce_list),
+      nodes: None,
+      comment: None,
+      created_by: None,
+      creation_date: None,
+      encoding: None,
+      info,
     };
 
     if !options.quiet {
@@ -122,6 +384,58 @@ impl FromLink {
 
     Ok(())
   }
+
+  // Start fetching from the highest priority candidates, until
+  // `max_connections` fetches are in flight, or half as many until a peer
+  // tells us our external address.
+  fn fill(
+    &self,
+    reactor: &mut Reactor<Job>,
+    candidates: &mut peer::Candidates,
+    fetching: &mut usize,
+    infohash: Infohash,
+    proxy: Option<&Proxy>,
+  ) {
+    while *fetching < candidates.limit(self.max_connections) {
+      let Some(addr) = candidates.pop() else {
+        break;
+      };
+
+      reactor.push(Job::Fetch(
+        peer::Fetcher::new(addr, infohash, self.limits(), self.encryption)
+          .via(proxy)
+          .with_utp(self.utp),
+      ));
+
+      *fetching += 1;
+    }
+  }
+
+  fn limits(&self) -> peer::Limits {
+    let mut limits = peer::Limits::default();
+
+    if let Some(max) = self.max_message_size {
+      limits.max_message_length = max.count().try_into().unwrap_or(u32::MAX);
+    }
+
+    if let Some(max) = self.max_metadata_size {
+      limits.max_metadata_size = max.count().try_into().unwrap_or(usize::MAX);
+    }
+
+    if let Some(max) = self.max_peer_bytes {
+      limits.max_bytes_read = Some(max.count());
+    }
+
+    if let Some(seconds) = self.peer_deadline {
+      limits.deadline = Some(Duration::from_secs(seconds));
+    }
+
+    if let Some(seconds) = self.connect_timeout {
+      limits.connect_timeout = Duration::from_secs(seconds);
+    }
+
+    limits
+  }
 }
 
 #[cfg(test)]
@@ -162,6 +476,8 @@ mod tests {
         mode: Mode::Single {
           length: Bytes(2 * 16 * 1024),
           md5sum: None,
+          sha1: None,
+          sha256: None,
         },
         update_url: None,
       },
@@ -189,6 +505,8 @@ mod tests {
       mode: Mode::Single {
         length: Bytes(2 * 16 * 1024),
         md5sum: None,
+        sha1: None,
+        sha256: None,
       },
       update_url: None,
     };
@@ -236,6 +554,8 @@ mod tests {
       mode: Mode::Single {
         length: Bytes(2 * 16 * 1024),
         md5sum: None,
+        sha1: None,
+        sha256: None,
       },
       update_url: None,
     };
@@ -285,6 +605,8 @@ mod tests {
       mode: Mode::Single {
         length: Bytes(2 * 16 * 1024),
         md5sum: None,
+        sha1: None,
+        sha256: None,
       },
       update_url: None,
     };
@@ -325,4 +647,181 @@ mod tests {
     env.assert_ok();
     assert_eq!(metainfo, env.load_metainfo("foo.torrent"));
   }
+
+  #[test]
+  fn add_exchanged_trackers() {
+    let info = Info {
+      private: None,
+      piece_length: Bytes(16 * 1024),
+      source: None,
+      name: "testing".into(),
+      pieces: PieceList::from_pieces(["test", "data"]),
+      mode: Mode::Single {
+        length: Bytes(2 * 16 * 1024),
+        md5sum: None,
+        sha1: None,
+        sha256: None,
+      },
+      update_url: None,
+    };
+    let infohash = info.infohash_lossy().unwrap();
+    let (_, addr_s) = peer::Client::spawn_info_dict_seeder_with_trackers(
+      &info,
+      &[
+        "udp://127.0.0.1:1",
+        "udp://tracker.example.com:1337",
+        "http://example.com/announce",
+      ],
+    );
+    let records = HashMap::from([(infohash.into(), HashSet::from([addr_s]))]);
+    let (_, addr_d) = tracker::Daemon::spawn_with_records(records);
+    let tracker_url = format!("udp://{addr_d}");
+
+    let link = MagnetLink::from_metainfo_lossy(&Metainfo {
+      announce: None,
+      announce_list: Some(vec![vec![tracker_url.clone()]]),
+      nodes: None,
+      comment: None,
+      created_by: None,
+      creation_date: None,
+      encoding: None,
+      info: info.clone(),
+    })
+    .unwrap()
+    .to_url()
+    .to_string();
+
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "from-link",
+        link,
+        "--add-exchanged-trackers",
+        "-o",
+        "foo.torrent",
+      ],
+      tree: {},
+    };
+    env.assert_ok();
+
+    let metainfo = env.load_metainfo("foo.torrent");
+    assert_eq!(
+      metainfo.announce_list,
+      Some(vec![
+        vec![tracker_url],
+        vec![
+          "udp://tracker.example.com:1337".into(),
+          "http://example.com/announce".into()
+        ],
+      ])
+    );
+    assert_eq!(metainfo.info, info);
+    assert!(env
+      .err()
+      .contains("Learned tracker `http://example.com/announce` from peer."));
+  }
+
+  #[test]
+  fn blocklist() {
+    let info = Info {
+      private: None,
+      piece_length: Bytes(16 * 1024),
+      source: None,
+      name: "testing".into(),
+      pieces: PieceList::from_pieces(["test", "data"]),
+      mode: Mode::Single {
+        length: Bytes(2 * 16 * 1024),
+        md5sum: None,
+        sha1: None,
+        sha256: None,
+      },
+      update_url: None,
+    };
+    let infohash = info.infohash_lossy().unwrap();
+    let (_, addr_s) = peer::Client::spawn_info_dict_seeder(&info);
+    let records = HashMap::from([(infohash.into(), HashSet::from([addr_s]))]);
+    let (_, addr_d) = tracker::Daemon::spawn_with_records(records);
+
+    let mut link = MagnetLink::with_infohash(infohash);
+    link.add_tracker(format!("udp://{addr_d}").parse().unwrap());
+
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "from-link",
+        link.to_url().as_str(),
+        "--blocklist",
+        "ipfilter.dat",
+      ],
+      tree: {
+        "ipfilter.dat": "127.000.000.000 - 127.255.255.255 , 000 , Loopback\n",
+      },
+    };
+
+    assert_matches!(env.run(), Err(Error::FromLinkNoInfo));
+    assert!(env.err().contains("Blocklist filtered 1 peers."));
+  }
+
+  #[test]
+  fn link_peers() {
+    let info = Info {
+      private: None,
+      piece_length: Bytes(16 * 1024),
+      source: None,
+      name: "testing".into(),
+      pieces: PieceList::from_pieces(["test", "data"]),
+      mode: Mode::Single {
+        length: Bytes(2 * 16 * 1024),
+        md5sum: None,
+        sha1: None,
+        sha256: None,
+      },
+      update_url: None,
+    };
+    let infohash = info.infohash_lossy().unwrap();
+    let (_, addr_s) = peer::Client::spawn_info_dict_seeder(&info);
+
+    let mut link = MagnetLink::with_infohash(infohash);
+    link.add_peer(addr_s.to_string().parse().unwrap());
+
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "from-link",
+        link.to_url().as_str(),
+        "-o",
+        "foo.torrent",
+      ],
+      tree: {},
+    };
+    env.assert_ok();
+    assert_eq!(env.load_metainfo("foo.torrent").info, info);
+  }
+
+  #[test]
+  fn tracker_peer_count() {
+    let infohash = Infohash::from([0; 20]);
+    let (_, addr_d) = tracker::Daemon::spawn_with_records(HashMap::new());
+
+    let addr_p = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+      .unwrap()
+      .local_addr()
+      .unwrap();
+
+    let mut link = MagnetLink::with_infohash(infohash);
+    link.add_tracker(format!("udp://{addr_d}").parse().unwrap());
+    link.add_peer(addr_p.to_string().parse().unwrap());
+
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "from-link",
+        link.to_url().as_str(),
+      ],
+      tree: {},
+    };
+
+    assert_matches!(env.run(), Err(Error::FromLinkNoInfo));
+    assert!(env.err().contains("Trackers returned 0 peers."));
+  }
 }
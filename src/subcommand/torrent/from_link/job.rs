@@ -0,0 +1,67 @@
+use crate::common::*;
+
+/// The work `from-link` performs on its event loop: announcing to trackers and
+/// listening for local peer announcements to discover peers, fetching the
+/// info dictionary from those peers, and resolving the addresses of trackers
+/// that peers tell us about.
+pub(crate) enum Job {
+  Announce(tracker::Announcer),
+  Discover(lsd::Searcher),
+  Fetch(peer::Fetcher),
+  Resolve(tracker::Resolver),
+}
+
+pub(crate) enum Outcome {
+  Announcer(tracker::Announcer),
+  Peers(Vec<SocketAddr>),
+  Info(Info),
+}
+
+impl Task for Job {
+  type Output = Outcome;
+
+  fn start(&mut self, now: Instant) -> Result<()> {
+    match self {
+      Self::Announce(announcer) => announcer.start(now),
+      Self::Discover(searcher) => searcher.start(now),
+      Self::Fetch(fetcher) => fetcher.start(now),
+      Self::Resolve(resolver) => resolver.start(now),
+    }
+  }
+
+  fn source(&mut self) -> &mut dyn mio::event::Source {
+    match self {
+      Self::Announce(announcer) => announcer.source(),
+      Self::Discover(searcher) => searcher.source(),
+      Self::Fetch(fetcher) => fetcher.source(),
+      Self::Resolve(resolver) => resolver.source(),
+    }
+  }
+
+  fn interest(&self) -> mio::Interest {
+    match self {
+      Self::Announce(announcer) => announcer.interest(),
+      Self::Discover(searcher) => searcher.interest(),
+      Self::Fetch(fetcher) => fetcher.interest(),
+      Self::Resolve(resolver) => resolver.interest(),
+    }
+  }
+
+  fn wakeup(&self) -> Option<Instant> {
+    match self {
+      Self::Announce(announcer) => announcer.wakeup(),
+      Self::Discover(searcher) => searcher.wakeup(),
+      Self::Fetch(fetcher) => fetcher.wakeup(),
+      Self::Resolve(resolver) => resolver.wakeup(),
+    }
+  }
+
+  fn advance(&mut self, now: Instant) -> Result<Option<Outcome>> {
+    match self {
+      Self::Announce(announcer) => Ok(announcer.advance(now)?.map(Outcome::Peers)),
+      Self::Discover(searcher) => Ok(searcher.advance(now)?.map(Outcome::Peers)),
+      Self::Fetch(fetcher) => Ok(fetcher.advance(now)?.map(Outcome::Info)),
+      Self::Resolve(resolver) => Ok(resolver.advance(now)?.map(Outcome::Announcer)),
+    }
+  }
+}
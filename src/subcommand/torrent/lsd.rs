@@ -0,0 +1,24 @@
+use crate::common::*;
+
+mod announce;
+mod listen;
+
+#[derive(StructOpt)]
+#[structopt(
+  help_message(consts::HELP_MESSAGE),
+  version_message(consts::VERSION_MESSAGE),
+  about("Find peers on the local network with BEP 14 local service discovery.")
+)]
+pub(crate) enum Lsd {
+  Announce(announce::Announce),
+  Listen(listen::Listen),
+}
+
+impl Lsd {
+  pub(crate) fn run(self, env: &mut Env, options: &Options) -> Result<(), Error> {
+    match self {
+      Self::Announce(announce) => announce.run(env, options),
+      Self::Listen(listen) => listen.run(env),
+    }
+  }
+}
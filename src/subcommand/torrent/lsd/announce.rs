@@ -0,0 +1,132 @@
+use crate::common::*;
+
+const INPUT_HELP: &str = "Announce the torrent described by the metainfo in `INPUT`. If `INPUT` \
+                          is `-`, read metainfo from standard input.";
+
+const INPUT_FLAG: &str = "input-flag";
+
+const INPUT_POSITIONAL: &str = "<INPUT>";
+
+#[derive(StructOpt)]
+#[structopt(
+  help_message(consts::HELP_MESSAGE),
+  version_message(consts::VERSION_MESSAGE),
+  about("Announce a .torrent file to peers on the local network.")
+)]
+pub(crate) struct Announce {
+  #[structopt(
+    name = INPUT_FLAG,
+    long = "input",
+    short = "i",
+    value_name = "INPUT",
+    empty_values(false),
+    parse(try_from_os_str = InputTarget::try_from_os_str),
+    help = INPUT_HELP,
+  )]
+  input_flag: Option<InputTarget>,
+  #[structopt(
+    name = INPUT_POSITIONAL,
+    value_name = "INPUT",
+    empty_values(false),
+    parse(try_from_os_str = InputTarget::try_from_os_str),
+    required_unless = INPUT_FLAG,
+    conflicts_with = INPUT_FLAG,
+    help = INPUT_HELP,
+  )]
+  input_positional: Option<InputTarget>,
+  #[structopt(
+    long = "port",
+    short = "p",
+    value_name = "N",
+    help = "Announce that peer connections are accepted on port `N`."
+  )]
+  port: u16,
+}
+
+impl Announce {
+  pub(crate) fn run(self, env: &mut Env, options: &Options) -> Result<(), Error> {
+    let target = xor_args(
+      "input_flag",
+      self.input_flag.as_ref(),
+      "input_positional",
+      self.input_positional.as_ref(),
+    )?;
+
+    let input = env.read(target)?;
+    let infohash = Infohash::from_input(&input)?;
+    let metainfo = Metainfo::from_input(&input)?;
+
+    // BEP 27 forbids finding peers of private torrents outside of their
+    // trackers.
+    if metainfo.info.private == Some(true) {
+      return Err(Error::LsdPrivate);
+    }
+
+    lsd::send(&lsd::Announcement {
+      port: self.port,
+      infohashes: vec![infohash],
+      cookie: None,
+    })?;
+
+    if !options.quiet {
+      errln!(env, "Announced {} on port {}.", infohash, self.port)?;
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn port_required() {
+    test_env! {
+      args: [
+        "torrent",
+        "lsd",
+        "announce",
+        "foo.torrent",
+      ],
+      tree: {},
+      matches: Err(Error::Clap { .. }),
+    };
+  }
+
+  #[test]
+  fn input_required() {
+    test_env! {
+      args: [
+        "torrent",
+        "lsd",
+        "announce",
+        "--port",
+        "6881",
+      ],
+      tree: {},
+      matches: Err(Error::Clap { .. }),
+    };
+  }
+
+  #[test]
+  fn private() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "lsd",
+        "announce",
+        "foo.torrent",
+        "--port",
+        "6881",
+      ],
+      tree: {},
+    };
+
+    let metainfo = Metainfo::test_value_single();
+    assert_eq!(metainfo.info.private, Some(true));
+    metainfo.dump(env.resolve("foo.torrent").unwrap()).unwrap();
+
+    assert_matches!(env.run(), Err(Error::LsdPrivate));
+  }
+}
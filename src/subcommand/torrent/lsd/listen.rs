@@ -0,0 +1,118 @@
+use crate::common::*;
+
+use std::net::UdpSocket;
+
+#[derive(StructOpt)]
+#[structopt(
+  help_message(consts::HELP_MESSAGE),
+  version_message(consts::VERSION_MESSAGE),
+  about("Print announcements from peers on the local network.")
+)]
+pub(crate) struct Listen {
+  #[structopt(
+    long = "timeout",
+    value_name = "SECONDS",
+    help = "Stop listening after `SECONDS`. Defaults to listening until interrupted."
+  )]
+  timeout: Option<u64>,
+}
+
+impl Listen {
+  const RX_BUF_LEN: usize = 8192;
+
+  pub(crate) fn run(self, env: &mut Env) -> Result<(), Error> {
+    let socket = lsd::bind()?;
+
+    let deadline = self
+      .timeout
+      .map(|seconds| Instant::now() + Duration::from_secs(seconds));
+
+    Self::listen(env, &socket, deadline)
+  }
+
+  // Print a line with the infohash and peer address for each infohash in each
+  // announcement received before `deadline`.
+  fn listen(env: &mut Env, socket: &UdpSocket, deadline: Option<Instant>) -> Result<(), Error> {
+    let mut buf = [0; Self::RX_BUF_LEN];
+
+    loop {
+      let timeout = match deadline {
+        Some(deadline) => {
+          let remaining = deadline.saturating_duration_since(Instant::now());
+          if remaining.is_zero() {
+            return Ok(());
+          }
+          Some(remaining)
+        }
+        None => None,
+      };
+
+      socket
+        .set_read_timeout(timeout)
+        .context(error::LsdReceive)?;
+
+      let (n, addr) = match socket.recv_from(&mut buf) {
+        Ok(received) => received,
+        Err(err)
+          if matches!(
+            err.kind(),
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::Interrupted
+          ) =>
+        {
+          continue
+        }
+        Err(source) => return Err(Error::LsdReceive { source }),
+      };
+
+      let Some(announcement) = lsd::Announcement::parse(&buf[..n]) else {
+        continue;
+      };
+
+      let peer = SocketAddr::new(addr.ip(), announcement.port);
+
+      for infohash in announcement.infohashes {
+        outln!(env, "{}\t{}", infohash, peer)?;
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn output() {
+    let mut env = test_env! {
+      args: [],
+      tree: {},
+    };
+
+    let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let addr = socket.local_addr().unwrap();
+
+    let sender = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let announcement = lsd::Announcement {
+      port: 6881,
+      infohashes: vec![[0xab; 20].into(), [0x01; 20].into()],
+      cookie: None,
+    };
+    sender
+      .send_to(announcement.serialize().as_bytes(), addr)
+      .unwrap();
+    sender.send_to(b"garbage", addr).unwrap();
+
+    Listen::listen(
+      &mut env,
+      &socket,
+      Some(Instant::now() + Duration::from_millis(200)),
+    )
+    .unwrap();
+
+    assert_eq!(
+      env.out(),
+      "abababababababababababababababababababab\t127.0.0.1:6881\n\
+       0101010101010101010101010101010101010101\t127.0.0.1:6881\n"
+    );
+  }
+}
@@ -0,0 +1,458 @@
+use crate::common::*;
+use availability::Availability;
+use job::{Job, Outcome};
+
+mod availability;
+mod job;
+
+const INPUT_HELP: &str = "Inspect the swarm of the torrent at `INPUT`. If `INPUT` is `-`, read \
+                          metainfo from standard input.";
+
+const INPUT_FLAG: &str = "input-flag";
+
+const INPUT_POSITIONAL: &str = "<INPUT>";
+
+#[derive(StructOpt)]
+#[structopt(
+  help_message(consts::HELP_MESSAGE),
+  version_message(consts::VERSION_MESSAGE),
+  about(
+    "Announce a .torrent file, connect to the peers that trackers return, and report each \
+     peer's client, capabilities, and completion, along with how well each piece and file is \
+     seeded."
+  )
+)]
+pub(crate) struct Peers {
+  #[structopt(
+    name = INPUT_FLAG,
+    long = "input",
+    short = "i",
+    value_name = "INPUT",
+    empty_values(false),
+    parse(try_from_os_str = InputTarget::try_from_os_str),
+    help = INPUT_HELP,
+  )]
+  input_flag: Option<InputTarget>,
+  #[structopt(
+    name = INPUT_POSITIONAL,
+    value_name = "INPUT",
+    empty_values(false),
+    parse(try_from_os_str = InputTarget::try_from_os_str),
+    required_unless = INPUT_FLAG,
+    conflicts_with = INPUT_FLAG,
+    help = INPUT_HELP,
+  )]
+  input_positional: Option<InputTarget>,
+  #[structopt(
+    long = "connect-timeout",
+    value_name = "SECONDS",
+    help = "Give up on connecting to a peer after `SECONDS`. Defaults to 3 seconds."
+  )]
+  connect_timeout: Option<u64>,
+  #[structopt(
+    long = "encryption",
+    value_name = "MODE",
+    default_value = "prefer",
+    possible_values = peer::Encryption::VARIANTS,
+    help = "Use message stream encryption on peer connections. `prefer` tries an encrypted \
+            connection first and falls back to plaintext, `require` only uses encrypted \
+            connections, and `disable` only uses plaintext connections."
+  )]
+  encryption: peer::Encryption,
+  #[structopt(
+    long = "max-connections",
+    value_name = "N",
+    default_value = "50",
+    help = "Communicate with at most `N` trackers and peers at once."
+  )]
+  max_connections: usize,
+  #[structopt(
+    long = "tracker-timeout",
+    value_name = "SECONDS",
+    help = "Resend requests to trackers that have not responded within `SECONDS`, giving up after \
+            three attempts. Defaults to 3 seconds."
+  )]
+  tracker_timeout: Option<u64>,
+  #[structopt(
+    long = "utp",
+    help = "Try connecting to peers over uTP before TCP. Peers that don't answer over uTP are \
+            retried over TCP once the connect timeout expires. Ignored with `--proxy`."
+  )]
+  utp: bool,
+}
+
+impl Peers {
+  pub(crate) fn run(self, env: &mut Env, options: &Options) -> Result<()> {
+    let target = xor_args(
+      "input_flag",
+      self.input_flag.as_ref(),
+      "input_positional",
+      self.input_positional.as_ref(),
+    )?;
+
+    let input = env.read(target)?;
+    let infohash = Infohash::from_input(&input)?;
+    let metainfo = Metainfo::from_input(&input)?;
+    let piece_count = metainfo.info.pieces.count();
+
+    let tracker_timeout = self
+      .tracker_timeout
+      .map_or(tracker::Announcer::TIMEOUT, Duration::from_secs);
+
+    let proxy = options.proxy(env)?;
+
+    let mut limits = peer::Limits::default();
+    if let Some(seconds) = self.connect_timeout {
+      limits.connect_timeout = Duration::from_secs(seconds);
+    }
+
+    let mut reactor = Reactor::new(self.max_connections)?;
+    let mut usable_trackers = 0;
+
+    for tracker_url in metainfo.trackers() {
+      let tracker_url = match tracker_url {
+        Ok(tracker_url) => tracker_url,
+        Err(err) => {
+          errln!(env, "Skipping tracker: {}", err)?;
+          continue;
+        }
+      };
+
+      match tracker::Resolver::new(&tracker_url, infohash, tracker_timeout) {
+        Ok(resolver) => reactor.push(Job::Resolve(resolver.via(proxy.as_ref()))),
+        Err(err) => errln!(env, "Couldn't build tracker client. {}", err)?,
+      }
+    }
+
+    let mut peers = HashSet::new();
+    let mut reports = Vec::new();
+    let mut unreachable = 0;
+    reactor.run(|reactor, job, result| {
+      match (job, result) {
+        (Job::Announce(_), Ok(Outcome::Peers(list))) => {
+          for addr in list {
+            if peers.insert(addr) {
+              reactor.push(Job::Probe(
+                peer::Probe::new(addr, infohash, piece_count, limits, self.encryption)
+                  .via(proxy.as_ref())
+                  .with_utp(self.utp),
+              ));
+            }
+          }
+        }
+        (Job::Announce(announcer), Err(err)) => {
+          errln!(env, "Announce to `{}` failed: {}", announcer.url(), err)?;
+        }
+        (Job::Resolve(_), Ok(Outcome::Announcer(announcer))) => {
+          usable_trackers += 1;
+          reactor.push(Job::Announce(announcer));
+        }
+        (Job::Resolve(_), Err(err)) => errln!(env, "Couldn't build tracker client. {}", err)?,
+        (Job::Probe(_), Ok(Outcome::Report(report))) => reports.push(report),
+        (Job::Probe(probe), Err(err)) => {
+          if let Some(probe) = probe.fallback(&err) {
+            reactor.push(Job::Probe(probe));
+            return Ok(Control::Continue);
+          }
+
+          unreachable += 1;
+          if !options.quiet {
+            errln!(env, "Couldn't probe `{}`: {}", probe.addr(), err)?;
+          }
+        }
+        (_, Ok(_)) => {}
+      }
+
+      Ok(Control::Continue)
+    })?;
+
+    if usable_trackers == 0 {
+      return Err(Error::MetainfoMissingTrackers);
+    }
+
+    reports.sort_by_key(|report| report.addr);
+
+    let mut availability = Availability::new(piece_count, metainfo.info.piece_length);
+
+    for report in &reports {
+      if let Some(pieces) = &report.pieces {
+        availability.add(pieces);
+      }
+
+      Self::write_table(env, &Self::peer_table(report, piece_count))?;
+    }
+
+    Self::write_table(
+      env,
+      &Self::swarm_table(&metainfo.info, &reports, unreachable, &availability),
+    )?;
+
+    Ok(())
+  }
+
+  fn write_table(env: &mut Env, table: &Table) -> Result<()> {
+    if env.out().is_term() {
+      let style = env.out().style();
+      table
+        .write_human_readable(env.out_mut(), style)
+        .context(error::Stdout)?;
+      outln!(env)?;
+    } else {
+      table
+        .write_tab_delimited(env.out_mut())
+        .context(error::Stdout)?;
+    }
+
+    Ok(())
+  }
+
+  fn peer_table(report: &peer::Report, piece_count: usize) -> Table {
+    let mut table = Table::new();
+
+    table.row("Peer", report.addr);
+
+    table.row(
+      "Client",
+      peer::ClientId::from_peer_id(&report.handshake.peer_id)
+        .map_or_else(|| "Unknown".to_owned(), |client_id| client_id.to_string()),
+    );
+
+    if let Some(version) = report
+      .extension_handshake
+      .as_ref()
+      .and_then(|handshake| handshake.version.as_ref())
+    {
+      table.row("Version", version);
+    }
+
+    table.list(
+      "Capabilities",
+      report
+        .handshake
+        .capabilities()
+        .into_iter()
+        .map(str::to_owned)
+        .collect(),
+    );
+
+    if let Some(handshake) = &report.extension_handshake {
+      table.row(
+        "Upload Only",
+        if handshake.upload_only.unwrap_or(0) > 0 {
+          "yes"
+        } else {
+          "no"
+        },
+      );
+    }
+
+    table.row(
+      "Completion",
+      match &report.pieces {
+        Some(pieces) => Self::completion(pieces.iter().filter(|have| **have).count(), piece_count),
+        None => "unknown".to_owned(),
+      },
+    );
+
+    table
+  }
+
+  fn swarm_table(
+    info: &Info,
+    reports: &[peer::Report],
+    unreachable: usize,
+    availability: &Availability,
+  ) -> Table {
+    let mut table = Table::new();
+
+    let seeds = reports
+      .iter()
+      .filter(|report| {
+        report
+          .pieces
+          .as_ref()
+          .is_some_and(|pieces| pieces.iter().all(|have| *have))
+      })
+      .count();
+
+    let (available, copies) = availability.summarize(0..availability.piece_count());
+
+    table.row("Peers", reports.len());
+    table.row("Unreachable", unreachable);
+    table.row("Seeds", seeds);
+    table.row(
+      "Available",
+      Self::completion(available, availability.piece_count()),
+    );
+    table.row("Rarest Piece", Self::copies(copies));
+    table.row(
+      "Fully Seeded",
+      if available == availability.piece_count() {
+        "yes"
+      } else {
+        "no"
+      },
+    );
+
+    let files = match &info.mode {
+      Mode::Single { length, .. } => vec![(info.name.clone(), *length)],
+      Mode::Multiple { files } => files
+        .iter()
+        .map(|file| (file.path.to_string(), file.length))
+        .collect(),
+    };
+
+    let mut offset = 0;
+    let mut lines = Vec::new();
+    for (path, length) in files {
+      let range = availability.pieces(offset, length.count());
+      let (available, copies) = availability.summarize(range.clone());
+      lines.push(format!(
+        "{}: {}, {} of rarest piece",
+        path,
+        Self::completion(available, range.len()),
+        Self::copies(copies),
+      ));
+      offset += length.count();
+    }
+
+    table.list("Files", lines);
+
+    table
+  }
+
+  fn copies(n: usize) -> String {
+    format!("{} {}", n, if n == 1 { "copy" } else { "copies" })
+  }
+
+  fn completion(have: usize, total: usize) -> String {
+    let percent = if total == 0 {
+      100.0
+    } else {
+      #[allow(clippy::cast_precision_loss)]
+      let percent = have as f64 / total as f64 * 100.0;
+      percent
+    };
+
+    format!("{percent:.2}% ({have}/{total} pieces)")
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn input_required() {
+    test_env! {
+      args: [
+        "torrent",
+        "peers",
+      ],
+      tree: {
+      },
+      matches: Err(Error::Clap { .. }),
+    };
+  }
+
+  #[test]
+  fn metainfo_missing_trackers() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "peers",
+        "test.torrent",
+      ],
+      tree: {},
+    };
+    env.write(
+      "test.torrent",
+      Metainfo::test_value_single().serialize().unwrap(),
+    );
+    assert_matches!(env.run(), Err(Error::MetainfoMissingTrackers));
+  }
+
+  #[test]
+  fn swarm() {
+    let tempdir = temptree! {};
+    let contents = "abcdefghij".repeat(4000).into_bytes();
+    fs::write(tempdir.path().join("foo"), &contents).unwrap();
+
+    let info = Info {
+      private: None,
+      piece_length: Bytes::kib() * 16,
+      name: "foo".into(),
+      source: None,
+      pieces: PieceList::from_pieces(contents.chunks(16 * 1024)),
+      mode: Mode::Single {
+        md5sum: None,
+        sha1: None,
+        sha256: None,
+        length: Bytes::from(contents.len().into_u64()),
+      },
+      update_url: None,
+    };
+
+    let infohash = info.infohash_lossy().unwrap();
+    let store = Arc::new(PieceStore::new(&info, &tempdir.path().join("foo")).unwrap());
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let seeder = listener.local_addr().unwrap();
+    thread::spawn(move || {
+      let (stream, _) = listener.accept().unwrap();
+      peer::Seeder::accept(stream.into(), infohash, store, peer::Encryption::default())?.serve()
+    });
+
+    // Nothing is listening here, since the listener is dropped immediately.
+    let unreachable = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+      .unwrap()
+      .local_addr()
+      .unwrap();
+
+    let records = HashMap::from([(infohash.into(), HashSet::from([seeder, unreachable]))]);
+    let (_, tracker) = tracker::Daemon::spawn_with_records(records);
+
+    let metainfo = Metainfo {
+      announce: Some(format!("udp://{tracker}")),
+      announce_list: None,
+      nodes: None,
+      comment: None,
+      created_by: None,
+      creation_date: None,
+      encoding: None,
+      info,
+    };
+
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "peers",
+        "test.torrent",
+      ],
+      tree: {},
+    };
+    env.write("test.torrent", metainfo.serialize().unwrap());
+    env.assert_ok();
+
+    assert_eq!(
+      env.out(),
+      format!(
+        "peer\t{seeder}
+client\tIntermodal {}
+version\tintermodal {}
+capabilities\tExtension Protocol\tFast Extension
+upload only\tyes
+completion\t100.00% (3/3 pieces)
+peers\t1
+unreachable\t1
+seeds\t1
+available\t100.00% (3/3 pieces)
+rarest piece\t1 copy
+fully seeded\tyes
+files\tfoo: 100.00% (3/3 pieces), 1 copy of rarest piece
+",
+        env!("CARGO_PKG_VERSION"),
+        consts::VERSION,
+      ),
+    );
+  }
+}
@@ -0,0 +1,77 @@
+use crate::common::*;
+
+/// The number of peers that have each piece of a torrent.
+pub(crate) struct Availability {
+  piece_length: u64,
+  counts: Vec<usize>,
+}
+
+impl Availability {
+  pub(crate) fn new(piece_count: usize, piece_length: Bytes) -> Self {
+    Self {
+      piece_length: piece_length.count(),
+      counts: vec![0; piece_count],
+    }
+  }
+
+  pub(crate) fn add(&mut self, pieces: &[bool]) {
+    for (count, have) in self.counts.iter_mut().zip(pieces) {
+      if *have {
+        *count += 1;
+      }
+    }
+  }
+
+  pub(crate) fn piece_count(&self) -> usize {
+    self.counts.len()
+  }
+
+  /// The number of pieces in `range` that at least one peer has, and the
+  /// number of copies of the rarest piece in `range`.
+  pub(crate) fn summarize(&self, range: Range<usize>) -> (usize, usize) {
+    let counts = &self.counts[range];
+
+    (
+      counts.iter().filter(|count| **count > 0).count(),
+      counts.iter().copied().min().unwrap_or(0),
+    )
+  }
+
+  /// The pieces containing the `length` bytes at `offset` into the torrent's
+  /// content.
+  pub(crate) fn pieces(&self, offset: u64, length: u64) -> Range<usize> {
+    if length == 0 || self.piece_length == 0 {
+      return 0..0;
+    }
+
+    let start = usize::try_from(offset / self.piece_length).unwrap_or(usize::MAX);
+    let end = usize::try_from((offset + length).div_ceil(self.piece_length)).unwrap_or(usize::MAX);
+
+    start.min(self.counts.len())..end.min(self.counts.len())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn summarize() {
+    let mut availability = Availability::new(4, Bytes(10));
+    availability.add(&[true, true, false, false]);
+    availability.add(&[true, false, true, false]);
+    assert_eq!(availability.summarize(0..4), (3, 0));
+    assert_eq!(availability.summarize(0..3), (3, 1));
+    assert_eq!(availability.summarize(0..1), (1, 2));
+    assert_eq!(availability.summarize(0..0), (0, 0));
+  }
+
+  #[test]
+  fn pieces() {
+    let availability = Availability::new(4, Bytes(10));
+    assert_eq!(availability.pieces(0, 10), 0..1);
+    assert_eq!(availability.pieces(5, 10), 0..2);
+    assert_eq!(availability.pieces(10, 25), 1..4);
+    assert_eq!(availability.pieces(20, 0), 0..0);
+  }
+}
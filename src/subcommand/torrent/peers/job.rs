@@ -0,0 +1,59 @@
+use crate::common::*;
+
+/// The work `peers` performs on its event loop: resolving trackers, announcing
+/// to them to discover peers, and probing those peers.
+pub(crate) enum Job {
+  Announce(tracker::Announcer),
+  Probe(peer::Probe),
+  Resolve(tracker::Resolver),
+}
+
+pub(crate) enum Outcome {
+  Announcer(tracker::Announcer),
+  Peers(Vec<SocketAddr>),
+  Report(peer::Report),
+}
+
+impl Task for Job {
+  type Output = Outcome;
+
+  fn start(&mut self, now: Instant) -> Result<()> {
+    match self {
+      Self::Announce(announcer) => announcer.start(now),
+      Self::Probe(probe) => probe.start(now),
+      Self::Resolve(resolver) => resolver.start(now),
+    }
+  }
+
+  fn source(&mut self) -> &mut dyn mio::event::Source {
+    match self {
+      Self::Announce(announcer) => announcer.source(),
+      Self::Probe(probe) => probe.source(),
+      Self::Resolve(resolver) => resolver.source(),
+    }
+  }
+
+  fn interest(&self) -> mio::Interest {
+    match self {
+      Self::Announce(announcer) => announcer.interest(),
+      Self::Probe(probe) => probe.interest(),
+      Self::Resolve(resolver) => resolver.interest(),
+    }
+  }
+
+  fn wakeup(&self) -> Option<Instant> {
+    match self {
+      Self::Announce(announcer) => announcer.wakeup(),
+      Self::Probe(probe) => probe.wakeup(),
+      Self::Resolve(resolver) => resolver.wakeup(),
+    }
+  }
+
+  fn advance(&mut self, now: Instant) -> Result<Option<Outcome>> {
+    match self {
+      Self::Announce(announcer) => Ok(announcer.advance(now)?.map(Outcome::Peers)),
+      Self::Probe(probe) => Ok(probe.advance(now)?.map(Outcome::Report)),
+      Self::Resolve(resolver) => Ok(resolver.advance(now)?.map(Outcome::Announcer)),
+    }
+  }
+}
@@ -4,13 +4,42 @@ use crate::common::*;
 #[structopt(
   help_message(consts::HELP_MESSAGE),
   version_message(consts::VERSION_MESSAGE),
-  about("Display information about automatic piece length selection.")
+  about(
+    "Display information about automatic piece length selection, or compare piece lengths \
+         for the content at `PATH`."
+  )
 )]
-pub(crate) struct PieceLength {}
+pub(crate) struct PieceLength {
+  #[structopt(
+    name = "PATH",
+    empty_values(false),
+    parse(from_os_str),
+    help = "Compare candidate piece lengths for the content at `PATH`, showing the resulting \
+            piece count, metainfo size, and the padding that BEP 47 padding files would add to \
+            align each file to a piece boundary. Files are selected as by `torrent create` \
+            without any flags."
+  )]
+  path: Option<PathBuf>,
+  #[structopt(
+    long = "piece-length-strategy",
+    value_name = "STRATEGY",
+    requires = "PATH",
+    help = "Mark the piece length picked by `STRATEGY`, as accepted by `torrent create \
+            --piece-length-strategy`. May be given more than once. Defaults to `auto` and \
+            `client-compat`."
+  )]
+  strategies: Vec<PieceLengthStrategy>,
+}
 
-#[allow(clippy::unused_self)]
 impl PieceLength {
   pub(crate) fn run(self, env: &mut Env) -> Result<(), Error> {
+    match &self.path {
+      Some(path) => self.report(env, path),
+      None => Self::table(env),
+    }
+  }
+
+  fn table(env: &mut Env) -> Result<(), Error> {
     let mut rows: Vec<(String, String, String, String)> = vec![(
       "Content".into(),
       "Piece Length".into(),
@@ -63,3 +92,254 @@ impl PieceLength {
     Ok(())
   }
 }
+
+impl PieceLength {
+  fn report(&self, env: &mut Env, path: &Path) -> Result<(), Error> {
+    let root = env.resolve(path)?;
+
+    let files = Walker::new(&root).files()?;
+
+    let name = root
+      .file_name()
+      .and_then(OsStr::to_str)
+      .ok_or_else(|| Error::FilenameExtract { path: root.clone() })?
+      .to_owned();
+
+    let content_size = files.total_size();
+
+    let mode = match files.contents() {
+      Some(contents) => Mode::Multiple {
+        files: contents
+          .iter()
+          .map(|file_path| {
+            let absolute = file_path.absolute(files.root());
+            let metadata = absolute
+              .metadata()
+              .context(error::Filesystem { path: &absolute })?;
+            Ok(FileInfo {
+              length: Bytes(metadata.len()),
+              path: file_path.clone(),
+              md5sum: None,
+              sha1: None,
+              sha256: None,
+            })
+          })
+          .collect::<Result<Vec<FileInfo>>>()?,
+      },
+      None => Mode::Single {
+        length: content_size,
+        md5sum: None,
+        sha1: None,
+        sha256: None,
+      },
+    };
+
+    let strategies = if self.strategies.is_empty() {
+      vec![PieceLengthStrategy::Auto, PieceLengthStrategy::ClientCompat]
+    } else {
+      self.strategies.clone()
+    };
+
+    let picks = strategies
+      .iter()
+      .map(|strategy| (strategy, strategy.pick(content_size)))
+      .collect::<Vec<(&PieceLengthStrategy, Bytes)>>();
+
+    outln!(
+      env,
+      "Content `{}`: {} in {} {}",
+      path.display(),
+      content_size,
+      match &mode {
+        Mode::Single { .. } => 1,
+        Mode::Multiple { files } => files.len(),
+      },
+      match &mode {
+        Mode::Multiple { files } if files.len() != 1 => "files",
+        _ => "file",
+      }
+    )?;
+
+    outln!(env)?;
+
+    let mut rows = vec![[
+      "Piece Length".to_owned(),
+      "Pieces".to_owned(),
+      "Metainfo".to_owned(),
+      "Padding".to_owned(),
+      "Strategies".to_owned(),
+    ]];
+
+    let mut piece_length = Bytes::kib() * 16;
+
+    loop {
+      let piece_count = PieceLengthPicker::piece_count(content_size, piece_length);
+
+      let picked = picks
+        .iter()
+        .filter(|(_, pick)| *pick == piece_length)
+        .map(|(strategy, _)| strategy.to_string())
+        .collect::<Vec<String>>();
+
+      rows.push([
+        piece_length.to_string(),
+        piece_count.to_string(),
+        Self::metainfo_size(&name, &mode, piece_length, piece_count)?.to_string(),
+        Self::padding(&mode, piece_length).to_string(),
+        picked.join(", "),
+      ]);
+
+      if piece_length >= content_size || piece_length.count() >= 1 << 31 {
+        break;
+      }
+
+      piece_length *= 2;
+    }
+
+    let mut widths = [0; 5];
+    for row in &rows {
+      for (width, cell) in widths.iter_mut().zip(row) {
+        *width = (*width).max(UnicodeWidthStr::width(cell.as_str()));
+      }
+    }
+
+    for row in rows {
+      let line = row
+        .iter()
+        .zip(widths)
+        .map(|(cell, width)| format!("{cell:width$}"))
+        .collect::<Vec<String>>()
+        .join("  ");
+
+      outln!(env, "{}", line.trim_end())?;
+    }
+
+    Ok(())
+  }
+
+  // The size of the metainfo for a torrent of `mode` with no trackers or
+  // optional fields, without building a piece list that may be very large.
+  fn metainfo_size(
+    name: &str,
+    mode: &Mode,
+    piece_length: Bytes,
+    piece_count: u64,
+  ) -> Result<Bytes> {
+    let metainfo = Metainfo {
+      announce: None,
+      announce_list: None,
+      comment: None,
+      created_by: None,
+      creation_date: None,
+      encoding: None,
+      info: Info {
+        name: name.to_owned(),
+        piece_length,
+        source: None,
+        update_url: None,
+        mode: mode.clone(),
+        pieces: PieceList::new(),
+        private: None,
+      },
+      nodes: None,
+    };
+
+    // The empty piece list is encoded as `0:`, which a full piece list
+    // replaces with its length, a colon, and its digests.
+    let pieces = piece_count * sha1_smol::DIGEST_LENGTH.into_u64();
+
+    Ok(Bytes(
+      metainfo.serialize()?.len().into_u64() - 1 + pieces.to_string().len().into_u64() + pieces,
+    ))
+  }
+
+  // The total length of the padding files that BEP 47 would insert after
+  // each file but the last, to start the next file on a piece boundary.
+  fn padding(mode: &Mode, piece_length: Bytes) -> Bytes {
+    let Mode::Multiple { files } = mode else {
+      return Bytes(0);
+    };
+
+    let piece_length = piece_length.count();
+
+    Bytes(
+      files
+        .iter()
+        .rev()
+        .skip(1)
+        .map(|file| (piece_length - file.length.count() % piece_length) % piece_length)
+        .sum(),
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn table() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "piece-length",
+      ],
+      tree: {},
+    };
+
+    env.assert_ok();
+
+    assert!(env
+      .out()
+      .starts_with("Content -> Piece Length x Count    = Piece List Size"));
+  }
+
+  #[test]
+  fn report() {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "piece-length",
+        "foo",
+        "--piece-length-strategy",
+        "target-piece-count:2",
+      ],
+      tree: {
+        foo: {
+          a: "",
+          b: "",
+        },
+      },
+    };
+
+    env.write("foo/a", "a".repeat(20 * 1024));
+    env.write("foo/b", "b".repeat(30 * 1024));
+
+    env.assert_ok();
+
+    assert_eq!(
+      env.out(),
+      "Content `foo`: 50 KiB in 2 files
+
+Piece Length  Pieces  Metainfo   Padding  Strategies
+16 KiB        4       199 bytes  12 KiB
+32 KiB        2       159 bytes  12 KiB   target-piece-count:2
+64 KiB        1       139 bytes  44 KiB
+",
+    );
+  }
+
+  #[test]
+  fn strategy_requires_path() {
+    test_env! {
+      args: [
+        "torrent",
+        "piece-length",
+        "--piece-length-strategy",
+        "auto",
+      ],
+      tree: {},
+      matches: Err(Error::Clap { .. }),
+    };
+  }
+}
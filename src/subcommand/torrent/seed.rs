@@ -0,0 +1,400 @@
+use crate::common::*;
+
+use seed_step::SeedStep;
+use std::{
+  num::NonZeroUsize,
+  sync::{mpsc, Mutex},
+};
+
+mod seed_step;
+
+const INPUT_HELP: &str = "Seed the torrent described by the metainfo in `INPUT`. If `INPUT` is \
+                          `-`, read metainfo from standard input.";
+
+const INPUT_FLAG: &str = "input-flag";
+
+const INPUT_POSITIONAL: &str = "<INPUT>";
+
+#[derive(StructOpt)]
+#[structopt(
+  help_message(consts::HELP_MESSAGE),
+  version_message(consts::VERSION_MESSAGE),
+  about("Serve torrent content to peers.")
+)]
+#[cfg_attr(test, structopt(setting = AppSettings::ColorNever))]
+pub(crate) struct Seed {
+  #[structopt(
+    long = "content",
+    short = "c",
+    value_name = "PATH",
+    empty_values(false),
+    parse(from_os_str),
+    help = "Serve torrent content from `PATH`. Defaults to the `name` field of the torrent info \
+            dictionary."
+  )]
+  content: Option<PathBuf>,
+  #[structopt(
+    long = "encryption",
+    value_name = "MODE",
+    default_value = "prefer",
+    possible_values = peer::Encryption::VARIANTS,
+    help = "Accept peer connections according to `MODE`. `prefer` accepts both encrypted and \
+            plaintext connections, `require` only accepts connections that use message stream \
+            encryption, and `disable` only accepts plaintext connections."
+  )]
+  encryption: peer::Encryption,
+  #[structopt(
+    name = INPUT_FLAG,
+    long = "input",
+    short = "i",
+    value_name = "INPUT",
+    empty_values(false),
+    parse(try_from_os_str = InputTarget::try_from_os_str),
+    help = INPUT_HELP,
+  )]
+  input_flag: Option<InputTarget>,
+  #[structopt(
+    name = INPUT_POSITIONAL,
+    value_name = "INPUT",
+    empty_values(false),
+    parse(try_from_os_str = InputTarget::try_from_os_str),
+    required_unless = INPUT_FLAG,
+    conflicts_with = INPUT_FLAG,
+    help = INPUT_HELP,
+  )]
+  input_positional: Option<InputTarget>,
+  #[structopt(
+    long = "listen",
+    short = "l",
+    value_name = "ADDR",
+    default_value = "0.0.0.0:6881",
+    help = "Accept peer connections over TCP and uTP on `ADDR`. The port of `ADDR` is announced \
+            to trackers."
+  )]
+  listen: SocketAddr,
+  #[structopt(
+    long = "max-connections",
+    value_name = "N",
+    default_value = "50",
+    help = "Serve at most `N` peers at once. Further connections wait to be served until \
+            another closes."
+  )]
+  max_connections: NonZeroUsize,
+}
+
+// What the accepting threads and the workers report to the main thread.
+enum Event {
+  Accepted(io::Result<peer::Transport>),
+  Served {
+    peer: Option<SocketAddr>,
+    result: Result<()>,
+  },
+}
+
+impl Seed {
+  // Trackers typically ask peers to re-announce every half hour.
+  const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+  pub(crate) fn run(self, env: &mut Env, options: &Options) -> Result<(), Error> {
+    let target = xor_args(
+      "input_flag",
+      self.input_flag.as_ref(),
+      "input_positional",
+      self.input_positional.as_ref(),
+    )?;
+
+    SeedStep::Loading { metainfo: &target }.print(env)?;
+
+    let input = env.read(target.clone())?;
+
+    let infohash = Infohash::from_input(&input)?;
+
+    let metainfo = Metainfo::from_input(&input)?;
+
+    let proxy = options.proxy(env)?;
+
+    let content = self.content.clone().unwrap_or_else(|| match target {
+      InputTarget::Path(path) => path.join("..").join(&metainfo.info.name).lexiclean(),
+      InputTarget::Stdin => PathBuf::from(&metainfo.info.name),
+    });
+
+    let progress_bar = if env.err().is_styled_term() && !options.quiet {
+      let style = ProgressStyle::default_bar()
+        .template(consts::PROGRESS_STYLE)
+        .tick_chars(consts::TICK_CHARS)
+        .progress_chars(consts::PROGRESS_CHARS);
+
+      Some(ProgressBar::new(metainfo.content_size().count()).with_style(style))
+    } else {
+      None
+    };
+
+    SeedStep::Verifying { content: &content }.print(env)?;
+
+    let content = env.resolve(content)?;
+
+    let status = metainfo.verify(&content, None, progress_bar, None)?;
+
+    if !status.good() {
+      status.print(env)?;
+      return Err(Error::Verify);
+    }
+
+    let store = Arc::new(PieceStore::new(&metainfo.info, &content)?);
+
+    let listener =
+      TcpListener::bind(self.listen).context(error::SeedListen { addr: self.listen })?;
+
+    let addr = listener
+      .local_addr()
+      .context(error::SeedListen { addr: self.listen })?;
+
+    // Also accept uTP connections on the same port.
+    let utp_listener = utp::Listener::bind(addr).context(error::SeedListen { addr })?;
+
+    SeedStep::Announcing.print(env)?;
+
+    let mut trackers = Vec::new();
+    for tracker_url in metainfo.trackers() {
+      match tracker_url {
+        Ok(tracker_url) => trackers.push(tracker_url),
+        Err(err) => errln!(env, "Skipping tracker: {}", err)?,
+      }
+    }
+
+    for (tracker_url, result) in Self::announce(
+      &trackers,
+      infohash,
+      addr.port(),
+      proxy.as_ref(),
+      tracker::Event::Started,
+    )? {
+      if let Err(err) = result {
+        errln!(env, "Announce to `{}` failed: {}", tracker_url, err)?;
+      }
+    }
+
+    // Keep our entry in each tracker's peer list fresh for as long as we
+    // are seeding.
+    thread::spawn(move || loop {
+      thread::sleep(Self::ANNOUNCE_INTERVAL);
+      Self::announce(
+        &trackers,
+        infohash,
+        addr.port(),
+        proxy.as_ref(),
+        tracker::Event::None,
+      )
+      .ok();
+    });
+
+    SeedStep::Seeding { addr }.print(env)?;
+
+    // Both listeners and the workers report to the main thread on a single
+    // channel.
+    let (tx, rx) = mpsc::channel();
+
+    let workers = self.spawn_workers(infohash, &store, &tx);
+
+    let tcp_tx = tx.clone();
+    thread::spawn(move || {
+      for stream in listener.incoming() {
+        if tcp_tx
+          .send(Event::Accepted(stream.map(peer::Transport::from)))
+          .is_err()
+        {
+          return;
+        }
+      }
+    });
+
+    thread::spawn(move || loop {
+      if tx
+        .send(Event::Accepted(
+          utp_listener.accept().map(|(stream, _)| stream.into()),
+        ))
+        .is_err()
+      {
+        return;
+      }
+    });
+
+    for event in rx {
+      match event {
+        Event::Accepted(Ok(stream)) => {
+          if !options.quiet {
+            if let Ok(peer) = stream.peer_addr() {
+              errln!(env, "Accepted connection from `{}`.", peer)?;
+            }
+          }
+
+          workers
+            .send(stream)
+            .map_err(|_| Error::internal("Seed workers exited"))?;
+        }
+        Event::Accepted(Err(err)) => errln!(env, "Failed to accept connection: {}", err)?,
+        Event::Served {
+          peer,
+          result: Err(err),
+        } if !options.quiet => match peer {
+          Some(peer) => errln!(env, "Connection from `{}` failed: {}", peer, err)?,
+          None => errln!(env, "Connection failed: {}", err)?,
+        },
+        Event::Served { .. } => {}
+      }
+    }
+
+    Ok(())
+  }
+
+  // Start `--max-connections` threads, which each serve one peer sent to the
+  // returned sender at a time and report how it went on `events`. Sending
+  // blocks while every worker is busy, so that at most `--max-connections`
+  // peers are served at once.
+  fn spawn_workers(
+    &self,
+    infohash: Infohash,
+    store: &Arc<PieceStore>,
+    events: &mpsc::Sender<Event>,
+  ) -> mpsc::SyncSender<peer::Transport> {
+    let (tx, rx) = mpsc::sync_channel::<peer::Transport>(0);
+    let rx = Arc::new(Mutex::new(rx));
+
+    for _ in 0..self.max_connections.get() {
+      let rx = rx.clone();
+      let store = store.clone();
+      let events = events.clone();
+      let encryption = self.encryption;
+      thread::spawn(move || {
+        // Workers exit once the sender is dropped.
+        let next = || rx.lock().ok()?.recv().ok();
+
+        while let Some(stream) = next() {
+          let peer = stream.peer_addr().ok();
+
+          let result = peer::Seeder::accept(stream, infohash, store.clone(), encryption)
+            .and_then(peer::Seeder::serve);
+
+          if events.send(Event::Served { peer, result }).is_err() {
+            return;
+          }
+        }
+      });
+    }
+
+    tx
+  }
+
+  fn announce(
+    trackers: &[Url],
+    infohash: Infohash,
+    port: u16,
+    proxy: Option<&Proxy>,
+    event: tracker::Event,
+  ) -> Result<Vec<(Url, Result<()>)>> {
+    let mut results = Vec::new();
+
+    // Tracker hosts are resolved on helper threads before announcing, so that
+    // a slow look-up doesn't hold up announces to the other trackers.
+    let mut resolvers = Reactor::new(trackers.len())?;
+
+    for tracker_url in trackers {
+      match tracker::Resolver::new(tracker_url, infohash, tracker::Announcer::TIMEOUT) {
+        Ok(resolver) => resolvers.push(resolver.via(proxy)),
+        Err(err) => results.push((tracker_url.clone(), Err(err))),
+      }
+    }
+
+    let mut reactor = Reactor::new(trackers.len())?;
+
+    resolvers.run(|_, resolver, result| {
+      match result {
+        Ok(announcer) => reactor.push(announcer.seeding(port, event)),
+        Err(err) => results.push((resolver.url().clone(), Err(err))),
+      }
+      Ok(Control::Continue)
+    })?;
+
+    reactor.run(|_, announcer, result| {
+      results.push((announcer.url().clone(), result.map(|_| ())));
+      Ok(Control::Continue)
+    })?;
+
+    Ok(results)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn input_required() {
+    test_env! {
+      args: [
+        "torrent",
+        "seed",
+      ],
+      tree: {
+      },
+      matches: Err(Error::Clap { .. }),
+    };
+  }
+
+  #[test]
+  fn listen_invalid() {
+    test_env! {
+      args: [
+        "torrent",
+        "seed",
+        "foo.torrent",
+        "--listen",
+        "foo",
+      ],
+      tree: {
+      },
+      matches: Err(Error::Clap { .. }),
+    };
+  }
+
+  #[test]
+  fn content_corrupt() -> Result<()> {
+    let mut create_env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--announce",
+        "udp://127.0.0.1:1",
+      ],
+      tree: {
+        foo: {
+          a: "abc",
+          d: "efg",
+        },
+      },
+    };
+
+    create_env.assert_ok();
+
+    create_env.write("foo/a", "xyz");
+
+    let torrent = create_env.resolve("foo.torrent")?;
+
+    let mut seed_env = test_env! {
+      args: [
+        "torrent",
+        "seed",
+        &torrent,
+        "--listen",
+        "127.0.0.1:0",
+      ],
+      tree: {},
+    };
+
+    assert_matches!(seed_env.run(), Err(Error::Verify));
+
+    Ok(())
+  }
+}
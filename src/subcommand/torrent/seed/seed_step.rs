@@ -0,0 +1,44 @@
+use crate::common::*;
+
+#[derive(Clone, Copy)]
+pub(crate) enum SeedStep<'a> {
+  Loading { metainfo: &'a InputTarget },
+  Verifying { content: &'a Path },
+  Announcing,
+  Seeding { addr: SocketAddr },
+}
+
+impl Step for SeedStep<'_> {
+  fn n(&self) -> usize {
+    match self {
+      Self::Loading { .. } => 1,
+      Self::Verifying { .. } => 2,
+      Self::Announcing => 3,
+      Self::Seeding { .. } => 4,
+    }
+  }
+
+  fn symbol(&self) -> &str {
+    match self {
+      Self::Loading { .. } => "\u{1F4BE}",
+      Self::Verifying { .. } => "\u{1F9EE}",
+      Self::Announcing => "\u{1F4E3}",
+      Self::Seeding { .. } => "\u{1F331}",
+    }
+  }
+
+  fn total() -> usize {
+    4
+  }
+
+  fn write_message(&self, write: &mut dyn Write) -> io::Result<()> {
+    match self {
+      Self::Loading { metainfo } => write!(write, "Loading metainfo from {metainfo}…"),
+      Self::Verifying { content } => {
+        write!(write, "Verifying pieces from `{}`…", content.display())
+      }
+      Self::Announcing => write!(write, "Announcing to trackers…"),
+      Self::Seeding { addr } => write!(write, "Seeding on `{addr}`…"),
+    }
+  }
+}
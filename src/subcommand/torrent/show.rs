@@ -585,7 +585,8 @@ files\tNAME
 "udp://announce.example:1337","announce_list":[["http://a.example:4567",
 "https://b.example:77"],["udp://c.example:88"]],"update_url":"https://update.example/",
 "dht_nodes":["node.example:12","1.1.1.1:16","[2001:db8:85a3::8a2e:370]:7334"],
-"piece_size":16384,"piece_count":2,"file_count":1,"files":["NAME"]}"#
+"piece_size":16384,"piece_count":2,"file_count":1,"files":["NAME"],
+"file_checksums":{"NAME":{"md5":"000102030405060708090a0b0c0d0e0f"}}}"#
         .replace('\n', "");
       want.push('\n');
       let mut env = TestEnvBuilder::new()
@@ -633,4 +634,40 @@ files\tNAME
       assert_eq!(have, want);
     }
   }
+
+  #[test]
+  fn output_json_file_checksums() {
+    let mut metainfo = Metainfo::test_value_multiple_unset();
+
+    if let Mode::Multiple { files } = &mut metainfo.info.mode {
+      files[0].sha1 = Some(Sha1Digest::from_data("foo"));
+      files[0].sha256 = Some(Sha256Digest::from_data("foo"));
+    }
+
+    let mut env = TestEnvBuilder::new()
+      .arg_slice(&[
+        "imdl",
+        "torrent",
+        "show",
+        "--input",
+        "foo.torrent",
+        "--json",
+      ])
+      .build();
+    let path = env.resolve("foo.torrent").unwrap();
+    metainfo.dump(path).unwrap();
+    env.assert_ok();
+
+    let json = serde_json::from_str::<serde_json::Value>(&env.out()).unwrap();
+
+    assert_eq!(
+      json["file_checksums"],
+      serde_json::json!({
+        "NAME/a/b": {
+          "sha1": Sha1Digest::from_data("foo").to_string(),
+          "sha256": Sha256Digest::from_data("foo").to_string(),
+        },
+      })
+    );
+  }
 }
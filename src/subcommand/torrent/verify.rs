@@ -18,6 +18,14 @@ const INPUT_FLAG: &str = "input-flag";
 )]
 #[cfg_attr(test, structopt(setting = AppSettings::ColorNever))]
 pub(crate) struct Verify {
+  #[structopt(
+    long = "add",
+    value_name = "SRC[:DEST]",
+    help = "Look for torrent files under `DEST` in `SRC`, as added to the torrent with `imdl \
+            torrent create --add`. May be given more than once. Files not under any `DEST` are \
+            looked for in the usual content directory."
+  )]
+  adds: Vec<Mapping>,
   #[structopt(
     long = "base-directory",
     short = "b",
@@ -59,6 +67,11 @@ pub(crate) struct Verify {
     help = METAINFO_HELP,
   )]
   input_flag: Option<InputTarget>,
+  #[structopt(
+    long = "no-cache",
+    help = "Read and hash every file, even if `--hash-cache` is given, and don't update the cache."
+  )]
+  no_cache: bool,
 }
 
 impl Verify {
@@ -102,7 +115,28 @@ impl Verify {
 
     VerifyStep::Verifying { content: &content }.print(env)?;
 
-    let status = metainfo.verify(&env.resolve(content)?, progress_bar)?;
+    let cache = if self.no_cache {
+      None
+    } else {
+      options.hash_cache(env)?
+    };
+
+    let layout = if self.adds.is_empty() {
+      None
+    } else {
+      Some(Layout::new(&self.adds, env)?)
+    };
+
+    let status = metainfo.verify(
+      &env.resolve(content)?,
+      layout.as_ref(),
+      progress_bar,
+      cache.as_ref(),
+    )?;
+
+    if let Some(err) = cache.as_ref().and_then(HashCache::take_failure) {
+      errln!(env, "Couldn't update hash cache: {}", err)?;
+    }
 
     status.print(env)?;
 
@@ -717,6 +751,68 @@ mod tests {
     Ok(())
   }
 
+  #[test]
+  fn file_checksum_mismatch() -> Result<()> {
+    let mut create_env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--announce",
+        "https://bar",
+        "--file-checksum",
+        "sha256",
+      ],
+      tree: {
+        foo: "abc",
+      },
+    };
+
+    create_env.assert_ok();
+
+    let torrent = create_env.resolve("foo.torrent")?;
+
+    create_env.write("foo", "xyz");
+
+    let mut verify_env = test_env! {
+      args: [
+        "torrent",
+        "verify",
+        "--input",
+        &torrent,
+      ],
+      tree: {},
+    };
+
+    assert_matches!(verify_env.status(), Err(EXIT_FAILURE));
+
+    let want = [
+      &format!(
+        "[1/2] \u{1F4BE} Loading metainfo from `{}`…",
+        torrent.display()
+      ),
+      &format!(
+        "[2/2] \u{1F9EE} Verifying pieces from `{}`…",
+        create_env.resolve("foo")?.display()
+      ),
+      &format!(
+        "SHA-256 checksum mismatch: {} (expected {})",
+        Sha256Digest::from_data("xyz"),
+        Sha256Digest::from_data("abc"),
+      ),
+      "Pieces corrupted.",
+      "error: Torrent verification failed.",
+      "",
+    ]
+    .join("\n");
+
+    assert_eq!(verify_env.err(), want);
+    assert_eq!(verify_env.out(), "");
+
+    Ok(())
+  }
+
   #[test]
   fn stdin_uses_name() -> Result<()> {
     let mut create_env = test_env! {
@@ -800,4 +896,129 @@ mod tests {
 
     Ok(())
   }
+
+  #[test]
+  fn hash_cache() -> Result<()> {
+    let mut create_env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        "foo",
+        "--piece-length",
+        "16KiB",
+        "--md5",
+      ],
+      tree: {},
+    };
+
+    create_env.write("foo", "a".repeat(40_000));
+
+    create_env.assert_ok();
+
+    let torrent = create_env.resolve("foo.torrent")?;
+    let cache = create_env.resolve("cache")?;
+
+    let verify = |flags: &[&str]| {
+      TestEnvBuilder::new()
+        .arg("imdl")
+        .arg("--hash-cache")
+        .arg(&cache)
+        .arg("torrent")
+        .arg("verify")
+        .arg(&torrent)
+        .arg_slice(flags)
+        .build()
+        .run()
+    };
+
+    // The first verification fills the cache, and the second uses it.
+    verify(&[])?;
+    assert_eq!(fs::read_dir(&cache).unwrap().count(), 1);
+    verify(&[])?;
+
+    let key = HashCache::key(&create_env.resolve("foo")?, 16 * 1024, 0).unwrap();
+
+    HashCache::open(&cache)?.insert(
+      key,
+      PieceList::from_pieces(["x", "y"]),
+      Checksums {
+        md5sum: Some(Md5Digest::from_data("a".repeat(40_000))),
+        ..Checksums::default()
+      },
+    )?;
+
+    assert_matches!(verify(&[]), Err(Error::Verify));
+
+    verify(&["--no-cache"])?;
+
+    Ok(())
+  }
+
+  #[test]
+  fn add() -> Result<()> {
+    let mut create_env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--announce",
+        "https://bar",
+        "--name",
+        "combined",
+        "--add",
+        "foo:x",
+        "--add",
+        "bar.txt:x/y",
+      ],
+      tree: {
+        foo: {
+          a: "abc",
+        },
+        "bar.txt": "de",
+      },
+    };
+
+    create_env.assert_ok();
+
+    let torrent = create_env.resolve("combined.torrent")?;
+
+    let foo = format!("{}:x", create_env.resolve("foo")?.display());
+    let bar = format!("{}:x/y", create_env.resolve("bar.txt")?.display());
+
+    let mut verify_env = test_env! {
+      args: [
+        "torrent",
+        "verify",
+        "--input",
+        &torrent,
+        "--add",
+        &foo,
+        "--add",
+        &bar,
+      ],
+      tree: {},
+    };
+
+    verify_env.assert_ok();
+
+    create_env.write("bar.txt", "xy");
+
+    let mut verify_env = test_env! {
+      args: [
+        "torrent",
+        "verify",
+        "--input",
+        &torrent,
+        "--add",
+        &foo,
+        "--add",
+        &bar,
+      ],
+      tree: {},
+    };
+
+    assert_matches!(verify_env.status(), Err(EXIT_FAILURE));
+
+    Ok(())
+  }
 }
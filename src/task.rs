@@ -0,0 +1,24 @@
+use crate::common::*;
+
+/// A non-blocking network operation, driven to completion by a `Reactor`.
+pub(crate) trait Task {
+  type Output;
+
+  /// Begin the operation, opening its socket.
+  fn start(&mut self, now: Instant) -> Result<()>;
+
+  /// The socket to poll for readiness. Only called after `start` succeeds.
+  fn source(&mut self) -> &mut dyn mio::event::Source;
+
+  /// The readiness the task is currently waiting for.
+  fn interest(&self) -> mio::Interest;
+
+  /// When the task next needs to be advanced even if its socket isn't ready,
+  /// for example to retransmit a request or to give up.
+  fn wakeup(&self) -> Option<Instant>;
+
+  /// Make as much progress as possible without blocking, returning the output
+  /// once the operation is complete. Called when the socket becomes ready, and
+  /// when the wakeup time passes.
+  fn advance(&mut self, now: Instant) -> Result<Option<Self::Output>>;
+}
@@ -6,6 +6,7 @@ macro_rules! test_env {
     $(cwd: $cwd:expr,)?
     $(input: $input:expr,)?
     $(err_style: $err_style:expr,)?
+    $(vars: {$($name:literal: $value:expr),* $(,)?},)?
     tree: {
       $($tree:tt)*
     }
@@ -20,6 +21,7 @@ macro_rules! test_env {
         $(.current_dir(tempdir.path().join($cwd)))?
         $(.err_style($err_style))?
         $(.input($input))?
+        $($(.var($name, $value))*)?
         .tempdir(tempdir)
         .arg("imdl")
         $(.arg($arg))*
@@ -8,6 +8,7 @@ pub(crate) struct TestEnvBuilder {
   out_is_term: bool,
   tempdir: Option<TempDir>,
   use_color: bool,
+  vars: BTreeMap<OsString, OsString>,
 }
 
 impl TestEnvBuilder {
@@ -20,6 +21,7 @@ impl TestEnvBuilder {
       out_is_term: false,
       tempdir: None,
       use_color: false,
+      vars: BTreeMap::new(),
     }
   }
 
@@ -55,6 +57,11 @@ impl TestEnvBuilder {
     self
   }
 
+  pub(crate) fn var(mut self, name: impl Into<OsString>, value: impl Into<OsString>) -> Self {
+    self.vars.insert(name.into(), value.into());
+    self
+  }
+
   pub(crate) fn tempdir(mut self, tempdir: TempDir) -> Self {
     self.tempdir = Some(tempdir);
     self
@@ -83,6 +90,7 @@ impl TestEnvBuilder {
     let env = Env::new(
       current_dir,
       self.args,
+      self.vars,
       self.input.unwrap_or_else(|| Box::new(io::empty())),
       out_stream,
       err_stream,
@@ -25,6 +25,19 @@ pub(crate) struct TorrentSummaryJson {
   piece_count: usize,
   file_count: usize,
   files: Vec<String>,
+  #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+  file_checksums: BTreeMap<String, FileChecksumsJson>,
+}
+
+/// The whole-file checksums of one file, as hexadecimal strings.
+#[derive(Serialize)]
+struct FileChecksumsJson {
+  #[serde(skip_serializing_if = "Option::is_none")]
+  md5: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  sha1: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  sha256: Option<String>,
 }
 
 impl TorrentSummary {
@@ -178,13 +191,24 @@ impl TorrentSummary {
   }
 
   fn torrent_summary_data(&self) -> TorrentSummaryJson {
-    let (file_count, files) = match &self.metainfo.info.mode {
-      Mode::Single { .. } => (1, vec![self.metainfo.info.name.clone()]),
-      Mode::Multiple { files } => (
-        files.len(),
-        files
-          .iter()
-          .map(|file_info| {
+    let files = match &self.metainfo.info.mode {
+      Mode::Single {
+        md5sum,
+        sha1,
+        sha256,
+        ..
+      } => vec![(
+        self.metainfo.info.name.clone(),
+        Checksums {
+          md5sum: *md5sum,
+          sha1: *sha1,
+          sha256: *sha256,
+        },
+      )],
+      Mode::Multiple { files } => files
+        .iter()
+        .map(|file_info| {
+          (
             format!(
               "{}",
               file_info
@@ -192,12 +216,32 @@ impl TorrentSummary {
                 .absolute(Path::new(&self.metainfo.info.name))
                 .as_path()
                 .display()
-            )
-          })
-          .collect(),
-      ),
+            ),
+            Checksums {
+              md5sum: file_info.md5sum,
+              sha1: file_info.sha1,
+              sha256: file_info.sha256,
+            },
+          )
+        })
+        .collect(),
     };
 
+    let file_checksums = files
+      .iter()
+      .filter(|(_, checksums)| !ChecksumKinds::of(checksums).is_empty())
+      .map(|(path, checksums)| {
+        (
+          path.clone(),
+          FileChecksumsJson {
+            md5: checksums.md5sum.map(|digest| digest.to_string()),
+            sha1: checksums.sha1.map(|digest| digest.to_string()),
+            sha256: checksums.sha256.map(|digest| digest.to_string()),
+          },
+        )
+      })
+      .collect();
+
     TorrentSummaryJson {
       name: self.metainfo.info.name.clone(),
       comment: self.metainfo.comment.clone(),
@@ -226,8 +270,9 @@ impl TorrentSummary {
         .collect::<Vec<String>>(),
       piece_size: self.metainfo.info.piece_length.count(),
       piece_count: self.metainfo.info.pieces.count(),
-      file_count,
-      files,
+      file_count: files.len(),
+      files: files.into_iter().map(|(path, _)| path).collect(),
+      file_checksums,
     }
   }
 }
@@ -2,16 +2,32 @@ use request::Request;
 use response::Response;
 
 pub(crate) use action::Action;
+pub(crate) use announcer::Announcer;
+#[cfg(test)]
 pub(crate) use client::Client;
 #[cfg(test)]
 pub(crate) use daemon::Daemon;
+pub(crate) use event::Event;
+pub(crate) use resolver::Resolver;
+pub(crate) use swarm::Swarm;
+pub(crate) use web_socket_announcer::WebSocketAnnouncer;
+#[cfg(test)]
+pub(crate) use web_socket_daemon::WebSocketDaemon;
 
+mod announcer;
+#[cfg(test)]
 mod client;
 #[cfg(test)]
 pub mod daemon;
 mod request;
+mod resolver;
 mod response;
+mod swarm;
+mod web_socket_announcer;
+#[cfg(test)]
+mod web_socket_daemon;
 
 mod action;
 mod announce;
 mod connect;
+mod event;
@@ -10,8 +10,9 @@ pub(crate) struct Request {
   pub(crate) downloaded: u64,     // 64
   pub(crate) left: u64,           // 72
   pub(crate) uploaded: u64,       // 80
-  pub(crate) event: u64,          // 88
-  pub(crate) ip_address: u32,     // 92
+  pub(crate) event: u32,          // 84
+  pub(crate) ip_address: u32,     // 88
+  pub(crate) key: u32,            // 92
   pub(crate) num_want: u32,       // 96
   pub(crate) port: u16,           // 98
 }
@@ -30,12 +31,27 @@ impl Request {
       downloaded: 0x0000,
       left: u64::MAX,
       uploaded: 0x0000,
-      event: 0x0000,
+      event: tracker::Event::None.into(),
       ip_address: 0x0000,
+      key: 0x0000,
       num_want: u32::MAX,
       port,
     }
   }
+
+  pub(crate) fn seeding(
+    connection_id: u64,
+    btinh: Infohash,
+    peer_id: [u8; 20],
+    port: u16,
+    event: tracker::Event,
+  ) -> Self {
+    Self {
+      left: 0,
+      event: event.into(),
+      ..Self::new(connection_id, btinh, peer_id, port)
+    }
+  }
 }
 
 #[derive(Debug, PartialEq)]
@@ -67,6 +83,7 @@ impl super::Request for Request {
     msg.extend_from_slice(&self.uploaded.to_be_bytes());
     msg.extend_from_slice(&self.event.to_be_bytes());
     msg.extend_from_slice(&self.ip_address.to_be_bytes());
+    msg.extend_from_slice(&self.key.to_be_bytes());
     msg.extend_from_slice(&self.num_want.to_be_bytes());
     msg.extend_from_slice(&self.port.to_be_bytes());
 
@@ -129,12 +146,17 @@ impl super::Response for Request {
             .try_into()
             .invariant_unwrap("buf size is at least Request::LENGTH"),
         ),
-        event: u64::from_be_bytes(
-          buf[80..88]
+        event: u32::from_be_bytes(
+          buf[80..84]
             .try_into()
             .invariant_unwrap("buf size is at least Request::LENGTH"),
         ),
         ip_address: u32::from_be_bytes(
+          buf[84..88]
+            .try_into()
+            .invariant_unwrap("buf size is at least Request::LENGTH"),
+        ),
+        key: u32::from_be_bytes(
           buf[88..92]
             .try_into()
             .invariant_unwrap("buf size is at least Request::LENGTH"),
@@ -256,8 +278,9 @@ mod tests {
       uploaded: 0x08,
       event: 0x09,
       ip_address: 0x0a,
-      num_want: 0x0b,
-      port: 0x0c,
+      key: 0x0b,
+      num_want: 0x0c,
+      port: 0x0d,
     };
     let buf = req.serialize();
     let (req2, _) = announce::Request::deserialize(&buf).unwrap();
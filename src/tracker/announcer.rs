@@ -0,0 +1,596 @@
+use super::*;
+use crate::common::*;
+
+/// A non-blocking BEP 15 UDP tracker announce, driven by a `Reactor`. Each
+/// request is retransmitted if no response arrives within the timeout.
+/// Datagrams may be relayed through a SOCKS5 proxy, which looks up the
+/// tracker's host itself.
+#[derive(Debug)]
+pub(crate) struct Announcer {
+  url: Url,
+  targets: Vec<HostPort>,
+  infohash: Infohash,
+  peer_id: [u8; 20],
+  seeding: Option<(u16, tracker::Event)>,
+  timeout: Duration,
+  proxy: Option<Proxy>,
+  association: Option<proxy::Association>,
+  socket: Option<(mio::net::UdpSocket, HostPort)>,
+  state: State,
+  attempts: u32,
+  sent: Option<Instant>,
+}
+
+#[derive(Debug)]
+enum State {
+  Connect(connect::Request),
+  Announce(announce::Request),
+}
+
+impl Announcer {
+  pub(crate) const TIMEOUT: Duration = Duration::from_secs(3);
+  const ATTEMPTS: u32 = 3;
+  const RX_BUF_LEN: usize = 8192;
+
+  /// An announcer for `tracker_url`, relaying datagrams through `proxy`, if
+  /// any. The tracker's host is only looked up here without a proxy, so that
+  /// look-ups don't bypass it.
+  pub(crate) fn from_url(
+    tracker_url: &Url,
+    infohash: Infohash,
+    timeout: Duration,
+    proxy: Option<&Proxy>,
+  ) -> Result<Self> {
+    let host_port = Self::host_port(tracker_url)?;
+
+    if let Some(proxy) = proxy {
+      return Ok(Self {
+        proxy: Some(proxy.clone()),
+        ..Self::with_targets(tracker_url, vec![host_port], infohash, timeout)
+      });
+    }
+
+    let addrs = host_port
+      .to_socket_addrs() // this may cause DNS look-ups!
+      .context(error::TrackerSocketAddrs)?
+      .collect::<Vec<SocketAddr>>();
+
+    Self::new(tracker_url, addrs, infohash, timeout)
+  }
+
+  /// An announcer for `tracker_url`, whose host has already been resolved to
+  /// `addrs`.
+  pub(crate) fn new(
+    tracker_url: &Url,
+    addrs: Vec<SocketAddr>,
+    infohash: Infohash,
+    timeout: Duration,
+  ) -> Result<Self> {
+    if addrs.is_empty() {
+      return Err(Error::TrackerNoHosts);
+    }
+
+    Ok(Self::with_targets(
+      tracker_url,
+      addrs.into_iter().map(HostPort::from).collect(),
+      infohash,
+      timeout,
+    ))
+  }
+
+  fn with_targets(
+    tracker_url: &Url,
+    targets: Vec<HostPort>,
+    infohash: Infohash,
+    timeout: Duration,
+  ) -> Self {
+    Self {
+      url: tracker_url.clone(),
+      targets,
+      infohash,
+      peer_id: peer::ClientId::peer_id(),
+      seeding: None,
+      timeout,
+      proxy: None,
+      association: None,
+      socket: None,
+      state: State::Connect(connect::Request::new()),
+      attempts: 0,
+      sent: None,
+    }
+  }
+
+  /// The host and port of `tracker_url`, which must be a UDP tracker.
+  pub(crate) fn host_port(tracker_url: &Url) -> Result<HostPort> {
+    if tracker_url.scheme() != "udp" {
+      return Err(Error::TrackerUdpOnly {
+        tracker_url: tracker_url.clone(),
+      });
+    }
+
+    HostPort::try_from(tracker_url).context(error::TrackerHostPort {
+      tracker_url: tracker_url.clone(),
+    })
+  }
+
+  /// Announce that the complete torrent is available from this host, with
+  /// peers accepted on `port`, sending `event`.
+  pub(crate) fn seeding(self, port: u16, event: tracker::Event) -> Self {
+    Self {
+      seeding: Some((port, event)),
+      ..self
+    }
+  }
+
+  pub(crate) fn url(&self) -> &Url {
+    &self.url
+  }
+
+  fn open(addr: SocketAddr) -> Result<mio::net::UdpSocket> {
+    let socket = mio::net::UdpSocket::bind(match addr {
+      SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+      SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    })
+    .context(error::UdpSocketBind)?;
+    socket
+      .connect(addr)
+      .context(error::UdpSocketConnect { addr })?;
+    Ok(socket)
+  }
+
+  fn send(&mut self, now: Instant) -> Result<()> {
+    let msg = match &self.state {
+      State::Connect(req) => req.serialize(),
+      State::Announce(req) => req.serialize(),
+    };
+
+    if let Some((socket, target)) = &self.socket {
+      let msg = match self.association {
+        Some(_) => proxy::Association::wrap(target, &msg)?,
+        None => msg,
+      };
+
+      match socket.send(&msg) {
+        // A datagram that can't be sent is retransmitted like a lost one.
+        Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+        Err(source) => return Err(Error::TrackerSend { source }),
+        Ok(_) => {}
+      }
+    }
+
+    self.attempts += 1;
+    self.sent = Some(now);
+
+    Ok(())
+  }
+
+  // Handle a response, which lists IPv6 peers if it came from an IPv6
+  // address.
+  fn receive(&mut self, buf: &[u8], ipv6: bool, now: Instant) -> Result<Option<Vec<SocketAddr>>> {
+    let Some((socket, _)) = &self.socket else {
+      return Ok(None);
+    };
+
+    match &self.state {
+      State::Connect(req) => {
+        let (resp, _) = connect::Response::deserialize(buf)?;
+        Self::check(req, &resp)?;
+
+        let req = match self.seeding {
+          Some((port, event)) => {
+            announce::Request::seeding(resp.connection_id, self.infohash, self.peer_id, port, event)
+          }
+          None => announce::Request::new(
+            resp.connection_id,
+            self.infohash,
+            self.peer_id,
+            socket
+              .local_addr()
+              .context(error::UdpSocketLocalAddress)?
+              .port(),
+          ),
+        };
+
+        self.state = State::Announce(req);
+        self.attempts = 0;
+        self.send(now)?;
+
+        Ok(None)
+      }
+      State::Announce(req) => {
+        let (resp, payload) = announce::Response::deserialize(buf)?;
+        Self::check(req, &resp)?;
+        Self::parse_compact_peer_list(payload, ipv6).map(Some)
+      }
+    }
+  }
+
+  fn is_ipv6(host_port: &HostPort) -> bool {
+    host_port.socket_addr().is_some_and(|addr| addr.is_ipv6())
+  }
+
+  fn check<T: Request>(req: &T, resp: &T::Response) -> Result<()> {
+    if resp.transaction_id() != req.transaction_id() || resp.action() != req.action() {
+      return Err(Error::TrackerResponse);
+    }
+    Ok(())
+  }
+
+  pub(crate) fn parse_compact_peer_list(buf: &[u8], is_ipv6: bool) -> Result<Vec<SocketAddr>> {
+    let mut peer_list = Vec::<SocketAddr>::new();
+    let stride = if is_ipv6 { 18 } else { 6 };
+
+    let chunks = buf.chunks_exact(stride);
+    if !chunks.remainder().is_empty() {
+      return Err(Error::TrackerCompactPeerList);
+    }
+
+    for hostpost in chunks {
+      let (ip, port) = hostpost.split_at(stride - 2);
+      let ip = if is_ipv6 {
+        let octets: [u8; 16] = ip[0..16]
+          .try_into()
+          .invariant_unwrap("iterator guarantees bounds are OK");
+        IpAddr::from(std::net::Ipv6Addr::from(octets))
+      } else {
+        IpAddr::from(std::net::Ipv4Addr::new(ip[0], ip[1], ip[2], ip[3]))
+      };
+
+      let port = u16::from_be_bytes(
+        port
+          .try_into()
+          .invariant_unwrap("iterator guarantees bounds are OK"),
+      );
+
+      peer_list.push((ip, port).into());
+    }
+
+    Ok(peer_list)
+  }
+}
+
+impl Task for Announcer {
+  type Output = Vec<SocketAddr>;
+
+  fn start(&mut self, now: Instant) -> Result<()> {
+    // The proxy is nearby, so the association is negotiated synchronously.
+    if let Some(proxy) = &self.proxy {
+      self.association = Some(proxy.associate(self.timeout)?);
+    }
+
+    // Use the first address we can send to, in the order returned by the
+    // resolver. Through a proxy, the only target is the tracker's host, which
+    // the proxy looks up.
+    for target in self.targets.clone() {
+      let destination = match &self.association {
+        Some(association) => association.relay(),
+        None => target
+          .socket_addr()
+          .invariant_unwrap("targets are resolved without a proxy"),
+      };
+
+      let Ok(socket) = Self::open(destination) else {
+        continue;
+      };
+
+      self.socket = Some((socket, target));
+
+      if self.send(now).is_ok() {
+        return Ok(());
+      }
+
+      self.attempts = 0;
+    }
+
+    self.socket = None;
+
+    Err(Error::TrackerNoHosts)
+  }
+
+  fn source(&mut self) -> &mut dyn mio::event::Source {
+    &mut self
+      .socket
+      .as_mut()
+      .invariant_unwrap("socket is opened by start")
+      .0
+  }
+
+  fn interest(&self) -> mio::Interest {
+    mio::Interest::READABLE
+  }
+
+  fn wakeup(&self) -> Option<Instant> {
+    self.sent.map(|sent| sent + self.timeout)
+  }
+
+  fn advance(&mut self, now: Instant) -> Result<Option<Vec<SocketAddr>>> {
+    let mut buf = [0u8; Self::RX_BUF_LEN];
+
+    loop {
+      let Some((socket, target)) = &self.socket else {
+        return Ok(None);
+      };
+
+      let len = match socket.recv(&mut buf) {
+        Ok(len) => len,
+        Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+        // Receive errors, such as ICMP port unreachable, are treated like lost
+        // datagrams.
+        Err(_) => break,
+      };
+
+      let (payload, ipv6) = if self.association.is_some() {
+        match proxy::Association::unwrap(&buf[..len]) {
+          Some((source, payload)) => (payload, Self::is_ipv6(&source)),
+          None => continue,
+        }
+      } else {
+        (&buf[..len], Self::is_ipv6(target))
+      };
+
+      if let Some(peers) = self.receive(payload, ipv6, now)? {
+        return Ok(Some(peers));
+      }
+    }
+
+    if self.wakeup().is_some_and(|wakeup| now >= wakeup) {
+      if self.attempts >= Self::ATTEMPTS {
+        let (_, target) = self
+          .socket
+          .as_ref()
+          .invariant_unwrap("socket is opened by start");
+        return Err(match target.socket_addr() {
+          Some(tracker_addr) => Error::TrackerExchange { tracker_addr },
+          None => Error::TrackerTimeout {
+            tracker_url: self.url.clone(),
+          },
+        });
+      }
+
+      self.send(now)?;
+    }
+
+    Ok(None)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use std::net::UdpSocket;
+
+  struct TestServer {
+    sock: UdpSocket,
+    peer_list: Vec<u8>,
+  }
+
+  impl TestServer {
+    fn new(ip: IpAddr) -> (Self, Url) {
+      let sock = UdpSocket::bind((ip, 0)).unwrap();
+
+      let port = sock.local_addr().unwrap().port();
+      let stride = if ip.is_ipv6() { 18 } else { 6 };
+      let peer_list = (0..10 * stride)
+        .map(|_| rand::rng().random::<u8>())
+        .collect::<Vec<u8>>();
+
+      let url = if ip.is_ipv6() {
+        format!("udp://[::1]:{port}")
+      } else {
+        format!("udp://127.0.0.1:{port}")
+      };
+
+      (TestServer { sock, peer_list }, url.parse().unwrap())
+    }
+
+    fn connect_exchange(&self) -> SocketAddr {
+      let mut buf = [0u8; 8192];
+      let (n, peer) = self.sock.recv_from(&mut buf).unwrap();
+      let (req, _) = connect::Request::deserialize(&buf[..n]).unwrap();
+      let resp = connect::Response {
+        action: Action::Connect.into(),
+        transaction_id: req.transaction_id,
+        connection_id: rand::rng().random(),
+      }
+      .serialize();
+      self.sock.send_to(&resp, peer).unwrap();
+      peer
+    }
+
+    fn announce_exchange(&self) -> announce::Request {
+      let mut buf = [0u8; 8192];
+      let (n, peer) = self.sock.recv_from(&mut buf).unwrap();
+      let (req, _) = announce::Request::deserialize(&buf[..n]).unwrap();
+      let mut resp = announce::Response {
+        action: Action::Announce.into(),
+        transaction_id: req.transaction_id,
+        interval: 0x1337_1337,
+        leechers: 0xcafe_babe,
+        seeders: 0xdead_beef,
+      }
+      .serialize();
+      resp.extend_from_slice(&self.peer_list);
+      self.sock.send_to(&resp, peer).unwrap();
+      req
+    }
+  }
+
+  fn run(announcer: Announcer) -> Result<Vec<SocketAddr>> {
+    let mut reactor = Reactor::new(1).unwrap();
+    reactor.push(announcer);
+    let mut output = None;
+    reactor
+      .run(|_, _, result| {
+        output = Some(result);
+        Ok(Control::Continue)
+      })
+      .unwrap();
+    output.unwrap()
+  }
+
+  fn announcer(url: &Url) -> Announcer {
+    announcer_via(url, None)
+  }
+
+  fn announcer_via(url: &Url, proxy: Option<&Proxy>) -> Announcer {
+    Announcer::from_url(
+      url,
+      Infohash::from([0; 20]),
+      Duration::from_millis(100),
+      proxy,
+    )
+    .unwrap()
+  }
+
+  #[test]
+  fn from_url_no_port() {
+    let tracker_url = Url::parse("udp://intermodal.io/announce").unwrap();
+    assert_matches!(
+      Announcer::from_url(
+        &tracker_url,
+        Infohash::from([0; 20]),
+        Announcer::TIMEOUT,
+        None
+      ),
+      Err(Error::TrackerHostPort { .. })
+    );
+  }
+
+  #[test]
+  fn from_url_not_udp() {
+    let tracker_url = Url::parse("https://intermodal.io:100/announce").unwrap();
+    assert_matches!(
+      Announcer::from_url(
+        &tracker_url,
+        Infohash::from([0; 20]),
+        Announcer::TIMEOUT,
+        None
+      ),
+      Err(Error::TrackerUdpOnly { .. })
+    );
+  }
+
+  #[test]
+  fn announce_ipv4() {
+    let (server, url) = TestServer::new(Ipv4Addr::LOCALHOST.into());
+    let expected = Announcer::parse_compact_peer_list(&server.peer_list, false).unwrap();
+    thread::spawn(move || {
+      server.connect_exchange();
+      server.announce_exchange();
+    });
+    assert_eq!(run(announcer(&url)).unwrap(), expected);
+  }
+
+  #[test]
+  fn announce_ipv6() {
+    let (server, url) = TestServer::new(Ipv6Addr::LOCALHOST.into());
+    let expected = Announcer::parse_compact_peer_list(&server.peer_list, true).unwrap();
+    thread::spawn(move || {
+      server.connect_exchange();
+      server.announce_exchange();
+    });
+    assert_eq!(run(announcer(&url)).unwrap(), expected);
+  }
+
+  #[test]
+  fn announce_seeding() {
+    let (server, url) = TestServer::new(Ipv4Addr::LOCALHOST.into());
+    let handle = thread::spawn(move || {
+      server.connect_exchange();
+      server.announce_exchange()
+    });
+    run(announcer(&url).seeding(1234, tracker::Event::Started)).unwrap();
+    let req = handle.join().unwrap();
+    assert_eq!(req.port, 1234);
+    assert_eq!(req.left, 0);
+    assert_eq!(req.event, 2);
+  }
+
+  #[test]
+  fn reannounce_seeding() {
+    let (server, url) = TestServer::new(Ipv4Addr::LOCALHOST.into());
+    let handle = thread::spawn(move || {
+      server.connect_exchange();
+      server.announce_exchange()
+    });
+    run(announcer(&url).seeding(1234, tracker::Event::None)).unwrap();
+    let req = handle.join().unwrap();
+    assert_eq!(req.event, 0);
+    assert_eq!(req.ip_address, 0);
+  }
+
+  #[test]
+  fn announce_through_proxy() {
+    let (server, url) = TestServer::new(Ipv4Addr::LOCALHOST.into());
+    let tracker = server.sock.local_addr().unwrap();
+    let expected = Announcer::parse_compact_peer_list(&server.peer_list, false).unwrap();
+    thread::spawn(move || {
+      server.connect_exchange();
+      server.announce_exchange();
+    });
+
+    let (proxy_server, proxy) = proxy::Server::spawn();
+    let proxy = Proxy::from_url(&format!("socks5://{proxy}").parse().unwrap()).unwrap();
+
+    assert_eq!(run(announcer_via(&url, Some(&proxy))).unwrap(), expected);
+    assert_eq!(proxy_server.targets(), [tracker.into(), tracker.into()]);
+  }
+
+  #[test]
+  fn announce_domain_through_proxy() {
+    let (server, _) = TestServer::new(Ipv4Addr::LOCALHOST.into());
+    let port = server.sock.local_addr().unwrap().port();
+    let expected = Announcer::parse_compact_peer_list(&server.peer_list, false).unwrap();
+    thread::spawn(move || {
+      server.connect_exchange();
+      server.announce_exchange();
+    });
+
+    let (proxy_server, proxy) = proxy::Server::spawn();
+    let proxy = Proxy::from_url(&format!("socks5://{proxy}").parse().unwrap()).unwrap();
+
+    let url = format!("udp://localhost:{port}").parse().unwrap();
+    let target = format!("localhost:{port}").parse::<HostPort>().unwrap();
+
+    assert_eq!(run(announcer_via(&url, Some(&proxy))).unwrap(), expected);
+    assert_eq!(proxy_server.targets(), [target.clone(), target]);
+  }
+
+  #[test]
+  fn announce_through_http_proxy() {
+    let (_server, url) = TestServer::new(Ipv4Addr::LOCALHOST.into());
+    let proxy = Proxy::from_url(&"http://127.0.0.1:3128".parse().unwrap()).unwrap();
+    assert_matches!(run(announcer_via(&url, Some(&proxy))), Err(Error::ProxyUdp));
+  }
+
+  #[test]
+  fn connect_timeout() {
+    let (_server, url) = TestServer::new(Ipv4Addr::LOCALHOST.into());
+    assert_matches!(run(announcer(&url)), Err(Error::TrackerExchange { .. }));
+  }
+
+  #[test]
+  fn announce_timeout() {
+    let (server, url) = TestServer::new(Ipv4Addr::LOCALHOST.into());
+    thread::spawn(move || {
+      server.connect_exchange();
+      // Hold the socket open without answering the announce.
+      thread::sleep(Duration::from_secs(5));
+    });
+    assert_matches!(run(announcer(&url)), Err(Error::TrackerExchange { .. }));
+  }
+
+  #[test]
+  fn retransmit() {
+    let (server, url) = TestServer::new(Ipv4Addr::LOCALHOST.into());
+    thread::spawn(move || {
+      // Ignore the first connect request.
+      let mut buf = [0u8; 8192];
+      server.sock.recv_from(&mut buf).unwrap();
+      server.connect_exchange();
+      server.announce_exchange();
+    });
+    assert_matches!(run(announcer(&url)), Ok(..));
+  }
+}
@@ -1,6 +1,9 @@
 use super::*;
 use crate::common::*;
 
+use std::net::UdpSocket;
+
+/// A blocking tracker client, used to exercise the tracker daemon in tests.
 #[derive(Debug)]
 pub(crate) struct Client {
   peer_id: [u8; 20],
@@ -50,7 +53,7 @@ impl Client {
         Self::UDP_SOCKET_READ_TIMEOUT_S,
         Self::UDP_SOCKET_READ_TIMEOUT_NS,
       )))
-      .context(error::UdpSocketReadTimeout)?;
+      .context(error::Network)?;
     Ok(sock)
   }
 
@@ -88,7 +91,7 @@ impl Client {
     let mut buf = [0u8; Self::RX_BUF_LEN];
     let (_, payload) = self.exchange(&req, &mut buf)?;
 
-    Client::parse_compact_peer_list(payload, local_addr.is_ipv6())
+    Announcer::parse_compact_peer_list(payload, local_addr.is_ipv6())
   }
 
   fn exchange<'a, T: Request>(
@@ -121,39 +124,6 @@ impl Client {
     Ok((resp, payload))
   }
 
-  fn parse_compact_peer_list(buf: &[u8], is_ipv6: bool) -> Result<Vec<SocketAddr>> {
-    let mut peer_list = Vec::<SocketAddr>::new();
-    let stride = if is_ipv6 { 18 } else { 6 };
-
-    let chunks = buf.chunks_exact(stride);
-    if !chunks.remainder().is_empty() {
-      return Err(Error::TrackerCompactPeerList);
-    }
-
-    for hostpost in chunks {
-      let (ip, port) = hostpost.split_at(stride - 2);
-      let ip = if is_ipv6 {
-        let octets: [u8; 16] = ip[0..16]
-          .try_into()
-          .invariant_unwrap("iterator guarantees bounds are OK");
-        IpAddr::from(std::net::Ipv6Addr::from(octets))
-      } else {
-        IpAddr::from(std::net::Ipv4Addr::new(ip[0], ip[1], ip[2], ip[3]))
-      };
-
-      let port = u16::from_be_bytes(
-        port
-          .try_into()
-          .invariant_unwrap("iterator guarantees bounds are OK"),
-      );
-
-      peer_list.push((ip, port).into());
-    }
-
-    Ok(peer_list)
-  }
-
-  #[cfg(test)]
   pub fn local_addr(&self) -> SocketAddr {
     (Ipv4Addr::LOCALHOST, self.sock.local_addr().unwrap().port()).into()
   }
@@ -334,7 +304,7 @@ mod tests {
       .unwrap();
     assert_eq!(
       addrs,
-      Client::parse_compact_peer_list(&expected_targets, addr.is_ipv6()).unwrap()
+      Announcer::parse_compact_peer_list(&expected_targets, addr.is_ipv6()).unwrap()
     );
   }
 
@@ -352,7 +322,7 @@ mod tests {
       .unwrap();
     assert_eq!(
       addrs,
-      Client::parse_compact_peer_list(&expected_targets, addr.is_ipv6()).unwrap()
+      Announcer::parse_compact_peer_list(&expected_targets, addr.is_ipv6()).unwrap()
     );
   }
 }
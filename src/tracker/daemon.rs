@@ -1,6 +1,8 @@
 use super::*;
 use crate::common::*;
 
+use std::net::UdpSocket;
+
 #[cfg(test)]
 pub(crate) struct Daemon {
   pub(crate) sock: UdpSocket,
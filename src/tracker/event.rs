@@ -0,0 +1,17 @@
+/// The BEP 15 `event` of an announce.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Event {
+  /// A regular re-announce.
+  None,
+  /// The first announce after starting.
+  Started,
+}
+
+impl From<Event> for u32 {
+  fn from(event: Event) -> Self {
+    match event {
+      Event::None => 0,
+      Event::Started => 2,
+    }
+  }
+}
@@ -0,0 +1,218 @@
+use crate::common::*;
+
+use std::sync::mpsc;
+
+/// Resolves a UDP tracker's host on a helper thread, so that a slow DNS
+/// look-up doesn't hold up the other tasks on a `Reactor`, and produces the
+/// `Announcer` for the tracker. The helper thread wakes the reactor by
+/// sending a datagram to a loopback socket once it is done. Through a proxy,
+/// the proxy looks up the host instead, so nothing is looked up here.
+#[derive(Debug)]
+pub(crate) struct Resolver {
+  url: Url,
+  host_port: HostPort,
+  infohash: Infohash,
+  timeout: Duration,
+  public_only: bool,
+  proxy: Option<Proxy>,
+  socket: Option<mio::net::UdpSocket>,
+  addrs: Option<mpsc::Receiver<io::Result<Vec<SocketAddr>>>>,
+  started: Option<Instant>,
+}
+
+impl Resolver {
+  /// Give up on a look-up after this long.
+  const TIMEOUT: Duration = Duration::from_secs(10);
+
+  pub(crate) fn new(tracker_url: &Url, infohash: Infohash, timeout: Duration) -> Result<Self> {
+    Ok(Self {
+      url: tracker_url.clone(),
+      host_port: tracker::Announcer::host_port(tracker_url)?,
+      infohash,
+      timeout,
+      public_only: false,
+      proxy: None,
+      socket: None,
+      addrs: None,
+      started: None,
+    })
+  }
+
+  /// Only announce to addresses that may be reachable from the public
+  /// internet, for trackers whose URLs came from untrusted peers. Addresses
+  /// that a proxy looks up aren't checked.
+  pub(crate) fn public_only(self) -> Self {
+    Self {
+      public_only: true,
+      ..self
+    }
+  }
+
+  /// Relay the announce through `proxy`, if any.
+  pub(crate) fn via(self, proxy: Option<&Proxy>) -> Self {
+    Self {
+      proxy: proxy.cloned(),
+      ..self
+    }
+  }
+
+  pub(crate) fn url(&self) -> &Url {
+    &self.url
+  }
+
+  fn announcer(&self, addrs: io::Result<Vec<SocketAddr>>) -> Result<tracker::Announcer> {
+    if let Some(proxy) = &self.proxy {
+      return tracker::Announcer::from_url(&self.url, self.infohash, self.timeout, Some(proxy));
+    }
+
+    let mut addrs = addrs.context(error::TrackerSocketAddrs)?;
+
+    if self.public_only {
+      addrs.retain(|addr| is_public(addr.ip()));
+    }
+
+    tracker::Announcer::new(&self.url, addrs, self.infohash, self.timeout)
+  }
+}
+
+impl Task for Resolver {
+  type Output = tracker::Announcer;
+
+  fn start(&mut self, now: Instant) -> Result<()> {
+    let socket =
+      mio::net::UdpSocket::bind((Ipv4Addr::LOCALHOST, 0).into()).context(error::UdpSocketBind)?;
+    let wake = socket.local_addr().context(error::UdpSocketLocalAddress)?;
+
+    let (tx, rx) = mpsc::channel();
+    let host_port = self.host_port.clone();
+    let proxied = self.proxy.is_some();
+
+    thread::spawn(move || {
+      let addrs = if proxied {
+        Ok(Vec::new())
+      } else {
+        host_port
+          .to_socket_addrs()
+          .map(Iterator::collect::<Vec<SocketAddr>>)
+      };
+
+      // The resolver has given up if either of these fail.
+      if tx.send(addrs).is_ok() {
+        if let Ok(socket) = std::net::UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)) {
+          socket.send_to(&[0], wake).ok();
+        }
+      }
+    });
+
+    self.socket = Some(socket);
+    self.addrs = Some(rx);
+    self.started = Some(now);
+
+    Ok(())
+  }
+
+  fn source(&mut self) -> &mut dyn mio::event::Source {
+    self
+      .socket
+      .as_mut()
+      .invariant_unwrap("socket is opened by start")
+  }
+
+  fn interest(&self) -> mio::Interest {
+    mio::Interest::READABLE
+  }
+
+  fn wakeup(&self) -> Option<Instant> {
+    self.started.map(|started| started + Self::TIMEOUT)
+  }
+
+  fn advance(&mut self, now: Instant) -> Result<Option<tracker::Announcer>> {
+    if let Some(socket) = &self.socket {
+      let mut buf = [0];
+      while socket.recv(&mut buf).is_ok() {}
+    }
+
+    let addrs = self
+      .addrs
+      .as_ref()
+      .invariant_unwrap("look-up is started by start")
+      .try_recv();
+
+    match addrs {
+      Ok(addrs) => self.announcer(addrs).map(Some),
+      Err(mpsc::TryRecvError::Empty) if self.wakeup().is_some_and(|wakeup| now < wakeup) => {
+        Ok(None)
+      }
+      Err(_) => Err(Error::TrackerSocketAddrs {
+        source: io::ErrorKind::TimedOut.into(),
+      }),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn resolve(resolver: Resolver) -> Result<tracker::Announcer> {
+    let mut reactor = Reactor::new(1).unwrap();
+    reactor.push(resolver);
+    let mut output = None;
+    reactor
+      .run(|_, _, result| {
+        output = Some(result);
+        Ok(Control::Continue)
+      })
+      .unwrap();
+    output.unwrap()
+  }
+
+  fn resolver(url: &str) -> Resolver {
+    Resolver::new(
+      &url.parse().unwrap(),
+      Infohash::from([0; 20]),
+      tracker::Announcer::TIMEOUT,
+    )
+    .unwrap()
+  }
+
+  #[test]
+  fn resolve_ip() {
+    let announcer = resolve(resolver("udp://127.0.0.1:1337")).unwrap();
+    assert_eq!(announcer.url().as_str(), "udp://127.0.0.1:1337");
+  }
+
+  #[test]
+  fn public_only() {
+    assert_matches!(
+      resolve(resolver("udp://127.0.0.1:1337").public_only()),
+      Err(Error::TrackerNoHosts)
+    );
+  }
+
+  #[test]
+  fn proxied() {
+    let proxy = Proxy::from_url(&"socks5://127.0.0.1:1080".parse().unwrap()).unwrap();
+
+    let announcer = resolve(
+      resolver("udp://tracker.invalid:1337")
+        .public_only()
+        .via(Some(&proxy)),
+    )
+    .unwrap();
+
+    assert_eq!(announcer.url().as_str(), "udp://tracker.invalid:1337");
+  }
+
+  #[test]
+  fn not_udp() {
+    assert_matches!(
+      Resolver::new(
+        &"https://127.0.0.1:1337".parse().unwrap(),
+        Infohash::from([0; 20]),
+        tracker::Announcer::TIMEOUT,
+      ),
+      Err(Error::TrackerUdpOnly { .. })
+    );
+  }
+}
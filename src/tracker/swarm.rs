@@ -0,0 +1,24 @@
+use crate::common::*;
+
+/// The size of a torrent's swarm, as reported by a tracker.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct Swarm {
+  /// Peers with the complete torrent.
+  pub(crate) complete: u64,
+  /// Peers still downloading.
+  pub(crate) incomplete: u64,
+  /// Completed downloads, if the tracker answered a scrape.
+  pub(crate) downloaded: Option<u64>,
+}
+
+impl Display for Swarm {
+  fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+    write!(f, "{} seeders, {} leechers", self.complete, self.incomplete)?;
+
+    if let Some(downloaded) = self.downloaded {
+      write!(f, ", {downloaded} downloads")?;
+    }
+
+    Ok(())
+  }
+}
@@ -0,0 +1,723 @@
+use super::*;
+use crate::common::*;
+
+use mio::net::TcpStream;
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, ClientConnection, RootCertStore};
+use std::sync::LazyLock;
+
+static TLS_CONFIG: LazyLock<Arc<ClientConfig>> = LazyLock::new(|| {
+  Arc::new(
+    ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+      .with_safe_default_protocol_versions()
+      .invariant_unwrap("ring supports the default protocol versions")
+      .with_root_certificates(RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+      })
+      .with_no_client_auth(),
+  )
+});
+
+/// A non-blocking `WebTorrent` tracker announce and scrape, over a `ws://` or
+/// `wss://` WebSocket, driven by a `Reactor`. `WebTorrent` trackers exchange
+/// WebRTC signaling messages instead of peer addresses, so we send no offers,
+/// and the result is the size of the swarm.
+#[derive(Debug)]
+pub(crate) struct WebSocketAnnouncer {
+  url: Url,
+  target: HostPort,
+  infohash: Infohash,
+  peer_id: [u8; 20],
+  timeout: Duration,
+  proxy: Option<Proxy>,
+  tunnel: Option<proxy::Tunnel>,
+  tls: Option<ClientConnection>,
+  websocket: Option<websocket::Client>,
+  stream: Option<TcpStream>,
+  started: Option<Instant>,
+  connected: bool,
+  state: State,
+  swarm: Swarm,
+  // Bytes as sent and received on the socket, which are encrypted with TLS
+  // for `wss://` trackers.
+  raw_in: Vec<u8>,
+  raw_out: Vec<u8>,
+  // Bytes of the WebSocket connection.
+  plain_in: Vec<u8>,
+  plain_out: Vec<u8>,
+  eof: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+  Handshake,
+  Announce,
+  Scrape,
+}
+
+#[derive(Serialize)]
+struct AnnounceRequest {
+  action: &'static str,
+  info_hash: String,
+  peer_id: String,
+  uploaded: u64,
+  downloaded: u64,
+  event: &'static str,
+  numwant: u64,
+  offers: Vec<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+struct ScrapeRequest {
+  action: &'static str,
+  info_hash: String,
+}
+
+#[derive(Deserialize)]
+struct Response {
+  action: Option<String>,
+  info_hash: Option<String>,
+  interval: Option<u64>,
+  complete: Option<u64>,
+  incomplete: Option<u64>,
+  files: Option<HashMap<String, ScrapeFile>>,
+  #[serde(rename = "failure reason")]
+  failure_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ScrapeFile {
+  complete: Option<u64>,
+  incomplete: Option<u64>,
+  downloaded: Option<u64>,
+}
+
+impl WebSocketAnnouncer {
+  // Unlike UDP requests, requests over TCP aren't retransmitted, so give the
+  // whole exchange as long as the UDP announcer's retries would take.
+  const ATTEMPTS: u32 = 3;
+  const READ_CHUNK: usize = 16 * 1024;
+
+  /// Whether `tracker_url` is a WebSocket tracker.
+  pub(crate) fn supports(tracker_url: &Url) -> bool {
+    matches!(tracker_url.scheme(), "ws" | "wss")
+  }
+
+  /// An announcer for `tracker_url`, connecting through `proxy`, if any. The
+  /// tracker's host is only looked up here without a proxy, so that look-ups
+  /// don't bypass it.
+  pub(crate) fn from_url(
+    tracker_url: &Url,
+    infohash: Infohash,
+    timeout: Duration,
+    proxy: Option<&Proxy>,
+  ) -> Result<Self> {
+    let port = tracker_url.port_or_known_default();
+
+    let target = match (tracker_url.host(), port) {
+      (Some(Host::Domain(domain)), Some(port)) if proxy.is_some() => {
+        HostPort::new(Host::Domain(domain.to_owned()), port)
+      }
+      (Some(Host::Domain(domain)), Some(port)) => (domain, port) // this may cause DNS look-ups!
+        .to_socket_addrs()
+        .context(error::TrackerSocketAddrs)?
+        .next()
+        .ok_or(Error::TrackerNoHosts)?
+        .into(),
+      (Some(Host::Ipv4(ip)), Some(port)) => SocketAddr::from((ip, port)).into(),
+      (Some(Host::Ipv6(ip)), Some(port)) => SocketAddr::from((ip, port)).into(),
+      _ => {
+        return Err(Error::TrackerHostPort {
+          source: HostPortParseError::HostPortMissing {
+            text: tracker_url.as_str().to_owned(),
+          },
+          tracker_url: tracker_url.clone(),
+        })
+      }
+    };
+
+    Ok(Self {
+      url: tracker_url.clone(),
+      target,
+      infohash,
+      peer_id: peer::ClientId::peer_id(),
+      timeout,
+      proxy: proxy.cloned(),
+      tunnel: None,
+      tls: None,
+      websocket: None,
+      stream: None,
+      started: None,
+      connected: false,
+      state: State::Handshake,
+      swarm: Swarm::default(),
+      raw_in: Vec::new(),
+      raw_out: Vec::new(),
+      plain_in: Vec::new(),
+      plain_out: Vec::new(),
+      eof: false,
+    })
+  }
+
+  pub(crate) fn url(&self) -> &Url {
+    &self.url
+  }
+
+  // WebTorrent trackers expect binary strings, like infohashes and peer ids,
+  // as JSON strings with one character per byte.
+  fn binary_string(bytes: &[u8]) -> String {
+    bytes.iter().copied().map(char::from).collect()
+  }
+
+  fn info_hash(&self) -> String {
+    Self::binary_string(&<[u8; 20]>::from(self.infohash))
+  }
+
+  // Start TLS, for `wss://` trackers, and the WebSocket handshake.
+  fn begin(&mut self) -> Result<()> {
+    if self.url.scheme() == "wss" {
+      let server_name = match self.url.host() {
+        Some(Host::Domain(domain)) => {
+          ServerName::try_from(domain.to_owned()).map_err(|_| Error::TrackerHostPort {
+            source: HostPortParseError::HostMissing {
+              text: self.url.as_str().to_owned(),
+            },
+            tracker_url: self.url.clone(),
+          })?
+        }
+        Some(Host::Ipv4(ip)) => ServerName::IpAddress(IpAddr::from(ip).into()),
+        Some(Host::Ipv6(ip)) => ServerName::IpAddress(IpAddr::from(ip).into()),
+        None => return Err(Error::TrackerNoHosts),
+      };
+
+      self.tls =
+        Some(ClientConnection::new(TLS_CONFIG.clone(), server_name).context(error::TrackerTls)?);
+    }
+
+    let (websocket, handshake) = websocket::Client::new(&self.url);
+    self.websocket = Some(websocket);
+    self.plain_out.extend(handshake);
+
+    Ok(())
+  }
+
+  fn send(&mut self, message: &impl Serialize) {
+    let text = serde_json::to_string(message).invariant_unwrap("requests serialize");
+    self
+      .websocket
+      .as_mut()
+      .invariant_unwrap("websocket is opened by begin")
+      .send(&text, &mut self.plain_out);
+  }
+
+  fn announce(&mut self) {
+    self.state = State::Announce;
+    self.send(&AnnounceRequest {
+      action: "announce",
+      info_hash: self.info_hash(),
+      peer_id: Self::binary_string(&self.peer_id),
+      uploaded: 0,
+      downloaded: 0,
+      event: "started",
+      numwant: 0,
+      offers: Vec::new(),
+    });
+  }
+
+  fn scrape(&mut self) {
+    self.state = State::Scrape;
+    self.send(&ScrapeRequest {
+      action: "scrape",
+      info_hash: self.info_hash(),
+    });
+  }
+
+  // Handle a message from the tracker, returning the swarm once the scrape
+  // is answered.
+  fn handle(&mut self, text: &str) -> Result<Option<Swarm>> {
+    let response = serde_json::from_str::<Response>(text).map_err(|_| Error::TrackerResponse)?;
+
+    if let Some(reason) = response.failure_reason {
+      // Trackers that don't support scrape still told us the swarm's size
+      // when we announced.
+      if self.state == State::Scrape {
+        return Ok(Some(self.swarm));
+      }
+
+      return Err(Error::TrackerFailure { reason });
+    }
+
+    let info_hash = self.info_hash();
+
+    if response
+      .info_hash
+      .as_ref()
+      .is_some_and(|other| *other != info_hash)
+    {
+      return Ok(None);
+    }
+
+    match (self.state, response.action.as_deref()) {
+      // Signaling messages from other peers also have the announce action,
+      // but no interval.
+      (State::Announce, Some("announce")) if response.interval.is_some() => {
+        self.swarm.complete = response.complete.unwrap_or_default();
+        self.swarm.incomplete = response.incomplete.unwrap_or_default();
+        self.scrape();
+        Ok(None)
+      }
+      (State::Scrape, Some("scrape")) => {
+        let mut files = response.files.unwrap_or_default();
+
+        let file = match files.remove(&info_hash) {
+          Some(file) => Some(file),
+          // Some trackers key the response differently, but we only asked
+          // about one torrent.
+          None if files.len() == 1 => files.into_values().next(),
+          None => None,
+        };
+
+        if let Some(file) = file {
+          self.swarm.complete = file.complete.unwrap_or(self.swarm.complete);
+          self.swarm.incomplete = file.incomplete.unwrap_or(self.swarm.incomplete);
+          self.swarm.downloaded = file.downloaded;
+        }
+
+        Ok(Some(self.swarm))
+      }
+      _ => Ok(None),
+    }
+  }
+
+  fn stream(&mut self) -> &mut TcpStream {
+    self
+      .stream
+      .as_mut()
+      .invariant_unwrap("stream is opened by start")
+  }
+
+  // Returns true once the non-blocking connect has completed.
+  fn connect(&mut self) -> Result<bool> {
+    if self.connected {
+      return Ok(true);
+    }
+
+    let stream = self.stream();
+
+    if let Some(source) = stream.take_error().context(error::Network)? {
+      return Err(Error::Network { source });
+    }
+
+    match stream.peer_addr() {
+      Ok(_) => {
+        self.connected = true;
+        Ok(true)
+      }
+      Err(err)
+        if matches!(
+          err.kind(),
+          io::ErrorKind::NotConnected | io::ErrorKind::WouldBlock
+        ) =>
+      {
+        Ok(false)
+      }
+      Err(source) => Err(Error::Network { source }),
+    }
+  }
+
+  fn flush(&mut self) -> Result<()> {
+    while !self.raw_out.is_empty() {
+      let stream = self
+        .stream
+        .as_mut()
+        .invariant_unwrap("stream is opened by start");
+
+      match stream.write(&self.raw_out) {
+        Ok(n) => {
+          self.raw_out.drain(..n);
+        }
+        Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+        Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+        Err(source) => return Err(Error::Network { source }),
+      }
+    }
+
+    Ok(())
+  }
+
+  fn fill(&mut self) -> Result<()> {
+    let mut buf = [0; Self::READ_CHUNK];
+
+    loop {
+      match self.stream().read(&mut buf) {
+        Ok(0) => {
+          self.eof = true;
+          return Ok(());
+        }
+        Ok(n) => self.raw_in.extend_from_slice(&buf[..n]),
+        Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+        Err(source) => return Err(Error::Network { source }),
+      }
+    }
+  }
+
+  // Advance the proxy handshake, if one is in progress, returning true once
+  // the tunnel is open.
+  fn dig(&mut self) -> Result<bool> {
+    let Some(tunnel) = &mut self.tunnel else {
+      return Ok(true);
+    };
+
+    if tunnel
+      .advance(&mut self.raw_in, &mut self.raw_out)?
+      .is_none()
+    {
+      return Ok(false);
+    }
+
+    self.tunnel = None;
+    self.begin()?;
+
+    Ok(true)
+  }
+
+  // Move received bytes to the WebSocket, decrypting them if necessary.
+  fn decrypt(&mut self) -> Result<()> {
+    let Some(tls) = &mut self.tls else {
+      self.plain_in.append(&mut self.raw_in);
+      return Ok(());
+    };
+
+    let mut raw = self.raw_in.as_slice();
+
+    loop {
+      while !raw.is_empty() && tls.wants_read() {
+        tls.read_tls(&mut raw).context(error::Network)?;
+        tls.process_new_packets().context(error::TrackerTls)?;
+      }
+
+      match tls.reader().read_to_end(&mut self.plain_in) {
+        // The tracker sent a TLS close notification.
+        Ok(_) => self.eof = true,
+        Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+        Err(source) => return Err(Error::Network { source }),
+      }
+
+      if raw.is_empty() || self.eof {
+        break;
+      }
+    }
+
+    self.raw_in.clear();
+
+    Ok(())
+  }
+
+  // Move bytes from the WebSocket to be sent, encrypting them if necessary.
+  fn encrypt(&mut self) -> Result<()> {
+    let Some(tls) = &mut self.tls else {
+      self.raw_out.append(&mut self.plain_out);
+      return Ok(());
+    };
+
+    if !self.plain_out.is_empty() {
+      tls
+        .writer()
+        .write_all(&self.plain_out)
+        .context(error::Network)?;
+      self.plain_out.clear();
+    }
+
+    while tls.wants_write() {
+      tls.write_tls(&mut self.raw_out).context(error::Network)?;
+    }
+
+    Ok(())
+  }
+
+  fn process(&mut self) -> Result<Option<Swarm>> {
+    let Some(websocket) = &mut self.websocket else {
+      return Ok(None);
+    };
+
+    let mut messages = Vec::new();
+    while let Some(text) = websocket.recv(&mut self.plain_in, &mut self.plain_out)? {
+      messages.push(text);
+    }
+
+    if self.state == State::Handshake && websocket.is_open() {
+      self.announce();
+    }
+
+    for text in messages {
+      if let Some(swarm) = self.handle(&text)? {
+        return Ok(Some(swarm));
+      }
+    }
+
+    Ok(None)
+  }
+}
+
+impl Task for WebSocketAnnouncer {
+  type Output = Swarm;
+
+  fn start(&mut self, now: Instant) -> Result<()> {
+    let addr = match &self.proxy {
+      Some(proxy) => proxy.addr(),
+      None => self
+        .target
+        .socket_addr()
+        .invariant_unwrap("target is resolved without a proxy"),
+    };
+    self.stream = Some(TcpStream::connect(addr).context(error::Network)?);
+    self.started = Some(now);
+
+    if let Some(proxy) = &self.proxy {
+      let (tunnel, output) = proxy.tunnel(self.target.clone());
+      self.tunnel = Some(tunnel);
+      self.raw_out.extend(output);
+    } else {
+      self.begin()?;
+      self.encrypt()?;
+    }
+
+    Ok(())
+  }
+
+  fn source(&mut self) -> &mut dyn mio::event::Source {
+    self.stream()
+  }
+
+  fn interest(&self) -> mio::Interest {
+    if !self.connected || !self.raw_out.is_empty() {
+      mio::Interest::READABLE | mio::Interest::WRITABLE
+    } else {
+      mio::Interest::READABLE
+    }
+  }
+
+  fn wakeup(&self) -> Option<Instant> {
+    self
+      .started
+      .map(|started| started + self.timeout * Self::ATTEMPTS)
+  }
+
+  fn advance(&mut self, now: Instant) -> Result<Option<Swarm>> {
+    if self.wakeup().is_some_and(|wakeup| now >= wakeup) {
+      // A tracker that answered the announce but not the scrape still told
+      // us the swarm's size.
+      if self.state == State::Scrape {
+        return Ok(Some(self.swarm));
+      }
+
+      return Err(Error::TrackerTimeout {
+        tracker_url: self.url.clone(),
+      });
+    }
+
+    if !self.connect()? {
+      return Ok(None);
+    }
+
+    self.flush()?;
+    self.fill()?;
+
+    if !self.dig()? {
+      self.flush()?;
+      return Ok(None);
+    }
+
+    self.decrypt()?;
+    let swarm = self.process()?;
+    self.encrypt()?;
+    self.flush()?;
+
+    if let Some(swarm) = swarm {
+      return Ok(Some(swarm));
+    }
+
+    if self.eof {
+      return Err(Error::Network {
+        source: io::ErrorKind::UnexpectedEof.into(),
+      });
+    }
+
+    Ok(None)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn run(announcer: WebSocketAnnouncer) -> Result<Swarm> {
+    let mut reactor = Reactor::new(1).unwrap();
+    reactor.push(announcer);
+    let mut output = None;
+    reactor
+      .run(|_, _, result| {
+        output = Some(result);
+        Ok(Control::Continue)
+      })
+      .unwrap();
+    output.unwrap()
+  }
+
+  fn announcer(url: &str) -> WebSocketAnnouncer {
+    announcer_via(url, None)
+  }
+
+  fn announcer_via(url: &str, proxy: Option<&Proxy>) -> WebSocketAnnouncer {
+    WebSocketAnnouncer::from_url(
+      &url.parse().unwrap(),
+      Infohash::from([0xFF; 20]),
+      Duration::from_millis(500),
+      proxy,
+    )
+    .unwrap()
+  }
+
+  fn swarm() -> Swarm {
+    Swarm {
+      complete: 3,
+      incomplete: 7,
+      downloaded: Some(12),
+    }
+  }
+
+  #[test]
+  fn supports() {
+    assert!(WebSocketAnnouncer::supports(
+      &"wss://tracker.example".parse().unwrap()
+    ));
+    assert!(!WebSocketAnnouncer::supports(
+      &"udp://tracker.example:1337".parse().unwrap()
+    ));
+  }
+
+  #[test]
+  fn default_port() {
+    assert_eq!(announcer("wss://127.0.0.1/").target.port(), 443);
+    assert_eq!(announcer("ws://127.0.0.1/").target.port(), 80);
+  }
+
+  #[test]
+  fn announce_and_scrape() {
+    let (daemon, addr) = WebSocketDaemon::spawn(swarm());
+
+    assert_eq!(run(announcer(&format!("ws://{addr}/"))).unwrap(), swarm());
+
+    let requests = daemon.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0]["action"], "announce");
+    assert_eq!(requests[0]["info_hash"], "\u{FF}".repeat(20));
+    assert_eq!(requests[0]["event"], "started");
+    assert_eq!(requests[0]["peer_id"].as_str().unwrap().chars().count(), 20);
+    assert_eq!(requests[1]["action"], "scrape");
+    assert_eq!(requests[1]["info_hash"], "\u{FF}".repeat(20));
+  }
+
+  #[test]
+  fn scrape_unsupported() {
+    let (_, addr) = WebSocketDaemon::spawn(Swarm {
+      downloaded: None,
+      ..swarm()
+    });
+
+    assert_eq!(
+      run(announcer(&format!("ws://{addr}/"))).unwrap(),
+      Swarm {
+        downloaded: None,
+        ..swarm()
+      }
+    );
+  }
+
+  #[test]
+  fn through_proxy() {
+    let (_, addr) = WebSocketDaemon::spawn(swarm());
+    let (server, proxy) = proxy::Server::spawn();
+    let proxy = Proxy::from_url(&format!("http://{proxy}").parse().unwrap()).unwrap();
+
+    assert_eq!(
+      run(announcer_via(&format!("ws://{addr}/"), Some(&proxy))).unwrap(),
+      swarm()
+    );
+    assert_eq!(server.targets(), [addr.into()]);
+  }
+
+  #[test]
+  fn domain_through_proxy() {
+    let (_, addr) = WebSocketDaemon::spawn(swarm());
+    let (server, proxy) = proxy::Server::spawn();
+    let proxy = Proxy::from_url(&format!("http://{proxy}").parse().unwrap()).unwrap();
+
+    let target = format!("localhost:{}", addr.port());
+
+    assert_eq!(
+      run(announcer_via(&format!("ws://{target}/"), Some(&proxy))).unwrap(),
+      swarm()
+    );
+    assert_eq!(server.targets(), [target.parse().unwrap()]);
+  }
+
+  #[test]
+  fn not_websocket() {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+      let (mut stream, _) = listener.accept().unwrap();
+      stream
+        .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+        .unwrap();
+      thread::sleep(Duration::from_secs(1));
+    });
+
+    assert_matches!(
+      run(announcer(&format!("ws://{addr}/"))),
+      Err(Error::WebSocketHandshake { .. })
+    );
+  }
+
+  #[test]
+  fn timeout() {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+      let _stream = listener.accept().unwrap();
+      thread::sleep(Duration::from_secs(5));
+    });
+
+    let announcer = WebSocketAnnouncer::from_url(
+      &format!("ws://{addr}/").parse().unwrap(),
+      Infohash::from([0; 20]),
+      Duration::from_millis(50),
+      None,
+    )
+    .unwrap();
+
+    assert_matches!(run(announcer), Err(Error::TrackerTimeout { .. }));
+  }
+
+  #[test]
+  fn tls() {
+    // A plaintext server's response isn't a TLS record.
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+      let (mut stream, _) = listener.accept().unwrap();
+      stream.read_exact(&mut [0; 5]).unwrap();
+      stream
+        .write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n")
+        .unwrap();
+      thread::sleep(Duration::from_secs(1));
+    });
+
+    assert_matches!(
+      run(announcer(&format!("wss://{addr}/"))),
+      Err(Error::TrackerTls { .. })
+    );
+  }
+}
@@ -0,0 +1,149 @@
+use super::*;
+use crate::common::*;
+
+use std::sync::Mutex;
+
+/// A loopback stand-in for a `WebTorrent` tracker, which answers announces and
+/// scrapes with a fixed swarm, and records the requests it receives. If the
+/// swarm has no download count, scrapes fail, like on trackers that don't
+/// support them.
+pub(crate) struct WebSocketDaemon {
+  requests: Arc<Mutex<Vec<serde_json::Value>>>,
+}
+
+impl WebSocketDaemon {
+  const GUID: &'static str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+  pub(crate) fn spawn(swarm: Swarm) -> (Self, SocketAddr) {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let daemon = Self {
+      requests: requests.clone(),
+    };
+
+    thread::spawn(move || {
+      for stream in listener.incoming() {
+        let requests = requests.clone();
+        thread::spawn(move || Self::serve(stream.unwrap(), swarm, &requests));
+      }
+    });
+
+    (daemon, addr)
+  }
+
+  pub(crate) fn requests(&self) -> Vec<serde_json::Value> {
+    self.requests.lock().unwrap().clone()
+  }
+
+  fn serve(mut stream: TcpStream, swarm: Swarm, requests: &Mutex<Vec<serde_json::Value>>) {
+    let mut head = Vec::new();
+    let mut byte = [0];
+    while !head.ends_with(b"\r\n\r\n") {
+      if stream.read_exact(&mut byte).is_err() {
+        return;
+      }
+      head.push(byte[0]);
+    }
+
+    let head = String::from_utf8_lossy(&head).into_owned();
+
+    let key = head
+      .lines()
+      .find_map(|line| line.strip_prefix("Sec-WebSocket-Key: "))
+      .unwrap();
+
+    let accept = base64(&Sha1::from(format!("{key}{}", Self::GUID)).digest().bytes());
+
+    stream
+      .write_all(
+        format!(
+          "HTTP/1.1 101 Switching Protocols\r\n\
+           Upgrade: websocket\r\n\
+           Connection: Upgrade\r\n\
+           Sec-WebSocket-Accept: {accept}\r\n\r\n"
+        )
+        .as_bytes(),
+      )
+      .unwrap();
+
+    while let Some(text) = Self::recv(&mut stream) {
+      let request = serde_json::from_str::<serde_json::Value>(&text).unwrap();
+      requests.lock().unwrap().push(request.clone());
+
+      let info_hash = request["info_hash"].clone();
+
+      let response = match (request["action"].as_str(), swarm.downloaded) {
+        (Some("announce"), _) => serde_json::json!({
+          "action": "announce",
+          "interval": 120,
+          "info_hash": info_hash,
+          "complete": swarm.complete,
+          "incomplete": swarm.incomplete,
+        }),
+        (Some("scrape"), Some(downloaded)) => {
+          let mut files = serde_json::Map::new();
+          files.insert(
+            info_hash.as_str().unwrap().to_owned(),
+            serde_json::json!({
+              "complete": swarm.complete,
+              "incomplete": swarm.incomplete,
+              "downloaded": downloaded,
+            }),
+          );
+          serde_json::json!({ "action": "scrape", "files": files })
+        }
+        _ => serde_json::json!({ "failure reason": "invalid action" }),
+      };
+
+      Self::send(&mut stream, &response.to_string());
+    }
+  }
+
+  // Read a masked text frame from the client.
+  fn recv(stream: &mut TcpStream) -> Option<String> {
+    let mut header = [0; 2];
+    stream.read_exact(&mut header).ok()?;
+
+    let length = match header[1] & 0x7F {
+      126 => {
+        let mut length = [0; 2];
+        stream.read_exact(&mut length).ok()?;
+        u16::from_be_bytes(length).into()
+      }
+      127 => panic!("unexpectedly long frame"),
+      length => usize::from(length),
+    };
+
+    let mut mask = [0; 4];
+    stream.read_exact(&mut mask).ok()?;
+
+    let mut payload = vec![0; length];
+    stream.read_exact(&mut payload).ok()?;
+
+    for (byte, mask) in payload.iter_mut().zip(mask.iter().cycle()) {
+      *byte ^= mask;
+    }
+
+    assert_eq!(header[0], 0x81);
+
+    Some(String::from_utf8(payload).unwrap())
+  }
+
+  // Send an unmasked text frame to the client.
+  fn send(stream: &mut TcpStream, text: &str) {
+    let mut frame = vec![0x81];
+
+    match text.len() {
+      length @ 0..=125 => frame.push(u8::try_from(length).unwrap()),
+      length => {
+        frame.push(126);
+        frame.extend(u16::try_from(length).unwrap().to_be_bytes());
+      }
+    }
+
+    frame.extend(text.as_bytes());
+    stream.write_all(&frame).unwrap();
+  }
+}
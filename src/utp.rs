@@ -0,0 +1,18 @@
+//! The uTP transport protocol, from BEP 29: reliable, ordered streams over
+//! UDP, with LEDBAT congestion control that backs off when other traffic is
+//! competing for the link.
+
+use ledbat::Ledbat;
+use packet::{Kind, Packet};
+use socket::Socket;
+
+pub(crate) use bridge::{bridge, READY};
+pub(crate) use listener::Listener;
+pub(crate) use stream::Stream;
+
+mod bridge;
+mod ledbat;
+mod listener;
+mod packet;
+mod socket;
+mod stream;
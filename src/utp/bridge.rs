@@ -0,0 +1,172 @@
+use crate::common::*;
+
+use super::Stream;
+use std::net::Shutdown;
+
+/// Sent over the loopback connection once the uTP connection is open. If it
+/// can't be opened, the loopback connection is closed without sending it.
+pub(crate) const READY: u8 = 1;
+
+// How long the relay waits for data from the peer before checking for data
+// to send to it.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Open a uTP connection to `addr` on a background thread, which relays it
+/// over a loopback TCP connection, so that it can be polled by a `Reactor`
+/// like any other socket. Returns our end of the loopback connection, which
+/// receives `READY` once the uTP connection is open.
+pub(crate) fn bridge(addr: SocketAddr, timeout: Duration) -> io::Result<mio::net::TcpStream> {
+  let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+  let local = mio::net::TcpStream::connect(listener.local_addr()?)?;
+  let expected = local.local_addr()?;
+
+  thread::spawn(move || {
+    // Don't relay for other local processes that happen to connect first.
+    let relay = loop {
+      match listener.accept() {
+        Ok((relay, peer)) if peer == expected => break relay,
+        Ok(_) => {}
+        Err(_) => return,
+      }
+    };
+
+    drop(listener);
+
+    if let Ok(stream) = Stream::connect(addr, timeout) {
+      self::relay(stream, relay).ok();
+    }
+  });
+
+  Ok(local)
+}
+
+// Copy data between `stream` and `local` until the owner of the other end of
+// `local` closes it.
+fn relay(mut stream: Stream, mut local: TcpStream) -> io::Result<()> {
+  local.write_all(&[READY])?;
+  local.set_nonblocking(true)?;
+  stream.set_read_timeout(Some(POLL_INTERVAL));
+
+  let mut buf = [0; 16 * 1024];
+  let mut inbound = Vec::new();
+  let mut peer_eof = false;
+  let mut shutdown = false;
+
+  loop {
+    match local.read(&mut buf) {
+      Ok(0) => return Ok(()),
+      Ok(n) => stream.write_all(&buf[..n])?,
+      Err(err)
+        if matches!(
+          err.kind(),
+          io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted
+        ) => {}
+      Err(err) => return Err(err),
+    }
+
+    while !inbound.is_empty() {
+      match local.write(&inbound) {
+        Ok(n) => {
+          inbound.drain(..n);
+        }
+        Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+        Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+        Err(err) => return Err(err),
+      }
+    }
+
+    if !inbound.is_empty() || peer_eof {
+      if peer_eof && inbound.is_empty() && !shutdown {
+        local.shutdown(Shutdown::Write)?;
+        shutdown = true;
+      }
+
+      // Nothing more can be read from the peer until the owner catches up or
+      // hangs up.
+      thread::sleep(POLL_INTERVAL);
+      continue;
+    }
+
+    match stream.read(&mut buf) {
+      Ok(0) => peer_eof = true,
+      Ok(n) => inbound.extend_from_slice(&buf[..n]),
+      Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+      Err(err) => return Err(err),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use utp::Listener;
+
+  // Write `output` to `local` and read until it is closed, waiting for the
+  // non-blocking socket as necessary.
+  fn exchange(mut local: mio::net::TcpStream, mut output: &[u8]) -> Vec<u8> {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let mut input = Vec::new();
+    let mut buf = [0; 1024];
+
+    loop {
+      assert!(Instant::now() < deadline, "timed out");
+
+      if !output.is_empty() {
+        match local.write(output) {
+          Ok(n) => output = &output[n..],
+          Err(err)
+            if matches!(
+              err.kind(),
+              io::ErrorKind::WouldBlock | io::ErrorKind::NotConnected
+            ) => {}
+          Err(err) => panic!("{err}"),
+        }
+      }
+
+      match local.read(&mut buf) {
+        Ok(0) => return input,
+        Ok(n) => input.extend_from_slice(&buf[..n]),
+        Err(err)
+          if matches!(
+            err.kind(),
+            io::ErrorKind::WouldBlock | io::ErrorKind::NotConnected
+          ) =>
+        {
+          thread::sleep(Duration::from_millis(10));
+        }
+        Err(err) => panic!("{err}"),
+      }
+    }
+  }
+
+  #[test]
+  fn relay() {
+    let listener = Listener::bind((Ipv4Addr::LOCALHOST, 0).into()).unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    thread::spawn(move || {
+      let (mut stream, _) = listener.accept().unwrap();
+      let mut buf = [0; 5];
+      stream.read_exact(&mut buf).unwrap();
+      stream.write_all(&buf).unwrap();
+      stream.write_all(b" world").unwrap();
+    });
+
+    let local = bridge(addr, Duration::from_secs(3)).unwrap();
+
+    assert_eq!(exchange(local, b"hello"), b"\x01hello world");
+  }
+
+  #[test]
+  fn refused() {
+    let addr = std::net::UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))
+      .unwrap()
+      .local_addr()
+      .unwrap();
+
+    let local = bridge(addr, Duration::from_secs(1)).unwrap();
+
+    assert_eq!(exchange(local, b""), b"");
+  }
+}
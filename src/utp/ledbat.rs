@@ -0,0 +1,163 @@
+use crate::common::*;
+
+use super::Packet;
+
+/// LEDBAT congestion control, from RFC 6817. The congestion window grows
+/// while the queuing delay we add is below a target, and shrinks when it is
+/// above, so that uTP yields bandwidth to other traffic on the same link.
+#[derive(Debug)]
+pub(crate) struct Ledbat {
+  window: u64,
+  // The lowest one-way delay measured during each of the last few minutes,
+  // oldest first. The minimum of these approximates the delay of an empty
+  // queue, including any offset between our clock and the peer's.
+  base_delays: VecDeque<(Instant, u32)>,
+}
+
+impl Ledbat {
+  /// Queuing delay to aim for, in microseconds.
+  const TARGET: i128 = 100_000;
+
+  const BASE_HISTORY: usize = 2;
+
+  const BASE_INTERVAL: Duration = Duration::from_secs(60);
+
+  const SEGMENT: u64 = Packet::MAX_PAYLOAD as u64;
+
+  const MIN_WINDOW: u64 = Self::SEGMENT;
+
+  const INITIAL_WINDOW: u64 = 2 * Self::SEGMENT;
+
+  const MAX_WINDOW: u64 = 1 << 20;
+
+  pub(crate) fn new() -> Self {
+    Self {
+      window: Self::INITIAL_WINDOW,
+      base_delays: VecDeque::new(),
+    }
+  }
+
+  /// Bytes that may be in flight at once.
+  pub(crate) fn window(&self) -> usize {
+    usize::try_from(self.window).unwrap_or(usize::MAX)
+  }
+
+  /// Account for `acked` bytes having been acknowledged by a packet whose
+  /// sender measured `delay` microseconds of one-way delay.
+  pub(crate) fn on_ack(&mut self, acked: usize, delay: u32, now: Instant) {
+    let base = self.update_base_delay(delay, now);
+
+    let queuing_delay = i128::from(delay.wrapping_sub(base));
+
+    let off_target = (Self::TARGET - queuing_delay).max(-Self::TARGET);
+
+    let delta = off_target * i128::from(acked.into_u64()) * i128::from(Self::SEGMENT)
+      / (Self::TARGET * i128::from(self.window));
+
+    self.set_window(i128::from(self.window) + delta);
+  }
+
+  /// Halve the window after a packet was lost.
+  pub(crate) fn on_loss(&mut self) {
+    self.set_window(i128::from(self.window / 2));
+  }
+
+  /// Collapse the window after the retransmission timer fired.
+  pub(crate) fn on_timeout(&mut self) {
+    self.window = Self::MIN_WINDOW;
+  }
+
+  fn set_window(&mut self, window: i128) {
+    self.window = u64::try_from(window.clamp(Self::MIN_WINDOW.into(), Self::MAX_WINDOW.into()))
+      .invariant_unwrap("window is clamped to u64 range");
+  }
+
+  fn update_base_delay(&mut self, delay: u32, now: Instant) -> u32 {
+    match self.base_delays.back_mut() {
+      Some((started, min)) if now.duration_since(*started) < Self::BASE_INTERVAL => {
+        *min = (*min).min(delay);
+      }
+      _ => {
+        self.base_delays.push_back((now, delay));
+        if self.base_delays.len() > Self::BASE_HISTORY {
+          self.base_delays.pop_front();
+        }
+      }
+    }
+
+    self
+      .base_delays
+      .iter()
+      .map(|(_, min)| *min)
+      .min()
+      .unwrap_or(delay)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn grows_below_target() {
+    let now = Instant::now();
+    let mut ledbat = Ledbat::new();
+    ledbat.on_ack(0, 5_000, now);
+    let before = ledbat.window();
+    ledbat.on_ack(before, 10_000, now);
+    assert!(ledbat.window() > before);
+  }
+
+  #[test]
+  fn shrinks_above_target() {
+    let now = Instant::now();
+    let mut ledbat = Ledbat::new();
+    ledbat.on_ack(0, 5_000, now);
+    for _ in 0..100 {
+      ledbat.on_ack(ledbat.window(), 10_000, now);
+    }
+    let before = ledbat.window();
+    ledbat.on_ack(before, 5_000 + 300_000, now);
+    assert!(ledbat.window() < before);
+  }
+
+  #[test]
+  fn clock_offset() {
+    // The delay measured by the peer includes the difference between our
+    // clocks, which may wrap around.
+    let now = Instant::now();
+    let mut ledbat = Ledbat::new();
+    ledbat.on_ack(0, u32::MAX - 10, now);
+    let before = ledbat.window();
+    ledbat.on_ack(before, 5, now);
+    assert!(ledbat.window() > before);
+  }
+
+  #[test]
+  fn base_delay_expires() {
+    let now = Instant::now();
+    let mut ledbat = Ledbat::new();
+    ledbat.on_ack(0, 1_000, now);
+    ledbat.on_ack(0, 500_000, now + Ledbat::BASE_INTERVAL);
+    assert_eq!(
+      ledbat.update_base_delay(500_000, now + Ledbat::BASE_INTERVAL * 2),
+      500_000
+    );
+  }
+
+  #[test]
+  fn loss_and_timeout() {
+    let now = Instant::now();
+    let mut ledbat = Ledbat::new();
+    for _ in 0..1000 {
+      ledbat.on_ack(ledbat.window(), 1_000, now);
+    }
+    let before = ledbat.window();
+    ledbat.on_loss();
+    assert_eq!(ledbat.window(), before / 2);
+    ledbat.on_timeout();
+    assert_eq!(ledbat.window(), Packet::MAX_PAYLOAD);
+    ledbat.on_loss();
+    assert_eq!(ledbat.window(), Packet::MAX_PAYLOAD);
+  }
+}
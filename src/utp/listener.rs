@@ -0,0 +1,33 @@
+use crate::common::*;
+
+use super::{Packet, Socket, Stream};
+use std::sync::mpsc;
+
+/// Accepts incoming uTP connections, like `TcpListener`.
+#[derive(Debug)]
+pub(crate) struct Listener {
+  socket: Arc<Socket>,
+  syns: mpsc::Receiver<(SocketAddr, Packet)>,
+}
+
+impl Listener {
+  pub(crate) fn bind(addr: SocketAddr) -> io::Result<Self> {
+    let (socket, syns) = Socket::listen(addr)?;
+    Ok(Self { socket, syns })
+  }
+
+  #[cfg(test)]
+  pub(crate) fn local_addr(&self) -> io::Result<SocketAddr> {
+    self.socket.local_addr()
+  }
+
+  /// Wait for a peer to open a connection.
+  pub(crate) fn accept(&self) -> io::Result<(Stream, SocketAddr)> {
+    let (addr, syn) = self
+      .syns
+      .recv()
+      .map_err(|mpsc::RecvError| io::Error::from(io::ErrorKind::BrokenPipe))?;
+
+    Ok((Stream::accept(self.socket.clone(), addr, &syn)?, addr))
+  }
+}
@@ -0,0 +1,241 @@
+use crate::common::*;
+
+/// The kind of a uTP packet, stored in the high nibble of its first byte.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum Kind {
+  Data,
+  Fin,
+  State,
+  Reset,
+  Syn,
+}
+
+impl Kind {
+  fn code(self) -> u8 {
+    match self {
+      Self::Data => 0,
+      Self::Fin => 1,
+      Self::State => 2,
+      Self::Reset => 3,
+      Self::Syn => 4,
+    }
+  }
+
+  fn from_code(code: u8) -> Option<Self> {
+    match code {
+      0 => Some(Self::Data),
+      1 => Some(Self::Fin),
+      2 => Some(Self::State),
+      3 => Some(Self::Reset),
+      4 => Some(Self::Syn),
+      _ => None,
+    }
+  }
+}
+
+/// A uTP packet, as described in BEP 29.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Packet {
+  pub(crate) kind: Kind,
+  pub(crate) connection_id: u16,
+  /// When the packet was sent, in microseconds, according to the sender's
+  /// clock.
+  pub(crate) timestamp: u32,
+  /// The sender's most recent measurement of the one-way delay from us to
+  /// it, in microseconds.
+  pub(crate) timestamp_difference: u32,
+  /// Bytes the sender is willing to buffer.
+  pub(crate) window: u32,
+  pub(crate) seq_nr: u16,
+  pub(crate) ack_nr: u16,
+  /// Bitmask of packets received after `ack_nr + 1`, starting with
+  /// `ack_nr + 2` in the least significant bit of the first byte.
+  pub(crate) selective_ack: Option<Vec<u8>>,
+  pub(crate) payload: Vec<u8>,
+}
+
+impl Packet {
+  pub(crate) const HEADER_LENGTH: usize = 20;
+
+  /// Largest payload we send, chosen so that packets fit in a typical MTU.
+  pub(crate) const MAX_PAYLOAD: usize = 1400 - Self::HEADER_LENGTH;
+
+  const VERSION: u8 = 1;
+
+  const EXTENSION_NONE: u8 = 0;
+  const EXTENSION_SELECTIVE_ACK: u8 = 1;
+
+  pub(crate) fn new(kind: Kind, connection_id: u16, seq_nr: u16, ack_nr: u16) -> Self {
+    Self {
+      kind,
+      connection_id,
+      timestamp: 0,
+      timestamp_difference: 0,
+      window: 0,
+      seq_nr,
+      ack_nr,
+      selective_ack: None,
+      payload: Vec::new(),
+    }
+  }
+
+  /// Parse a datagram, returning `None` if it isn't a well-formed uTP packet.
+  pub(crate) fn parse(buf: &[u8]) -> Option<Self> {
+    let header = buf.get(..Self::HEADER_LENGTH)?;
+
+    if header[0] & 0x0f != Self::VERSION {
+      return None;
+    }
+
+    let kind = Kind::from_code(header[0] >> 4)?;
+    let u16_at = |i: usize| u16::from_be_bytes([header[i], header[i + 1]]);
+    let u32_at =
+      |i: usize| u32::from_be_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]]);
+
+    let mut packet = Self {
+      kind,
+      connection_id: u16_at(2),
+      timestamp: u32_at(4),
+      timestamp_difference: u32_at(8),
+      window: u32_at(12),
+      seq_nr: u16_at(16),
+      ack_nr: u16_at(18),
+      selective_ack: None,
+      payload: Vec::new(),
+    };
+
+    let mut extension = header[1];
+    let mut rest = &buf[Self::HEADER_LENGTH..];
+
+    while extension != Self::EXTENSION_NONE {
+      let (&next, &length) = (rest.first()?, rest.get(1)?);
+      let data = rest.get(2..2 + usize::from(length))?;
+
+      if extension == Self::EXTENSION_SELECTIVE_ACK {
+        if length == 0 || length % 4 != 0 {
+          return None;
+        }
+        packet.selective_ack = Some(data.to_vec());
+      }
+
+      extension = next;
+      rest = &rest[2 + usize::from(length)..];
+    }
+
+    packet.payload = rest.to_vec();
+
+    Some(packet)
+  }
+
+  pub(crate) fn serialize(&self) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(Self::HEADER_LENGTH + self.payload.len());
+
+    buf.push(self.kind.code() << 4 | Self::VERSION);
+    buf.push(if self.selective_ack.is_some() {
+      Self::EXTENSION_SELECTIVE_ACK
+    } else {
+      Self::EXTENSION_NONE
+    });
+    buf.extend(self.connection_id.to_be_bytes());
+    buf.extend(self.timestamp.to_be_bytes());
+    buf.extend(self.timestamp_difference.to_be_bytes());
+    buf.extend(self.window.to_be_bytes());
+    buf.extend(self.seq_nr.to_be_bytes());
+    buf.extend(self.ack_nr.to_be_bytes());
+
+    if let Some(mask) = &self.selective_ack {
+      buf.push(Self::EXTENSION_NONE);
+      buf.push(u8::try_from(mask.len()).invariant_unwrap("selective ack mask is short"));
+      buf.extend(mask);
+    }
+
+    buf.extend(&self.payload);
+
+    buf
+  }
+
+  /// Whether the selective ack mask says `seq_nr` was received.
+  pub(crate) fn selectively_acks(&self, seq_nr: u16) -> bool {
+    let Some(mask) = &self.selective_ack else {
+      return false;
+    };
+
+    let bit = usize::from(seq_nr.wrapping_sub(self.ack_nr).wrapping_sub(2));
+
+    mask
+      .get(bit / 8)
+      .is_some_and(|byte| byte & (1 << (bit % 8)) != 0)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn round_trip() {
+    let packet = Packet {
+      kind: Kind::Data,
+      connection_id: 0x1234,
+      timestamp: 1,
+      timestamp_difference: 2,
+      window: 3,
+      seq_nr: 4,
+      ack_nr: 5,
+      selective_ack: None,
+      payload: b"foo".to_vec(),
+    };
+
+    let buf = packet.serialize();
+    assert_eq!(
+      buf[..Packet::HEADER_LENGTH],
+      [0x01, 0, 0x12, 0x34, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 4, 0, 5]
+    );
+    assert_eq!(Packet::parse(&buf), Some(packet));
+  }
+
+  #[test]
+  fn selective_ack() {
+    let mut packet = Packet::new(Kind::State, 1, 2, 10);
+    packet.selective_ack = Some(vec![0b0000_0101, 0, 0, 0b1000_0000]);
+
+    let parsed = Packet::parse(&packet.serialize()).unwrap();
+    assert_eq!(parsed, packet);
+
+    assert!(!parsed.selectively_acks(10));
+    assert!(!parsed.selectively_acks(11));
+    assert!(parsed.selectively_acks(12));
+    assert!(!parsed.selectively_acks(13));
+    assert!(parsed.selectively_acks(14));
+    assert!(parsed.selectively_acks(43));
+    assert!(!parsed.selectively_acks(44));
+  }
+
+  #[test]
+  fn unknown_extension() {
+    let mut buf = Packet::new(Kind::State, 1, 2, 3).serialize();
+    buf[1] = 9;
+    buf.extend([0, 2, 0xff, 0xff]);
+    buf.extend(b"bar");
+    assert_eq!(Packet::parse(&buf).unwrap().payload, b"bar");
+  }
+
+  #[test]
+  fn invalid() {
+    let buf = Packet::new(Kind::Syn, 1, 2, 3).serialize();
+    assert_eq!(Packet::parse(&buf[..19]), None);
+
+    let mut bad_version = buf.clone();
+    bad_version[0] = 0x42;
+    assert_eq!(Packet::parse(&bad_version), None);
+
+    let mut bad_kind = buf.clone();
+    bad_kind[0] = 0x51;
+    assert_eq!(Packet::parse(&bad_kind), None);
+
+    let mut truncated_extension = buf;
+    truncated_extension[1] = 1;
+    truncated_extension.extend([0, 4, 0]);
+    assert_eq!(Packet::parse(&truncated_extension), None);
+  }
+}
@@ -0,0 +1,182 @@
+use crate::common::*;
+
+use super::{Kind, Packet};
+use std::net::UdpSocket;
+use std::sync::{mpsc, Mutex, MutexGuard, Weak};
+
+type Routes = HashMap<(SocketAddr, u16), mpsc::Sender<Packet>>;
+
+type Syn = (SocketAddr, Packet);
+
+/// A UDP socket shared by uTP connections. A background thread reads
+/// datagrams and routes each packet to the connection it belongs to, and
+/// passes SYN packets for new connections to the listener, if any.
+#[derive(Debug)]
+pub(crate) struct Socket {
+  udp: UdpSocket,
+  connected: bool,
+  routes: Mutex<Routes>,
+  syns: Option<mpsc::Sender<Syn>>,
+}
+
+impl Socket {
+  // How often the background thread checks whether the socket is still in
+  // use.
+  const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+  const MAX_DATAGRAM: usize = 64 * 1024;
+
+  /// Bind a socket that accepts incoming connections, returning it and a
+  /// receiver for SYN packets that start new connections.
+  pub(crate) fn listen(addr: SocketAddr) -> io::Result<(Arc<Self>, mpsc::Receiver<Syn>)> {
+    let (tx, rx) = mpsc::channel();
+    let socket = Self::spawn(UdpSocket::bind(addr)?, false, Some(tx))?;
+    Ok((socket, rx))
+  }
+
+  /// Bind a socket for a single outgoing connection to `addr`. The socket is
+  /// connected, so that ICMP errors are reported if nothing is listening.
+  pub(crate) fn connect(addr: SocketAddr) -> io::Result<Arc<Self>> {
+    let udp = if addr.is_ipv4() {
+      UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?
+    } else {
+      UdpSocket::bind((Ipv6Addr::UNSPECIFIED, 0))?
+    };
+    udp.connect(addr)?;
+    Self::spawn(udp, true, None)
+  }
+
+  fn spawn(
+    udp: UdpSocket,
+    connected: bool,
+    syns: Option<mpsc::Sender<Syn>>,
+  ) -> io::Result<Arc<Self>> {
+    let reader = udp.try_clone()?;
+    reader.set_read_timeout(Some(Self::POLL_INTERVAL))?;
+
+    let socket = Arc::new(Self {
+      udp,
+      connected,
+      routes: Mutex::new(HashMap::new()),
+      syns,
+    });
+
+    let weak = Arc::downgrade(&socket);
+    thread::spawn(move || Self::run(&reader, &weak));
+
+    Ok(socket)
+  }
+
+  #[cfg(test)]
+  pub(crate) fn local_addr(&self) -> io::Result<SocketAddr> {
+    self.udp.local_addr()
+  }
+
+  // Read and route packets until every connection and listener using the
+  // socket has been dropped.
+  fn run(reader: &UdpSocket, socket: &Weak<Self>) {
+    let mut buf = vec![0; Self::MAX_DATAGRAM];
+
+    loop {
+      let result = reader.recv_from(&mut buf);
+
+      let Some(socket) = socket.upgrade() else {
+        return;
+      };
+
+      match result {
+        Ok((n, addr)) => {
+          if let Some(packet) = Packet::parse(&buf[..n]) {
+            socket.route(addr, packet);
+          }
+        }
+        Err(err)
+          if matches!(
+            err.kind(),
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut | io::ErrorKind::Interrupted
+          ) => {}
+        // On a connected socket, errors are ICMP errors from the peer, so the
+        // connection is gone.
+        Err(_) if socket.connected => socket.routes().clear(),
+        Err(_) => {}
+      }
+    }
+  }
+
+  fn route(&self, addr: SocketAddr, packet: Packet) {
+    let mut routes = self.routes();
+
+    let id = packet.connection_id;
+
+    let candidates = match packet.kind {
+      // A SYN carries the initiator's receive ID, which is one less than
+      // ours. If we already have a connection, the SYN is a retransmission.
+      Kind::Syn => vec![id.wrapping_add(1)],
+      // Peers send resets using whichever ID they think is right.
+      Kind::Reset => vec![id, id.wrapping_add(1), id.wrapping_sub(1)],
+      Kind::Data | Kind::Fin | Kind::State => vec![id],
+    };
+
+    for candidate in candidates {
+      if let Some(tx) = routes.get(&(addr, candidate)) {
+        if tx.send(packet).is_err() {
+          routes.remove(&(addr, candidate));
+        }
+        return;
+      }
+    }
+
+    drop(routes);
+
+    match packet.kind {
+      Kind::Syn => {
+        if let Some(syns) = &self.syns {
+          syns.send((addr, packet)).ok();
+        }
+      }
+      Kind::Reset => {}
+      // Tell the peer that the connection no longer exists.
+      Kind::Data | Kind::Fin | Kind::State => {
+        let reset = Packet::new(Kind::Reset, id, 0, packet.seq_nr);
+        self.send(addr, &reset).ok();
+      }
+    }
+  }
+
+  /// Start routing packets from `addr` with connection ID `id`, returning
+  /// `None` if the ID is taken.
+  pub(crate) fn register(&self, addr: SocketAddr, id: u16) -> Option<mpsc::Receiver<Packet>> {
+    let mut routes = self.routes();
+
+    if routes.contains_key(&(addr, id)) {
+      return None;
+    }
+
+    let (tx, rx) = mpsc::channel();
+    routes.insert((addr, id), tx);
+    Some(rx)
+  }
+
+  pub(crate) fn deregister(&self, addr: SocketAddr, id: u16) {
+    self.routes().remove(&(addr, id));
+  }
+
+  pub(crate) fn send(&self, addr: SocketAddr, packet: &Packet) -> io::Result<()> {
+    let buf = packet.serialize();
+
+    if self.connected {
+      self.udp.send(&buf)?;
+    } else {
+      self.udp.send_to(&buf, addr)?;
+    }
+
+    Ok(())
+  }
+
+  fn routes(&self) -> MutexGuard<'_, Routes> {
+    self
+      .routes
+      .lock()
+      .invariant_unwrap("route table lock is never poisoned")
+  }
+}
@@ -0,0 +1,840 @@
+use crate::common::*;
+
+use super::{Kind, Ledbat, Packet, Socket};
+use std::sync::mpsc::{self, RecvTimeoutError};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum State {
+  Connecting,
+  Connected,
+}
+
+// A packet we have sent, but that the peer has not yet acknowledged.
+#[derive(Debug)]
+struct Sent {
+  seq_nr: u16,
+  kind: Kind,
+  payload: Vec<u8>,
+  transmitted_at: Instant,
+  transmissions: u32,
+  fast_retransmitted: bool,
+}
+
+/// A uTP connection, with blocking reads and writes like `TcpStream`.
+/// Packets are only processed, and lost packets retransmitted, during calls
+/// to `read`, `write`, and `flush`.
+#[derive(Debug)]
+pub(crate) struct Stream {
+  socket: Arc<Socket>,
+  packets: mpsc::Receiver<Packet>,
+  addr: SocketAddr,
+  recv_id: u16,
+  send_id: u16,
+  state: State,
+  /// Sequence number of the next packet we send.
+  seq_nr: u16,
+  /// Sequence number of the last packet received in order.
+  ack_nr: u16,
+  unacked: VecDeque<Sent>,
+  out_of_order: HashMap<u16, Packet>,
+  readable: VecDeque<u8>,
+  ack_pending: bool,
+  eof: bool,
+  reset: bool,
+  ledbat: Ledbat,
+  rtt: Option<Duration>,
+  rtt_var: Duration,
+  rto: Duration,
+  duplicate_acks: u32,
+  peer_window: usize,
+  /// Our most recent measurement of the delay from the peer to us, which is
+  /// echoed back so the peer can run its congestion control.
+  reply_micro: u32,
+  read_timeout: Option<Duration>,
+  #[cfg(test)]
+  drop_every: Option<usize>,
+  #[cfg(test)]
+  data_sent: usize,
+}
+
+impl Stream {
+  const INITIAL_RTO: Duration = Duration::from_secs(1);
+
+  const MIN_RTO: Duration = Duration::from_millis(500);
+
+  const MAX_RTO: Duration = Duration::from_secs(60);
+
+  /// Give up on a packet after this many attempts to send it.
+  const MAX_TRANSMISSIONS: u32 = 6;
+
+  const DUPLICATE_ACK_THRESHOLD: u32 = 3;
+
+  /// How far past the next expected packet we buffer out-of-order packets.
+  /// Selective acks can describe up to this many packets.
+  const REORDER_WINDOW: u16 = 256;
+
+  const RECEIVE_WINDOW: usize = 1 << 20;
+
+  /// How long dropping a stream waits for unacknowledged data to be
+  /// delivered.
+  const LINGER: Duration = Duration::from_secs(5);
+
+  /// Connect to `addr`, giving up after `timeout`.
+  pub(crate) fn connect(addr: SocketAddr, timeout: Duration) -> io::Result<Self> {
+    let socket = Socket::connect(addr)?;
+    let recv_id = rand::rng().random::<u16>();
+    let packets = socket
+      .register(addr, recv_id)
+      .ok_or(io::ErrorKind::AddrInUse)?;
+
+    let mut stream = Self::new(
+      socket,
+      packets,
+      addr,
+      recv_id,
+      recv_id.wrapping_add(1),
+      State::Connecting,
+    );
+
+    stream.seq_nr = 1;
+    stream.transmit(Kind::Syn, Vec::new())?;
+
+    let deadline = Instant::now() + timeout;
+
+    while stream.state == State::Connecting {
+      stream.check_reset()?;
+
+      if Instant::now() >= deadline {
+        return Err(io::ErrorKind::TimedOut.into());
+      }
+
+      stream.pump(Some(deadline))?;
+    }
+
+    Ok(stream)
+  }
+
+  /// Accept a connection that `syn` from `addr` asked to open.
+  pub(crate) fn accept(socket: Arc<Socket>, addr: SocketAddr, syn: &Packet) -> io::Result<Self> {
+    let recv_id = syn.connection_id.wrapping_add(1);
+    let packets = socket
+      .register(addr, recv_id)
+      .ok_or(io::ErrorKind::AddrInUse)?;
+
+    let mut stream = Self::new(
+      socket,
+      packets,
+      addr,
+      recv_id,
+      syn.connection_id,
+      State::Connected,
+    );
+
+    stream.seq_nr = rand::rng().random();
+    stream.ack_nr = syn.seq_nr;
+    stream.peer_window = syn.window.into_usize();
+    stream.reply_micro = timestamp().wrapping_sub(syn.timestamp);
+    stream.send_state()?;
+
+    Ok(stream)
+  }
+
+  fn new(
+    socket: Arc<Socket>,
+    packets: mpsc::Receiver<Packet>,
+    addr: SocketAddr,
+    recv_id: u16,
+    send_id: u16,
+    state: State,
+  ) -> Self {
+    Self {
+      socket,
+      packets,
+      addr,
+      recv_id,
+      send_id,
+      state,
+      seq_nr: 0,
+      ack_nr: 0,
+      unacked: VecDeque::new(),
+      out_of_order: HashMap::new(),
+      readable: VecDeque::new(),
+      ack_pending: false,
+      eof: false,
+      reset: false,
+      ledbat: Ledbat::new(),
+      rtt: None,
+      rtt_var: Duration::ZERO,
+      rto: Self::INITIAL_RTO,
+      duplicate_acks: 0,
+      peer_window: Packet::MAX_PAYLOAD,
+      reply_micro: 0,
+      read_timeout: None,
+      #[cfg(test)]
+      drop_every: None,
+      #[cfg(test)]
+      data_sent: 0,
+    }
+  }
+
+  /// Simulate packet loss by never sending the first transmission of every
+  /// `n`th data packet.
+  #[cfg(test)]
+  fn with_loss(mut self, n: usize) -> Self {
+    self.drop_every = Some(n);
+    self
+  }
+
+  pub(crate) fn peer_addr(&self) -> SocketAddr {
+    self.addr
+  }
+
+  pub(crate) fn read_timeout(&self) -> Option<Duration> {
+    self.read_timeout
+  }
+
+  pub(crate) fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+    self.read_timeout = timeout;
+  }
+
+  fn packet(&self, kind: Kind, seq_nr: u16, payload: Vec<u8>) -> Packet {
+    Packet {
+      kind,
+      connection_id: if kind == Kind::Syn {
+        self.recv_id
+      } else {
+        self.send_id
+      },
+      timestamp: timestamp(),
+      timestamp_difference: self.reply_micro,
+      window: u32::try_from(Self::RECEIVE_WINDOW.saturating_sub(self.readable.len()))
+        .unwrap_or(u32::MAX),
+      seq_nr,
+      ack_nr: self.ack_nr,
+      selective_ack: self.selective_ack(),
+      payload,
+    }
+  }
+
+  // Describe which out-of-order packets we have buffered.
+  fn selective_ack(&self) -> Option<Vec<u8>> {
+    let bits = self
+      .out_of_order
+      .keys()
+      .map(|seq_nr| usize::from(seq_nr.wrapping_sub(self.ack_nr).wrapping_sub(2)))
+      .collect::<Vec<usize>>();
+
+    let max = bits.iter().max()?;
+
+    let mut mask = vec![0; (max / 32 + 1) * 4];
+    for bit in bits {
+      mask[bit / 8] |= 1 << (bit % 8);
+    }
+
+    Some(mask)
+  }
+
+  // Send a new packet that the peer must acknowledge.
+  fn transmit(&mut self, kind: Kind, payload: Vec<u8>) -> io::Result<()> {
+    let seq_nr = self.seq_nr;
+    self.seq_nr = self.seq_nr.wrapping_add(1);
+
+    let packet = self.packet(kind, seq_nr, payload);
+
+    #[cfg(test)]
+    let lost = self.simulate_loss(kind);
+    #[cfg(not(test))]
+    let lost = false;
+
+    if !lost {
+      self.socket.send(self.addr, &packet)?;
+    }
+
+    self.unacked.push_back(Sent {
+      seq_nr,
+      kind,
+      payload: packet.payload,
+      transmitted_at: Instant::now(),
+      transmissions: 1,
+      fast_retransmitted: false,
+    });
+
+    Ok(())
+  }
+
+  #[cfg(test)]
+  fn simulate_loss(&mut self, kind: Kind) -> bool {
+    if kind != Kind::Data {
+      return false;
+    }
+
+    self.data_sent += 1;
+
+    self
+      .drop_every
+      .is_some_and(|n| self.data_sent.is_multiple_of(n))
+  }
+
+  fn retransmit_oldest(&mut self) -> io::Result<()> {
+    let Some(sent) = self.unacked.front() else {
+      return Ok(());
+    };
+
+    let packet = self.packet(sent.kind, sent.seq_nr, sent.payload.clone());
+
+    self.socket.send(self.addr, &packet)?;
+
+    let sent = self
+      .unacked
+      .front_mut()
+      .invariant_unwrap("queue is non-empty");
+    sent.transmitted_at = Instant::now();
+    sent.transmissions += 1;
+
+    Ok(())
+  }
+
+  fn send_state(&mut self) -> io::Result<()> {
+    let packet = self.packet(Kind::State, self.seq_nr, Vec::new());
+    self.ack_pending = false;
+    self.socket.send(self.addr, &packet)
+  }
+
+  fn in_flight(&self) -> usize {
+    self.unacked.iter().map(|sent| sent.payload.len()).sum()
+  }
+
+  fn check_reset(&self) -> io::Result<()> {
+    if self.reset {
+      Err(io::ErrorKind::ConnectionReset.into())
+    } else {
+      Ok(())
+    }
+  }
+
+  // Wait for packets until `deadline` or the retransmission timer, handle
+  // everything that has arrived, and retransmit if the timer has expired.
+  fn pump(&mut self, deadline: Option<Instant>) -> io::Result<()> {
+    let timer = self
+      .unacked
+      .front()
+      .map(|sent| sent.transmitted_at + self.rto);
+
+    let received = match deadline.into_iter().chain(timer).min() {
+      Some(wakeup) => self
+        .packets
+        .recv_timeout(wakeup.saturating_duration_since(Instant::now())),
+      None => self
+        .packets
+        .recv()
+        .map_err(|mpsc::RecvError| RecvTimeoutError::Disconnected),
+    };
+
+    match received {
+      Ok(packet) => {
+        self.handle(packet)?;
+        while let Ok(packet) = self.packets.try_recv() {
+          self.handle(packet)?;
+        }
+      }
+      Err(RecvTimeoutError::Timeout) => {}
+      // The socket got an ICMP error from the peer.
+      Err(RecvTimeoutError::Disconnected) => {
+        return Err(
+          match self.state {
+            State::Connecting => io::ErrorKind::ConnectionRefused,
+            State::Connected => io::ErrorKind::ConnectionReset,
+          }
+          .into(),
+        )
+      }
+    }
+
+    if self.ack_pending {
+      self.send_state()?;
+    }
+
+    self.check_timer()
+  }
+
+  fn check_timer(&mut self) -> io::Result<()> {
+    let Some(oldest) = self.unacked.front() else {
+      return Ok(());
+    };
+
+    if Instant::now() < oldest.transmitted_at + self.rto {
+      return Ok(());
+    }
+
+    if oldest.transmissions >= Self::MAX_TRANSMISSIONS {
+      return Err(io::ErrorKind::TimedOut.into());
+    }
+
+    self.rto = (self.rto * 2).min(Self::MAX_RTO);
+    self.ledbat.on_timeout();
+    self.retransmit_oldest()
+  }
+
+  fn handle(&mut self, packet: Packet) -> io::Result<()> {
+    match packet.kind {
+      Kind::Reset => {
+        self.reset = true;
+        return Ok(());
+      }
+      // The peer didn't get our reply to its SYN.
+      Kind::Syn => {
+        self.ack_pending = true;
+        return Ok(());
+      }
+      Kind::Data | Kind::Fin | Kind::State => {}
+    }
+
+    self.reply_micro = timestamp().wrapping_sub(packet.timestamp);
+    self.peer_window = packet.window.into_usize();
+
+    if self.state == State::Connecting {
+      if packet.kind != Kind::State {
+        return Ok(());
+      }
+
+      // The peer's first data packet will carry the sequence number of its
+      // reply to our SYN.
+      self.ack_nr = packet.seq_nr.wrapping_sub(1);
+      self.state = State::Connected;
+    }
+
+    self.handle_ack(&packet)?;
+
+    if matches!(packet.kind, Kind::Data | Kind::Fin) {
+      self.handle_data(packet);
+    }
+
+    Ok(())
+  }
+
+  fn handle_ack(&mut self, packet: &Packet) -> io::Result<()> {
+    let now = Instant::now();
+
+    let mut acked = Vec::new();
+
+    while let Some(sent) = self.unacked.front() {
+      if packet.ack_nr.wrapping_sub(sent.seq_nr) >= 0x8000 {
+        break;
+      }
+      acked.extend(self.unacked.pop_front());
+    }
+
+    if packet.selective_ack.is_some() {
+      let (selected, remaining) = mem::take(&mut self.unacked)
+        .into_iter()
+        .partition::<Vec<Sent>, _>(|sent| packet.selectively_acks(sent.seq_nr));
+      acked.extend(selected);
+      self.unacked = remaining.into();
+    }
+
+    let bytes = acked.iter().map(|sent| sent.payload.len()).sum::<usize>();
+
+    for sent in &acked {
+      // Samples from retransmitted packets are ambiguous.
+      if sent.transmissions == 1 {
+        self.update_rtt(now.duration_since(sent.transmitted_at));
+      }
+    }
+
+    if acked.is_empty() {
+      if packet.kind == Kind::State && !self.unacked.is_empty() {
+        self.duplicate_acks += 1;
+      }
+    } else {
+      self.duplicate_acks = 0;
+
+      if packet.timestamp_difference != 0 {
+        self.ledbat.on_ack(bytes, packet.timestamp_difference, now);
+      }
+    }
+
+    let selectively_acked = packet
+      .selective_ack
+      .iter()
+      .flatten()
+      .map(|byte| byte.count_ones())
+      .sum::<u32>();
+
+    // Packets after the oldest have arrived, so it was probably lost.
+    if self.duplicate_acks >= Self::DUPLICATE_ACK_THRESHOLD
+      || selectively_acked >= Self::DUPLICATE_ACK_THRESHOLD
+    {
+      if let Some(oldest) = self.unacked.front_mut() {
+        if !oldest.fast_retransmitted {
+          oldest.fast_retransmitted = true;
+          self.duplicate_acks = 0;
+          self.ledbat.on_loss();
+          self.retransmit_oldest()?;
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  fn update_rtt(&mut self, sample: Duration) {
+    let rtt = match self.rtt {
+      None => {
+        self.rtt_var = sample / 2;
+        sample
+      }
+      Some(rtt) => {
+        self.rtt_var = (self.rtt_var * 3 + rtt.abs_diff(sample)) / 4;
+        (rtt * 7 + sample) / 8
+      }
+    };
+
+    self.rtt = Some(rtt);
+    self.rto = (rtt + self.rtt_var * 4).clamp(Self::MIN_RTO, Self::MAX_RTO);
+  }
+
+  fn handle_data(&mut self, packet: Packet) {
+    self.ack_pending = true;
+
+    if self.eof {
+      return;
+    }
+
+    let offset = packet.seq_nr.wrapping_sub(self.ack_nr);
+
+    // Duplicates, and packets too far ahead to buffer, are dropped.
+    if offset == 0 || offset > Self::REORDER_WINDOW {
+      return;
+    }
+
+    if offset > 1 {
+      self.out_of_order.insert(packet.seq_nr, packet);
+      return;
+    }
+
+    self.deliver(packet);
+
+    while let Some(packet) = self.out_of_order.remove(&self.ack_nr.wrapping_add(1)) {
+      self.deliver(packet);
+    }
+  }
+
+  fn deliver(&mut self, packet: Packet) {
+    self.ack_nr = packet.seq_nr;
+
+    if packet.kind == Kind::Fin {
+      self.eof = true;
+      self.out_of_order.clear();
+    } else {
+      self.readable.extend(packet.payload);
+    }
+  }
+
+  // Handle packets that have already arrived, without waiting.
+  fn poll(&mut self) -> io::Result<()> {
+    while let Ok(packet) = self.packets.try_recv() {
+      self.handle(packet)?;
+    }
+
+    if self.ack_pending {
+      self.send_state()?;
+    }
+
+    self.check_timer()
+  }
+}
+
+impl Read for Stream {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let deadline = self.read_timeout.map(|timeout| Instant::now() + timeout);
+
+    loop {
+      if !self.readable.is_empty() {
+        let n = buf.len().min(self.readable.len());
+        for (dst, src) in buf.iter_mut().zip(self.readable.drain(..n)) {
+          *dst = src;
+        }
+        return Ok(n);
+      }
+
+      if self.eof || buf.is_empty() {
+        return Ok(0);
+      }
+
+      self.check_reset()?;
+
+      if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+        return Err(io::ErrorKind::WouldBlock.into());
+      }
+
+      self.pump(deadline)?;
+    }
+  }
+}
+
+impl Write for Stream {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    if buf.is_empty() {
+      return Ok(0);
+    }
+
+    let length = buf.len().min(Packet::MAX_PAYLOAD);
+
+    // Wait for room in the window, but always allow one packet in flight, so
+    // that a closed window is probed.
+    loop {
+      self.check_reset()?;
+
+      let in_flight = self.in_flight();
+
+      if in_flight == 0 || in_flight + length <= self.ledbat.window().min(self.peer_window) {
+        break;
+      }
+
+      self.pump(None)?;
+    }
+
+    self.transmit(Kind::Data, buf[..length].to_vec())?;
+
+    self.poll()?;
+
+    Ok(length)
+  }
+
+  /// Wait until everything written has been acknowledged.
+  fn flush(&mut self) -> io::Result<()> {
+    while !self.unacked.is_empty() {
+      self.check_reset()?;
+      self.pump(None)?;
+    }
+
+    Ok(())
+  }
+}
+
+impl Drop for Stream {
+  fn drop(&mut self) {
+    if self.state == State::Connected && !self.reset {
+      let deadline = Instant::now() + Self::LINGER;
+
+      while !self.unacked.is_empty() && !self.reset && Instant::now() < deadline {
+        if self.pump(Some(deadline)).is_err() {
+          break;
+        }
+      }
+
+      if !self.reset {
+        let fin = self.packet(Kind::Fin, self.seq_nr, Vec::new());
+        self.socket.send(self.addr, &fin).ok();
+      }
+    }
+
+    self.socket.deregister(self.addr, self.recv_id);
+  }
+}
+
+// The low 32 bits of the current time in microseconds. Only differences
+// between timestamps are meaningful.
+fn timestamp() -> u32 {
+  let micros = SystemTime::now()
+    .duration_since(SystemTime::UNIX_EPOCH)
+    .unwrap_or_default()
+    .as_micros();
+
+  u32::try_from(micros % (1 << 32)).invariant_unwrap("timestamp is reduced modulo 2^32")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  use utp::Listener;
+
+  const TIMEOUT: Duration = Duration::from_secs(3);
+
+  // Accept one connection, and pass it to `peer` on a background thread.
+  fn spawn_peer<T: Send + 'static>(
+    peer: impl FnOnce(Stream) -> T + Send + 'static,
+  ) -> (SocketAddr, thread::JoinHandle<T>) {
+    let listener = Listener::bind((Ipv4Addr::LOCALHOST, 0).into()).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = thread::spawn(move || {
+      let (stream, _) = listener.accept().unwrap();
+      peer(stream)
+    });
+    (addr, handle)
+  }
+
+  fn data(length: usize) -> Vec<u8> {
+    (0..length).map(|i| (i % 251).to_le_bytes()[0]).collect()
+  }
+
+  fn transfer(loss: Option<usize>) {
+    let expected = data(256 * 1024);
+    let length = expected.len();
+
+    let (addr, handle) = spawn_peer(move |mut stream| {
+      let mut buf = vec![0; length];
+      stream.read_exact(&mut buf).unwrap();
+      buf
+    });
+
+    let mut stream = Stream::connect(addr, TIMEOUT).unwrap();
+    if let Some(n) = loss {
+      stream = stream.with_loss(n);
+    }
+    stream.write_all(&expected).unwrap();
+    stream.flush().unwrap();
+
+    assert!(handle.join().unwrap() == expected);
+  }
+
+  #[test]
+  fn transfer_lossless() {
+    transfer(None);
+  }
+
+  #[test]
+  fn transfer_with_loss() {
+    // Lost packets are detected by selective acks from the packets after
+    // them, and retransmitted without waiting for the timer.
+    transfer(Some(10));
+  }
+
+  #[test]
+  fn retransmit_after_timeout() {
+    // The first transmission of every data packet is lost, and there are no
+    // later packets to trigger a fast retransmit, so only the retransmission
+    // timer recovers.
+    let (addr, handle) = spawn_peer(|mut stream| {
+      let mut buf = [0; 5];
+      stream.read_exact(&mut buf).unwrap();
+      buf
+    });
+
+    let mut stream = Stream::connect(addr, TIMEOUT).unwrap().with_loss(1);
+    stream.write_all(b"hello").unwrap();
+    stream.flush().unwrap();
+
+    assert_eq!(&handle.join().unwrap(), b"hello");
+  }
+
+  #[test]
+  fn bidirectional() {
+    let (addr, handle) = spawn_peer(|mut stream| {
+      let mut buf = [0; 4];
+      stream.read_exact(&mut buf).unwrap();
+      stream.write_all(b"pong").unwrap();
+      stream.flush().unwrap();
+      buf
+    });
+
+    let mut stream = Stream::connect(addr, TIMEOUT).unwrap();
+    stream.write_all(b"ping").unwrap();
+
+    let mut buf = [0; 4];
+    stream.read_exact(&mut buf).unwrap();
+
+    assert_eq!(&buf, b"pong");
+    assert_eq!(&handle.join().unwrap(), b"ping");
+  }
+
+  #[test]
+  fn eof() {
+    let (addr, handle) = spawn_peer(|mut stream| {
+      let mut buf = Vec::new();
+      stream.read_to_end(&mut buf).unwrap();
+      buf
+    });
+
+    let mut stream = Stream::connect(addr, TIMEOUT).unwrap();
+    stream.write_all(b"foo").unwrap();
+    drop(stream);
+
+    assert_eq!(handle.join().unwrap(), b"foo");
+  }
+
+  #[test]
+  fn reset() {
+    let (addr, handle) = spawn_peer(drop);
+
+    let mut stream = Stream::connect(addr, TIMEOUT).unwrap();
+    handle.join().unwrap();
+
+    let mut buf = Vec::new();
+    stream.read_to_end(&mut buf).unwrap();
+    assert!(buf.is_empty());
+
+    stream.write_all(b"foo").unwrap();
+    assert_eq!(
+      stream.flush().unwrap_err().kind(),
+      io::ErrorKind::ConnectionReset
+    );
+  }
+
+  #[test]
+  fn read_timeout() {
+    let (addr, _handle) = spawn_peer(|_stream| thread::sleep(TIMEOUT));
+
+    let mut stream = Stream::connect(addr, TIMEOUT).unwrap();
+    stream.set_read_timeout(Some(Duration::from_millis(100)));
+
+    let mut buf = [0; 1];
+    assert_eq!(
+      stream.read(&mut buf).unwrap_err().kind(),
+      io::ErrorKind::WouldBlock
+    );
+  }
+
+  #[test]
+  fn connect_timeout() {
+    // A socket that never replies.
+    let silent = std::net::UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let addr = silent.local_addr().unwrap();
+
+    assert_eq!(
+      Stream::connect(addr, Duration::from_millis(200))
+        .unwrap_err()
+        .kind(),
+      io::ErrorKind::TimedOut
+    );
+  }
+
+  #[test]
+  fn connection_refused() {
+    let addr = std::net::UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))
+      .unwrap()
+      .local_addr()
+      .unwrap();
+
+    assert_eq!(
+      Stream::connect(addr, TIMEOUT).unwrap_err().kind(),
+      io::ErrorKind::ConnectionRefused
+    );
+  }
+
+  #[test]
+  fn selective_ack() {
+    let listener = Listener::bind((Ipv4Addr::LOCALHOST, 0).into()).unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || listener.accept().unwrap());
+
+    let mut stream = Stream::connect(addr, TIMEOUT).unwrap();
+    stream.ack_nr = 10;
+    for seq_nr in [12, 13, 20, 45] {
+      stream
+        .out_of_order
+        .insert(seq_nr, Packet::new(Kind::Data, 0, seq_nr, 0));
+    }
+
+    let packet = stream.packet(Kind::State, 0, Vec::new());
+    assert_eq!(packet.selective_ack.as_ref().unwrap().len(), 8);
+    for seq_nr in 11..50 {
+      assert_eq!(
+        packet.selectively_acks(seq_nr),
+        [12, 13, 20, 45].contains(&seq_nr),
+        "{seq_nr}"
+      );
+    }
+  }
+}
@@ -1,9 +1,13 @@
 use crate::common::*;
 
+use hash_cache::PieceHasher;
+
 pub(crate) struct Verifier<'a> {
   metainfo: &'a Metainfo,
   base: &'a Path,
+  layout: Option<&'a Layout>,
   buffer: Vec<u8>,
+  cache: Option<&'a HashCache>,
   piece_length: usize,
   pieces: PieceList,
   sha1: Sha1,
@@ -15,16 +19,20 @@ impl<'a> Verifier<'a> {
   fn new(
     metainfo: &'a Metainfo,
     base: &'a Path,
+    layout: Option<&'a Layout>,
     progress_bar: Option<ProgressBar>,
+    cache: Option<&'a HashCache>,
   ) -> Result<Verifier<'a>> {
     let piece_length = metainfo.info.piece_length.as_piece_length()?.into_usize();
 
     Ok(Verifier {
       buffer: vec![0; piece_length],
+      cache,
       piece_bytes_hashed: 0,
       pieces: PieceList::new(),
       sha1: Sha1::new(),
       base,
+      layout,
       metainfo,
       piece_length,
       progress_bar,
@@ -34,16 +42,28 @@ impl<'a> Verifier<'a> {
   pub(crate) fn verify(
     metainfo: &'a Metainfo,
     base: &'a Path,
+    layout: Option<&'a Layout>,
     progress_bar: Option<ProgressBar>,
+    cache: Option<&'a HashCache>,
   ) -> Result<Status> {
-    Ok(Self::new(metainfo, base, progress_bar)?.verify_metainfo())
+    Ok(Self::new(metainfo, base, layout, progress_bar, cache)?.verify_metainfo())
   }
 
   fn verify_metainfo(mut self) -> Status {
     match &self.metainfo.info.mode {
-      Mode::Single { length, md5sum } => {
-        self.hash(self.base).ok();
-        let error = FileError::verify(self.base, *length, *md5sum).err();
+      Mode::Single {
+        length,
+        md5sum,
+        sha1,
+        sha256,
+      } => {
+        let checksums = Checksums {
+          md5sum: *md5sum,
+          sha1: *sha1,
+          sha256: *sha256,
+        };
+        let actual = self.hash(self.base, ChecksumKinds::of(&checksums)).ok();
+        let error = FileError::verify(self.base, *length, checksums, actual).err();
 
         let pieces = self.finish();
         Status::single(pieces, error)
@@ -52,14 +72,24 @@ impl<'a> Verifier<'a> {
         let mut status = Vec::new();
 
         for file in files {
-          let path = file.path.absolute(self.base);
-          self.hash(&path).ok();
+          // Files not covered by the layout are looked for under the base.
+          let path = self
+            .layout
+            .and_then(|layout| layout.source(&file.path))
+            .unwrap_or_else(|| file.path.absolute(self.base));
+          let checksums = Checksums {
+            md5sum: file.md5sum,
+            sha1: file.sha1,
+            sha256: file.sha256,
+          };
+          let actual = self.hash(&path, ChecksumKinds::of(&checksums)).ok();
 
           status.push(FileStatus::status(
             &path,
             file.path.clone(),
             file.length,
-            file.md5sum,
+            checksums,
+            actual,
           ));
         }
 
@@ -70,8 +100,41 @@ impl<'a> Verifier<'a> {
     }
   }
 
-  pub(crate) fn hash(&mut self, path: &Path) -> io::Result<()> {
-    let mut file = BufReader::new(File::open(path)?);
+  // Hash the file at `path`, returning its checksums of the given `kinds`.
+  fn hash(&mut self, path: &Path, kinds: ChecksumKinds) -> io::Result<Checksums> {
+    HashCache::hash(self.cache, self, path, kinds).map(|(checksums, _)| checksums)
+  }
+
+  fn finish(&mut self) -> bool {
+    if self.piece_bytes_hashed > 0 {
+      self.pieces.push(self.sha1.digest().into());
+      self.sha1.reset();
+      self.piece_bytes_hashed = 0;
+    }
+
+    self.pieces == self.metainfo.info.pieces
+  }
+}
+
+impl PieceHasher for Verifier<'_> {
+  fn piece_length(&self) -> usize {
+    self.piece_length
+  }
+
+  fn piece_bytes_hashed(&self) -> usize {
+    self.piece_bytes_hashed
+  }
+
+  fn pieces(&self) -> &PieceList {
+    &self.pieces
+  }
+
+  fn hash_read(
+    &mut self,
+    file: &mut dyn BufRead,
+    mut checksums: Option<&mut ChecksumContext>,
+  ) -> io::Result<u64> {
+    let mut bytes_hashed = 0;
 
     loop {
       let remaining = &mut self.buffer[..self.piece_length - self.piece_bytes_hashed];
@@ -86,6 +149,11 @@ impl<'a> Verifier<'a> {
 
       self.sha1.update(read);
 
+      if let Some(checksums) = checksums.as_mut() {
+        checksums.update(read);
+      }
+
+      bytes_hashed += bytes_read.into_u64();
       self.piece_bytes_hashed += bytes_read;
 
       if self.piece_bytes_hashed == self.piece_length {
@@ -99,17 +167,15 @@ impl<'a> Verifier<'a> {
       }
     }
 
-    Ok(())
+    Ok(bytes_hashed)
   }
 
-  fn finish(&mut self) -> bool {
-    if self.piece_bytes_hashed > 0 {
-      self.pieces.push(self.sha1.digest().into());
-      self.sha1.reset();
-      self.piece_bytes_hashed = 0;
-    }
+  fn skip(&mut self, pieces: &PieceList, length: u64) {
+    self.pieces.extend(pieces);
 
-    self.pieces == self.metainfo.info.pieces
+    if let Some(progress_bar) = &self.progress_bar {
+      progress_bar.inc(length);
+    }
   }
 }
 
@@ -141,7 +207,9 @@ mod tests {
 
     let metainfo = env.load_metainfo("foo.torrent");
 
-    assert!(metainfo.verify(&env.resolve("foo")?, None)?.good());
+    assert!(metainfo
+      .verify(&env.resolve("foo")?, None, None, None)?
+      .good());
 
     Ok(())
   }
@@ -172,7 +240,7 @@ mod tests {
 
     let metainfo = env.load_metainfo("foo.torrent");
 
-    let status = metainfo.verify(&env.resolve("foo")?, None)?;
+    let status = metainfo.verify(&env.resolve("foo")?, None, None, None)?;
 
     assert_eq!(status.count_bad(), 0);
 
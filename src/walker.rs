@@ -13,6 +13,8 @@ pub(crate) struct Walker {
   include_hidden: bool,
   include_junk: bool,
   ignore: bool,
+  list: Option<Vec<FilePath>>,
+  normalize: bool,
   sort_by: Vec<SortSpec>,
   patterns: Vec<Pattern>,
   root: PathBuf,
@@ -26,6 +28,8 @@ impl Walker {
       include_hidden: false,
       include_junk: false,
       ignore: false,
+      list: None,
+      normalize: false,
       sort_by: Vec::new(),
       patterns: Vec::new(),
       root: root.to_owned(),
@@ -51,6 +55,22 @@ impl Walker {
     Self { ignore, ..self }
   }
 
+  /// Include exactly the files at the paths in `list`, relative to the root,
+  /// instead of searching for them. They are kept in the given order unless
+  /// `sort_by` is non-empty or `normalize` is set, and aren't filtered by
+  /// globs or by whether they're hidden or junk.
+  pub(crate) fn list(self, list: Option<Vec<FilePath>>) -> Self {
+    Self { list, ..self }
+  }
+
+  /// Sort files as if their paths were in Unicode Normalization Form C, so
+  /// that their order doesn't depend on how the filesystem composes names.
+  /// Listed files are sorted too, so that their order doesn't depend on how
+  /// the list was made.
+  pub(crate) fn normalize(self, normalize: bool) -> Self {
+    Self { normalize, ..self }
+  }
+
   pub(crate) fn sort_by(self, sort_by: Vec<SortSpec>) -> Self {
     Self { sort_by, ..self }
   }
@@ -79,16 +99,11 @@ impl Walker {
     Self { spinner, ..self }
   }
 
-  pub(crate) fn files(self) -> Result<Files, Error> {
-    if !self.follow_symlinks
-      && self
-        .root
-        .symlink_metadata()
-        .context(error::Filesystem { path: &self.root })?
-        .file_type()
-        .is_symlink()
-    {
-      return Err(Error::SymlinkRoot { root: self.root });
+  pub(crate) fn files(mut self) -> Result<Files, Error> {
+    self.check_root(&self.root)?;
+
+    if let Some(list) = self.list.take() {
+      return self.listed(list);
     }
 
     let root_metadata = self
@@ -100,10 +115,125 @@ impl Walker {
       return Ok(Files::file(self.root, Bytes::from(root_metadata.len())));
     }
 
+    let file_infos = self
+      .walk(&self.root, None)?
+      .into_iter()
+      .map(|(file_info, _)| file_info)
+      .collect::<Vec<FileInfo>>();
+
+    let total_size = file_infos.iter().map(|file_info| file_info.length).sum();
+
+    let contents = self.sort(file_infos);
+
+    Ok(Files::dir(self.root, total_size, contents))
+  }
+
+  /// The files added by the mappings of `layout`. Files are added from
+  /// directory sources as they would be from a root directory, and file
+  /// sources are added as-is. It's an error for two sources to add the same
+  /// path, or for a path to be both a file and a directory.
+  pub(crate) fn layout(self, layout: Layout) -> Result<Files, Error> {
+    let mut added: BTreeMap<FilePath, (FileInfo, PathBuf)> = BTreeMap::new();
+
+    for (destination, source) in layout.mappings() {
+      self.check_root(source)?;
+
+      let metadata = source
+        .metadata()
+        .context(error::Filesystem { path: source })?;
+
+      let file_infos = if metadata.is_file() {
+        vec![(
+          FileInfo {
+            path: destination.clone(),
+            length: Bytes(metadata.len()),
+            md5sum: None,
+            sha1: None,
+            sha256: None,
+          },
+          source.clone(),
+        )]
+      } else {
+        self.walk(source, Some(destination))?
+      };
+
+      for (file_info, path) in file_infos {
+        if let Some((_, first)) = added.get(&file_info.path) {
+          return Err(Error::AddConflict {
+            path: file_info.path,
+            first: first.clone(),
+            second: path,
+          });
+        }
+
+        added.insert(file_info.path.clone(), (file_info, path));
+      }
+    }
+
+    // Paths beneath a file path sort directly after it.
+    for ((a, (_, first)), (b, (_, second))) in added.iter().zip(added.iter().skip(1)) {
+      if b.starts_with(a) {
+        return Err(Error::AddConflict {
+          path: a.clone(),
+          first: first.clone(),
+          second: second.clone(),
+        });
+      }
+    }
+
+    // Files must be found where the layout says they are, or the torrent
+    // couldn't be verified with the same mappings.
+    for (file_path, (_, path)) in &added {
+      match layout.source(file_path) {
+        Some(source) if source == *path => {}
+        source => {
+          return Err(Error::AddConflict {
+            path: file_path.clone(),
+            first: source.unwrap_or_default(),
+            second: path.clone(),
+          })
+        }
+      }
+    }
+
+    let file_infos = added
+      .into_values()
+      .map(|(file_info, _)| file_info)
+      .collect::<Vec<FileInfo>>();
+
+    let total_size = file_infos.iter().map(|file_info| file_info.length).sum();
+
+    let contents = self.sort(file_infos);
+
+    Ok(Files::layout(self.root, total_size, contents, layout))
+  }
+
+  fn check_root(&self, root: &Path) -> Result<(), Error> {
+    if !self.follow_symlinks
+      && root
+        .symlink_metadata()
+        .context(error::Filesystem { path: root })?
+        .file_type()
+        .is_symlink()
+    {
+      return Err(Error::SymlinkRoot {
+        root: root.to_owned(),
+      });
+    }
+
+    Ok(())
+  }
+
+  // Search the directory `root` for files, returning each, with its path
+  // under `prefix`, and where it was found.
+  fn walk(
+    &self,
+    root: &Path,
+    prefix: Option<&FilePath>,
+  ) -> Result<Vec<(FileInfo, PathBuf)>, Error> {
     let mut file_infos = Vec::new();
-    let mut total_size = 0;
 
-    let mut walk_builder = WalkBuilder::new(&self.root);
+    let mut walk_builder = WalkBuilder::new(root);
     walk_builder
       .follow_links(self.follow_symlinks)
       .standard_filters(self.ignore)
@@ -114,7 +244,7 @@ impl Walker {
       let path = entry.path();
 
       if let Some(s) = &self.spinner {
-        let display_path = path.strip_prefix(&self.root).unwrap_or(path);
+        let display_path = path.strip_prefix(root).unwrap_or(path);
         s.set_message(&display_path.display().to_string());
         s.tick();
       }
@@ -126,49 +256,159 @@ impl Walker {
       }
 
       let relative = path
-        .strip_prefix(&self.root)
-        .context(error::PathStripPrefix {
-          path,
-          prefix: &self.root,
-        })?;
+        .strip_prefix(root)
+        .context(error::PathStripPrefix { path, prefix: root })?;
 
       if relative.components().count() == 0 {
         return Err(Error::PathStripEmpty {
-          prefix: self.root.clone(),
+          prefix: root.to_owned(),
           path: path.to_owned(),
         });
       }
 
-      if !self.pattern_filter(relative) {
+      let relative = match prefix {
+        Some(prefix) => prefix.absolute(Path::new("")).join(relative),
+        None => relative.to_owned(),
+      };
+
+      if !self.pattern_filter(&relative) {
         continue;
       }
 
-      let file_path = FilePath::from_relative_path(relative)?;
+      let file_path = FilePath::from_relative_path(&relative)?;
 
-      if !self.include_junk && JUNK.contains(&file_path.name()) {
+      if !self.include_junk && Self::is_junk(file_path.name()) {
         continue;
       }
 
-      let len = metadata.len();
-      total_size += len;
+      file_infos.push((
+        FileInfo {
+          path: file_path,
+          length: Bytes(metadata.len()),
+          md5sum: None,
+          sha1: None,
+          sha256: None,
+        },
+        path.to_owned(),
+      ));
+    }
+
+    Ok(file_infos)
+  }
+
+  fn listed(self, list: Vec<FilePath>) -> Result<Files, Error> {
+    let mut file_infos = Vec::new();
+    let mut total_size = Bytes(0);
+
+    for file_path in list {
+      let path = file_path.absolute(&self.root);
+
+      let metadata = if self.follow_symlinks {
+        path.metadata()
+      } else {
+        path.symlink_metadata()
+      };
+
+      let length = match metadata {
+        Ok(metadata) if metadata.is_file() => Bytes(metadata.len()),
+        Err(source) if source.kind() != io::ErrorKind::NotFound => {
+          return Err(Error::Filesystem { source, path });
+        }
+        _ => {
+          return Err(Error::FilesFromMissing {
+            root: self.root,
+            path: file_path,
+          })
+        }
+      };
+
+      total_size += length;
 
       file_infos.push(FileInfo {
         path: file_path,
-        length: Bytes(len),
+        length,
         md5sum: None,
+        sha1: None,
+        sha256: None,
       });
     }
 
-    file_infos.sort_by(|a, b| SortSpec::compare(&self.sort_by, a, b));
-
-    Ok(Files::dir(
-      self.root,
-      Bytes::from(total_size),
+    let contents = if self.sort_by.is_empty() && !self.normalize {
       file_infos
         .into_iter()
         .map(|file_info| file_info.path)
-        .collect(),
-    ))
+        .collect()
+    } else {
+      self.sort(file_infos)
+    };
+
+    Ok(Files::dir(self.root, total_size, contents))
+  }
+
+  /// The entries of `archive` that aren't filtered out, as if it were a
+  /// directory that had been extracted to `root`. Symlinks in archives are
+  /// never followed, and ignore files aren't read.
+  pub(crate) fn archive(self, archive: Archive) -> Files {
+    let mut file_infos = Vec::new();
+    let mut total_size = Bytes(0);
+
+    for (file_path, length) in archive.entries() {
+      if !self.include_hidden
+        && file_path
+          .components()
+          .iter()
+          .any(|component| component.starts_with('.'))
+      {
+        continue;
+      }
+
+      if !self.pattern_filter(&file_path.absolute(Path::new(""))) {
+        continue;
+      }
+
+      if !self.include_junk && Self::is_junk(file_path.name()) {
+        continue;
+      }
+
+      total_size += length;
+
+      file_infos.push(FileInfo {
+        path: file_path.clone(),
+        length,
+        md5sum: None,
+        sha1: None,
+        sha256: None,
+      });
+    }
+
+    let contents = self.sort(file_infos);
+
+    Files::archive(self.root, total_size, contents, archive)
+  }
+
+  pub(crate) fn is_junk(name: &str) -> bool {
+    JUNK.contains(&name)
+  }
+
+  fn sort(&self, file_infos: Vec<FileInfo>) -> Vec<FilePath> {
+    let mut keyed = file_infos
+      .into_iter()
+      .map(|file_info| {
+        let key = if self.normalize {
+          FileInfo {
+            path: file_info.path.normalized(),
+            ..file_info.clone()
+          }
+        } else {
+          file_info.clone()
+        };
+        (key, file_info.path)
+      })
+      .collect::<Vec<(FileInfo, FilePath)>>();
+
+    keyed.sort_by(|(a, _), (b, _)| SortSpec::compare(&self.sort_by, a, b));
+
+    keyed.into_iter().map(|(_, path)| path).collect()
   }
 
   fn pattern_filter(&self, relative: &Path) -> bool {
@@ -200,4 +440,54 @@ mod tests {
     assert!(walker.pattern_filter(Path::new("b")));
     assert!(walker.pattern_filter(Path::new("c")));
   }
+
+  #[test]
+  fn normalize() {
+    let tree = temptree! {
+      "e\u{301}": "",
+      f: "",
+    };
+
+    let paths = |normalize| {
+      Walker::new(tree.path())
+        .normalize(normalize)
+        .files()
+        .unwrap()
+        .contents()
+        .unwrap()
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<String>>()
+    };
+
+    assert_eq!(paths(false), ["e\u{301}", "f"]);
+    assert_eq!(paths(true), ["f", "e\u{301}"]);
+  }
+
+  #[test]
+  fn listed() {
+    let tree = temptree! {
+      a: "",
+      b: "",
+    };
+
+    let paths = |normalize| {
+      Walker::new(tree.path())
+        .list(Some(vec![
+          FilePath::from_components(&["b"]),
+          FilePath::from_components(&["a"]),
+        ]))
+        .normalize(normalize)
+        .files()
+        .unwrap()
+        .contents()
+        .unwrap()
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<String>>()
+    };
+
+    assert_eq!(paths(false), ["b", "a"]);
+    assert_eq!(paths(true), ["a", "b"]);
+  }
 }
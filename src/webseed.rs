@@ -0,0 +1,384 @@
+//! A BEP 19 web seed: an HTTP server for torrent content. Clients find a
+//! single-file torrent's content at `/NAME`, and a multi-file torrent's
+//! files at `/NAME/PATH`, and fetch the pieces they need with range
+//! requests.
+
+use crate::common::*;
+
+use std::{
+  num::NonZeroUsize,
+  sync::{mpsc, Mutex},
+};
+
+use byte_range::ByteRange;
+use request::{Malformed, Request};
+
+mod byte_range;
+mod request;
+
+#[derive(Debug)]
+pub(crate) struct Server {
+  files: HashMap<Vec<String>, (PathBuf, u64)>,
+}
+
+impl Server {
+  // Close connections that have been idle this long, so abandoned ones
+  // don't tie up threads.
+  const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+  /// Serve the content described by `info`, found at `content`.
+  pub(crate) fn new(info: &Info, content: &Path) -> Self {
+    let files = match &info.mode {
+      Mode::Single { length, .. } => HashMap::from([(
+        vec![info.name.clone()],
+        (content.to_owned(), length.count()),
+      )]),
+      Mode::Multiple { files } => files
+        .iter()
+        .map(|file| {
+          let path = iter::once(info.name.clone())
+            .chain(file.path.components().iter().cloned())
+            .collect();
+          (path, (file.path.absolute(content), file.length.count()))
+        })
+        .collect(),
+    };
+
+    Self { files }
+  }
+
+  /// Start `workers` threads, which each serve one connection sent to the
+  /// returned sender at a time. Sending blocks while every worker is busy,
+  /// so that at most `workers` connections are served at once.
+  pub(crate) fn spawn_workers(
+    self: &Arc<Self>,
+    workers: NonZeroUsize,
+  ) -> mpsc::SyncSender<TcpStream> {
+    let (tx, rx) = mpsc::sync_channel::<TcpStream>(0);
+    let rx = Arc::new(Mutex::new(rx));
+
+    for _ in 0..workers.get() {
+      let server = self.clone();
+      let rx = rx.clone();
+      thread::spawn(move || {
+        // Workers exit once the sender is dropped.
+        let next = || rx.lock().ok()?.recv().ok();
+
+        while let Some(stream) = next() {
+          server.serve(stream).ok();
+        }
+      });
+    }
+
+    tx
+  }
+
+  /// Answer requests on `stream` until the client closes it, asks us to, or
+  /// goes idle.
+  pub(crate) fn serve(&self, stream: TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(Self::IDLE_TIMEOUT))?;
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = io::BufWriter::new(stream);
+
+    loop {
+      let request = match Request::read(&mut reader) {
+        Ok(Some(request)) => request,
+        Ok(None) => return Ok(()),
+        Err(Malformed) => {
+          Self::status(&mut writer, "400 Bad Request", false)?;
+          return writer.flush();
+        }
+      };
+
+      self.respond(&request, &mut writer)?;
+      writer.flush()?;
+
+      if !request.keep_alive {
+        return Ok(());
+      }
+    }
+  }
+
+  fn respond(&self, request: &Request, writer: &mut impl Write) -> io::Result<()> {
+    let head = match request.method.as_str() {
+      "GET" => false,
+      "HEAD" => true,
+      _ => {
+        write!(
+          writer,
+          "HTTP/1.1 405 Method Not Allowed\r\nAllow: GET, HEAD\r\n"
+        )?;
+        return Self::empty(writer, request.keep_alive);
+      }
+    };
+
+    let Some((path, length)) = self.files.get(&request.path) else {
+      return Self::status(writer, "404 Not Found", request.keep_alive);
+    };
+
+    let mut file = match File::open(path) {
+      Ok(file) => file,
+      Err(err) if err.kind() == io::ErrorKind::NotFound => {
+        return Self::status(writer, "404 Not Found", request.keep_alive);
+      }
+      Err(_) => return Self::status(writer, "500 Internal Server Error", request.keep_alive),
+    };
+
+    let length = *length;
+
+    let range = match ByteRange::resolve(request.range.as_deref(), length) {
+      ByteRange::Full => {
+        write!(writer, "HTTP/1.1 200 OK\r\n")?;
+        0..length
+      }
+      ByteRange::Partial(range) => {
+        write!(
+          writer,
+          "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {}-{}/{length}\r\n",
+          range.start,
+          range.end - 1,
+        )?;
+        range
+      }
+      ByteRange::Unsatisfiable => {
+        write!(
+          writer,
+          "HTTP/1.1 416 Range Not Satisfiable\r\nContent-Range: bytes */{length}\r\n"
+        )?;
+        return Self::empty(writer, request.keep_alive);
+      }
+    };
+
+    let content_length = range.end - range.start;
+
+    write!(
+      writer,
+      "Accept-Ranges: bytes\r\n\
+       Content-Type: application/octet-stream\r\n\
+       Content-Length: {content_length}\r\n",
+    )?;
+    Self::connection(writer, request.keep_alive)?;
+    write!(writer, "\r\n")?;
+
+    if head {
+      return Ok(());
+    }
+
+    file.seek(SeekFrom::Start(range.start))?;
+
+    // A file shorter than the torrent says would leave the response short,
+    // so fail, closing the connection, to tell the client.
+    if io::copy(&mut file.take(content_length), writer)? < content_length {
+      return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok(())
+  }
+
+  fn status(writer: &mut impl Write, status: &str, keep_alive: bool) -> io::Result<()> {
+    write!(writer, "HTTP/1.1 {status}\r\n")?;
+    Self::empty(writer, keep_alive)
+  }
+
+  fn empty(writer: &mut impl Write, keep_alive: bool) -> io::Result<()> {
+    write!(writer, "Content-Length: 0\r\n")?;
+    Self::connection(writer, keep_alive)?;
+    write!(writer, "\r\n")
+  }
+
+  fn connection(writer: &mut impl Write, keep_alive: bool) -> io::Result<()> {
+    if keep_alive {
+      Ok(())
+    } else {
+      write!(writer, "Connection: close\r\n")
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn spawn(tree: &str) -> (TestEnv, SocketAddr) {
+    spawn_limited(tree, 16)
+  }
+
+  fn spawn_limited(tree: &str, workers: usize) -> (TestEnv, SocketAddr) {
+    let mut env = test_env! {
+      args: [
+        "torrent",
+        "create",
+        "--input",
+        tree,
+        "--announce",
+        "udp://127.0.0.1:1",
+      ],
+      tree: {
+        foo: "0123456789",
+        bar: {
+          a: "abc",
+          "b c": "defg",
+        },
+      },
+    };
+
+    env.assert_ok();
+
+    let metainfo = env.load_metainfo(format!("{tree}.torrent"));
+    let server = Arc::new(Server::new(&metainfo.info, &env.resolve(tree).unwrap()));
+
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let workers = server.spawn_workers(NonZeroUsize::new(workers).unwrap());
+
+    thread::spawn(move || {
+      for stream in listener.incoming() {
+        workers.send(stream.unwrap()).unwrap();
+      }
+    });
+
+    (env, addr)
+  }
+
+  fn get(addr: SocketAddr, requests: &str) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(requests.as_bytes()).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+  }
+
+  #[test]
+  fn single_file() {
+    let (_env, addr) = spawn("foo");
+
+    assert_eq!(
+      get(addr, "GET /foo HTTP/1.0\r\n\r\n"),
+      "HTTP/1.1 200 OK\r\n\
+       Accept-Ranges: bytes\r\n\
+       Content-Type: application/octet-stream\r\n\
+       Content-Length: 10\r\n\
+       Connection: close\r\n\
+       \r\n\
+       0123456789",
+    );
+  }
+
+  #[test]
+  fn range() {
+    let (_env, addr) = spawn("foo");
+
+    assert_eq!(
+      get(addr, "GET /foo HTTP/1.0\r\nRange: bytes=2-4\r\n\r\n"),
+      "HTTP/1.1 206 Partial Content\r\n\
+       Content-Range: bytes 2-4/10\r\n\
+       Accept-Ranges: bytes\r\n\
+       Content-Type: application/octet-stream\r\n\
+       Content-Length: 3\r\n\
+       Connection: close\r\n\
+       \r\n\
+       234",
+    );
+  }
+
+  #[test]
+  fn range_unsatisfiable() {
+    let (_env, addr) = spawn("foo");
+
+    assert_eq!(
+      get(addr, "GET /foo HTTP/1.0\r\nRange: bytes=10-\r\n\r\n"),
+      "HTTP/1.1 416 Range Not Satisfiable\r\n\
+       Content-Range: bytes */10\r\n\
+       Content-Length: 0\r\n\
+       Connection: close\r\n\
+       \r\n",
+    );
+  }
+
+  #[test]
+  fn head() {
+    let (_env, addr) = spawn("foo");
+
+    assert_eq!(
+      get(addr, "HEAD /foo HTTP/1.0\r\n\r\n"),
+      "HTTP/1.1 200 OK\r\n\
+       Accept-Ranges: bytes\r\n\
+       Content-Type: application/octet-stream\r\n\
+       Content-Length: 10\r\n\
+       Connection: close\r\n\
+       \r\n",
+    );
+  }
+
+  #[test]
+  fn multiple_files() {
+    let (_env, addr) = spawn("bar");
+
+    let response = get(
+      addr,
+      "GET /bar/a HTTP/1.1\r\nRange: bytes=1-\r\n\r\n\
+       GET /bar/b%20c HTTP/1.1\r\nConnection: close\r\n\r\n",
+    );
+
+    assert!(response.contains("Content-Range: bytes 1-2/3\r\n"));
+    assert!(response.contains("\r\n\r\nbcHTTP/1.1 200 OK\r\n"));
+    assert!(response.ends_with("\r\n\r\ndefg"));
+  }
+
+  #[test]
+  fn not_found() {
+    let (_env, addr) = spawn("bar");
+
+    for path in ["/a", "/bar", "/bar/", "/bar/a/b"] {
+      assert!(
+        get(addr, &format!("GET {path} HTTP/1.0\r\n\r\n")).starts_with("HTTP/1.1 404 Not Found"),
+        "{path}",
+      );
+    }
+  }
+
+  #[test]
+  fn method_not_allowed() {
+    let (_env, addr) = spawn("foo");
+
+    assert_eq!(
+      get(addr, "POST /foo HTTP/1.0\r\n\r\n"),
+      "HTTP/1.1 405 Method Not Allowed\r\n\
+       Allow: GET, HEAD\r\n\
+       Content-Length: 0\r\n\
+       Connection: close\r\n\
+       \r\n",
+    );
+  }
+
+  #[test]
+  fn workers_limited() {
+    let (_env, addr) = spawn_limited("foo", 1);
+
+    // Occupy the only worker with an idle connection.
+    let idle = TcpStream::connect(addr).unwrap();
+
+    let mut waiting = TcpStream::connect(addr).unwrap();
+    waiting.write_all(b"HEAD /foo HTTP/1.0\r\n\r\n").unwrap();
+    waiting
+      .set_read_timeout(Some(Duration::from_millis(200)))
+      .unwrap();
+    assert!(waiting.read(&mut [0]).is_err());
+
+    drop(idle);
+
+    waiting.set_read_timeout(None).unwrap();
+    let mut response = String::new();
+    waiting.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+  }
+
+  #[test]
+  fn bad_request() {
+    let (_env, addr) = spawn("foo");
+
+    assert!(get(addr, "GET /foo\r\n\r\n").starts_with("HTTP/1.1 400 Bad Request\r\n"));
+  }
+}
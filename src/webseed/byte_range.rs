@@ -0,0 +1,110 @@
+use super::*;
+
+/// How much of a file to send in response to a request, given its `Range`
+/// header.
+#[derive(Debug, PartialEq)]
+pub(crate) enum ByteRange {
+  Full,
+  Partial(Range<u64>),
+  Unsatisfiable,
+}
+
+impl ByteRange {
+  /// Resolve a `Range` header against a file of `length` bytes. Headers we
+  /// don't understand, including ones asking for more than one range, are
+  /// ignored, so the whole file is sent, which RFC 9110 permits.
+  pub(crate) fn resolve(header: Option<&str>, length: u64) -> Self {
+    let Some(header) = header else {
+      return Self::Full;
+    };
+
+    let Some(spec) = header.trim().strip_prefix("bytes=") else {
+      return Self::Full;
+    };
+
+    let Some((first, last)) = spec.trim().split_once('-') else {
+      return Self::Full;
+    };
+
+    if first.is_empty() {
+      // A suffix range, asking for the last `last` bytes.
+      return match last.parse::<u64>() {
+        Ok(0) => Self::Unsatisfiable,
+        Ok(_) if length == 0 => Self::Unsatisfiable,
+        Ok(suffix) => Self::Partial(length.saturating_sub(suffix)..length),
+        Err(_) => Self::Full,
+      };
+    }
+
+    let Ok(first) = first.parse::<u64>() else {
+      return Self::Full;
+    };
+
+    let end = if last.is_empty() {
+      length
+    } else {
+      match last.parse::<u64>() {
+        Ok(last) if last >= first => last.saturating_add(1).min(length),
+        _ => return Self::Full,
+      }
+    };
+
+    if first >= length {
+      return Self::Unsatisfiable;
+    }
+
+    Self::Partial(first..end)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn resolve(header: &str, length: u64) -> ByteRange {
+    ByteRange::resolve(Some(header), length)
+  }
+
+  #[test]
+  fn absent() {
+    assert_eq!(ByteRange::resolve(None, 10), ByteRange::Full);
+  }
+
+  #[test]
+  fn bounded() {
+    assert_eq!(resolve("bytes=2-5", 10), ByteRange::Partial(2..6));
+    assert_eq!(resolve("bytes=0-0", 10), ByteRange::Partial(0..1));
+  }
+
+  #[test]
+  fn bounded_past_end() {
+    assert_eq!(resolve("bytes=8-100", 10), ByteRange::Partial(8..10));
+  }
+
+  #[test]
+  fn open_ended() {
+    assert_eq!(resolve("bytes=4-", 10), ByteRange::Partial(4..10));
+  }
+
+  #[test]
+  fn suffix() {
+    assert_eq!(resolve("bytes=-3", 10), ByteRange::Partial(7..10));
+    assert_eq!(resolve("bytes=-30", 10), ByteRange::Partial(0..10));
+  }
+
+  #[test]
+  fn unsatisfiable() {
+    assert_eq!(resolve("bytes=10-", 10), ByteRange::Unsatisfiable);
+    assert_eq!(resolve("bytes=-0", 10), ByteRange::Unsatisfiable);
+    assert_eq!(resolve("bytes=0-", 0), ByteRange::Unsatisfiable);
+    assert_eq!(resolve("bytes=-1", 0), ByteRange::Unsatisfiable);
+  }
+
+  #[test]
+  fn ignored() {
+    assert_eq!(resolve("bytes=5-2", 10), ByteRange::Full);
+    assert_eq!(resolve("bytes=0-1,4-5", 10), ByteRange::Full);
+    assert_eq!(resolve("items=0-1", 10), ByteRange::Full);
+    assert_eq!(resolve("bytes=a-b", 10), ByteRange::Full);
+  }
+}
@@ -0,0 +1,179 @@
+use super::*;
+
+/// The parts of an HTTP/1.x request that a web seed needs.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Request {
+  pub(crate) method: String,
+  pub(crate) path: Vec<String>,
+  pub(crate) range: Option<String>,
+  pub(crate) keep_alive: bool,
+}
+
+impl Request {
+  const MAX_HEAD: u64 = 16 * 1024;
+
+  /// Read the next request head from `reader`, returning `None` if the
+  /// connection was closed before one began. Request bodies aren't
+  /// supported, which is fine for `GET` and `HEAD`.
+  pub(crate) fn read(reader: &mut impl BufRead) -> Result<Option<Self>, Malformed> {
+    let mut reader = reader.take(Self::MAX_HEAD);
+
+    let mut line = String::new();
+    if reader.read_line(&mut line).map_err(|_| Malformed)? == 0 {
+      return Ok(None);
+    }
+
+    let mut parts = line.split_whitespace();
+
+    let (Some(method), Some(target), Some(version), None) =
+      (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+      return Err(Malformed);
+    };
+
+    let mut keep_alive = match version {
+      "HTTP/1.1" => true,
+      "HTTP/1.0" => false,
+      _ => return Err(Malformed),
+    };
+
+    let path = Self::decode(target).ok_or(Malformed)?;
+
+    let method = method.to_owned();
+
+    let mut range = None;
+
+    loop {
+      let mut line = String::new();
+      if reader.read_line(&mut line).map_err(|_| Malformed)? == 0 {
+        return Err(Malformed);
+      }
+
+      let line = line.trim_end_matches(['\r', '\n']);
+
+      if line.is_empty() {
+        break;
+      }
+
+      let (name, value) = line.split_once(':').ok_or(Malformed)?;
+      let value = value.trim();
+
+      if name.eq_ignore_ascii_case("range") {
+        range = Some(value.to_owned());
+      } else if name.eq_ignore_ascii_case("connection") {
+        for option in value.split(',').map(str::trim) {
+          if option.eq_ignore_ascii_case("close") {
+            keep_alive = false;
+          } else if option.eq_ignore_ascii_case("keep-alive") {
+            keep_alive = true;
+          }
+        }
+      }
+    }
+
+    Ok(Some(Self {
+      method,
+      path,
+      range,
+      keep_alive,
+    }))
+  }
+
+  // Split an origin-form request target into percent-decoded path segments,
+  // ignoring any query string, which clients may add to defeat caches.
+  fn decode(target: &str) -> Option<Vec<String>> {
+    let path = target.strip_prefix('/')?;
+    let path = path.split_once('?').map_or(path, |(path, _)| path);
+
+    path
+      .split('/')
+      .map(|segment| {
+        percent_encoding::percent_decode_str(segment)
+          .decode_utf8()
+          .ok()
+          .map(Cow::into_owned)
+      })
+      .collect()
+  }
+}
+
+/// A request that couldn't be parsed, which is answered with `400 Bad
+/// Request` before closing the connection.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Malformed;
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn read(head: &str) -> Result<Option<Request>, Malformed> {
+    Request::read(&mut head.as_bytes())
+  }
+
+  #[test]
+  fn get() {
+    assert_eq!(
+      read("GET /foo/bar%20baz.txt HTTP/1.1\r\nHost: x\r\nRange: bytes=1-2\r\n\r\n"),
+      Ok(Some(Request {
+        method: "GET".into(),
+        path: vec!["foo".into(), "bar baz.txt".into()],
+        range: Some("bytes=1-2".into()),
+        keep_alive: true,
+      }))
+    );
+  }
+
+  #[test]
+  fn query_ignored() {
+    assert_eq!(
+      read("HEAD /foo?x=1 HTTP/1.1\r\n\r\n")
+        .unwrap()
+        .unwrap()
+        .path,
+      ["foo"],
+    );
+  }
+
+  #[test]
+  fn keep_alive() {
+    assert!(
+      !read("GET /foo HTTP/1.0\r\n\r\n")
+        .unwrap()
+        .unwrap()
+        .keep_alive
+    );
+    assert!(
+      read("GET /foo HTTP/1.0\r\nConnection: Keep-Alive\r\n\r\n")
+        .unwrap()
+        .unwrap()
+        .keep_alive
+    );
+    assert!(
+      !read("GET /foo HTTP/1.1\r\nconnection: close\r\n\r\n")
+        .unwrap()
+        .unwrap()
+        .keep_alive
+    );
+  }
+
+  #[test]
+  fn closed() {
+    assert_eq!(read(""), Ok(None));
+  }
+
+  #[test]
+  fn malformed() {
+    assert_eq!(read("GET /foo\r\n\r\n"), Err(Malformed));
+    assert_eq!(read("GET foo HTTP/1.1\r\n\r\n"), Err(Malformed));
+    assert_eq!(read("GET /foo HTTP/2\r\n\r\n"), Err(Malformed));
+    assert_eq!(read("GET /foo HTTP/1.1\r\nHost\r\n\r\n"), Err(Malformed));
+    assert_eq!(read("GET /foo HTTP/1.1\r\nHost: x\r\n"), Err(Malformed));
+    assert_eq!(read("GET /%FF HTTP/1.1\r\n\r\n"), Err(Malformed));
+  }
+
+  #[test]
+  fn head_too_long() {
+    let head = format!("GET /foo HTTP/1.1\r\nX: {}\r\n\r\n", "a".repeat(20 * 1024));
+    assert_eq!(read(&head), Err(Malformed));
+  }
+}
@@ -0,0 +1,434 @@
+//! A sans-IO RFC 6455 WebSocket client. The caller moves bytes between the
+//! network and the `input` and `output` buffers, and the client handles the
+//! opening handshake, framing, masking, and answering pings.
+
+use crate::common::*;
+
+#[derive(Debug)]
+pub(crate) struct Client {
+  key: String,
+  open: bool,
+  message: Option<(bool, Vec<u8>)>,
+}
+
+impl Client {
+  // Appended to our key to compute the accept header the server should send.
+  const GUID: &'static str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+  const MAX_HANDSHAKE_RESPONSE: usize = 8 * 1024;
+  const MAX_MESSAGE_LENGTH: usize = 1024 * 1024;
+
+  const FIN: u8 = 0x80;
+  const RESERVED: u8 = 0x70;
+  const OPCODE: u8 = 0x0F;
+  const MASK: u8 = 0x80;
+
+  const CONTINUATION: u8 = 0x0;
+  const TEXT: u8 = 0x1;
+  const BINARY: u8 = 0x2;
+  const CLOSE: u8 = 0x8;
+  const PING: u8 = 0x9;
+  const PONG: u8 = 0xA;
+
+  /// Begin opening a WebSocket to `url`, returning the client and the opening
+  /// handshake to send.
+  pub(crate) fn new(url: &Url) -> (Self, Vec<u8>) {
+    let key = base64(&rand::rng().random::<[u8; 16]>());
+
+    let host = match url.port() {
+      Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+      None => url.host_str().unwrap_or_default().to_owned(),
+    };
+
+    let lines = [
+      format!(
+        "GET {} HTTP/1.1",
+        &url[url::Position::BeforePath..url::Position::AfterQuery]
+      ),
+      format!("Host: {host}"),
+      "Upgrade: websocket".into(),
+      "Connection: Upgrade".into(),
+      format!("Sec-WebSocket-Key: {key}"),
+      "Sec-WebSocket-Version: 13".into(),
+      String::new(),
+      String::new(),
+    ];
+
+    let client = Self {
+      key,
+      open: false,
+      message: None,
+    };
+
+    (client, lines.join("\r\n").into_bytes())
+  }
+
+  /// Whether the server has accepted the opening handshake. No messages
+  /// should be sent before it has.
+  pub(crate) fn is_open(&self) -> bool {
+    self.open
+  }
+
+  /// Queue `text` as a text message.
+  pub(crate) fn send(&mut self, text: &str, output: &mut Vec<u8>) {
+    debug_assert!(self.open, "sent before the handshake completed");
+    Self::frame(Self::TEXT, text.as_bytes(), output);
+  }
+
+  // Append a masked frame, since clients must mask every frame they send.
+  fn frame(opcode: u8, payload: &[u8], output: &mut Vec<u8>) {
+    output.push(Self::FIN | opcode);
+
+    match payload.len() {
+      len @ 0..=125 => output.push(Self::MASK | u8::try_from(len).invariant_unwrap("len < 126")),
+      len @ 126..=0xFFFF => {
+        output.push(Self::MASK | 0x7E);
+        output.extend(
+          u16::try_from(len)
+            .invariant_unwrap("len < 2^16")
+            .to_be_bytes(),
+        );
+      }
+      len => {
+        output.push(Self::MASK | 0x7F);
+        output.extend(len.into_u64().to_be_bytes());
+      }
+    }
+
+    let mask = rand::rng().random::<[u8; 4]>();
+    output.extend(mask);
+    output.extend(
+      payload
+        .iter()
+        .zip(mask.iter().cycle())
+        .map(|(byte, mask)| byte ^ mask),
+    );
+  }
+
+  /// Consume what the server has sent from `input`, queueing any replies in
+  /// `output`, and return the next complete text message, if any. Binary
+  /// messages are ignored.
+  pub(crate) fn recv(
+    &mut self,
+    input: &mut Vec<u8>,
+    output: &mut Vec<u8>,
+  ) -> Result<Option<String>> {
+    if !self.open {
+      self.open = self.handshake(input)?;
+      if !self.open {
+        return Ok(None);
+      }
+    }
+
+    while let Some((header, opcode, payload)) = Self::parse_frame(input)? {
+      let fin = header & Self::FIN != 0;
+
+      match opcode {
+        Self::PING => Self::frame(Self::PONG, &payload, output),
+        Self::PONG => {}
+        Self::CLOSE => return Err(Error::WebSocketClosed),
+        Self::TEXT | Self::BINARY if self.message.is_none() => {
+          self.message = Some((opcode == Self::TEXT, payload));
+        }
+        Self::CONTINUATION => match &mut self.message {
+          Some((_, message)) => message.extend(payload),
+          None => return Err(Error::WebSocketFrame),
+        },
+        _ => return Err(Error::WebSocketFrame),
+      }
+
+      if let Some((_, message)) = &self.message {
+        if message.len() > Self::MAX_MESSAGE_LENGTH {
+          return Err(Error::WebSocketMessageTooLarge {
+            max: Self::MAX_MESSAGE_LENGTH,
+          });
+        }
+      }
+
+      if fin && opcode < Self::CLOSE {
+        if let Some((true, message)) = self.message.take() {
+          return String::from_utf8(message)
+            .map(Some)
+            .map_err(|_| Error::WebSocketFrame);
+        }
+      }
+    }
+
+    Ok(None)
+  }
+
+  // Check the server's handshake response, returning true once it has
+  // arrived and accepted the connection.
+  fn handshake(&self, input: &mut Vec<u8>) -> Result<bool> {
+    let Some(end) = input.windows(4).position(|window| window == b"\r\n\r\n") else {
+      if input.len() > Self::MAX_HANDSHAKE_RESPONSE {
+        return Err(Error::WebSocketHandshake {
+          reason: "response too long".into(),
+        });
+      }
+      return Ok(false);
+    };
+
+    let head = String::from_utf8_lossy(&input[..end]).into_owned();
+    input.drain(..end + 4);
+
+    let mut lines = head.lines();
+
+    let status_line = lines.next().unwrap_or_default();
+
+    if status_line.split_whitespace().nth(1) != Some("101") {
+      return Err(Error::WebSocketHandshake {
+        reason: status_line.to_owned(),
+      });
+    }
+
+    let accept = lines.find_map(|line| {
+      let (name, value) = line.split_once(':')?;
+      name
+        .trim()
+        .eq_ignore_ascii_case("sec-websocket-accept")
+        .then(|| value.trim().to_owned())
+    });
+
+    let expected = base64(
+      &Sha1::from(format!("{}{}", self.key, Self::GUID))
+        .digest()
+        .bytes(),
+    );
+
+    if accept.as_deref() != Some(expected.as_str()) {
+      return Err(Error::WebSocketHandshake {
+        reason: "missing or incorrect `Sec-WebSocket-Accept` header".into(),
+      });
+    }
+
+    Ok(true)
+  }
+
+  // Remove the next complete frame from `input`, returning its first byte,
+  // opcode, and payload.
+  fn parse_frame(input: &mut Vec<u8>) -> Result<Option<(u8, u8, Vec<u8>)>> {
+    let Some(&[header, length]) = input.get(..2) else {
+      return Ok(None);
+    };
+
+    let opcode = header & Self::OPCODE;
+
+    // Servers must not mask frames, and we negotiate no extensions that use
+    // the reserved bits.
+    if header & Self::RESERVED != 0 || length & Self::MASK != 0 {
+      return Err(Error::WebSocketFrame);
+    }
+
+    let (start, length) = match length {
+      126 => {
+        let Some(bytes) = input.get(2..4) else {
+          return Ok(None);
+        };
+        (
+          4,
+          u16::from_be_bytes(bytes.try_into().invariant_unwrap("length is checked")).into(),
+        )
+      }
+      127 => {
+        let Some(bytes) = input.get(2..10) else {
+          return Ok(None);
+        };
+        (
+          10,
+          u64::from_be_bytes(bytes.try_into().invariant_unwrap("length is checked")),
+        )
+      }
+      length => (2, length.into()),
+    };
+
+    // Control frames can't be fragmented, and carry at most 125 bytes.
+    if opcode >= Self::CLOSE && (header & Self::FIN == 0 || length > 125) {
+      return Err(Error::WebSocketFrame);
+    }
+
+    let length = usize::try_from(length)
+      .ok()
+      .filter(|&length| length <= Self::MAX_MESSAGE_LENGTH)
+      .ok_or(Error::WebSocketMessageTooLarge {
+        max: Self::MAX_MESSAGE_LENGTH,
+      })?;
+
+    let Some(payload) = input.get(start..start + length) else {
+      return Ok(None);
+    };
+
+    let payload = payload.to_vec();
+    input.drain(..start + length);
+
+    Ok(Some((header, opcode, payload)))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn open() -> Client {
+    let (mut client, _) = Client::new(&"ws://localhost/announce".parse().unwrap());
+    client.open = true;
+    client
+  }
+
+  fn unmask(frame: &[u8]) -> (u8, Vec<u8>) {
+    assert_eq!(frame[1] & Client::MASK, Client::MASK);
+    let length = usize::from(frame[1] & 0x7F);
+    let mask = &frame[2..6];
+    let payload = frame[6..6 + length]
+      .iter()
+      .zip(mask.iter().cycle())
+      .map(|(byte, mask)| byte ^ mask)
+      .collect();
+    (frame[0], payload)
+  }
+
+  #[test]
+  fn request() {
+    let (client, request) =
+      Client::new(&"wss://tracker.example:8443/announce?foo".parse().unwrap());
+    let request = String::from_utf8(request).unwrap();
+    assert_eq!(
+      request,
+      format!(
+        "GET /announce?foo HTTP/1.1\r\n\
+         Host: tracker.example:8443\r\n\
+         Upgrade: websocket\r\n\
+         Connection: Upgrade\r\n\
+         Sec-WebSocket-Key: {}\r\n\
+         Sec-WebSocket-Version: 13\r\n\r\n",
+        client.key
+      )
+    );
+  }
+
+  #[test]
+  fn handshake() {
+    let (mut client, _) = Client::new(&"ws://localhost/".parse().unwrap());
+
+    // The example from RFC 6455.
+    client.key = "dGhlIHNhbXBsZSBub25jZQ==".into();
+
+    let mut input = b"HTTP/1.1 101 Switching Protocols\r\n\
+      Upgrade: websocket\r\n\
+      Connection: Upgrade\r\n\
+      sec-websocket-accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n\x81\x02hi"
+      .to_vec();
+
+    assert_eq!(
+      client.recv(&mut input, &mut Vec::new()).unwrap(),
+      Some("hi".into())
+    );
+  }
+
+  #[test]
+  fn handshake_rejected() {
+    let (mut client, _) = Client::new(&"ws://localhost/".parse().unwrap());
+    let mut input = b"HTTP/1.1 404 Not Found\r\n\r\n".to_vec();
+    assert_matches!(
+      client.recv(&mut input, &mut Vec::new()),
+      Err(Error::WebSocketHandshake { reason }) if reason == "HTTP/1.1 404 Not Found"
+    );
+  }
+
+  #[test]
+  fn handshake_wrong_accept() {
+    let (mut client, _) = Client::new(&"ws://localhost/".parse().unwrap());
+    let mut input =
+      b"HTTP/1.1 101 Switching Protocols\r\nSec-WebSocket-Accept: foo\r\n\r\n".to_vec();
+    assert_matches!(
+      client.recv(&mut input, &mut Vec::new()),
+      Err(Error::WebSocketHandshake { .. })
+    );
+  }
+
+  #[test]
+  fn send_masked() {
+    let mut client = open();
+    let mut output = Vec::new();
+    client.send("hello", &mut output);
+    assert_eq!(unmask(&output), (0x81, b"hello".to_vec()));
+  }
+
+  #[test]
+  fn send_long() {
+    let mut client = open();
+    let mut output = Vec::new();
+    client.send(&"a".repeat(300), &mut output);
+    assert_eq!(output[1], 0x80 | 0x7E);
+    assert_eq!(&output[2..4], 300u16.to_be_bytes());
+    assert_eq!(output.len(), 4 + 4 + 300);
+  }
+
+  #[test]
+  fn fragmented() {
+    let mut client = open();
+    let mut input = b"\x01\x03foo\x89\x01!".to_vec();
+    let mut output = Vec::new();
+    assert_eq!(client.recv(&mut input, &mut output).unwrap(), None);
+    assert_eq!(unmask(&output), (0x8A, b"!".to_vec()));
+    input.extend(b"\x80\x03bar");
+    assert_eq!(
+      client.recv(&mut input, &mut output).unwrap(),
+      Some("foobar".into())
+    );
+  }
+
+  #[test]
+  fn partial() {
+    let mut client = open();
+    let mut input = b"\x81\x7e\x01".to_vec();
+    assert_eq!(client.recv(&mut input, &mut Vec::new()).unwrap(), None);
+    input.push(0x00);
+    input.extend(vec![b'a'; 256]);
+    assert_eq!(
+      client.recv(&mut input, &mut Vec::new()).unwrap(),
+      Some("a".repeat(256))
+    );
+    assert!(input.is_empty());
+  }
+
+  #[test]
+  fn binary_ignored() {
+    let mut client = open();
+    let mut input = b"\x82\x01x\x81\x01y".to_vec();
+    assert_eq!(
+      client.recv(&mut input, &mut Vec::new()).unwrap(),
+      Some("y".into())
+    );
+  }
+
+  #[test]
+  fn close() {
+    let mut client = open();
+    let mut input = b"\x88\x00".to_vec();
+    assert_matches!(
+      client.recv(&mut input, &mut Vec::new()),
+      Err(Error::WebSocketClosed)
+    );
+  }
+
+  #[test]
+  fn masked_server_frame() {
+    let mut client = open();
+    let mut input = b"\x81\x81\x00\x00\x00\x00x".to_vec();
+    assert_matches!(
+      client.recv(&mut input, &mut Vec::new()),
+      Err(Error::WebSocketFrame)
+    );
+  }
+
+  #[test]
+  fn too_large() {
+    let mut client = open();
+    let mut input = b"\x81\x7f\x00\x00\x00\x01\x00\x00\x00\x00".to_vec();
+    assert_matches!(
+      client.recv(&mut input, &mut Vec::new()),
+      Err(Error::WebSocketMessageTooLarge { .. })
+    );
+  }
+}